@@ -0,0 +1,311 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use nom::{
+    bytes::complete::take,
+    number::complete::{le_u32, le_u64},
+    IResult,
+};
+
+use crate::file_subset::FileSubset;
+use crate::machine;
+use crate::macho::{FatMachO, MachO, MachOErr, MachOResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveMember {
+    pub name: String,
+    pub date: u64,
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+    /// Offset of the member's `ar` header, which is what the symbol index refers to.
+    pub header_offset: u64,
+    /// Offset and size of the member's contents, excluding any BSD long name.
+    pub offset: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveSymbol {
+    pub name: String,
+    pub member_offset: u64,
+}
+
+#[derive(Debug)]
+pub struct Archive<T: Read + Seek> {
+    pub members: Vec<ArchiveMember>,
+    pub symbols: Vec<ArchiveSymbol>,
+    buf: T,
+}
+
+impl<T: Read + Seek> Archive<T> {
+    pub const MAGIC: &'static [u8] = b"!<arch>\n";
+    pub const HEADER_SIZE: usize = 60;
+    const BSD_LONG_NAME: &'static str = "#1/";
+
+    pub fn is_archive_magic(buf: &mut T) -> MachOResult<bool> {
+        let mut magic = [0u8; 8];
+        buf.seek(SeekFrom::Start(0)).map_err(MachOErr::IOError)?;
+        Ok(buf.read_exact(&mut magic).is_ok() && magic == Self::MAGIC)
+    }
+
+    pub fn parse(mut buf: T) -> MachOResult<Self> {
+        if !Self::is_archive_magic(&mut buf)? {
+            return Err(MachOErr::MagicError);
+        }
+        let end = buf.seek(SeekFrom::End(0)).map_err(MachOErr::IOError)?;
+
+        let mut members = Vec::new();
+        let mut symbols = Vec::new();
+        let mut offset = Self::MAGIC.len() as u64;
+        while offset + Self::HEADER_SIZE as u64 <= end {
+            let mut header = vec![0u8; Self::HEADER_SIZE];
+            buf.seek(SeekFrom::Start(offset))
+                .map_err(MachOErr::IOError)?;
+            buf.read_exact(&mut header).map_err(MachOErr::IOError)?;
+            let mut member = Self::parse_member_header(&header, offset)?;
+
+            if let Some(len) = member.name.strip_prefix(Self::BSD_LONG_NAME) {
+                let len: u64 = len.parse().map_err(|_| {
+                    MachOErr::ParsingError(format!("Invalid BSD long name length {:?}", len))
+                })?;
+                if len > member.size {
+                    return Err(MachOErr::ParsingError(
+                        "BSD long name is larger than its member".to_string(),
+                    ));
+                }
+                let mut name = vec![0u8; len as usize];
+                buf.read_exact(&mut name).map_err(MachOErr::IOError)?;
+                let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+                member.name = String::from_utf8_lossy(&name[..name_len]).to_string();
+                member.offset += len;
+                member.size -= len;
+            }
+
+            if member.offset + member.size > end {
+                return Err(MachOErr::ParsingError(format!(
+                    "Archive member {} extends past the end of the file",
+                    member.name
+                )));
+            }
+
+            // Members are 2 byte aligned.
+            offset = (member.offset + member.size + 1) & !1;
+
+            if member.name.starts_with("__.SYMDEF") {
+                let mut table = vec![0u8; member.size as usize];
+                buf.seek(SeekFrom::Start(member.offset))
+                    .map_err(MachOErr::IOError)?;
+                buf.read_exact(&mut table).map_err(MachOErr::IOError)?;
+                let is_64 = member.name.starts_with("__.SYMDEF_64");
+                symbols = Self::parse_symbol_table(&table, is_64)?;
+            } else {
+                members.push(member);
+            }
+        }
+
+        Ok(Self {
+            members,
+            symbols,
+            buf,
+        })
+    }
+
+    fn parse_member_header(header: &[u8], header_offset: u64) -> MachOResult<ArchiveMember> {
+        if &header[58..60] != b"`\n" {
+            return Err(MachOErr::ParsingError(format!(
+                "Bad archive member header at 0x{:x}",
+                header_offset
+            )));
+        }
+
+        let field = |range: std::ops::Range<usize>| {
+            String::from_utf8_lossy(&header[range])
+                .trim_end()
+                .to_string()
+        };
+        let number = |range: std::ops::Range<usize>, radix: u32| {
+            let text = field(range);
+            if text.is_empty() {
+                return Ok(0);
+            }
+            u64::from_str_radix(&text, radix).map_err(|_| {
+                MachOErr::ParsingError(format!("Invalid archive header field {:?}", text))
+            })
+        };
+
+        Ok(ArchiveMember {
+            name: field(0..16),
+            date: number(16..28, 10)?,
+            uid: number(28..34, 10)? as u32,
+            gid: number(34..40, 10)? as u32,
+            mode: number(40..48, 8)? as u32,
+            header_offset,
+            offset: header_offset + Self::HEADER_SIZE as u64,
+            size: number(48..58, 10)?,
+        })
+    }
+
+    fn parse_ranlib(bytes: &[u8], is_64: bool) -> IResult<&[u8], u64> {
+        if is_64 {
+            le_u64(bytes)
+        } else {
+            let (bytes, value) = le_u32(bytes)?;
+            Ok((bytes, value as u64))
+        }
+    }
+
+    fn parse_symbol_table(table: &[u8], is_64: bool) -> MachOResult<Vec<ArchiveSymbol>> {
+        let (cursor, ranlib_size) = Self::parse_ranlib(table, is_64)?;
+        let (cursor, mut ranlibs) = take(ranlib_size as usize)(cursor)?;
+        let (cursor, strtab_size) = Self::parse_ranlib(cursor, is_64)?;
+        let (_, strtab) = take(strtab_size as usize)(cursor)?;
+
+        let mut symbols = Vec::new();
+        while !ranlibs.is_empty() {
+            let (next, strx) = Self::parse_ranlib(ranlibs, is_64)?;
+            let (next, member_offset) = Self::parse_ranlib(next, is_64)?;
+            ranlibs = next;
+
+            let name = strtab.get(strx as usize..).ok_or_else(|| {
+                MachOErr::ParsingError(format!("Symbol name offset 0x{:x} out of range", strx))
+            })?;
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            symbols.push(ArchiveSymbol {
+                name: String::from_utf8_lossy(&name[..len]).to_string(),
+                member_offset,
+            });
+        }
+        Ok(symbols)
+    }
+
+    /// Parses the member at `index` as a MachO.
+    pub fn member(&mut self, index: usize) -> MachOResult<MachO<FileSubset<'_, T>>> {
+        let member = self.members.get(index).ok_or_else(|| {
+            MachOErr::InvalidValue(format!("Archive member index {} out of range", index))
+        })?;
+
+        let mut subset = FileSubset::new(&mut self.buf, member.offset, member.size)
+            .map_err(MachOErr::IOError)?;
+        if !MachO::is_macho_magic(&mut subset)? {
+            return Err(MachOErr::InvalidValue(format!(
+                "Archive member {} is not a MachO",
+                member.name
+            )));
+        }
+
+        MachO::parse(subset)
+    }
+
+    pub fn member_by_name(&mut self, name: &str) -> MachOResult<MachO<FileSubset<'_, T>>> {
+        let index = self
+            .members
+            .iter()
+            .position(|member| member.name == name)
+            .ok_or_else(|| MachOErr::InvalidValue(format!("No archive member named {}", name)))?;
+        self.member(index)
+    }
+
+    /// Returns the index of the member that defines `symbol`, according to the symbol index.
+    pub fn member_for_symbol(&self, symbol: &str) -> Option<usize> {
+        let symbol = self.symbols.iter().find(|sym| sym.name == symbol)?;
+        self.members
+            .iter()
+            .position(|member| member.header_offset == symbol.member_offset)
+    }
+}
+
+impl<'a, T: Seek + Read> FatMachO<'a, T> {
+    /// Parses the static archive slice for `cputype` from a fat file.
+    pub fn archive(
+        &'a mut self,
+        cputype: machine::CpuType,
+    ) -> MachOResult<Archive<FileSubset<'a, T>>> {
+        let arch = self
+            .archs
+            .iter()
+            .find(|arch| arch.cputype() == cputype)
+            .ok_or(MachOErr::InvalidValue(format!(
+                "CPU type {:?} not found in fat binary",
+                cputype
+            )))?;
+        let (offset, size) = (arch.offset(), arch.size());
+
+        let subset = FileSubset::new(self.buf, offset, size)
+            .map_err(|_| MachOErr::InvalidValue("Unable to create subset".to_string()))?;
+        Archive::parse(subset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::header::MHFileType;
+    use crate::testing::image64;
+
+    fn member(name: &str, data: &[u8]) -> Vec<u8> {
+        let (name, data) = if name.len() > 16 || name.contains(' ') {
+            let mut long = name.as_bytes().to_vec();
+            long.resize((name.len() + 8) & !7, 0);
+            (format!("#1/{}", long.len()), [long, data.to_vec()].concat())
+        } else {
+            (name.to_string(), data.to_vec())
+        };
+
+        let mut bytes = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            name,
+            0,
+            501,
+            20,
+            "100644",
+            data.len()
+        )
+        .into_bytes();
+        bytes.extend(data);
+        if bytes.len() % 2 != 0 {
+            bytes.push(b'\n');
+        }
+        bytes
+    }
+
+    fn object() -> Vec<u8> {
+        image64(MHFileType::MhObject, &[])
+    }
+
+    #[test]
+    fn test_archive() {
+        let first = member("a.o", &object());
+        // Header, the 24 byte padded long name, then the 32 byte table.
+        let symdef_len = Archive::<Cursor<Vec<u8>>>::HEADER_SIZE + 24 + 32;
+
+        // The symbol index refers to member header offsets.
+        let a_offset = 8 + symdef_len as u32;
+        let b_offset = a_offset + first.len() as u32;
+        let mut symdef = Vec::new();
+        symdef.extend(16u32.to_le_bytes());
+        symdef.extend(
+            [0u32, a_offset, 3, b_offset]
+                .iter()
+                .flat_map(|v| v.to_le_bytes()),
+        );
+        symdef.extend(8u32.to_le_bytes());
+        symdef.extend(b"_a\0_b\0\0\0");
+
+        let mut bytes = b"!<arch>\n".to_vec();
+        bytes.extend(member("__.SYMDEF SORTED", &symdef));
+        assert_eq!(bytes.len() as u32, a_offset);
+        bytes.extend(first);
+        bytes.extend(member("a_very_long_member_name.o", &object()));
+
+        let mut archive = Archive::parse(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.members.len(), 2);
+        assert_eq!(archive.members[1].name, "a_very_long_member_name.o");
+        assert_eq!(archive.symbols.len(), 2);
+        assert_eq!(archive.member_for_symbol("_b"), Some(1));
+
+        let macho = archive.member_by_name("a_very_long_member_name.o").unwrap();
+        assert_eq!(*macho.header.filetype(), MHFileType::MhObject);
+    }
+}
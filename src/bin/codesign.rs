@@ -0,0 +1,92 @@
+use std::{env, fs, process};
+
+use macho2::edit::fat::{arch_from_name, extract_slice, is_fat, map_slices, replace_slice};
+use macho2::edit::{HeaderOverflow, MachOEditor};
+use macho2::macho::MachOResult;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} --remove-signature <file_path> [-o <output>]",
+        program
+    );
+    eprintln!(
+        "       {} --replace-signature <signature> [-arch <arch>] <file_path> [-o <output>]",
+        program
+    );
+    process::exit(1);
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn read(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| fail(format!("Failed to read {}: {}", path, e)))
+}
+
+fn replace(data: Vec<u8>, signature: &[u8]) -> MachOResult<Vec<u8>> {
+    let mut editor = MachOEditor::parse(data)?;
+    editor.replace_code_signature(signature)?;
+    editor.write(HeaderOverflow::Fail)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+
+    let mut signature = None;
+    let mut remove = false;
+    let mut arch = None;
+    let mut file_path = None;
+    let mut output = None;
+    let mut rest = args[1..].iter().cloned();
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().unwrap_or_else(|| usage(program));
+        match arg.as_str() {
+            "--remove-signature" => remove = true,
+            "--replace-signature" => signature = Some(value()),
+            "-arch" => arch = Some(value()),
+            "-o" => output = Some(value()),
+            _ if arg.starts_with('-') || file_path.is_some() => usage(program),
+            _ => file_path = Some(arg),
+        }
+    }
+    if remove == signature.is_some() || (remove && arch.is_some()) {
+        usage(program);
+    }
+    let file_path = file_path.unwrap_or_else(|| usage(program));
+    let output = output.unwrap_or_else(|| file_path.clone());
+    let data = read(&file_path);
+
+    let result = match signature {
+        None => map_slices(data, |slice| {
+            let mut editor = MachOEditor::parse(slice)?;
+            editor.remove_code_signature()?;
+            editor.write(HeaderOverflow::Fail)
+        }),
+        // Each slice has its own signature, so a fat file takes one at a time.
+        Some(signature) => {
+            let signature = read(&signature);
+            match (is_fat(&data), arch) {
+                (false, _) => replace(data, &signature),
+                (true, Some(name)) => {
+                    let (cputype, cpusubtype) = arch_from_name(&name)
+                        .unwrap_or_else(|| fail(format!("Unknown architecture {}", name)));
+                    extract_slice(&data, cputype, cpusubtype)
+                        .and_then(|slice| replace(slice, &signature))
+                        .and_then(|slice| replace_slice(&data, slice))
+                }
+                (true, None) => fail(format!(
+                    "{} is a fat file, choose the slice to sign with -arch",
+                    file_path
+                )),
+            }
+        }
+    };
+    let output_data = result.unwrap_or_else(|e| fail(e.to_string()));
+
+    if let Err(e) = fs::write(&output, output_data) {
+        fail(format!("Failed to write {}: {}", output, e));
+    }
+}
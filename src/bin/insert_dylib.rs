@@ -0,0 +1,72 @@
+use std::{env, fs, process};
+
+use macho2::edit::fat::map_slices;
+use macho2::edit::insert_dylib::CodeSignatureAction;
+use macho2::edit::{HeaderOverflow, MachOEditor};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [--weak] [--strip-codesig | --invalidate-codesig] [--current-version v] [--compatibility-version v] <dylib_path> <file_path> [output]",
+        program
+    );
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let mut weak = false;
+    let mut action = CodeSignatureAction::Keep;
+    let mut current_version = "0.0.0".to_string();
+    let mut compatibility_version = "0.0.0".to_string();
+    let mut positional = Vec::new();
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--weak" => weak = true,
+            "--strip-codesig" => action = CodeSignatureAction::Strip,
+            "--invalidate-codesig" => action = CodeSignatureAction::Invalidate,
+            "--current-version" => {
+                current_version = iter.next().cloned().unwrap_or_else(|| usage(&args[0]))
+            }
+            "--compatibility-version" => {
+                compatibility_version = iter.next().cloned().unwrap_or_else(|| usage(&args[0]))
+            }
+            _ if arg.starts_with("--") => usage(&args[0]),
+            _ => positional.push(arg.clone()),
+        }
+    }
+    if positional.len() < 2 || positional.len() > 3 {
+        usage(&args[0]);
+    }
+    let dylib_path = &positional[0];
+    let file_path = &positional[1];
+    let output = positional.get(2).unwrap_or(file_path);
+
+    let data = match fs::read(file_path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Failed to read file: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let result = map_slices(data, |slice| {
+        let mut editor = MachOEditor::parse(slice)?;
+        editor.apply_code_signature_action(action)?;
+        editor.insert_dylib(dylib_path, &current_version, &compatibility_version, weak)?;
+        editor.write(HeaderOverflow::Fail)
+    });
+    let output_data = match result {
+        Ok(output_data) => output_data,
+        Err(e) => {
+            eprintln!("Failed to insert {}: {}", dylib_path, e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = fs::write(output, output_data) {
+        eprintln!("Failed to write file: {}", e);
+        process::exit(1);
+    }
+}
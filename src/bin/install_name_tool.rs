@@ -0,0 +1,85 @@
+use std::{env, fs, process};
+
+use macho2::edit::fat::map_slices;
+use macho2::edit::{HeaderOverflow, MachOEditor};
+
+enum Change {
+    Id(String),
+    InstallName(String, String),
+    AddRpath(String),
+    DeleteRpath(String),
+    RenameRpath(String, String),
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [-id name] [-change old new] [-add_rpath path] [-delete_rpath path] [-rpath old new] <file_path>",
+        program
+    );
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        usage(&args[0]);
+    }
+
+    let mut changes = Vec::new();
+    let mut file_path = None;
+    let mut iter = args[1..].iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().unwrap_or_else(|| usage(&args[0]));
+        let change = match arg.as_str() {
+            "-id" => Change::Id(value()),
+            "-change" => Change::InstallName(value(), value()),
+            "-add_rpath" => Change::AddRpath(value()),
+            "-delete_rpath" => Change::DeleteRpath(value()),
+            "-rpath" => Change::RenameRpath(value(), value()),
+            _ if file_path.is_none() && !arg.starts_with('-') => {
+                file_path = Some(arg.clone());
+                continue;
+            }
+            _ => usage(&args[0]),
+        };
+        changes.push(change);
+    }
+    let file_path = file_path.unwrap_or_else(|| usage(&args[0]));
+
+    let data = match fs::read(&file_path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Failed to read file: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let result = map_slices(data, |slice| {
+        let mut editor = MachOEditor::parse(slice)?;
+        for change in &changes {
+            match change {
+                Change::Id(name) => editor.set_id(name)?,
+                Change::InstallName(old, new) => {
+                    // Like Apple's tool, a missing dependency isn't an error.
+                    editor.change_install_name(old, new);
+                }
+                Change::AddRpath(path) => editor.add_rpath(path)?,
+                Change::DeleteRpath(path) => editor.delete_rpath(path)?,
+                Change::RenameRpath(old, new) => editor.rename_rpath(old, new)?,
+            }
+        }
+        editor.write(HeaderOverflow::Shift)
+    });
+    let output = match result {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = fs::write(&file_path, output) {
+        eprintln!("Failed to write file: {}", e);
+        process::exit(1);
+    }
+}
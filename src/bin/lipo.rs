@@ -0,0 +1,173 @@
+use std::{env, fs, process};
+
+use macho2::edit::fat::{
+    arch_from_name, build_fat, create_fat, extract_slice, is_fat, parse_fat, remove_slice,
+    replace_slice, FatSlice,
+};
+use macho2::macho::MachOResult;
+
+enum Operation {
+    Create,
+    Info,
+    Archs,
+    Thin(String),
+    Extract(Vec<String>),
+    Remove(Vec<String>),
+    Replace(String, String),
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} -create <file_path>... -output <output>", program);
+    eprintln!("       {} <file_path> (-info | -archs)", program);
+    eprintln!(
+        "       {} <file_path> -thin <arch> -output <output>",
+        program
+    );
+    eprintln!(
+        "       {} <file_path> (-extract <arch> | -remove <arch>)... -output <output>",
+        program
+    );
+    eprintln!(
+        "       {} <file_path> -replace <arch> <arch_file> -output <output>",
+        program
+    );
+    process::exit(1);
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn read(path: &str) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| fail(format!("Failed to read {}: {}", path, e)))
+}
+
+fn arch(name: &str) -> (u32, u32) {
+    arch_from_name(name).unwrap_or_else(|| fail(format!("Unknown architecture {}", name)))
+}
+
+fn run(operation: &Operation, inputs: &[String]) -> MachOResult<Option<Vec<u8>>> {
+    if let Operation::Create = operation {
+        return create_fat(inputs.iter().map(|input| read(input)).collect()).map(Some);
+    }
+
+    let data = read(&inputs[0]);
+    let slices = if is_fat(&data) {
+        parse_fat(&data)?
+    } else {
+        vec![FatSlice::from_macho(data.clone())?]
+    };
+
+    match operation {
+        Operation::Create => unreachable!(),
+        Operation::Info | Operation::Archs => {
+            let archs: Vec<String> = slices.iter().map(|slice| slice.arch_name()).collect();
+            if let Operation::Archs = operation {
+                println!("{}", archs.join(" "));
+            } else if is_fat(&data) {
+                println!(
+                    "Architectures in the fat file: {} are: {}",
+                    inputs[0],
+                    archs.join(" ")
+                );
+            } else {
+                println!(
+                    "Non-fat file: {} is architecture: {}",
+                    inputs[0],
+                    archs.join(" ")
+                );
+            }
+            Ok(None)
+        }
+        Operation::Thin(name) => {
+            let (cputype, cpusubtype) = arch(name);
+            extract_slice(&data, cputype, cpusubtype).map(Some)
+        }
+        Operation::Extract(names) => {
+            let mut extracted = Vec::new();
+            for name in names {
+                let (cputype, cpusubtype) = arch(name);
+                let slice = slices
+                    .iter()
+                    .find(|slice| slice.is_arch(cputype, cpusubtype))
+                    .unwrap_or_else(|| fail(format!("{} has no {} slice", inputs[0], name)));
+                extracted.push(slice.clone());
+            }
+            build_fat(&extracted).map(Some)
+        }
+        Operation::Remove(names) => {
+            let mut data = data;
+            for name in names {
+                let (cputype, cpusubtype) = arch(name);
+                data = remove_slice(&data, cputype, cpusubtype)?;
+            }
+            Ok(Some(data))
+        }
+        Operation::Replace(name, path) => {
+            let (cputype, cpusubtype) = arch(name);
+            let thin = read(path);
+            if !FatSlice::from_macho(thin.clone())?.is_arch(cputype, cpusubtype) {
+                fail(format!("{} is not architecture {}", path, name));
+            }
+            replace_slice(&data, thin).map(Some)
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+
+    let mut operation = None;
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut rest = args[1..].iter().cloned();
+    let next =
+        |rest: &mut dyn Iterator<Item = String>| rest.next().unwrap_or_else(|| usage(program));
+    while let Some(arg) = rest.next() {
+        operation = match (arg.as_str(), operation) {
+            ("-create", None) => Some(Operation::Create),
+            ("-info", None) => Some(Operation::Info),
+            ("-archs", None) => Some(Operation::Archs),
+            ("-thin", None) => Some(Operation::Thin(next(&mut rest))),
+            ("-extract", None) => Some(Operation::Extract(vec![next(&mut rest)])),
+            ("-extract", Some(Operation::Extract(mut names))) => {
+                names.push(next(&mut rest));
+                Some(Operation::Extract(names))
+            }
+            ("-remove", None) => Some(Operation::Remove(vec![next(&mut rest)])),
+            ("-remove", Some(Operation::Remove(mut names))) => {
+                names.push(next(&mut rest));
+                Some(Operation::Remove(names))
+            }
+            ("-replace", None) => {
+                let name = next(&mut rest);
+                Some(Operation::Replace(name, next(&mut rest)))
+            }
+            ("-output", operation) => {
+                output = Some(next(&mut rest));
+                operation
+            }
+            (arg, operation) if !arg.starts_with('-') => {
+                inputs.push(arg.to_string());
+                operation
+            }
+            _ => usage(program),
+        };
+    }
+
+    let operation = operation.unwrap_or_else(|| usage(program));
+    let creates = matches!(operation, Operation::Create);
+    if inputs.is_empty() || (!creates && inputs.len() > 1) {
+        usage(program);
+    }
+
+    let result = run(&operation, &inputs).unwrap_or_else(|e| fail(e.to_string()));
+    if let Some(result) = result {
+        let output = output.unwrap_or_else(|| usage(program));
+        if let Err(e) = fs::write(&output, result) {
+            fail(format!("Failed to write {}: {}", output, e));
+        }
+    }
+}
@@ -157,6 +157,13 @@ fn print_load_commands<T: Seek + Read>(mut macho: MachO<T>) {
                         println!(
                             "LC_UNIXTHREAD  eip=0x{:08x} esp=0x{:08x}", x86_thread_state32.eip, x86_thread_state32.esp);
                     },
+                    ThreadState::ArmState32(arm_thread_state32) => {
+                        println!(
+                            "LC_UNIXTHREAD  pc=0x{:08x} sp=0x{:08x}", arm_thread_state32.pc, arm_thread_state32.sp);
+                    },
+                    other => {
+                        println!("LC_UNIXTHREAD  flavor={}", other.base().flavor);
+                    },
                 }
             }
             else {
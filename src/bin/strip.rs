@@ -0,0 +1,75 @@
+use std::{env, fs, process};
+
+use macho2::edit::fat::map_slices;
+use macho2::edit::strip::StripMode;
+use macho2::edit::{HeaderOverflow, MachOEditor};
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [-x | -S | -u -r] <file_path> [-o <output>]",
+        program
+    );
+    eprintln!("  -x     remove local symbols");
+    eprintln!("  -S     remove debug symbols");
+    eprintln!("  -u -r  remove every symbol the dynamic linker doesn't need (the default)");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+
+    let mut mode = None;
+    let mut file_path = None;
+    let mut output = None;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let next = match arg.as_str() {
+            "-x" => StripMode::Locals,
+            "-S" => StripMode::Debug,
+            "-u" | "-r" => StripMode::All,
+            "-o" => {
+                output = Some(rest.next().cloned().unwrap_or_else(|| usage(program)));
+                continue;
+            }
+            _ if arg.starts_with('-') || file_path.is_some() => usage(program),
+            _ => {
+                file_path = Some(arg.clone());
+                continue;
+            }
+        };
+        if mode.is_some_and(|mode| mode != next) {
+            usage(program);
+        }
+        mode = Some(next);
+    }
+    let mode = mode.unwrap_or(StripMode::All);
+    let file_path = file_path.unwrap_or_else(|| usage(program));
+    let output = output.unwrap_or_else(|| file_path.clone());
+
+    let data = match fs::read(&file_path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Failed to read file: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let result = map_slices(data, |slice| {
+        let mut editor = MachOEditor::parse(slice)?;
+        editor.strip(mode)?;
+        editor.write(HeaderOverflow::Fail)
+    });
+    let output_data = match result {
+        Ok(output_data) => output_data,
+        Err(e) => {
+            eprintln!("Failed to strip {}: {}", file_path, e);
+            process::exit(1);
+        }
+    };
+
+    if let Err(e) = fs::write(&output, output_data) {
+        eprintln!("Failed to write file: {}", e);
+        process::exit(1);
+    }
+}
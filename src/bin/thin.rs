@@ -50,14 +50,6 @@ fn main() {
         args[2].to_string()
     };
 
-    let mut file = match File::create(&outname) {
-        Ok(file) => file,
-        Err(e) => {
-            eprintln!("Failed to create file: {}", e);
-            return;
-        }
-    };
-
     let mut buffer = Vec::new();
     if let Err(e) = file.seek(std::io::SeekFrom::Start(arch.offset())) {
         eprintln!("Failed to seek file: {}", e);
@@ -69,11 +61,10 @@ fn main() {
         return;
     }
 
-    let file_path = &args[2];
-    let mut outfile = match File::open(file_path) {
+    let mut outfile = match File::create(&outname) {
         Ok(file) => file,
         Err(e) => {
-            eprintln!("Failed to open file: {}", e);
+            eprintln!("Failed to create file: {}", e);
             return;
         }
     };
@@ -0,0 +1,154 @@
+use std::{env, fs, process, str::FromStr};
+
+use macho2::command::build_version::{BuildToolVersion, Platform, Tool};
+use macho2::edit::fat::map_slices;
+use macho2::edit::platform::BuildTarget;
+use macho2::edit::{HeaderOverflow, MachOEditor};
+use nom_derive::Parse;
+
+enum Action {
+    Show,
+    SetBuildVersion(BuildTarget),
+    AddBuildVersion(BuildTarget),
+    SetVersionMin(BuildTarget),
+    RemoveBuildVersion(Platform),
+}
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} -show <file_path>", program);
+    eprintln!(
+        "       {} (-set-build-version | -add-build-version) <platform> <minos> <sdk> [-tool <tool> <version>]... <file_path> [-output <output>]",
+        program
+    );
+    eprintln!(
+        "       {} -set-version-min <platform> <minos> <sdk> <file_path> [-output <output>]",
+        program
+    );
+    eprintln!(
+        "       {} -remove-build-version <platform> <file_path> [-output <output>]",
+        program
+    );
+    process::exit(1);
+}
+
+// Platforms can be given by name, like `iossimulator`, or by number.
+fn parse_platform(program: &str, platform: &str) -> Platform {
+    if let Ok(platform) = Platform::from_str(platform) {
+        return platform;
+    }
+    let number = platform.parse::<u32>().ok().map(u32::to_le_bytes);
+    match number.as_ref().map(|bytes| Platform::parse_le(bytes)) {
+        Some(Ok((_, platform))) => platform,
+        _ => {
+            eprintln!("Unknown platform {}", platform);
+            usage(program)
+        }
+    }
+}
+
+fn next_arg(program: &str, args: &mut impl Iterator<Item = String>) -> String {
+    args.next().unwrap_or_else(|| usage(program))
+}
+
+fn target(program: &str, args: &mut impl Iterator<Item = String>) -> BuildTarget {
+    BuildTarget {
+        platform: parse_platform(program, &next_arg(program, args)),
+        minos: next_arg(program, args),
+        sdk: next_arg(program, args),
+        tools: vec![],
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = &args[0];
+    let mut rest = args[1..].iter().cloned();
+
+    let mut action = match next_arg(program, &mut rest).as_str() {
+        "-show" => Action::Show,
+        "-set-build-version" => Action::SetBuildVersion(target(program, &mut rest)),
+        "-add-build-version" => Action::AddBuildVersion(target(program, &mut rest)),
+        "-set-version-min" => Action::SetVersionMin(target(program, &mut rest)),
+        "-remove-build-version" => {
+            Action::RemoveBuildVersion(parse_platform(program, &next_arg(program, &mut rest)))
+        }
+        _ => usage(program),
+    };
+
+    let mut file_path = None;
+    let mut output = None;
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-tool" => {
+                let tool = next_arg(program, &mut rest);
+                let version = next_arg(program, &mut rest);
+                let tool = Tool::from_str(&tool).unwrap_or_else(|_| {
+                    eprintln!("Unknown tool {}", tool);
+                    usage(program)
+                });
+                match &mut action {
+                    Action::SetBuildVersion(target) | Action::AddBuildVersion(target) => {
+                        target.tools.push(BuildToolVersion { tool, version })
+                    }
+                    _ => usage(program),
+                }
+            }
+            "-output" => output = Some(next_arg(program, &mut rest)),
+            _ if file_path.is_none() => file_path = Some(arg),
+            _ => usage(program),
+        }
+    }
+    let file_path = file_path.unwrap_or_else(|| usage(program));
+    let output = output.unwrap_or_else(|| file_path.clone());
+
+    let data = match fs::read(&file_path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("Failed to read file: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let result = map_slices(data, |slice| {
+        let mut editor = MachOEditor::parse(slice)?;
+        match &action {
+            Action::Show => {
+                println!(
+                    "{} {:?}",
+                    editor.header.cputype(),
+                    editor.header.cpusubtype()
+                );
+                for target in editor.build_targets() {
+                    println!(
+                        "  platform={} minos={} sdk={}",
+                        target.platform, target.minos, target.sdk
+                    );
+                    for tool in &target.tools {
+                        println!("    tool={} version={}", tool.tool, tool.version);
+                    }
+                }
+                return Ok(editor.data().to_vec());
+            }
+            Action::SetBuildVersion(target) => editor.set_build_version(target)?,
+            Action::AddBuildVersion(target) => editor.add_build_version(target)?,
+            Action::SetVersionMin(target) => editor.set_version_min(target)?,
+            Action::RemoveBuildVersion(platform) => editor.remove_build_version(*platform)?,
+        }
+        editor.write(HeaderOverflow::Shift)
+    });
+    let output_data = match result {
+        Ok(output_data) => output_data,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if matches!(action, Action::Show) {
+        return;
+    }
+    if let Err(e) = fs::write(&output, output_data) {
+        eprintln!("Failed to write file: {}", e);
+        process::exit(1);
+    }
+}
@@ -8,6 +8,7 @@ use super::{pad_to_size, LCLoadCommand, LoadCommandBase, LoadCommandParser};
 
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Nom, EnumString, Display)]
+#[strum(ascii_case_insensitive)]
 pub enum Tool {
     Clang = 1,
     Swift = 2,
@@ -24,6 +25,7 @@ pub enum Tool {
 
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Nom, EnumString, Display)]
+#[strum(ascii_case_insensitive)]
 pub enum Platform {
     Unknown = 0,
     Any = 0xFFFFFFFF,
@@ -43,7 +45,7 @@ pub enum Platform {
     SepOS = 14,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BuildToolVersion {
     pub tool: Tool,
     pub version: String,
@@ -868,9 +868,6 @@ impl DyldStartsInSegment {
             }
             let (cursor, start) = le_u16::<_, Error<_>>(bytes).unwrap();
             bytes = cursor;
-            if Self::DYLD_CHAINED_PTR_START_NONE == start {
-                break;
-            }
             if Self::DYLD_CHAINED_PTR_START_MULTI == start {
                 println!("DYLD_CHAINED_PTR_START_MULTI hit. TODO: idk what to do here.");
                 break;
@@ -1029,6 +1026,9 @@ impl DyldFixup {
         let ordinals: Vec<String> = imports.iter().map(|import| import.name.clone()).collect();
 
         for (i, page_start) in start.page_start.iter().enumerate() {
+            if *page_start == DyldStartsInSegment::DYLD_CHAINED_PTR_START_NONE {
+                continue;
+            }
             let mut offset =
                 start.segment_offset + i as u64 * start.page_size as u64 + *page_start as u64;
 
@@ -2,7 +2,7 @@ use std::io::{Read, Seek, SeekFrom};
 
 use nom::{error::Error, number::complete::le_u8, IResult};
 
-use crate::{helpers::{read_uleb, string_upto_null_terminator}, macho::{MachOErr, MachOResult}};
+use crate::{helpers::{read_uleb, string_upto_null_terminator, uleb_size, write_uleb}, macho::{MachOErr, MachOResult}};
 
 use super::{linkedit_data::LinkeditDataCommand, pad_to_size, LoadCommandParser, LoadCommandResolver};
 
@@ -30,7 +30,7 @@ impl DyldExportSymbolFlags {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DyldExport {
     pub flags: DyldExportSymbolFlags,
     pub address: u64,
@@ -42,6 +42,10 @@ pub struct DyldExport {
 impl DyldExport {
     pub fn parse(bytes: &[u8]) -> MachOResult<Vec<DyldExport>> {
         let mut exports = vec![];
+        // Images without exports may have no trie at all.
+        if bytes.is_empty() {
+            return Ok(exports);
+        }
         DyldExport::parse_recursive(bytes, bytes, String::new(), &mut exports)?;
         Ok(exports)
     }
@@ -93,6 +97,134 @@ impl DyldExport {
 
         Ok(())
     }
+
+    /// Encodes `exports` as a trie in the order ld64 lays one out: nodes in depth-first order,
+    /// edges sorted, and child offsets in the fewest ULEB bytes. Stub-and-resolver exports keep
+    /// their resolver offset in `ordinal`, as `parse` returns them.
+    pub fn build_trie(exports: &[DyldExport]) -> MachOResult<Vec<u8>> {
+        let mut sorted: Vec<&DyldExport> = exports.iter().collect();
+        sorted.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut nodes = vec![TrieNode::default()];
+        for export in sorted {
+            let terminal = export.terminal()?;
+            let mut node = 0;
+            let mut rest = export.name.as_bytes();
+            while !rest.is_empty() {
+                let child = nodes[node].children.iter().position(|(edge, _)| edge[0] == rest[0]);
+                let Some(child) = child else {
+                    nodes.push(TrieNode::default());
+                    let leaf = nodes.len() - 1;
+                    nodes[node].children.push((rest.to_vec(), leaf));
+                    node = leaf;
+                    break;
+                };
+                let (edge, next) = nodes[node].children[child].clone();
+                let common = edge.iter().zip(rest).take_while(|(a, b)| a == b).count();
+                if common < edge.len() {
+                    // Split the edge where the names diverge.
+                    nodes.push(TrieNode {
+                        children: vec![(edge[common..].to_vec(), next)],
+                        ..Default::default()
+                    });
+                    let split = nodes.len() - 1;
+                    nodes[node].children[child] = (edge[..common].to_vec(), split);
+                    node = split;
+                } else {
+                    node = next;
+                }
+                rest = &rest[common..];
+            }
+            if nodes[node].terminal.is_some() {
+                return Err(MachOErr::InvalidValue(format!("{} is exported twice", export.name)));
+            }
+            nodes[node].terminal = Some(terminal);
+        }
+
+        let mut order = vec![];
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            if nodes[node].children.len() > u8::MAX as usize {
+                return Err(MachOErr::InvalidValue(
+                    "An export trie node can't have more than 255 children".to_string(),
+                ));
+            }
+            nodes[node].children.sort();
+            order.push(node);
+            stack.extend(nodes[node].children.iter().rev().map(|(_, child)| *child));
+        }
+
+        // A node's size depends on its children's offsets, so lay the nodes out until no
+        // offset changes. Offsets only ever grow, so this settles.
+        loop {
+            let mut offset = 0;
+            let mut changed = false;
+            for &node in &order {
+                changed |= nodes[node].offset != offset;
+                nodes[node].offset = offset;
+                offset += nodes[node].size(&nodes);
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let mut trie = vec![];
+        for &node in &order {
+            let node = &nodes[node];
+            let terminal = node.terminal.as_deref().unwrap_or_default();
+            write_uleb(&mut trie, terminal.len() as u64);
+            trie.extend(terminal);
+            trie.push(node.children.len() as u8);
+            for (edge, child) in &node.children {
+                trie.extend(edge);
+                trie.push(0);
+                write_uleb(&mut trie, nodes[*child].offset);
+            }
+        }
+        Ok(trie)
+    }
+
+    // The export information stored in the export's terminal node.
+    fn terminal(&self) -> MachOResult<Vec<u8>> {
+        let missing = |what: &str| {
+            MachOErr::InvalidValue(format!("{} is missing its {}", self.name, what))
+        };
+        let mut buf = vec![];
+        write_uleb(&mut buf, self.flags.bits() as u64);
+        if self.flags.contains(DyldExportSymbolFlags::REEXPORT) {
+            let ordinal = self.ordinal.ok_or_else(|| missing("dylib ordinal"))?;
+            write_uleb(&mut buf, ordinal as u64);
+            buf.extend(self.import_name.as_deref().unwrap_or_default().as_bytes());
+            buf.push(0);
+        } else {
+            write_uleb(&mut buf, self.address);
+            if self.flags.contains(DyldExportSymbolFlags::STUB_AND_RESOLVER) {
+                let resolver = self.ordinal.ok_or_else(|| missing("resolver"))?;
+                write_uleb(&mut buf, resolver as u64);
+            }
+        }
+        Ok(buf)
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: Vec<(Vec<u8>, usize)>,
+    terminal: Option<Vec<u8>>,
+    offset: u64,
+}
+
+impl TrieNode {
+    fn size(&self, nodes: &[TrieNode]) -> u64 {
+        let terminal = self.terminal.as_ref().map_or(0, |terminal| terminal.len());
+        let children: usize = self
+            .children
+            .iter()
+            .map(|(edge, child)| edge.len() + 1 + uleb_size(nodes[*child].offset))
+            .sum();
+        (uleb_size(terminal as u64) + terminal + 1 + children) as u64
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -158,4 +290,60 @@ mod tests {
         let deserialized = DyldExportsTrie::parse(&serialized).unwrap();
         assert_eq!(dyldtrie, deserialized);
     }
+
+    fn export(name: &str, flags: DyldExportSymbolFlags, address: u64) -> DyldExport {
+        DyldExport {
+            flags,
+            address,
+            name: name.to_string(),
+            ordinal: None,
+            import_name: None,
+        }
+    }
+
+    #[test]
+    fn test_build_trie() {
+        let exports = [
+            export("_b", DyldExportSymbolFlags::empty(), 0x2000),
+            export("_a", DyldExportSymbolFlags::empty(), 0x1000),
+        ];
+        assert_eq!(
+            DyldExport::build_trie(&exports).unwrap(),
+            [
+                0, 1, b'_', 0, 5, 0, 2, b'a', 0, 13, b'b', 0, 18, 3, 0, 0x80, 0x20, 0, 3, 0, 0x80,
+                0x40, 0
+            ]
+        );
+        assert!(DyldExport::build_trie(&[exports[0].clone(), exports[0].clone()]).is_err());
+
+        let mut exports = vec![
+            export("_main", DyldExportSymbolFlags::empty(), 0x3f20),
+            export("_mai", DyldExportSymbolFlags::WEAK_DEFINITION, 0x3f00),
+            export("_tlv", DyldExportSymbolFlags::KIND_THREAD_LOCAL, 0x8000),
+            export("_abs", DyldExportSymbolFlags::KIND_ABSOLUTE, 0x1234),
+            DyldExport {
+                ordinal: Some(2),
+                import_name: Some("_strlen".to_string()),
+                ..export("_length", DyldExportSymbolFlags::REEXPORT, 0)
+            },
+            DyldExport {
+                ordinal: Some(1),
+                import_name: Some(String::new()),
+                ..export("_free", DyldExportSymbolFlags::REEXPORT, 0)
+            },
+            DyldExport {
+                ordinal: Some(0x4100),
+                ..export("_resolved", DyldExportSymbolFlags::STUB_AND_RESOLVER, 0x4000)
+            },
+        ];
+        // Enough siblings under "_f" for later child offsets to need two ULEB bytes.
+        exports.extend((0..100).map(|i| {
+            export(&format!("_f{:03}", i), DyldExportSymbolFlags::empty(), 0x10000 + i)
+        }));
+
+        let trie = DyldExport::build_trie(&exports).unwrap();
+        assert!(trie.len() > 0x80);
+        exports.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(DyldExport::parse(&trie).unwrap(), exports);
+    }
 }
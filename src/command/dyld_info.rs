@@ -9,7 +9,10 @@ use nom::{
 };
 use num_derive::FromPrimitive;
 
-use crate::{helpers::{read_sleb, read_uleb, string_upto_null_terminator}, macho::MachOResult};
+use crate::{
+    helpers::{read_sleb, read_uleb, string_upto_null_terminator, write_sleb, write_uleb},
+    macho::{MachOErr, MachOResult},
+};
 
 use super::{
     dyld_exports_trie::DyldExport, pad_to_size, LCLoadCommand, LoadCommandBase, LoadCommandParser, LoadCommandResolver 
@@ -138,6 +141,123 @@ impl RebaseInstruction {
             }
         }
     }
+
+    /// Encodes rebases the way ld64 does: sorted, with runs of adjacent and of evenly spaced
+    /// pointers folded into single opcodes. Pointers are 8 bytes, as `parse` assumes.
+    pub fn serialize(rebases: &[RebaseInstruction]) -> MachOResult<Vec<u8>> {
+        let mut sorted: Vec<&RebaseInstruction> = rebases.iter().collect();
+        sorted.sort_by_key(|r| (r.rebase_type as u8, r.segment_index, r.segment_offset));
+        sorted.dedup();
+
+        let mut ops = vec![];
+        let mut state = None;
+        let mut offset = 0;
+        for rebase in sorted {
+            check_segment_index(rebase.segment_index)?;
+            if state.map(|(type_, _)| type_) != Some(rebase.rebase_type) {
+                ops.push(RebaseOp::SetType(rebase.rebase_type));
+            }
+            if state.map(|(_, segment)| segment) != Some(rebase.segment_index)
+                || rebase.segment_offset < offset
+            {
+                ops.push(RebaseOp::SetSegmentAndOffset(rebase.segment_index, rebase.segment_offset));
+            } else if rebase.segment_offset > offset {
+                ops.push(RebaseOp::AddAddress(rebase.segment_offset - offset));
+            }
+            match ops.last_mut() {
+                Some(RebaseOp::Rebase(count)) => *count += 1,
+                _ => ops.push(RebaseOp::Rebase(1)),
+            }
+            state = Some((rebase.rebase_type, rebase.segment_index));
+            offset = rebase.segment_offset + 8;
+        }
+
+        let mut folded: Vec<RebaseOp> = vec![];
+        for op in ops {
+            match (folded.last().copied(), op) {
+                (Some(RebaseOp::Rebase(1)), RebaseOp::AddAddress(skip)) => {
+                    *folded.last_mut().unwrap() = RebaseOp::RebaseAddAddress(skip)
+                }
+                _ => folded.push(op),
+            }
+        }
+        let mut ops: Vec<RebaseOp> = vec![];
+        for op in folded {
+            match (ops.last().copied(), op) {
+                (Some(RebaseOp::RebaseAddAddress(prev)), RebaseOp::RebaseAddAddress(skip))
+                    if prev == skip =>
+                {
+                    *ops.last_mut().unwrap() = RebaseOp::RebaseSkipping(2, skip)
+                }
+                (Some(RebaseOp::RebaseSkipping(count, prev)), RebaseOp::RebaseAddAddress(skip))
+                    if prev == skip =>
+                {
+                    *ops.last_mut().unwrap() = RebaseOp::RebaseSkipping(count + 1, skip)
+                }
+                _ => ops.push(op),
+            }
+        }
+
+        let opcode = |opcode: RebaseOpcode, immediate: u8| (opcode as u8) << 4 | immediate;
+        let mut buf = vec![];
+        for op in ops {
+            match op {
+                RebaseOp::SetType(type_) => buf.push(opcode(RebaseOpcode::SetTypeImm, type_ as u8)),
+                RebaseOp::SetSegmentAndOffset(segment, offset) => {
+                    buf.push(opcode(RebaseOpcode::SetSegmentAndOffsetUleb, segment));
+                    write_uleb(&mut buf, offset);
+                }
+                RebaseOp::AddAddress(skip) if skip % 8 == 0 && skip / 8 < 16 => {
+                    buf.push(opcode(RebaseOpcode::AddAddressImmScaled, (skip / 8) as u8))
+                }
+                RebaseOp::AddAddress(skip) => {
+                    buf.push(opcode(RebaseOpcode::AddAddressUleb, 0));
+                    write_uleb(&mut buf, skip);
+                }
+                RebaseOp::Rebase(count) if count < 16 => {
+                    buf.push(opcode(RebaseOpcode::DoRebaseImmTimes, count as u8))
+                }
+                RebaseOp::Rebase(count) => {
+                    buf.push(opcode(RebaseOpcode::DoRebaseUlebTimes, 0));
+                    write_uleb(&mut buf, count);
+                }
+                RebaseOp::RebaseAddAddress(skip) => {
+                    buf.push(opcode(RebaseOpcode::DoRebaseAddAddressUleb, 0));
+                    write_uleb(&mut buf, skip);
+                }
+                RebaseOp::RebaseSkipping(count, skip) => {
+                    buf.push(opcode(RebaseOpcode::DoRebaseUlebTimesSkippingUleb, 0));
+                    write_uleb(&mut buf, count);
+                    write_uleb(&mut buf, skip);
+                }
+            }
+        }
+        buf.push(opcode(RebaseOpcode::Done, 0));
+        buf.resize(buf.len().div_ceil(8) * 8, 0);
+        Ok(buf)
+    }
+}
+
+// The segment index shares a byte with the opcode.
+fn check_segment_index(segment_index: u8) -> MachOResult<()> {
+    if segment_index > 0xf {
+        return Err(MachOErr::InvalidValue(format!(
+            "Segment {} can't be encoded in dyld info opcodes",
+            segment_index
+        )));
+    }
+    Ok(())
+}
+
+// Rebase opcodes before they're encoded, so runs of them can be folded together.
+#[derive(Debug, Clone, Copy)]
+enum RebaseOp {
+    SetType(RebaseType),
+    SetSegmentAndOffset(u8, u64),
+    AddAddress(u64),
+    Rebase(u64),
+    RebaseAddAddress(u64),
+    RebaseSkipping(u64, u64),
 }
 
 #[derive(Debug, FromPrimitive, Clone, Copy, PartialEq, Eq)]
@@ -206,11 +326,28 @@ pub struct BindInstruction {
     pub bind_type: BindType,
     pub dylib_ordinal: u8,
     pub symbol_name: String,
+    /// `BIND_SYMBOL_FLAGS_*`, e.g. `BindSymbolFlags::WeakImport`.
+    pub symbol_flags: u8,
     pub addend: i64,
 }
 
 impl BindInstruction {
     pub fn parse(bytes: &[u8]) -> IResult<&[u8], Vec<BindInstruction>> {
+        Self::parse_stream(bytes, &mut vec![])
+    }
+
+    /// Parses the weak bind stream, which also names the symbols the image defines strongly,
+    /// overriding weak definitions of them elsewhere. Returns the binds and those names.
+    pub fn parse_weak(bytes: &[u8]) -> IResult<&[u8], (Vec<BindInstruction>, Vec<String>)> {
+        let mut strong_definitions = vec![];
+        let (bytes, binds) = Self::parse_stream(bytes, &mut strong_definitions)?;
+        Ok((bytes, (binds, strong_definitions)))
+    }
+
+    fn parse_stream<'a>(
+        bytes: &'a [u8],
+        strong_definitions: &mut Vec<String>,
+    ) -> IResult<&'a [u8], Vec<BindInstruction>> {
         if bytes.is_empty() {
             return Ok((bytes, vec![]));
         }
@@ -222,8 +359,7 @@ impl BindInstruction {
         let mut symbol_name = String::new();
         let mut dylib_ordinal: u8 = 0;
         let mut addend = 0;
-        #[allow(unused_variables, unused_mut)] // TODO: Do something with this?
-        let mut symbol_flag = BindSymbolFlags::NonWeakDefinition;
+        let mut symbol_flags = 0;
         let mut cursor = bytes;
         loop {
             let (next, (opcode, immediate)) = BindOpcode::parse(&cursor)?;
@@ -241,14 +377,18 @@ impl BindInstruction {
                     dylib_ordinal = num as u8;
                 }
                 BindOpcode::SetDylibSpecialImm => {
-                    // TODO: This is meant to be negative.
-                    dylib_ordinal = immediate;
+                    // Special ordinals are negative, kept as the low byte like chained imports
+                    // keep them.
+                    dylib_ordinal = if immediate == 0 { 0 } else { immediate | 0xf0 };
                 }
                 BindOpcode::SetSymbolTrailingFlagsImm => {
-                    // symbol_flag = num::FromPrimitive::from_u8(immediate).unwrap(); // TODO: Do something with this?
+                    symbol_flags = immediate;
                     let (next, str) = string_upto_null_terminator(cursor).unwrap();
                     cursor = next;
                     symbol_name = str;
+                    if immediate & BindSymbolFlags::NonWeakDefinition as u8 != 0 {
+                        strong_definitions.push(symbol_name.clone());
+                    }
                 }
                 BindOpcode::SetTypeImm => {
                     type_ = num::FromPrimitive::from_u8(immediate).unwrap();
@@ -277,6 +417,7 @@ impl BindInstruction {
                         bind_type: type_,
                         dylib_ordinal,
                         symbol_name: symbol_name.clone(),
+                        symbol_flags,
                         addend,
                     });
                     offset = offset.wrapping_add(8);
@@ -288,6 +429,7 @@ impl BindInstruction {
                         bind_type: type_,
                         dylib_ordinal,
                         symbol_name: symbol_name.clone(),
+                        symbol_flags,
                         addend,
                     });
                     let (next, num) = read_uleb(cursor)?;
@@ -301,9 +443,10 @@ impl BindInstruction {
                         bind_type: type_,
                         dylib_ordinal,
                         symbol_name: symbol_name.clone(),
+                        symbol_flags,
                         addend,
                     });
-                    offset += offset.wrapping_add((immediate * 8) as u64 + 8);
+                    offset = offset.wrapping_add((immediate * 8) as u64 + 8);
                 }
                 BindOpcode::DoBindUlebTimesSkippingUleb => {
                     let (next, num) = read_uleb(cursor)?;
@@ -316,9 +459,10 @@ impl BindInstruction {
                             bind_type: type_,
                             dylib_ordinal,
                             symbol_name: symbol_name.clone(),
+                            symbol_flags,
                             addend,
                         });
-                        offset += offset.wrapping_add(skip + 8);
+                        offset = offset.wrapping_add(skip + 8);
                     }
                 }
                 BindOpcode::Threaded => {
@@ -337,6 +481,7 @@ impl BindInstruction {
                                 bind_type: type_,
                                 dylib_ordinal,
                                 symbol_name: symbol_name.clone(),
+                                symbol_flags,
                                 addend,
                             });
                             offset = offset.wrapping_add(8);
@@ -346,6 +491,227 @@ impl BindInstruction {
             }
         }
     }
+
+    /// Parses a lazy bind stream, where every symbol's entry ends with its own `DONE`.
+    pub fn parse_lazy(mut bytes: &[u8]) -> IResult<&[u8], Vec<BindInstruction>> {
+        let mut instructions = vec![];
+        while !bytes.is_empty() {
+            let (rest, entry) = Self::parse(bytes)?;
+            instructions.extend(entry);
+            bytes = rest;
+        }
+        Ok((bytes, instructions))
+    }
+
+    /// Encodes binds the way ld64 does: grouped by dylib and symbol, with evenly spaced
+    /// pointers folded into single opcodes. Pointers are 8 bytes, as `parse` assumes.
+    pub fn serialize(binds: &[BindInstruction]) -> MachOResult<Vec<u8>> {
+        let mut sorted: Vec<&BindInstruction> = binds.iter().collect();
+        sorted.sort_by_key(|b| {
+            (b.dylib_ordinal, &b.symbol_name, b.bind_type as u8, b.addend, b.segment_index, b.segment_offset)
+        });
+        Self::serialize_sorted(sorted, vec![], true)
+    }
+
+    /// Encodes the weak bind stream, which names symbols without saying where they're from,
+    /// along with the symbols in `strong_definitions` that override weak definitions of them.
+    pub fn serialize_weak(
+        binds: &[BindInstruction],
+        strong_definitions: &[String],
+    ) -> MachOResult<Vec<u8>> {
+        let mut sorted: Vec<&BindInstruction> = binds.iter().collect();
+        sorted.sort_by_key(|b| {
+            (&b.symbol_name, b.bind_type as u8, b.addend, b.segment_index, b.segment_offset)
+        });
+        let mut definitions: Vec<&str> = strong_definitions.iter().map(String::as_str).collect();
+        definitions.sort();
+        definitions.dedup();
+        Self::serialize_sorted(sorted, definitions, false)
+    }
+
+    // `strong_definitions` are written among the binds in name order, as ld64 does.
+    fn serialize_sorted<'a>(
+        mut sorted: Vec<&'a BindInstruction>,
+        strong_definitions: Vec<&'a str>,
+        ordinals: bool,
+    ) -> MachOResult<Vec<u8>> {
+        sorted.dedup();
+        let non_weak_definition = BindSymbolFlags::NonWeakDefinition as u8;
+        let mut definitions = strong_definitions.into_iter().peekable();
+        let mut ops = vec![];
+        let mut ordinal = None;
+        let mut symbol = None;
+        let mut type_ = None;
+        let mut addend = 0;
+        let mut segment = None;
+        let mut offset = 0;
+        for bind in sorted {
+            check_segment_index(bind.segment_index)?;
+            while let Some(name) = definitions.next_if(|name| *name <= bind.symbol_name.as_str()) {
+                ops.push(BindOp::Symbol(name, non_weak_definition));
+                symbol = Some((name, non_weak_definition));
+            }
+            if ordinals && ordinal != Some(bind.dylib_ordinal) {
+                ops.push(BindOp::Ordinal(bind.dylib_ordinal));
+                ordinal = Some(bind.dylib_ordinal);
+            }
+            if symbol != Some((bind.symbol_name.as_str(), bind.symbol_flags)) {
+                ops.push(BindOp::Symbol(&bind.symbol_name, bind.symbol_flags));
+                symbol = Some((bind.symbol_name.as_str(), bind.symbol_flags));
+            }
+            if type_ != Some(bind.bind_type) {
+                ops.push(BindOp::Type(bind.bind_type));
+                type_ = Some(bind.bind_type);
+            }
+            if addend != bind.addend {
+                ops.push(BindOp::Addend(bind.addend));
+                addend = bind.addend;
+            }
+            if segment != Some(bind.segment_index) || bind.segment_offset < offset {
+                ops.push(BindOp::SetSegmentAndOffset(bind.segment_index, bind.segment_offset));
+                segment = Some(bind.segment_index);
+            } else if bind.segment_offset > offset {
+                ops.push(BindOp::AddAddress(bind.segment_offset - offset));
+            }
+            ops.push(BindOp::Bind);
+            offset = bind.segment_offset + 8;
+        }
+        for name in definitions {
+            ops.push(BindOp::Symbol(name, non_weak_definition));
+        }
+
+        let mut folded: Vec<BindOp> = vec![];
+        for op in ops {
+            match (folded.last().copied(), op) {
+                (Some(BindOp::Bind), BindOp::AddAddress(skip)) => {
+                    *folded.last_mut().unwrap() = BindOp::BindAddAddress(skip)
+                }
+                _ => folded.push(op),
+            }
+        }
+        let mut ops: Vec<BindOp> = vec![];
+        for op in folded {
+            match (ops.last().copied(), op) {
+                (Some(BindOp::BindAddAddress(prev)), BindOp::BindAddAddress(skip))
+                    if prev == skip =>
+                {
+                    *ops.last_mut().unwrap() = BindOp::BindSkipping(2, skip)
+                }
+                (Some(BindOp::BindSkipping(count, prev)), BindOp::BindAddAddress(skip))
+                    if prev == skip =>
+                {
+                    *ops.last_mut().unwrap() = BindOp::BindSkipping(count + 1, skip)
+                }
+                _ => ops.push(op),
+            }
+        }
+
+        let mut buf = vec![];
+        for op in ops {
+            op.encode(&mut buf)?;
+        }
+        buf.push((BindOpcode::Done as u8) << 4);
+        buf.resize(buf.len().div_ceil(8) * 8, 0);
+        Ok(buf)
+    }
+
+    /// Encodes lazy binds in the order given, each as an entry of its own that dyld runs
+    /// when the symbol is first called. Returns the stream and the offset of each entry in
+    /// it, which the stub helpers pass to dyld.
+    pub fn serialize_lazy(binds: &[BindInstruction]) -> MachOResult<(Vec<u8>, Vec<u32>)> {
+        let mut buf = vec![];
+        let mut offsets = vec![];
+        for bind in binds {
+            check_segment_index(bind.segment_index)?;
+            offsets.push(buf.len() as u32);
+            let mut ops = vec![
+                BindOp::SetSegmentAndOffset(bind.segment_index, bind.segment_offset),
+                BindOp::Ordinal(bind.dylib_ordinal),
+                BindOp::Symbol(&bind.symbol_name, bind.symbol_flags),
+            ];
+            if bind.addend != 0 {
+                ops.push(BindOp::Addend(bind.addend));
+            }
+            ops.push(BindOp::Bind);
+            for op in ops {
+                op.encode(&mut buf)?;
+            }
+            buf.push((BindOpcode::Done as u8) << 4);
+        }
+        buf.resize(buf.len().div_ceil(8) * 8, 0);
+        Ok((buf, offsets))
+    }
+}
+
+// Bind opcodes before they're encoded, so runs of them can be folded together.
+#[derive(Debug, Clone, Copy)]
+enum BindOp<'a> {
+    Ordinal(u8),
+    Symbol(&'a str, u8),
+    Type(BindType),
+    Addend(i64),
+    SetSegmentAndOffset(u8, u64),
+    AddAddress(u64),
+    Bind,
+    BindAddAddress(u64),
+    BindSkipping(u64, u64),
+}
+
+impl BindOp<'_> {
+    fn encode(self, buf: &mut Vec<u8>) -> MachOResult<()> {
+        let opcode = |opcode: BindOpcode, immediate: u8| (opcode as u8) << 4 | immediate;
+        match self {
+            // Special ordinals are negative and only their low nibble is stored.
+            BindOp::Ordinal(ordinal) if ordinal == 0 || ordinal >= 0xf0 => {
+                buf.push(opcode(BindOpcode::SetDylibSpecialImm, ordinal & 0xf))
+            }
+            BindOp::Ordinal(ordinal) if ordinal < 16 => {
+                buf.push(opcode(BindOpcode::SetDylibOrdinalImm, ordinal))
+            }
+            BindOp::Ordinal(ordinal) => {
+                buf.push(opcode(BindOpcode::SetDylibOrdinalUleb, 0));
+                write_uleb(buf, ordinal as u64);
+            }
+            BindOp::Symbol(name, flags) => {
+                if flags > 0xf {
+                    return Err(MachOErr::InvalidValue(format!(
+                        "{} has symbol flags {:#x}, which don't fit in an opcode",
+                        name, flags
+                    )));
+                }
+                buf.push(opcode(BindOpcode::SetSymbolTrailingFlagsImm, flags));
+                buf.extend(name.as_bytes());
+                buf.push(0);
+            }
+            BindOp::Type(type_) => buf.push(opcode(BindOpcode::SetTypeImm, type_ as u8)),
+            BindOp::Addend(addend) => {
+                buf.push(opcode(BindOpcode::SetAddendSleb, 0));
+                write_sleb(buf, addend);
+            }
+            BindOp::SetSegmentAndOffset(segment, offset) => {
+                buf.push(opcode(BindOpcode::SetSegmentAndOffsetUleb, segment));
+                write_uleb(buf, offset);
+            }
+            BindOp::AddAddress(skip) => {
+                buf.push(opcode(BindOpcode::AddAddressUleb, 0));
+                write_uleb(buf, skip);
+            }
+            BindOp::Bind => buf.push(opcode(BindOpcode::DoBind, 0)),
+            BindOp::BindAddAddress(skip) if skip % 8 == 0 && skip / 8 < 16 => {
+                buf.push(opcode(BindOpcode::DoBindAddAddressImmScaled, (skip / 8) as u8))
+            }
+            BindOp::BindAddAddress(skip) => {
+                buf.push(opcode(BindOpcode::DoBindAddAddressUleb, 0));
+                write_uleb(buf, skip);
+            }
+            BindOp::BindSkipping(count, skip) => {
+                buf.push(opcode(BindOpcode::DoBindUlebTimesSkippingUleb, 0));
+                write_uleb(buf, count);
+                write_uleb(buf, skip);
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -427,6 +793,8 @@ pub struct DyldInfoCommandResolved {
     pub rebase_instructions: Vec<RebaseInstruction>,
     pub bind_instructions: Vec<BindInstruction>,
     pub weak_instructions: Vec<BindInstruction>,
+    /// Symbols the weak bind stream marks as strongly defined by this image.
+    pub strong_definitions: Vec<String>,
     pub lazy_instructions: Vec<BindInstruction>,
     pub exports: Vec<DyldExport>,
 }
@@ -446,12 +814,13 @@ impl<T: Read + Seek> LoadCommandResolver<T, DyldInfoCommandResolved> for DyldInf
         let mut weak_bind_blob = vec![0u8; self.weak_bind_size as usize];
         buf.seek(SeekFrom::Start(self.weak_bind_off as u64)).unwrap();
         buf.read_exact(&mut weak_bind_blob).unwrap();
-        let weak_instructions = BindInstruction::parse(&weak_bind_blob).unwrap().1;
+        let (weak_instructions, strong_definitions) =
+            BindInstruction::parse_weak(&weak_bind_blob).unwrap().1;
 
         let mut lazy_bind_blob = vec![0u8; self.lazy_bind_size as usize];
         buf.seek(SeekFrom::Start(self.lazy_bind_off as u64)).unwrap();
         buf.read_exact(&mut lazy_bind_blob).unwrap();
-        let lazy_instructions = BindInstruction::parse(&lazy_bind_blob).unwrap().1;
+        let lazy_instructions = BindInstruction::parse_lazy(&lazy_bind_blob).unwrap().1;
 
         let mut export_blob = vec![0u8; self.export_size as usize];
         buf.seek(SeekFrom::Start(self.export_off as u64)).unwrap();
@@ -463,6 +832,7 @@ impl<T: Read + Seek> LoadCommandResolver<T, DyldInfoCommandResolved> for DyldInf
                 rebase_instructions,
                 bind_instructions,
                 weak_instructions,
+                strong_definitions,
                 lazy_instructions,
                 exports,
             },
@@ -495,4 +865,82 @@ mod tests {
         let parsed = DyldInfoCommand::parse(&ser).unwrap();
         assert_eq!(parsed, dyld);
     }
+
+    #[test]
+    fn test_serialize_rebases() {
+        let rebases: Vec<RebaseInstruction> = [0x0, 0x8, 0x10, 0x40, 0x60, 0x80]
+            .into_iter()
+            .map(|segment_offset| RebaseInstruction {
+                segment_index: 2,
+                segment_offset,
+                rebase_type: RebaseType::Pointer,
+            })
+            .collect();
+
+        let bytes = RebaseInstruction::serialize(&rebases).unwrap();
+        assert_eq!(
+            bytes,
+            [0x11, 0x22, 0x00, 0x53, 0x45, 0x80, 0x02, 0x18, 0x51, 0x00, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(RebaseInstruction::parse(&bytes).unwrap().1, rebases);
+    }
+
+    #[test]
+    fn test_serialize_binds() {
+        let bind = |dylib_ordinal, symbol_name: &str, symbol_flags, segment_index, segment_offset, addend| {
+            BindInstruction {
+                segment_index,
+                segment_offset,
+                bind_type: BindType::Pointer,
+                dylib_ordinal,
+                symbol_name: symbol_name.to_string(),
+                symbol_flags,
+                addend,
+            }
+        };
+        let binds = vec![
+            bind(1, "_a", 0, 2, 0x10, 0),
+            bind(1, "_a", 0, 2, 0x20, 0),
+            bind(1, "_a", 0, 2, 0x30, 0),
+            bind(0xfe, "_b", 1, 3, 0x0, -4),
+        ];
+
+        let bytes = BindInstruction::serialize(&binds).unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x11, 0x40, b'_', b'a', 0, 0x51, 0x72, 0x10, 0xc0, 0x02, 0x08, 0x90, 0x3e, 0x41,
+                b'_', b'b', 0, 0x60, 0x7c, 0x73, 0x00, 0x90, 0x00, 0
+            ]
+        );
+        assert_eq!(BindInstruction::parse(&bytes).unwrap().1, binds);
+
+        let lazy = vec![bind(2, "_c", 0, 2, 0x8, 0), bind(1, "_d", 0, 2, 0x0, 0)];
+        let (bytes, offsets) = BindInstruction::serialize_lazy(&lazy).unwrap();
+        assert_eq!(offsets, [0, 9]);
+        assert_eq!(&bytes[9..16], [0x72, 0x00, 0x11, 0x40, b'_', b'd', 0]);
+        assert_eq!(BindInstruction::parse_lazy(&bytes).unwrap().1, lazy);
+    }
+
+    #[test]
+    fn test_serialize_weak_binds() {
+        let binds = vec![BindInstruction {
+            segment_index: 2,
+            segment_offset: 0x10,
+            bind_type: BindType::Pointer,
+            dylib_ordinal: 0,
+            symbol_name: "__ZdlPv".to_string(),
+            symbol_flags: 0,
+            addend: 0,
+        }];
+        let strong_definitions = vec!["__Znwm".to_string(), "__ZdaPv".to_string()];
+
+        let bytes = BindInstruction::serialize_weak(&binds, &strong_definitions).unwrap();
+        assert_eq!(&bytes[..9], b"\x48__ZdaPv\0");
+        assert_eq!(&bytes[bytes.len() - 10..bytes.len() - 2], b"\x48__Znwm\0");
+        let (parsed, mut definitions) = BindInstruction::parse_weak(&bytes).unwrap().1;
+        definitions.sort();
+        assert_eq!(parsed, binds);
+        assert_eq!(definitions, ["__ZdaPv", "__Znwm"]);
+    }
 }
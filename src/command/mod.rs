@@ -34,6 +34,7 @@ pub mod version_min;
 use std::io::{Read, Seek, SeekFrom};
 
 use crate::header::MachHeader;
+use crate::machine::CpuType;
 use crate::macho::{MachOErr, MachOResult};
 use nom::bytes::complete::take;
 use nom::number::complete::le_u32;
@@ -81,13 +82,12 @@ pub trait LoadCommandParser {
     fn serialize(&self) -> Vec<u8>;
 }
 
+// Commands that don't fit their cmdsize are left oversized, so callers writing them back out
+// can report the mismatch instead of producing a corrupt image.
 fn pad_to_size(buf: &mut Vec<u8>, size: usize) {
-    let pad_size = size.checked_sub(buf.len()).expect(&format!(
-        "Serialized buf size exceeds cmdsize. Expected: {}, Actual: {}",
-        size,
-        buf.len()
-    ));
-    buf.extend(vec![0u8; pad_size]);
+    if buf.len() < size {
+        buf.resize(size, 0);
+    }
 }
 
 pub trait LoadCommandResolver<T, R> {
@@ -277,11 +277,12 @@ impl LoadCommand {
     where
         T: Seek + Read,
     {
+        let cputype = *header.cputype();
         let cmds =
             iterate_load_commands(
                 buf,
                 header,
-                |base, ldcmd| LoadCommand::parse(base, ldcmd) 
+                |base, ldcmd| LoadCommand::parse_for(base, ldcmd, cputype)
             )?;
 
         Ok(cmds)
@@ -291,6 +292,16 @@ impl LoadCommand {
         base: LoadCommandBase,
         ldcmd: &[u8],
     ) -> MachOResult<Self>
+    {
+        LoadCommand::parse_for(base, ldcmd, CpuType::Any)
+    }
+
+    /// Parses the command of an image for `cputype`, which decides how thread states read.
+    pub fn parse_for(
+        base: LoadCommandBase,
+        ldcmd: &[u8],
+        cputype: CpuType,
+    ) -> MachOResult<Self>
     {
         match base.cmd {
             LCLoadCommand::LcSegment => {
@@ -325,7 +336,7 @@ impl LoadCommand {
                 Ok(LoadCommand::Symseg(SymsegCommand::parse(ldcmd)?))
             }
             LCLoadCommand::LcThread | LCLoadCommand::LcUnixThread => {
-                let cmd = ThreadCommand::parse(ldcmd)?;
+                let cmd = ThreadCommand::parse_for(ldcmd, cputype)?;
                 match base.cmd {
                     LCLoadCommand::LcThread => Ok(LoadCommand::Thread(cmd)),
                     LCLoadCommand::LcUnixThread => Ok(LoadCommand::UnixThread(cmd)),
@@ -528,4 +539,130 @@ impl LoadCommand {
             LoadCommand::AtomInfo(cmd) => cmd.serialize(),
         }
     }
+
+    /// Every non-zero file offset this command refers to, for working out where file content
+    /// begins after the load commands.
+    pub fn file_offsets(&self) -> Vec<u64> {
+        let offsets = match self {
+            LoadCommand::Segment32(seg) => {
+                let mut offsets = vec![seg.fileoff as u64];
+                for sect in &seg.sects {
+                    offsets.extend([sect.offset as u64, sect.reloff as u64]);
+                }
+                offsets
+            }
+            LoadCommand::Segment64(seg) => {
+                let mut offsets = vec![seg.fileoff];
+                for sect in &seg.sections {
+                    offsets.extend([sect.offset as u64, sect.reloff as u64]);
+                }
+                offsets
+            }
+            LoadCommand::Symtab(cmd) => vec![cmd.symoff as u64, cmd.stroff as u64],
+            LoadCommand::Dysymtab(cmd) => vec![
+                cmd.tocoff as u64,
+                cmd.modtaboff as u64,
+                cmd.extrefsymoff as u64,
+                cmd.indirectsymoff as u64,
+                cmd.extreloff as u64,
+                cmd.locreloff as u64,
+            ],
+            LoadCommand::Symseg(cmd) => vec![cmd.offset as u64],
+            LoadCommand::TwoLevelHints(cmd) => vec![cmd.offset as u64],
+            LoadCommand::DyldInfo(cmd) | LoadCommand::DyldInfoOnly(cmd) => vec![
+                cmd.rebase_off as u64,
+                cmd.bind_off as u64,
+                cmd.weak_bind_off as u64,
+                cmd.lazy_bind_off as u64,
+                cmd.export_off as u64,
+            ],
+            LoadCommand::CodeSignature(cmd) => vec![cmd.cmd.dataoff as u64],
+            LoadCommand::DyldExportsTrie(cmd) => vec![cmd.cmd.dataoff as u64],
+            LoadCommand::DyldChainedFixups(cmd) => vec![cmd.cmd.dataoff as u64],
+            LoadCommand::FunctionStarts(cmd) => vec![cmd.dataoff as u64],
+            LoadCommand::SegmentSplitInfo(cmd)
+            | LoadCommand::DataInCode(cmd)
+            | LoadCommand::DylibCodeSignDrs(cmd)
+            | LoadCommand::LinkerOptimizationHint(cmd)
+            | LoadCommand::AtomInfo(cmd) => vec![cmd.dataoff as u64],
+            LoadCommand::EncryptionInfo(cmd) => vec![cmd.cryptoff as u64],
+            LoadCommand::EncryptionInfo64(cmd) => vec![cmd.cryptoff as u64],
+            LoadCommand::Note(cmd) => vec![cmd.offset],
+            LoadCommand::FilesetEntry(cmd) => vec![cmd.fileoff],
+            _ => vec![],
+        };
+        offsets.into_iter().filter(|&offset| offset != 0).collect()
+    }
+
+    /// Moves every non-zero file offset at or past `start` forward by `delta` bytes. Fails
+    /// when an offset no longer fits its field.
+    pub fn shift_file_offsets(&mut self, start: u64, delta: u64) -> MachOResult<()> {
+        let shift = |offset: u64| {
+            if offset == 0 || offset < start {
+                return Ok(offset);
+            }
+            offset.checked_add(delta).ok_or_else(|| {
+                MachOErr::InvalidValue(format!("File offset {:#x} overflows when shifted", offset))
+            })
+        };
+        let shift32 = |offset: &mut u32| {
+            *offset = u32::try_from(shift(*offset as u64)?).map_err(|_| {
+                MachOErr::InvalidValue(format!("File offset {:#x} no longer fits 32 bits", offset))
+            })?;
+            Ok::<(), MachOErr>(())
+        };
+
+        match self {
+            LoadCommand::Segment32(seg) => {
+                shift32(&mut seg.fileoff)?;
+                for sect in seg.sects.iter_mut() {
+                    shift32(&mut sect.offset)?;
+                    shift32(&mut sect.reloff)?;
+                }
+            }
+            LoadCommand::Segment64(seg) => {
+                seg.fileoff = shift(seg.fileoff)?;
+                for sect in seg.sections.iter_mut() {
+                    shift32(&mut sect.offset)?;
+                    shift32(&mut sect.reloff)?;
+                }
+            }
+            LoadCommand::Symtab(cmd) => {
+                shift32(&mut cmd.symoff)?;
+                shift32(&mut cmd.stroff)?;
+            }
+            LoadCommand::Dysymtab(cmd) => {
+                shift32(&mut cmd.tocoff)?;
+                shift32(&mut cmd.modtaboff)?;
+                shift32(&mut cmd.extrefsymoff)?;
+                shift32(&mut cmd.indirectsymoff)?;
+                shift32(&mut cmd.extreloff)?;
+                shift32(&mut cmd.locreloff)?;
+            }
+            LoadCommand::Symseg(cmd) => shift32(&mut cmd.offset)?,
+            LoadCommand::TwoLevelHints(cmd) => shift32(&mut cmd.offset)?,
+            LoadCommand::DyldInfo(cmd) | LoadCommand::DyldInfoOnly(cmd) => {
+                shift32(&mut cmd.rebase_off)?;
+                shift32(&mut cmd.bind_off)?;
+                shift32(&mut cmd.weak_bind_off)?;
+                shift32(&mut cmd.lazy_bind_off)?;
+                shift32(&mut cmd.export_off)?;
+            }
+            LoadCommand::CodeSignature(cmd) => shift32(&mut cmd.cmd.dataoff)?,
+            LoadCommand::DyldExportsTrie(cmd) => shift32(&mut cmd.cmd.dataoff)?,
+            LoadCommand::DyldChainedFixups(cmd) => shift32(&mut cmd.cmd.dataoff)?,
+            LoadCommand::FunctionStarts(cmd) => shift32(&mut cmd.dataoff)?,
+            LoadCommand::SegmentSplitInfo(cmd)
+            | LoadCommand::DataInCode(cmd)
+            | LoadCommand::DylibCodeSignDrs(cmd)
+            | LoadCommand::LinkerOptimizationHint(cmd)
+            | LoadCommand::AtomInfo(cmd) => shift32(&mut cmd.dataoff)?,
+            LoadCommand::EncryptionInfo(cmd) => shift32(&mut cmd.cryptoff)?,
+            LoadCommand::EncryptionInfo64(cmd) => shift32(&mut cmd.cryptoff)?,
+            LoadCommand::Note(cmd) => cmd.offset = shift(cmd.offset)?,
+            LoadCommand::FilesetEntry(cmd) => cmd.fileoff = shift(cmd.fileoff)?,
+            _ => {}
+        }
+        Ok(())
+    }
 }
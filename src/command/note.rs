@@ -1,6 +1,15 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use nom::bytes::complete::take;
 use nom::number::complete::{le_u32, le_u64};
+use uuid::Uuid;
 
-use crate::{helpers::string_upto_null_terminator, macho::MachOResult};
+use crate::{
+    helpers::string_upto_null_terminator,
+    macho::{MachOErr, MachOResult},
+};
 
 use super::{pad_to_size, LCLoadCommand, LoadCommandBase, LoadCommandParser};
 
@@ -8,6 +17,7 @@ use super::{pad_to_size, LCLoadCommand, LoadCommandBase, LoadCommandParser};
 pub struct NoteCommand {
     pub cmd: LCLoadCommand,
     pub cmdsize: u32,
+    /// At most 16 bytes.
     pub data_owner: String,
     pub offset: u64,
     pub size: u64,
@@ -16,13 +26,11 @@ pub struct NoteCommand {
 impl LoadCommandParser for NoteCommand {
     fn parse(ldcmd: &[u8]) -> MachOResult<Self> {
         let (cursor, base) = LoadCommandBase::parse(ldcmd)?;
-        let (cursor, data_owner_offset) = le_u32(cursor)?;
+        let (cursor, data_owner) = take(16usize)(cursor)?;
+        let (_, data_owner) = string_upto_null_terminator(data_owner)?;
         let (cursor, offset) = le_u64(cursor)?;
         let (_, size) = le_u64(cursor)?;
 
-        let (_, data_owner) =
-            string_upto_null_terminator(&ldcmd[data_owner_offset as usize..])?;
-
         Ok(
             NoteCommand {
                 cmd: base.cmd,
@@ -38,16 +46,337 @@ impl LoadCommandParser for NoteCommand {
         let mut buf = Vec::new();
         buf.extend(self.cmd.serialize());
         buf.extend(self.cmdsize.to_le_bytes());
-        buf.extend((0x1C as u32).to_le_bytes()); // data_owner offset
+        // The owner field is 16 bytes, longer owners are cut short.
+        let owner = &self.data_owner.as_bytes()[..self.data_owner.len().min(16)];
+        buf.extend(owner);
+        buf.extend(vec![0; 16 - owner.len()]);
         buf.extend(self.offset.to_le_bytes());
         buf.extend(self.size.to_le_bytes());
-        buf.extend(self.data_owner.as_bytes());
-        buf.push(0);
         pad_to_size(&mut buf, self.cmdsize as usize);
         buf
     }
 }
 
+// Payloads use all ones for addresses they don't know.
+fn known(value: u64) -> Option<u64> {
+    (value != u64::MAX).then_some(value)
+}
+
+fn uuid(bytes: &[u8]) -> nom::IResult<&[u8], Option<Uuid>> {
+    let (bytes, uuid) = take(16usize)(bytes)?;
+    let uuid = Uuid::from_slice(uuid).unwrap();
+    Ok((bytes, (!uuid.is_nil()).then_some(uuid)))
+}
+
+/// The "addrable bits" note: how many bits of an address are significant, the rest being
+/// used for pointer authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddrableBits {
+    pub version: u32,
+    /// For addresses in low memory, where user space lives.
+    pub low_bits: u32,
+    /// For addresses in high memory, where the kernel lives.
+    pub high_bits: u32,
+}
+
+impl AddrableBits {
+    pub const DATA_OWNER: &'static str = "addrable bits";
+
+    pub fn parse(bytes: &[u8]) -> MachOResult<Self> {
+        let (bytes, version) = le_u32(bytes)?;
+        let (bytes, low_bits) = le_u32(bytes)?;
+        // Version 3 has a single count for all addresses.
+        let high_bits = match version {
+            3 => low_bits,
+            _ => le_u32(bytes)?.1,
+        };
+        Ok(AddrableBits {
+            version,
+            low_bits,
+            high_bits,
+        })
+    }
+}
+
+/// The "main bin spec" note, which says which binary the core file is of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MainBinSpec {
+    pub version: u32,
+    /// 1 for a kernel, 2 for a user process and 3 for a standalone binary.
+    pub bin_type: u32,
+    pub address: Option<u64>,
+    /// Only version 2 and later have a slide.
+    pub slide: Option<u64>,
+    pub uuid: Option<Uuid>,
+    pub log2_pagesize: u32,
+    /// Only version 2 and later have a platform.
+    pub platform: u32,
+}
+
+impl MainBinSpec {
+    pub const DATA_OWNER: &'static str = "main bin spec";
+
+    pub fn parse(bytes: &[u8]) -> MachOResult<Self> {
+        let (bytes, version) = le_u32(bytes)?;
+        let (bytes, bin_type) = le_u32(bytes)?;
+        let (bytes, address) = le_u64(bytes)?;
+        let (bytes, slide) = match version {
+            1 => (bytes, u64::MAX),
+            _ => le_u64(bytes)?,
+        };
+        let (bytes, uuid) = uuid(bytes)?;
+        let (bytes, log2_pagesize) = le_u32(bytes)?;
+        let (_, platform) = le_u32(bytes)?;
+        Ok(MainBinSpec {
+            version,
+            bin_type,
+            address: known(address),
+            slide: known(slide),
+            uuid,
+            log2_pagesize,
+            platform: if version == 1 { 0 } else { platform },
+        })
+    }
+}
+
+/// A "load binary" note, one of which is written for each binary loaded in the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadBinary {
+    pub version: u32,
+    pub uuid: Option<Uuid>,
+    pub load_address: Option<u64>,
+    pub slide: u64,
+    pub name: String,
+}
+
+impl LoadBinary {
+    pub const DATA_OWNER: &'static str = "load binary";
+
+    pub fn parse(bytes: &[u8]) -> MachOResult<Self> {
+        let (bytes, version) = le_u32(bytes)?;
+        let (bytes, uuid) = uuid(bytes)?;
+        let (bytes, load_address) = le_u64(bytes)?;
+        let (bytes, slide) = le_u64(bytes)?;
+        let (_, name) = string_upto_null_terminator(bytes)?;
+        Ok(LoadBinary {
+            version,
+            uuid,
+            load_address: known(load_address),
+            slide,
+            name,
+        })
+    }
+}
+
+/// A binary in the "all image infos" note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub path: Option<String>,
+    pub uuid: Option<Uuid>,
+    pub load_address: Option<u64>,
+    /// Where each segment of the binary was loaded.
+    pub segments: Vec<(String, u64)>,
+}
+
+/// The "all image infos" note, which lists every binary loaded in the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllImageInfos {
+    pub version: u32,
+    pub images: Vec<ImageInfo>,
+}
+
+impl AllImageInfos {
+    pub const DATA_OWNER: &'static str = "all image infos";
+
+    /// The payload refers to its entries, paths and segments by file offset, so `offset` is
+    /// where the payload is in the file. They have to be inside it.
+    pub fn parse(bytes: &[u8], offset: u64) -> MachOResult<Self> {
+        let at = |fileoff: u64| {
+            fileoff
+                .checked_sub(offset)
+                .and_then(|start| bytes.get(start as usize..))
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!(
+                        "The all image infos note refers to {:#x}, outside of itself",
+                        fileoff
+                    ))
+                })
+        };
+        // The file offset of the `index`th of a table of `size` byte entries at `start`.
+        let element = |start: u64, index: u64, size: u64| {
+            index
+                .checked_mul(size)
+                .and_then(|offset| start.checked_add(offset))
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!(
+                        "Entry {} of the table at {:#x} in the all image infos note overflows",
+                        index, start
+                    ))
+                })
+        };
+
+        let (cursor, version) = le_u32(bytes)?;
+        let (cursor, image_count) = le_u32(cursor)?;
+        let (cursor, entries_offset) = le_u64(cursor)?;
+        let (_, entry_size) = le_u32(cursor)?;
+
+        let mut images = vec![];
+        for i in 0..image_count as u64 {
+            let entry = at(element(entries_offset, i, entry_size as u64)?)?;
+            let (entry, path_offset) = le_u64(entry)?;
+            let (entry, uuid) = uuid(entry)?;
+            let (entry, load_address) = le_u64(entry)?;
+            let (entry, segments_offset) = le_u64(entry)?;
+            let (_, segment_count) = le_u32(entry)?;
+
+            let path = match known(path_offset) {
+                Some(path_offset) => Some(string_upto_null_terminator(at(path_offset)?)?.1),
+                None => None,
+            };
+            let mut segments = vec![];
+            for j in 0..segment_count as u64 {
+                let segment = at(element(segments_offset, j, 32)?)?;
+                let (segment, segname) = take(16usize)(segment)?;
+                let (_, segname) = string_upto_null_terminator(segname)?;
+                let (_, vmaddr) = le_u64(segment)?;
+                segments.push((segname, vmaddr));
+            }
+            images.push(ImageInfo {
+                path,
+                uuid,
+                load_address: known(load_address),
+                segments,
+            });
+        }
+        Ok(AllImageInfos { version, images })
+    }
+}
+
+/// The "process metadata" note, a JSON object describing the process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessMetadata {
+    pub version: u32,
+    pub json: String,
+}
+
+impl ProcessMetadata {
+    pub const DATA_OWNER: &'static str = "process metadata";
+
+    pub fn parse(bytes: &[u8]) -> MachOResult<Self> {
+        let (bytes, version) = le_u32(bytes)?;
+        let (_, json) = string_upto_null_terminator(bytes)?;
+        Ok(ProcessMetadata { version, json })
+    }
+}
+
+/// A note payload decoded by a decoder registered with `NoteRegistry`.
+pub trait NoteValue: Any + Debug {
+    fn as_any(&self) -> &dyn Any;
+    fn eq_value(&self, other: &dyn NoteValue) -> bool;
+}
+
+impl<T: Any + Debug + PartialEq> NoteValue for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_value(&self, other: &dyn NoteValue) -> bool {
+        other.as_any().downcast_ref::<T>() == Some(self)
+    }
+}
+
+impl PartialEq for dyn NoteValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_value(other)
+    }
+}
+
+/// The payload of an `LC_NOTE`, decoded if its owner is one of the standard core file ones
+/// or has a decoder in a `NoteRegistry`.
+#[derive(Debug, PartialEq)]
+pub enum NotePayload {
+    AddrableBits(AddrableBits),
+    AllImageInfos(AllImageInfos),
+    MainBinSpec(MainBinSpec),
+    LoadBinary(LoadBinary),
+    ProcessMetadata(ProcessMetadata),
+    Custom {
+        data_owner: String,
+        value: Box<dyn NoteValue>,
+    },
+    Unknown {
+        data_owner: String,
+        data: Vec<u8>,
+    },
+}
+
+impl NotePayload {
+    /// Decodes the payload of `note`, read from `note.offset`.
+    pub fn parse(note: &NoteCommand, bytes: &[u8]) -> MachOResult<Self> {
+        Ok(match note.data_owner.as_str() {
+            AddrableBits::DATA_OWNER => NotePayload::AddrableBits(AddrableBits::parse(bytes)?),
+            AllImageInfos::DATA_OWNER => {
+                NotePayload::AllImageInfos(AllImageInfos::parse(bytes, note.offset)?)
+            }
+            MainBinSpec::DATA_OWNER => NotePayload::MainBinSpec(MainBinSpec::parse(bytes)?),
+            LoadBinary::DATA_OWNER => NotePayload::LoadBinary(LoadBinary::parse(bytes)?),
+            ProcessMetadata::DATA_OWNER => {
+                NotePayload::ProcessMetadata(ProcessMetadata::parse(bytes)?)
+            }
+            _ => NotePayload::Unknown {
+                data_owner: note.data_owner.clone(),
+                data: bytes.to_vec(),
+            },
+        })
+    }
+
+    /// The value a registered decoder produced, if it's a `T`.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        match self {
+            NotePayload::Custom { value, .. } => value.as_ref().as_any().downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+type NoteDecoder = Box<dyn Fn(&[u8]) -> MachOResult<Box<dyn NoteValue>>>;
+
+/// Decoders for note payloads by data owner, used before the standard core file ones.
+#[derive(Default)]
+pub struct NoteRegistry {
+    decoders: HashMap<String, NoteDecoder>,
+}
+
+impl NoteRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the payloads of notes from `data_owner` with `decode`, replacing any decoder
+    /// the owner had.
+    pub fn register<T, F>(&mut self, data_owner: &str, decode: F)
+    where
+        T: Any + Debug + PartialEq,
+        F: Fn(&[u8]) -> MachOResult<T> + 'static,
+    {
+        self.decoders.insert(
+            data_owner.to_string(),
+            Box::new(move |bytes| Ok(Box::new(decode(bytes)?) as Box<dyn NoteValue>)),
+        );
+    }
+
+    /// Decodes the payload of `note`, read from `note.offset`.
+    pub fn decode(&self, note: &NoteCommand, bytes: &[u8]) -> MachOResult<NotePayload> {
+        match self.decoders.get(&note.data_owner) {
+            Some(decode) => Ok(NotePayload::Custom {
+                data_owner: note.data_owner.clone(),
+                value: decode(bytes)?,
+            }),
+            None => NotePayload::parse(note, bytes),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -57,8 +386,8 @@ mod tests {
     fn test_note_serialise() {
         let cmd = NoteCommand {
             cmd: LCLoadCommand::LcNote,
-            cmdsize: 47,
-            data_owner: "com.apple.dt.Xcode".to_string(),
+            cmdsize: 40,
+            data_owner: "com.apple.Xcode".to_string(),
             offset: 0,
             size: 0,
         };
@@ -66,5 +395,35 @@ mod tests {
         let serialized = cmd.serialize();
         let deserialized = NoteCommand::parse(&serialized).unwrap();
         assert_eq!(cmd, deserialized);
+
+        let long = NoteCommand {
+            data_owner: "com.apple.dt.Xcode.debugger".to_string(),
+            ..cmd
+        };
+        let serialized = long.serialize();
+        assert_eq!(serialized.len(), 40);
+        assert_eq!(
+            NoteCommand::parse(&serialized).unwrap().data_owner,
+            "com.apple.dt.Xco"
+        );
+    }
+
+    #[test]
+    fn test_all_image_infos_overflow() {
+        // Two entries a huge stride apart, in a payload at the very end of the file.
+        let offset = u64::MAX - 0x60;
+        let mut bytes = vec![];
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend((offset + 0x18).to_le_bytes());
+        bytes.extend(0x1000_0000u32.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(u64::MAX.to_le_bytes());
+        bytes.extend([0; 16]);
+        bytes.extend(u64::MAX.to_le_bytes());
+        bytes.extend(0u64.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.resize(0x60, 0);
+        assert!(AllImageInfos::parse(&bytes, offset).is_err());
     }
 }
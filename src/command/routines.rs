@@ -1,4 +1,5 @@
-use nom::number::complete::le_u64;
+use nom::number::complete::{le_u32, le_u64};
+use nom::IResult;
 
 use crate::macho::MachOResult;
 
@@ -18,17 +19,27 @@ pub struct RoutinesCommand64 {
     pub reserved6: u64,
 }
 
+// LC_ROUTINES has the same fields as LC_ROUTINES_64, each 32 bits wide.
+fn word(cursor: &[u8], is_64: bool) -> IResult<&[u8], u64> {
+    if is_64 {
+        le_u64(cursor)
+    } else {
+        le_u32(cursor).map(|(cursor, word)| (cursor, word as u64))
+    }
+}
+
 impl LoadCommandParser for RoutinesCommand64 {
     fn parse(ldcmd: &[u8]) -> MachOResult<Self> {
         let (cursor, base) = LoadCommandBase::parse(ldcmd)?;
-        let (cursor, init_address) = le_u64(cursor)?;
-        let (cursor, init_module) = le_u64(cursor)?;
-        let (cursor, reserved1) = le_u64(cursor)?;
-        let (cursor, reserved2) = le_u64(cursor)?;
-        let (cursor, reserved3) = le_u64(cursor)?;
-        let (cursor, reserved4) = le_u64(cursor)?;
-        let (cursor, reserved5) = le_u64(cursor)?;
-        let (_, reserved6) = le_u64(cursor)?;
+        let is_64 = base.cmd != LCLoadCommand::LcRoutines;
+        let (cursor, init_address) = word(cursor, is_64)?;
+        let (cursor, init_module) = word(cursor, is_64)?;
+        let (cursor, reserved1) = word(cursor, is_64)?;
+        let (cursor, reserved2) = word(cursor, is_64)?;
+        let (cursor, reserved3) = word(cursor, is_64)?;
+        let (cursor, reserved4) = word(cursor, is_64)?;
+        let (cursor, reserved5) = word(cursor, is_64)?;
+        let (_, reserved6) = word(cursor, is_64)?;
 
         Ok(
             RoutinesCommand64 {
@@ -50,14 +61,22 @@ impl LoadCommandParser for RoutinesCommand64 {
         let mut buf = Vec::new();
         buf.extend(self.cmd.serialize());
         buf.extend(self.cmdsize.to_le_bytes());
-        buf.extend(self.init_address.to_le_bytes());
-        buf.extend(self.init_module.to_le_bytes());
-        buf.extend(self.reserved1.to_le_bytes());
-        buf.extend(self.reserved2.to_le_bytes());
-        buf.extend(self.reserved3.to_le_bytes());
-        buf.extend(self.reserved4.to_le_bytes());
-        buf.extend(self.reserved5.to_le_bytes());
-        buf.extend(self.reserved6.to_le_bytes());
+        for word in [
+            self.init_address,
+            self.init_module,
+            self.reserved1,
+            self.reserved2,
+            self.reserved3,
+            self.reserved4,
+            self.reserved5,
+            self.reserved6,
+        ] {
+            if self.cmd == LCLoadCommand::LcRoutines {
+                buf.extend((word as u32).to_le_bytes());
+            } else {
+                buf.extend(word.to_le_bytes());
+            }
+        }
         pad_to_size(&mut buf, self.cmdsize as usize);
         buf
     }
@@ -87,4 +106,24 @@ mod tests {
         let deserialized = RoutinesCommand64::parse(&serialized).unwrap();
         assert_eq!(cmd, deserialized);
     }
+
+    #[test]
+    fn test_routines_command32() {
+        let cmd = RoutinesCommand64 {
+            cmd: LCLoadCommand::LcRoutines,
+            cmdsize: 40,
+            init_address: 0x1f00,
+            init_module: 0,
+            reserved1: 0,
+            reserved2: 0,
+            reserved3: 0,
+            reserved4: 0,
+            reserved5: 0,
+            reserved6: 0,
+        };
+
+        let serialized = cmd.serialize();
+        assert_eq!(serialized.len(), 40);
+        assert_eq!(RoutinesCommand64::parse(&serialized).unwrap(), cmd);
+    }
 }
@@ -63,19 +63,26 @@ impl NlistReferenceType {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct NlistDesc {
     pub reference_type: NlistReferenceType,
+    pub arm_thumb_def: bool,
     pub referenced_dynamically: bool,
     pub no_dead_strip: bool,
     pub n_weak_ref: bool,
     pub n_weak_def: bool,
+    /// The two-level namespace library ordinal of an undefined symbol. Defined symbols keep
+    /// their `N_SYMBOL_RESOLVER`, `N_ALT_ENTRY` and `N_COLD_FUNC` bits here instead.
     pub library_ordinal: u8,
 }
 
 impl NlistDesc {
-    pub const REFERENCED_DYNAMICALLY_BITMASK: u16 = 0x100;
-    pub const NO_DEAD_STRIP_BITMASK: u16 = 0x200;
-    pub const N_WEAK_REF_BITMASK: u16 = 0x400;
-    pub const N_WEAK_DEF_BITMASK: u16 = 0x800;
+    pub const N_ARM_THUMB_DEF_BITMASK: u16 = 0x8;
+    pub const REFERENCED_DYNAMICALLY_BITMASK: u16 = 0x10;
+    pub const NO_DEAD_STRIP_BITMASK: u16 = 0x20;
+    pub const N_WEAK_REF_BITMASK: u16 = 0x40;
+    pub const N_WEAK_DEF_BITMASK: u16 = 0x80;
     pub const LIBRARY_ORDINAL_BITMASK: u16 = 0xff00;
+    pub const N_SYMBOL_RESOLVER_BITMASK: u16 = 0x100;
+    pub const N_ALT_ENTRY_BITMASK: u16 = 0x200;
+    pub const N_COLD_FUNC_BITMASK: u16 = 0x400;
 
     pub fn parse(bytes: &[u8]) -> IResult<&[u8], NlistDesc> {
         let cursor = bytes;
@@ -86,6 +93,7 @@ impl NlistDesc {
             bytes,
             NlistDesc {
                 reference_type,
+                arm_thumb_def: n_desc & Self::N_ARM_THUMB_DEF_BITMASK != 0,
                 referenced_dynamically: n_desc & Self::REFERENCED_DYNAMICALLY_BITMASK != 0,
                 no_dead_strip: n_desc & Self::NO_DEAD_STRIP_BITMASK != 0,
                 n_weak_ref: n_desc & Self::N_WEAK_REF_BITMASK != 0,
@@ -94,11 +102,41 @@ impl NlistDesc {
             },
         ))
     }
+
+    pub fn raw(&self) -> u16 {
+        let flag = |set: bool, bitmask: u16| if set { bitmask } else { 0 };
+        self.reference_type as u16
+            | flag(self.arm_thumb_def, Self::N_ARM_THUMB_DEF_BITMASK)
+            | flag(self.referenced_dynamically, Self::REFERENCED_DYNAMICALLY_BITMASK)
+            | flag(self.no_dead_strip, Self::NO_DEAD_STRIP_BITMASK)
+            | flag(self.n_weak_ref, Self::N_WEAK_REF_BITMASK)
+            | flag(self.n_weak_def, Self::N_WEAK_DEF_BITMASK)
+            | (self.library_ordinal as u16) << 8
+    }
+
+    fn defined_flag(&self, bitmask: u16) -> bool {
+        self.raw() & bitmask != 0
+    }
+
+    pub fn symbol_resolver(&self) -> bool {
+        self.defined_flag(Self::N_SYMBOL_RESOLVER_BITMASK)
+    }
+
+    pub fn alt_entry(&self) -> bool {
+        self.defined_flag(Self::N_ALT_ENTRY_BITMASK)
+    }
+
+    pub fn cold_func(&self) -> bool {
+        self.defined_flag(Self::N_COLD_FUNC_BITMASK)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct NlistType {
     pub stab: bool,
+    /// The whole n_type of a debug entry, such as `N_FUN` or `N_SO`, since stabs use all of
+    /// its bits. Zero for other symbols.
+    pub stab_type: u8,
     pub pext: bool,
     pub type_: NlistTypeType,
     pub ext: bool,
@@ -112,17 +150,34 @@ impl NlistType {
     pub fn parse(bytes: &[u8]) -> IResult<&[u8], NlistType> {
         let cursor = bytes;
         let (bytes, n_type) = le_u8(cursor)?;
-        let (_, type_) = NlistTypeType::parse(cursor)?;
+        let stab = n_type & Self::NLIST_TYPE_STAB_BITMASK != 0;
+        let type_ = match NlistTypeType::parse(cursor) {
+            Ok((_, type_)) => type_,
+            // The low bits of a stab are part of its type, and don't have to be a valid one.
+            Err(_) if stab => NlistTypeType::Undefined,
+            Err(err) => return Err(err),
+        };
         Ok((
             bytes,
             NlistType {
-                stab: n_type & Self::NLIST_TYPE_STAB_BITMASK != 0,
+                stab,
+                stab_type: if stab { n_type } else { 0 },
                 pext: n_type & Self::NLIST_TYPE_PEXT_BITMASK != 0,
                 type_,
                 ext: n_type & Self::NLIST_TYPE_EXT_BITMASK != 0,
             },
         ))
     }
+
+    pub fn raw(&self) -> u8 {
+        if self.stab {
+            return self.stab_type;
+        }
+        let flag = |set: bool, bitmask: u8| if set { bitmask } else { 0 };
+        self.type_ as u8
+            | flag(self.pext, Self::NLIST_TYPE_PEXT_BITMASK)
+            | flag(self.ext, Self::NLIST_TYPE_EXT_BITMASK)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -165,6 +220,21 @@ impl Nlist {
             },
         ))
     }
+
+    /// Serializes the entry with its name at `n_strx` in the string table.
+    pub fn serialize(&self, n_strx: u32, is_64_bit: bool) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(n_strx.to_le_bytes());
+        buf.push(self.n_type.raw());
+        buf.push(self.n_sect);
+        buf.extend(self.n_desc.raw().to_le_bytes());
+        if is_64_bit {
+            buf.extend(self.n_value.to_le_bytes());
+        } else {
+            buf.extend((self.n_value as u32).to_le_bytes());
+        }
+        buf
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -268,4 +338,33 @@ mod tests {
         let deserialized = SymtabCommand::parse(&serialized).unwrap();
         assert_eq!(symtab, deserialized);
     }
+
+    #[test]
+    fn test_nlist_serialize() {
+        let strings = b" \0_main\0";
+        let raw = b"\x02\0\0\0\x0f\x01\x10\x02\x00\x3f\0\0\x01\0\0\0";
+        let (_, nlist) = Nlist::parse(raw, strings, true).unwrap();
+        assert_eq!(nlist.n_strx, "_main");
+        assert!(nlist.n_desc.referenced_dynamically);
+        assert_eq!(nlist.n_desc.library_ordinal, 2);
+        assert_eq!(nlist.serialize(2, true), raw);
+    }
+
+    #[test]
+    fn test_nlist_raw_bits() {
+        let strings = b" \0_main\0";
+        // A thumb resolver that's also an alt entry and cold.
+        let raw = b"\x02\0\0\0\x0f\x01\x08\x07\x00\x3f\0\0";
+        let (_, nlist) = Nlist::parse(raw, strings, false).unwrap();
+        assert!(nlist.n_desc.arm_thumb_def);
+        assert!(nlist.n_desc.symbol_resolver() && nlist.n_desc.alt_entry());
+        assert!(nlist.n_desc.cold_func());
+        assert_eq!(nlist.serialize(2, false), raw);
+
+        // N_ECOML, whose type bits aren't a valid n_type.
+        let raw = b"\x02\0\0\0\xe8\0\x01\0\0\0\0\0";
+        let (_, nlist) = Nlist::parse(raw, strings, false).unwrap();
+        assert!(nlist.n_type.stab);
+        assert_eq!(nlist.serialize(2, false), raw);
+    }
 }
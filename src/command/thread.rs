@@ -1,6 +1,4 @@
-use crate::{machine::{
-    Arm64ThreadState64, ThreadState, ThreadStateBase, ThreadStateFlavor, X86ThreadState32, X86ThreadState64
-}, macho::MachOResult};
+use crate::{machine::{CpuType, ThreadState, ThreadStateBase}, macho::MachOResult};
 
 use super::{pad_to_size, LCLoadCommand, LoadCommandBase, LoadCommandParser};
 
@@ -11,8 +9,9 @@ pub struct ThreadCommand {
     pub threads: Vec<ThreadState>,
 }
 
-impl LoadCommandParser for ThreadCommand {
-    fn parse(ldcmd: &[u8]) -> MachOResult<Self> {
+impl ThreadCommand {
+    /// Decodes the states with the flavors `cputype` defines.
+    pub fn parse_for(ldcmd: &[u8], cputype: CpuType) -> MachOResult<Self> {
         let (mut cursor, base) = LoadCommandBase::parse(ldcmd)?;
         let mut threads = Vec::new();
         loop {
@@ -21,7 +20,7 @@ impl LoadCommandParser for ThreadCommand {
             }
 
             let (next, base) = ThreadStateBase::parse(cursor)?;
-            let (next, thread) = ThreadState::parse(next, base)?;
+            let (next, thread) = ThreadState::parse_for(next, base, cputype)?;
             cursor = next;
             threads.push(thread);
         }
@@ -34,26 +33,20 @@ impl LoadCommandParser for ThreadCommand {
             },
         )
     }
+}
+
+impl LoadCommandParser for ThreadCommand {
+    /// Without the CPU type, flavors are told apart by their number and size.
+    fn parse(ldcmd: &[u8]) -> MachOResult<Self> {
+        ThreadCommand::parse_for(ldcmd, CpuType::Any)
+    }
 
     fn serialize(&self) -> Vec<u8> {
         let mut buf = Vec::new();
         buf.extend(self.cmd.serialize());
         buf.extend(self.cmdsize.to_le_bytes());
         for thread in &self.threads {
-            match thread {
-                ThreadState::X86State64(_) => {
-                    buf.extend((ThreadStateFlavor::X86ThreadState64 as u32).to_le_bytes());
-                    buf.extend(X86ThreadState64::SIZE.to_le_bytes());
-                }
-                ThreadState::Arm64State64(_) => {
-                    buf.extend((ThreadStateFlavor::Arm64ThreadState64 as u32).to_le_bytes());
-                    buf.extend(Arm64ThreadState64::SIZE.to_le_bytes());
-                },
-                ThreadState::X86State32(_) => {
-                    buf.extend((ThreadStateFlavor::X86ThreadState32 as u32).to_le_bytes());
-                    buf.extend(X86ThreadState32::SIZE.to_le_bytes());
-                },
-            }
+            buf.extend(thread.base().serialize());
             buf.extend(thread.serialize());
         }
         pad_to_size(&mut buf, self.cmdsize as usize);
@@ -66,7 +59,10 @@ mod tests {
     use super::*;
     use crate::{
         command::LCLoadCommand,
-        machine::Arm64ThreadState64,
+        machine::{
+            Arm64ExceptionState64, Arm64ThreadState64, ArmNeonState64, ArmThreadState32,
+            X86AvxState64,
+        },
     };
 
     #[test]
@@ -90,4 +86,58 @@ mod tests {
         let deserialised = ThreadCommand::parse(&serialised).unwrap();
         assert_eq!(cmd.cmd, deserialised.cmd);
     }
+
+    #[test]
+    fn test_thread_flavors() {
+        let cmd = ThreadCommand {
+            cmd: LCLoadCommand::LcThread,
+            cmdsize: 8 + 8 * 4 + 4 * (130 + 4 + 17 + 2),
+            threads: vec![
+                ThreadState::ArmNeonState64(ArmNeonState64 {
+                    q: [u128::MAX - 1; 32],
+                    fpsr: 7,
+                    fpcr: 8,
+                }),
+                ThreadState::Arm64ExceptionState64(Arm64ExceptionState64 {
+                    far: 0x1000,
+                    esr: 0x92000046,
+                    exception: 3,
+                }),
+                ThreadState::ArmState32(ArmThreadState32 {
+                    r: [9; 13],
+                    sp: 10,
+                    lr: 11,
+                    pc: 12,
+                    cpsr: 13,
+                }),
+                ThreadState::Unknown {
+                    flavor: 0x99,
+                    data: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                },
+            ],
+        };
+
+        let serialised = cmd.serialize();
+        assert_eq!(serialised.len(), cmd.cmdsize as usize);
+        assert_eq!(ThreadCommand::parse(&serialised).unwrap(), cmd);
+        assert_eq!(ThreadCommand::parse_for(&serialised, CpuType::Arm64).unwrap(), cmd);
+
+        // Flavor 17 is x86_AVX_STATE64 on x86 and ARM_NEON_STATE64 on ARM.
+        let base = || ThreadStateBase {
+            flavor: 17,
+            size: X86AvxState64::SIZE,
+        };
+        let avx = vec![0xff; X86AvxState64::SIZE as usize * 4];
+        match ThreadState::parse_for(&avx, base(), CpuType::X86_64).unwrap().1 {
+            ThreadState::X86AvxState64(state) => {
+                assert_eq!(state.ymmh, [u128::MAX; 16]);
+                assert_eq!(state.float.control.mxcsr, u32::MAX);
+            }
+            other => panic!("Unexpected thread state {:?}", other),
+        }
+        assert!(matches!(
+            ThreadState::parse_for(&avx, base(), CpuType::Arm64).unwrap().1,
+            ThreadState::Unknown { flavor: 17, .. }
+        ));
+    }
 }
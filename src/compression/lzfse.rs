@@ -0,0 +1,656 @@
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use nom::{
+    number::complete::{le_i32, le_u16, le_u32, le_u64},
+    IResult,
+};
+
+use crate::macho::{MachOErr, MachOResult};
+
+use super::lzvn;
+
+pub const ENDOFSTREAM_BLOCK_MAGIC: u32 = 0x24787662; // bvx$
+pub const UNCOMPRESSED_BLOCK_MAGIC: u32 = 0x2d787662; // bvx-
+pub const COMPRESSEDV1_BLOCK_MAGIC: u32 = 0x31787662; // bvx1
+pub const COMPRESSEDV2_BLOCK_MAGIC: u32 = 0x32787662; // bvx2
+pub const COMPRESSEDLZVN_BLOCK_MAGIC: u32 = 0x6e787662; // bvxn
+
+const L_SYMBOLS: usize = 20;
+const M_SYMBOLS: usize = 20;
+const D_SYMBOLS: usize = 64;
+const LITERAL_SYMBOLS: usize = 256;
+const L_STATES: usize = 64;
+const M_STATES: usize = 64;
+const D_STATES: usize = 256;
+const LITERAL_STATES: usize = 1024;
+const MATCHES_PER_BLOCK: u32 = 10000;
+const LITERALS_PER_BLOCK: u32 = 4 * MATCHES_PER_BLOCK;
+
+const L_EXTRA_BITS: [u8; L_SYMBOLS] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 3, 5, 8];
+const L_BASE_VALUE: [i32; L_SYMBOLS] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 20, 28, 60,
+];
+const M_EXTRA_BITS: [u8; M_SYMBOLS] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 5, 8, 11];
+const M_BASE_VALUE: [i32; M_SYMBOLS] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 24, 56, 312,
+];
+const D_EXTRA_BITS: [u8; D_SYMBOLS] = [
+    0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7,
+    8, 8, 8, 8, 9, 9, 9, 9, 10, 10, 10, 10, 11, 11, 11, 11, 12, 12, 12, 12, 13, 13, 13, 13, 14, 14,
+    14, 14, 15, 15, 15, 15,
+];
+const D_BASE_VALUE: [i32; D_SYMBOLS] = [
+    0, 1, 2, 3, 4, 6, 8, 10, 12, 16, 20, 24, 28, 36, 44, 52, 60, 76, 92, 108, 124, 156, 188, 220,
+    252, 316, 380, 444, 508, 636, 764, 892, 1020, 1276, 1532, 1788, 2044, 2556, 3068, 3580, 4092,
+    5116, 6140, 7164, 8188, 10236, 12284, 14332, 16380, 20476, 24572, 28668, 32764, 40956, 49148,
+    57340, 65532, 81916, 98300, 114684, 131068, 163836, 196604, 229372,
+];
+
+fn invalid(msg: &str) -> MachOErr {
+    MachOErr::ParsingError(format!("Invalid LZFSE stream: {}", msg))
+}
+
+/// The header of a compressed LZFSE block. `bvx2` blocks pack the same fields and store the
+/// frequency tables with a variable length code, so both versions decode into this.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedBlockHeader {
+    pub n_raw_bytes: u32,
+    pub n_payload_bytes: u32,
+    pub n_literals: u32,
+    pub n_matches: u32,
+    pub n_literal_payload_bytes: u32,
+    pub n_lmd_payload_bytes: u32,
+    pub literal_bits: i32,
+    pub literal_state: [u16; 4],
+    pub lmd_bits: i32,
+    pub l_state: u16,
+    pub m_state: u16,
+    pub d_state: u16,
+    pub l_freq: [u16; L_SYMBOLS],
+    pub m_freq: [u16; M_SYMBOLS],
+    pub d_freq: [u16; D_SYMBOLS],
+    pub literal_freq: [u16; LITERAL_SYMBOLS],
+}
+
+impl CompressedBlockHeader {
+    // sizeof(lzfse_compressed_block_header_v1), including trailing padding.
+    pub const V1_SIZE: usize = 772;
+    pub const V2_SIZE: usize = 32;
+
+    fn empty(n_raw_bytes: u32) -> CompressedBlockHeader {
+        CompressedBlockHeader {
+            n_raw_bytes,
+            n_payload_bytes: 0,
+            n_literals: 0,
+            n_matches: 0,
+            n_literal_payload_bytes: 0,
+            n_lmd_payload_bytes: 0,
+            literal_bits: 0,
+            literal_state: [0; 4],
+            lmd_bits: 0,
+            l_state: 0,
+            m_state: 0,
+            d_state: 0,
+            l_freq: [0; L_SYMBOLS],
+            m_freq: [0; M_SYMBOLS],
+            d_freq: [0; D_SYMBOLS],
+            literal_freq: [0; LITERAL_SYMBOLS],
+        }
+    }
+
+    fn freqs_mut(&mut self) -> impl Iterator<Item = &mut u16> {
+        self.l_freq
+            .iter_mut()
+            .chain(self.m_freq.iter_mut())
+            .chain(self.d_freq.iter_mut())
+            .chain(self.literal_freq.iter_mut())
+    }
+
+    /// Parses a `bvx1` header, starting after the block magic.
+    pub fn parse_v1(bytes: &[u8]) -> IResult<&[u8], CompressedBlockHeader> {
+        let (mut bytes, n_raw_bytes) = le_u32(bytes)?;
+        let mut header = CompressedBlockHeader::empty(n_raw_bytes);
+        (bytes, header.n_payload_bytes) = le_u32(bytes)?;
+        (bytes, header.n_literals) = le_u32(bytes)?;
+        (bytes, header.n_matches) = le_u32(bytes)?;
+        (bytes, header.n_literal_payload_bytes) = le_u32(bytes)?;
+        (bytes, header.n_lmd_payload_bytes) = le_u32(bytes)?;
+        (bytes, header.literal_bits) = le_i32(bytes)?;
+        for state in header.literal_state.iter_mut() {
+            (bytes, *state) = le_u16(bytes)?;
+        }
+        (bytes, header.lmd_bits) = le_i32(bytes)?;
+        (bytes, header.l_state) = le_u16(bytes)?;
+        (bytes, header.m_state) = le_u16(bytes)?;
+        (bytes, header.d_state) = le_u16(bytes)?;
+        for freq in header.freqs_mut() {
+            (bytes, *freq) = le_u16(bytes)?;
+        }
+        // Trailing struct padding.
+        let (bytes, _) = le_u16(bytes)?;
+
+        Ok((bytes, header))
+    }
+
+    /// Parses a `bvx2` header, starting after the block magic. Returns the header along with
+    /// the total header size, which includes the magic and the encoded frequency tables.
+    pub fn parse_v2(bytes: &[u8]) -> MachOResult<(CompressedBlockHeader, usize)> {
+        let (rest, n_raw_bytes) = le_u32(bytes)?;
+        let (rest, v0) = le_u64(rest)?;
+        let (rest, v1) = le_u64(rest)?;
+        let (rest, v2) = le_u64(rest)?;
+
+        let field = |v: u64, offset: u32, nbits: u32| ((v >> offset) & ((1 << nbits) - 1)) as u32;
+        let mut header = CompressedBlockHeader::empty(n_raw_bytes);
+        header.n_literals = field(v0, 0, 20);
+        header.n_literal_payload_bytes = field(v0, 20, 20);
+        header.n_matches = field(v0, 40, 20);
+        header.literal_bits = field(v0, 60, 3) as i32 - 7;
+        for (i, state) in header.literal_state.iter_mut().enumerate() {
+            *state = field(v1, 10 * i as u32, 10) as u16;
+        }
+        header.n_lmd_payload_bytes = field(v1, 40, 20);
+        header.lmd_bits = field(v1, 60, 3) as i32 - 7;
+        header.l_state = field(v2, 32, 10) as u16;
+        header.m_state = field(v2, 42, 10) as u16;
+        header.d_state = field(v2, 52, 10) as u16;
+        header.n_payload_bytes = header.n_literal_payload_bytes + header.n_lmd_payload_bytes;
+
+        let header_size = field(v2, 0, 32) as usize;
+        if header_size < Self::V2_SIZE {
+            return Err(invalid("header size is too small"));
+        }
+        let freq = rest
+            .get(..header_size - Self::V2_SIZE)
+            .ok_or_else(|| invalid("truncated frequency tables"))?;
+
+        let mut src = freq.iter();
+        let mut accum = 0u32;
+        let mut accum_nbits = 0u32;
+        for value in header.freqs_mut() {
+            while accum_nbits + 8 <= 32 {
+                let Some(&b) = src.next() else { break };
+                accum |= (b as u32) << accum_nbits;
+                accum_nbits += 8;
+            }
+            let (freq, nbits) = decode_freq_value(accum);
+            if nbits > accum_nbits {
+                return Err(invalid("truncated frequency tables"));
+            }
+            *value = freq;
+            accum >>= nbits;
+            accum_nbits -= nbits;
+        }
+        if accum_nbits >= 8 || src.next().is_some() {
+            return Err(invalid("trailing bytes after frequency tables"));
+        }
+
+        Ok((header, header_size))
+    }
+}
+
+fn decode_freq_value(bits: u32) -> (u16, u32) {
+    const NBITS: [u8; 32] = [
+        2, 3, 2, 5, 2, 3, 2, 8, 2, 3, 2, 5, 2, 3, 2, 14, 2, 3, 2, 5, 2, 3, 2, 8, 2, 3, 2, 5, 2, 3,
+        2, 14,
+    ];
+    const VALUE: [u8; 32] = [
+        0, 2, 1, 4, 0, 3, 1, 0, 0, 2, 1, 5, 0, 3, 1, 0, 0, 2, 1, 6, 0, 3, 1, 0, 0, 2, 1, 7, 0, 3,
+        1, 0,
+    ];
+
+    let b = (bits & 31) as usize;
+    match NBITS[b] {
+        8 => (8 + ((bits >> 4) & 0xf) as u16, 8),
+        14 => (24 + ((bits >> 4) & 0x3ff) as u16, 14),
+        n => (VALUE[b] as u16, n as u32),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct DecoderEntry {
+    k: u8,
+    symbol: u8,
+    delta: i16,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ValueDecoderEntry {
+    total_bits: u8,
+    value_bits: u8,
+    delta: i16,
+    vbase: i32,
+}
+
+// Returns the (symbol, nbits, delta) transition for every state, in state order.
+fn state_transitions(nstates: usize, freq: &[u16]) -> MachOResult<Vec<(usize, u8, i16)>> {
+    let n_clz = (nstates as u32).leading_zeros();
+    let mut sum = 0;
+    let mut entries = Vec::with_capacity(nstates);
+    for (symbol, &f) in freq.iter().enumerate() {
+        let f = f as u32;
+        if f == 0 {
+            continue;
+        }
+        sum += f as usize;
+        if sum > nstates {
+            return Err(invalid("frequency table overflows its states"));
+        }
+
+        let k = f.leading_zeros() - n_clz;
+        let j0 = ((2 * nstates as u32) >> k) - f;
+        for j in 0..f {
+            if j < j0 {
+                let delta = (((f + j) << k) - nstates as u32) as i16;
+                entries.push((symbol, k as u8, delta));
+            } else {
+                let delta = ((j - j0) << (k - 1)) as i16;
+                entries.push((symbol, (k - 1) as u8, delta));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn decoder_table(nstates: usize, freq: &[u16]) -> MachOResult<Vec<DecoderEntry>> {
+    Ok(state_transitions(nstates, freq)?
+        .into_iter()
+        .map(|(symbol, k, delta)| DecoderEntry {
+            k,
+            symbol: symbol as u8,
+            delta,
+        })
+        .collect())
+}
+
+fn value_decoder_table(
+    nstates: usize,
+    freq: &[u16],
+    extra_bits: &[u8],
+    base_value: &[i32],
+) -> MachOResult<Vec<ValueDecoderEntry>> {
+    Ok(state_transitions(nstates, freq)?
+        .into_iter()
+        .map(|(symbol, k, delta)| ValueDecoderEntry {
+            total_bits: k + extra_bits[symbol],
+            value_bits: extra_bits[symbol],
+            delta,
+            vbase: base_value[symbol],
+        })
+        .collect())
+}
+
+/// FSE bit stream, read backwards from the end of a payload.
+struct InStream<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    accum: u64,
+    accum_nbits: u32,
+}
+
+impl<'a> InStream<'a> {
+    fn read_le(bytes: &[u8]) -> u64 {
+        bytes
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &b| (acc << 8) | b as u64)
+    }
+
+    fn new(buf: &'a [u8], end: usize, n: i32) -> MachOResult<InStream<'a>> {
+        let len = if n != 0 { 8 } else { 7 };
+        let pos = end
+            .checked_sub(len)
+            .ok_or_else(|| invalid("truncated bit stream"))?;
+        let accum = Self::read_le(&buf[pos..end]);
+        let accum_nbits = 64 + n - (8 - len as i32) * 8;
+        if !(56..64).contains(&accum_nbits) || accum >> accum_nbits != 0 {
+            return Err(invalid("bad bit stream initial state"));
+        }
+
+        Ok(InStream {
+            buf,
+            pos,
+            accum,
+            accum_nbits: accum_nbits as u32,
+        })
+    }
+
+    fn flush(&mut self) -> MachOResult<()> {
+        let nbits = (63 - self.accum_nbits) & !7;
+        if nbits == 0 {
+            return Ok(());
+        }
+        let pos = self
+            .pos
+            .checked_sub(nbits as usize / 8)
+            .ok_or_else(|| invalid("bit stream underflow"))?;
+        let incoming = Self::read_le(&self.buf[pos..self.pos]);
+        self.accum = (self.accum << nbits) | incoming;
+        self.accum_nbits += nbits;
+        self.pos = pos;
+        Ok(())
+    }
+
+    fn pull(&mut self, n: u32) -> MachOResult<u64> {
+        if n > self.accum_nbits {
+            return Err(invalid("bit stream underflow"));
+        }
+        self.accum_nbits -= n;
+        let result = self.accum >> self.accum_nbits;
+        self.accum &= (1u64 << self.accum_nbits) - 1;
+        Ok(result)
+    }
+
+    fn decode(&mut self, state: &mut usize, table: &[DecoderEntry]) -> MachOResult<u8> {
+        let e = table
+            .get(*state)
+            .ok_or_else(|| invalid("state out of range"))?;
+        *state = (e.delta as i64 + self.pull(e.k as u32)? as i64) as usize;
+        Ok(e.symbol)
+    }
+
+    fn decode_value(&mut self, state: &mut usize, table: &[ValueDecoderEntry]) -> MachOResult<i32> {
+        let e = table
+            .get(*state)
+            .ok_or_else(|| invalid("state out of range"))?;
+        let bits = self.pull(e.total_bits as u32)?;
+        *state = (e.delta as i64 + (bits >> e.value_bits) as i64) as usize;
+        Ok(e.vbase + (bits & ((1u64 << e.value_bits) - 1)) as i32)
+    }
+}
+
+// `block` holds the whole block, and the payloads start at `header_size`.
+fn decode_compressed_block(
+    block: &[u8],
+    header_size: usize,
+    header: &CompressedBlockHeader,
+    dst: &mut Vec<u8>,
+) -> MachOResult<()> {
+    if header.n_literals > LITERALS_PER_BLOCK || header.n_matches > MATCHES_PER_BLOCK {
+        return Err(invalid("block exceeds literal or match limits"));
+    }
+
+    let literal_table = decoder_table(LITERAL_STATES, &header.literal_freq)?;
+    let l_table = value_decoder_table(L_STATES, &header.l_freq, &L_EXTRA_BITS, &L_BASE_VALUE)?;
+    let m_table = value_decoder_table(M_STATES, &header.m_freq, &M_EXTRA_BITS, &M_BASE_VALUE)?;
+    let d_table = value_decoder_table(D_STATES, &header.d_freq, &D_EXTRA_BITS, &D_BASE_VALUE)?;
+
+    // Literals are interleaved across four FSE states and always padded to a multiple of 4.
+    let literal_end = header_size + header.n_literal_payload_bytes as usize;
+    let mut input = InStream::new(block, literal_end, header.literal_bits)?;
+    let mut states = header.literal_state.map(|s| s as usize);
+    let mut literals = Vec::with_capacity(header.n_literals as usize + 4);
+    while literals.len() < header.n_literals as usize {
+        input.flush()?;
+        for state in states.iter_mut() {
+            literals.push(input.decode(state, &literal_table)?);
+        }
+    }
+
+    let lmd_end = literal_end + header.n_lmd_payload_bytes as usize;
+    let mut input = InStream::new(block, lmd_end, header.lmd_bits)?;
+    let mut l_state = header.l_state as usize;
+    let mut m_state = header.m_state as usize;
+    let mut d_state = header.d_state as usize;
+    let mut d_prev = 0usize;
+    let mut literal = 0usize;
+    let end = dst.len() + header.n_raw_bytes as usize;
+
+    for _ in 0..header.n_matches {
+        input.flush()?;
+        let l = input.decode_value(&mut l_state, &l_table)? as usize;
+        input.flush()?;
+        let m = input.decode_value(&mut m_state, &m_table)? as usize;
+        input.flush()?;
+        let d = input.decode_value(&mut d_state, &d_table)? as usize;
+        // A zero distance repeats the previous one.
+        let d = if d == 0 { d_prev } else { d };
+        d_prev = d;
+
+        let lits = literals
+            .get(literal..literal + l)
+            .ok_or_else(|| invalid("literal index out of range"))?;
+        if dst.len() + l + m > end {
+            return Err(invalid("block decodes past its expected size"));
+        }
+        dst.extend_from_slice(lits);
+        literal += l;
+
+        if m > 0 {
+            if d == 0 || d > dst.len() {
+                return Err(invalid("match distance out of range"));
+            }
+            for _ in 0..m {
+                dst.push(dst[dst.len() - d]);
+            }
+        }
+    }
+
+    if dst.len() != end {
+        return Err(invalid("block is shorter than expected"));
+    }
+    Ok(())
+}
+
+fn read_exact<R: Read>(src: &mut R, len: usize) -> MachOResult<Vec<u8>> {
+    let mut bytes = vec![0u8; len];
+    src.read_exact(&mut bytes).map_err(MachOErr::IOError)?;
+    Ok(bytes)
+}
+
+fn read_u32<R: Read>(src: &mut R) -> MachOResult<u32> {
+    let mut bytes = [0u8; 4];
+    src.read_exact(&mut bytes).map_err(MachOErr::IOError)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Reads and decodes the next block from `src`, appending the output to `dst`. Returns false
+/// once the end of stream block has been reached.
+pub fn decode_block<R: Read>(src: &mut R, dst: &mut Vec<u8>) -> MachOResult<bool> {
+    let magic = read_u32(src)?;
+    match magic {
+        ENDOFSTREAM_BLOCK_MAGIC => return Ok(false),
+        UNCOMPRESSED_BLOCK_MAGIC => {
+            let n_raw_bytes = read_u32(src)?;
+            dst.extend(read_exact(src, n_raw_bytes as usize)?);
+        }
+        COMPRESSEDLZVN_BLOCK_MAGIC => {
+            let n_raw_bytes = read_u32(src)?;
+            let n_payload_bytes = read_u32(src)?;
+            let payload = read_exact(src, n_payload_bytes as usize)?;
+            lzvn::decompress_into(&payload, dst, n_raw_bytes as usize)?;
+        }
+        COMPRESSEDV1_BLOCK_MAGIC => {
+            let mut block = magic.to_le_bytes().to_vec();
+            block.extend(read_exact(src, CompressedBlockHeader::V1_SIZE - 4)?);
+            let (_, header) = CompressedBlockHeader::parse_v1(&block[4..])?;
+            let payload_bytes = header
+                .n_literal_payload_bytes
+                .checked_add(header.n_lmd_payload_bytes)
+                .ok_or_else(|| invalid("payload sizes overflow"))?;
+            if header.n_payload_bytes != payload_bytes {
+                return Err(invalid("payload sizes don't add up"));
+            }
+            block.extend(read_exact(src, header.n_payload_bytes as usize)?);
+            decode_compressed_block(&block, CompressedBlockHeader::V1_SIZE, &header, dst)?;
+        }
+        COMPRESSEDV2_BLOCK_MAGIC => {
+            let mut block = magic.to_le_bytes().to_vec();
+            block.extend(read_exact(src, CompressedBlockHeader::V2_SIZE - 4)?);
+            let header_size = u32::from_le_bytes(block[24..28].try_into().unwrap()) as usize;
+            if header_size < CompressedBlockHeader::V2_SIZE {
+                return Err(invalid("header size is too small"));
+            }
+            block.extend(read_exact(
+                src,
+                header_size - CompressedBlockHeader::V2_SIZE,
+            )?);
+            let (header, header_size) = CompressedBlockHeader::parse_v2(&block[4..])?;
+            block.extend(read_exact(src, header.n_payload_bytes as usize)?);
+            decode_compressed_block(&block, header_size, &header, dst)?;
+        }
+        _ => {
+            return Err(MachOErr::ParsingError(format!(
+                "Unknown LZFSE block magic 0x{:08x}",
+                magic
+            )))
+        }
+    }
+    Ok(true)
+}
+
+/// Decompresses a complete LZFSE stream held in memory.
+pub fn decompress(bytes: &[u8]) -> MachOResult<Vec<u8>> {
+    let mut src = Cursor::new(bytes);
+    let mut dst = Vec::new();
+    while decode_block(&mut src, &mut dst)? {}
+    Ok(dst)
+}
+
+/// Lazily decompresses an LZFSE stream, decoding blocks only as far as reads and seeks require.
+/// Since it's `Read + Seek`, it can be handed straight to `MachO::parse`.
+pub struct LzfseReader<R: Read> {
+    src: R,
+    dst: Vec<u8>,
+    pos: u64,
+    done: bool,
+}
+
+impl<R: Read> LzfseReader<R> {
+    pub fn new(src: R) -> LzfseReader<R> {
+        LzfseReader {
+            src,
+            dst: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    fn fill_to(&mut self, len: u64) -> io::Result<()> {
+        while !self.done && (self.dst.len() as u64) < len {
+            self.done = !decode_block(&mut self.src, &mut self.dst)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(mut self) -> MachOResult<Vec<u8>> {
+        self.fill_to(u64::MAX).map_err(MachOErr::IOError)?;
+        Ok(self.dst)
+    }
+}
+
+impl<R: Read> Read for LzfseReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill_to(self.pos + buf.len() as u64)?;
+        let start = (self.pos as usize).min(self.dst.len());
+        let len = buf.len().min(self.dst.len() - start);
+        buf[..len].copy_from_slice(&self.dst[start..start + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read> Seek for LzfseReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => {
+                self.fill_to(u64::MAX)?;
+                (self.dst.len() as u64).checked_add_signed(offset)
+            }
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative offset",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes values LSB first, as the v2 frequency tables expect.
+    fn encode_freqs(freqs: &[u16]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut accum = 0u64;
+        let mut nbits = 0;
+        for &f in freqs {
+            let (code, len) = match f {
+                0 => (0u64, 2),
+                _ => (0xf | ((f as u64 - 24) << 4), 14),
+            };
+            accum |= code << nbits;
+            nbits += len;
+            while nbits >= 8 {
+                out.push(accum as u8);
+                accum >>= 8;
+                nbits -= 8;
+            }
+        }
+        if nbits > 0 {
+            out.push(accum as u8);
+        }
+        out
+    }
+
+    fn v2_block() -> Vec<u8> {
+        // Literals "abab" from two equally likely symbols, then L=4 M=8 D=4.
+        let mut freqs = vec![0u16; L_SYMBOLS + M_SYMBOLS + D_SYMBOLS + LITERAL_SYMBOLS];
+        freqs[4] = L_STATES as u16;
+        freqs[L_SYMBOLS + 8] = M_STATES as u16;
+        freqs[L_SYMBOLS + M_SYMBOLS + 4] = D_STATES as u16;
+        freqs[L_SYMBOLS + M_SYMBOLS + D_SYMBOLS + b'a' as usize] = 512;
+        freqs[L_SYMBOLS + M_SYMBOLS + D_SYMBOLS + b'b' as usize] = 512;
+        let freqs = encode_freqs(&freqs);
+        let header_size = (CompressedBlockHeader::V2_SIZE + freqs.len()) as u64;
+
+        let v0: u64 = 4 | (7 << 20) | (1 << 40) | (7 << 60);
+        let v1: u64 = (512 << 10) | (512 << 30) | (7 << 40) | (7 << 60);
+        let v2: u64 = header_size;
+
+        let mut block = Vec::new();
+        block.extend(COMPRESSEDV2_BLOCK_MAGIC.to_le_bytes());
+        block.extend(12u32.to_le_bytes());
+        block.extend(v0.to_le_bytes());
+        block.extend(v1.to_le_bytes());
+        block.extend(v2.to_le_bytes());
+        block.extend(freqs);
+        block.extend([0u8; 14]);
+        block
+    }
+
+    #[test]
+    fn test_lzfse_v2_block() {
+        let mut stream = v2_block();
+        stream.extend(ENDOFSTREAM_BLOCK_MAGIC.to_le_bytes());
+        assert_eq!(decompress(&stream).unwrap(), b"abababababab");
+    }
+
+    #[test]
+    fn test_lzfse_raw_and_lzvn_blocks() {
+        let mut stream = Vec::new();
+        stream.extend(UNCOMPRESSED_BLOCK_MAGIC.to_le_bytes());
+        stream.extend(3u32.to_le_bytes());
+        stream.extend(b"xyz");
+        // LZVN matches may reach back into earlier blocks.
+        let lzvn = [0x00, 0x03, 0x06, 0, 0, 0, 0, 0, 0, 0];
+        stream.extend(COMPRESSEDLZVN_BLOCK_MAGIC.to_le_bytes());
+        stream.extend(3u32.to_le_bytes());
+        stream.extend((lzvn.len() as u32).to_le_bytes());
+        stream.extend(lzvn);
+        stream.extend(ENDOFSTREAM_BLOCK_MAGIC.to_le_bytes());
+
+        let mut reader = LzfseReader::new(Cursor::new(&stream));
+        let mut head = [0u8; 2];
+        reader.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"xy");
+        assert_eq!(reader.seek(SeekFrom::End(0)).unwrap(), 6);
+        assert_eq!(reader.into_inner().unwrap(), b"xyzxyz");
+    }
+}
@@ -0,0 +1,174 @@
+use nom::{
+    bytes::complete::{tag, take},
+    number::complete::be_u32,
+    IResult,
+};
+
+use crate::macho::{MachOErr, MachOResult};
+
+// Parameters of the Okumura LZSS variant used by Apple's kernelcache/iBoot tooling.
+const N: usize = 4096;
+const F: usize = 18;
+const THRESHOLD: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComplzssHeader {
+    pub adler32: u32,
+    pub uncompressed_size: u32,
+    pub compressed_size: u32,
+    pub reserved: u32,
+}
+
+impl ComplzssHeader {
+    pub const MAGIC: &'static [u8] = b"complzss";
+    // The header is padded out to 0x180 bytes before the compressed data begins.
+    pub const SIZE: usize = 0x180;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], ComplzssHeader> {
+        let (bytes, _) = tag(Self::MAGIC)(bytes)?;
+        let (bytes, adler32) = be_u32(bytes)?;
+        let (bytes, uncompressed_size) = be_u32(bytes)?;
+        let (bytes, compressed_size) = be_u32(bytes)?;
+        let (bytes, reserved) = be_u32(bytes)?;
+        let (bytes, _) = take(Self::SIZE - Self::MAGIC.len() - 16)(bytes)?;
+
+        Ok((
+            bytes,
+            ComplzssHeader {
+                adler32,
+                uncompressed_size,
+                compressed_size,
+                reserved,
+            },
+        ))
+    }
+
+    pub fn is_complzss(bytes: &[u8]) -> bool {
+        bytes.starts_with(Self::MAGIC)
+    }
+}
+
+/// Decompresses a raw LZSS stream, stopping after `size` bytes have been produced or when the
+/// input runs out.
+pub fn decompress(src: &[u8], size: usize) -> Vec<u8> {
+    // `size` comes from the header, and no match expands two bytes into more than 18.
+    let mut dst = Vec::with_capacity(size.min(src.len().saturating_mul(9)));
+    let mut text_buf = [0u8; N + F - 1];
+    text_buf[..N - F].fill(b' ');
+
+    let mut r = N - F;
+    let mut flags: u32 = 0;
+    let mut src = src.iter().copied();
+
+    while dst.len() < size {
+        flags >>= 1;
+        if flags & 0x100 == 0 {
+            let Some(c) = src.next() else { break };
+            flags = c as u32 | 0xff00;
+        }
+
+        if flags & 1 != 0 {
+            let Some(c) = src.next() else { break };
+            dst.push(c);
+            text_buf[r] = c;
+            r = (r + 1) & (N - 1);
+        } else {
+            let (Some(i), Some(j)) = (src.next(), src.next()) else {
+                break;
+            };
+            let offset = i as usize | ((j as usize & 0xf0) << 4);
+            let length = (j as usize & 0x0f) + THRESHOLD;
+            for k in 0..=length {
+                if dst.len() >= size {
+                    break;
+                }
+                let c = text_buf[(offset + k) & (N - 1)];
+                dst.push(c);
+                text_buf[r] = c;
+                r = (r + 1) & (N - 1);
+            }
+        }
+    }
+
+    dst
+}
+
+/// Decompresses a "complzss" blob, verifying the decompressed size and adler32 checksum.
+pub fn decompress_complzss(bytes: &[u8]) -> MachOResult<Vec<u8>> {
+    let (data, header) = ComplzssHeader::parse(bytes)?;
+    let compressed_size = header.compressed_size as usize;
+    if compressed_size > data.len() {
+        return Err(MachOErr::InvalidValue(format!(
+            "complzss compressed size 0x{:x} exceeds available data 0x{:x}",
+            compressed_size,
+            data.len()
+        )));
+    }
+
+    let out = decompress(&data[..compressed_size], header.uncompressed_size as usize);
+    if out.len() != header.uncompressed_size as usize {
+        return Err(MachOErr::ParsingError(format!(
+            "complzss decompressed to 0x{:x} bytes, expected 0x{:x}",
+            out.len(),
+            header.uncompressed_size
+        )));
+    }
+
+    let checksum = adler32(&out);
+    if checksum != header.adler32 {
+        return Err(MachOErr::ParsingError(format!(
+            "complzss adler32 mismatch: 0x{:08x} != 0x{:08x}",
+            checksum, header.adler32
+        )));
+    }
+
+    Ok(out)
+}
+
+pub fn adler32(bytes: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    // 5552 is the largest block for which the sums can't overflow a u32.
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lzss_back_reference() {
+        // Three literals followed by a 6 byte match starting at the initial ring position.
+        let compressed = [0x07, b'a', b'b', b'c', 0xee, 0xf3];
+        assert_eq!(decompress(&compressed, 9), b"abcabcabc");
+    }
+
+    #[test]
+    fn test_complzss() {
+        let compressed = [0x07, b'a', b'b', b'c', 0xee, 0xf3];
+        let mut blob = Vec::new();
+        blob.extend(ComplzssHeader::MAGIC);
+        blob.extend(adler32(b"abcabcabc").to_be_bytes());
+        blob.extend(9u32.to_be_bytes());
+        blob.extend((compressed.len() as u32).to_be_bytes());
+        blob.extend(0u32.to_be_bytes());
+        blob.resize(ComplzssHeader::SIZE, 0);
+        blob.extend(compressed);
+
+        assert_eq!(decompress_complzss(&blob).unwrap(), b"abcabcabc");
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+}
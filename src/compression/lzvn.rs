@@ -0,0 +1,148 @@
+use crate::macho::{MachOErr, MachOResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Opcode {
+    SmallDistance,
+    MediumDistance,
+    LargeDistance,
+    PreviousDistance,
+    SmallMatch,
+    LargeMatch,
+    SmallLiteral,
+    LargeLiteral,
+    Nop,
+    EndOfStream,
+    Undefined,
+}
+
+impl Opcode {
+    fn from_byte(opc: u8) -> Opcode {
+        match opc {
+            0x06 => Opcode::EndOfStream,
+            0x0e | 0x16 => Opcode::Nop,
+            0x70..=0x7f | 0xd0..=0xdf => Opcode::Undefined,
+            0xa0..=0xbf => Opcode::MediumDistance,
+            0xe0 => Opcode::LargeLiteral,
+            0xe1..=0xef => Opcode::SmallLiteral,
+            0xf0 => Opcode::LargeMatch,
+            0xf1..=0xff => Opcode::SmallMatch,
+            // A literal count of zero can't reuse the previous distance.
+            _ if opc & 7 == 6 && opc < 0x40 => Opcode::Undefined,
+            _ if opc & 7 == 6 => Opcode::PreviousDistance,
+            _ if opc & 7 == 7 => Opcode::LargeDistance,
+            _ => Opcode::SmallDistance,
+        }
+    }
+}
+
+fn truncated() -> MachOErr {
+    MachOErr::ParsingError("Truncated LZVN stream".to_string())
+}
+
+/// Decodes an LZVN stream, appending at most `size` bytes to `dst`. Matches may refer back to
+/// anything already in `dst`, which lets LZFSE chain LZVN blocks together.
+pub fn decompress_into(src: &[u8], dst: &mut Vec<u8>, size: usize) -> MachOResult<()> {
+    let end = dst.len() + size;
+    let mut pos = 0;
+    let mut d_prev = 0usize;
+
+    while pos < src.len() {
+        let opc = src[pos];
+        let byte = |i: usize| src.get(pos + i).copied().ok_or_else(truncated);
+
+        let (opc_len, literals, matches, distance) = match Opcode::from_byte(opc) {
+            Opcode::SmallDistance => {
+                let d = ((opc as usize & 7) << 8) | byte(1)? as usize;
+                (2, opc as usize >> 6, ((opc as usize >> 3) & 7) + 3, d)
+            }
+            Opcode::MediumDistance => {
+                let b1 = byte(1)? as usize;
+                let b2 = byte(2)? as usize;
+                let m = (((opc as usize & 7) << 2) | (b1 & 3)) + 3;
+                (3, (opc as usize >> 3) & 3, m, (b1 | (b2 << 8)) >> 2)
+            }
+            Opcode::LargeDistance => {
+                let d = byte(1)? as usize | ((byte(2)? as usize) << 8);
+                (3, opc as usize >> 6, ((opc as usize >> 3) & 7) + 3, d)
+            }
+            Opcode::PreviousDistance => {
+                (1, opc as usize >> 6, ((opc as usize >> 3) & 7) + 3, d_prev)
+            }
+            Opcode::SmallMatch => (1, 0, opc as usize & 0xf, d_prev),
+            Opcode::LargeMatch => (2, 0, byte(1)? as usize + 16, d_prev),
+            Opcode::SmallLiteral => (1, opc as usize & 0xf, 0, d_prev),
+            Opcode::LargeLiteral => (2, byte(1)? as usize + 16, 0, d_prev),
+            Opcode::Nop => (1, 0, 0, d_prev),
+            Opcode::EndOfStream => break,
+            Opcode::Undefined => {
+                return Err(MachOErr::ParsingError(format!(
+                    "Undefined LZVN opcode 0x{:02x}",
+                    opc
+                )))
+            }
+        };
+        pos += opc_len;
+
+        if dst.len() + literals + matches > end {
+            return Err(MachOErr::ParsingError(
+                "LZVN stream decodes past its expected size".to_string(),
+            ));
+        }
+
+        let lits = src.get(pos..pos + literals).ok_or_else(truncated)?;
+        dst.extend_from_slice(lits);
+        pos += literals;
+
+        if matches > 0 {
+            if distance == 0 || distance > dst.len() {
+                return Err(MachOErr::ParsingError(format!(
+                    "Invalid LZVN match distance {}",
+                    distance
+                )));
+            }
+            for _ in 0..matches {
+                dst.push(dst[dst.len() - distance]);
+            }
+        }
+        d_prev = distance;
+    }
+
+    if dst.len() != end {
+        return Err(truncated());
+    }
+    Ok(())
+}
+
+/// Decompresses a raw LZVN stream, such as the chunks APFS/decmpfs tooling emits.
+pub fn decompress(src: &[u8], size: usize) -> MachOResult<Vec<u8>> {
+    let mut dst = Vec::with_capacity(size);
+    decompress_into(src, &mut dst, size)?;
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lzvn() {
+        // sml_d L=3 M=3 D=3, "abc", sml_m M=3 (previous distance), eos.
+        let src = [
+            0xc0, 0x03, b'a', b'b', b'c', 0xf3, 0x06, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        assert_eq!(decompress(&src, 9).unwrap(), b"abcabcabc");
+        assert!(decompress(&src, 8).is_err());
+    }
+
+    #[test]
+    fn test_lzvn_literals_and_large_match() {
+        // lrg_l with 16 literals, lrg_d L=0 M=3 D=16, lrg_m M=16, eos.
+        let mut src = vec![0xe0, 0x00];
+        src.extend(b"0123456789abcdef");
+        src.extend([0x07, 0x10, 0x00, 0xf0, 0x00, 0x06, 0, 0, 0, 0, 0, 0, 0]);
+
+        let out = decompress(&src, 35).unwrap();
+        assert_eq!(&out[..16], b"0123456789abcdef");
+        assert_eq!(&out[16..], b"0123456789abcdef012");
+    }
+}
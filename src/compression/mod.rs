@@ -0,0 +1,35 @@
+pub mod lzfse;
+pub mod lzss;
+pub mod lzvn;
+
+use crate::macho::MachOResult;
+
+use lzss::ComplzssHeader;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionFormat {
+    None,
+    Lzss,
+    Lzfse,
+}
+
+impl CompressionFormat {
+    pub fn detect(bytes: &[u8]) -> CompressionFormat {
+        if ComplzssHeader::is_complzss(bytes) {
+            CompressionFormat::Lzss
+        } else if bytes.starts_with(b"bvx") {
+            CompressionFormat::Lzfse
+        } else {
+            CompressionFormat::None
+        }
+    }
+}
+
+/// Decompresses a payload based on its leading magic. Uncompressed payloads are returned as-is.
+pub fn decompress(bytes: &[u8]) -> MachOResult<Vec<u8>> {
+    match CompressionFormat::detect(bytes) {
+        CompressionFormat::None => Ok(bytes.to_vec()),
+        CompressionFormat::Lzss => lzss::decompress_complzss(bytes),
+        CompressionFormat::Lzfse => lzfse::decompress(bytes),
+    }
+}
@@ -0,0 +1,273 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::command::note::{NotePayload, NoteRegistry};
+use crate::command::segment::Protection;
+use crate::command::LoadCommand;
+use crate::header::MHFileType;
+use crate::machine::ThreadState;
+use crate::macho::{MachO, MachOErr, MachOResult};
+
+/// A region of the process's memory, from one of the core file's `LC_SEGMENT_64` commands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreRegion {
+    pub vmaddr: u64,
+    pub vmsize: u64,
+    pub fileoff: u64,
+    /// Can be less than `vmsize`, in which case the rest of the region is zeros.
+    pub filesize: u64,
+    pub prot: Protection,
+}
+
+/// An `MH_CORE` file: the memory and threads of a process or kernel when it was dumped.
+pub struct CoreFile<T: Read + Seek> {
+    pub macho: MachO<T>,
+}
+
+impl<T: Read + Seek> CoreFile<T> {
+    pub fn parse(buf: T) -> MachOResult<Self> {
+        let macho = MachO::parse(buf)?;
+        if *macho.header.filetype() != MHFileType::MhCore {
+            return Err(MachOErr::InvalidValue("Not a core file".to_string()));
+        }
+        Ok(CoreFile { macho })
+    }
+
+    pub fn regions(&self) -> Vec<CoreRegion> {
+        self.macho
+            .load_commands
+            .iter()
+            .filter_map(|lc| match lc {
+                LoadCommand::Segment64(seg) => Some(CoreRegion {
+                    vmaddr: seg.vmaddr,
+                    vmsize: seg.vmsize,
+                    fileoff: seg.fileoff,
+                    filesize: seg.filesize,
+                    prot: seg.initprot,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The register state of each thread, one `LC_THREAD` per thread.
+    pub fn threads(&self) -> Vec<&[ThreadState]> {
+        self.macho
+            .load_commands
+            .iter()
+            .filter_map(|lc| match lc {
+                LoadCommand::Thread(cmd) => Some(cmd.threads.as_slice()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The payload of every `LC_NOTE`, in load command order.
+    pub fn notes(&mut self) -> MachOResult<Vec<NotePayload>> {
+        self.macho.resolve_notes(&NoteRegistry::new())
+    }
+
+    /// Reads `len` bytes of the process's memory from `vmaddr`, which may span regions as
+    /// long as there are no gaps between them.
+    pub fn read_memory(&mut self, vmaddr: u64, len: u64) -> MachOResult<Vec<u8>> {
+        let regions = self.regions();
+        let overflow =
+            |address: u64| MachOErr::InvalidValue(format!("Reading from {:#x} overflows", address));
+        // The buffer grows as regions are found, so a bogus length fails before it's
+        // allocated.
+        let mut memory = vec![];
+        let mut address = vmaddr;
+        while (memory.len() as u64) < len {
+            let (region, offset) = regions
+                .iter()
+                .find_map(|region| {
+                    let offset = address.checked_sub(region.vmaddr)?;
+                    (offset < region.vmsize).then_some((region, offset))
+                })
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!("{:#x} isn't in the core file", address))
+                })?;
+            let size = (len - memory.len() as u64).min(region.vmsize - offset);
+
+            let in_file = size.min(region.filesize.saturating_sub(offset));
+            let start = memory.len();
+            memory.resize(start + size as usize, 0);
+            if in_file > 0 {
+                let fileoff = region
+                    .fileoff
+                    .checked_add(offset)
+                    .ok_or_else(|| overflow(address))?;
+                self.macho
+                    .buf
+                    .seek(SeekFrom::Start(fileoff))
+                    .map_err(MachOErr::IOError)?;
+                self.macho
+                    .buf
+                    .read_exact(&mut memory[start..start + in_file as usize])
+                    .map_err(MachOErr::IOError)?;
+            }
+            // A region can end at the very top of the address space.
+            address = match address.checked_add(size) {
+                Some(next) => next,
+                None if memory.len() as u64 == len => break,
+                None => return Err(overflow(address)),
+            };
+        }
+        Ok(memory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::command::note::{AddrableBits, AllImageInfos, ImageInfo, MainBinSpec, NoteCommand};
+    use crate::command::thread::ThreadCommand;
+    use crate::command::{LCLoadCommand, LoadCommandParser};
+    use crate::machine::Arm64ThreadState64;
+    use crate::testing::{image64, segment64};
+
+    // A core file with two adjacent regions, the second partly zero filled, one thread and
+    // a few notes. Its contents start at 0x300.
+    fn core_file(thread: &Arm64ThreadState64) -> Vec<u8> {
+        let mut addrable_bits = vec![];
+        for value in [4u32, 47, 39, 0] {
+            addrable_bits.extend(value.to_le_bytes());
+        }
+        let mut main_bin_spec = vec![2, 0, 0, 0, 2, 0, 0, 0];
+        main_bin_spec.extend(0x1000u64.to_le_bytes());
+        main_bin_spec.extend(u64::MAX.to_le_bytes());
+        main_bin_spec.extend([0xaa; 16]);
+        main_bin_spec.extend([14, 0, 0, 0, 1, 0, 0, 0]);
+        // The image infos note starts at 0x370, with its entry at 0x388, its segment at
+        // 0x3b8 and its path at 0x3d8.
+        let mut image_infos = vec![1, 0, 0, 0, 1, 0, 0, 0];
+        image_infos.extend(0x388u64.to_le_bytes());
+        image_infos.extend([48, 0, 0, 0, 0, 0, 0, 0]);
+        image_infos.extend(0x3d8u64.to_le_bytes());
+        image_infos.extend([0; 16]);
+        image_infos.extend(0x1000u64.to_le_bytes());
+        image_infos.extend(0x3b8u64.to_le_bytes());
+        image_infos.extend([1, 0, 0, 0, 0, 0, 0, 0]);
+        image_infos.extend(b"__TEXT\0\0\0\0\0\0\0\0\0\0");
+        image_infos.extend(0x1000u64.to_le_bytes());
+        image_infos.extend(0u64.to_le_bytes());
+        image_infos.extend(b"/bin/cat\0");
+        let notes = [
+            ("addrable bits", addrable_bits),
+            ("main bin spec", main_bin_spec),
+            ("all image infos", image_infos),
+            ("com.example", b"provenance".to_vec()),
+        ];
+
+        let mut cmds = vec![
+            segment64("", 0x1000, 0x20, 0x300, 0x20, vec![]),
+            segment64("", 0x1020, 0x20, 0x320, 0x10, vec![]),
+            ThreadCommand {
+                cmd: LCLoadCommand::LcThread,
+                cmdsize: 288,
+                threads: vec![ThreadState::Arm64State64(thread.clone())],
+            }
+            .serialize(),
+        ];
+        let mut contents: Vec<u8> = (0..0x30).collect();
+        for (data_owner, payload) in notes {
+            cmds.push(
+                NoteCommand {
+                    cmd: LCLoadCommand::LcNote,
+                    cmdsize: 40,
+                    data_owner: data_owner.to_string(),
+                    offset: 0x300 + contents.len() as u64,
+                    size: payload.len() as u64,
+                }
+                .serialize(),
+            );
+            contents.extend(payload);
+        }
+        let mut bytes = image64(MHFileType::MhCore, &cmds);
+        bytes.resize(0x300, 0);
+        bytes.extend(contents);
+        bytes
+    }
+
+    #[test]
+    fn test_core_file() {
+        let thread = Arm64ThreadState64 {
+            x: [7; 29],
+            fp: 0x2000,
+            lr: 0x1004,
+            sp: 0x2010,
+            pc: 0x1008,
+            cpsr: 0x6000_0000,
+        };
+        let mut core = CoreFile::parse(Cursor::new(core_file(&thread))).unwrap();
+
+        assert_eq!(core.regions().len(), 2);
+        assert_eq!(core.regions()[1].filesize, 0x10);
+        assert_eq!(
+            core.threads(),
+            vec![&[ThreadState::Arm64State64(thread)][..]]
+        );
+
+        let mut expected: Vec<u8> = (0x18..0x30).collect();
+        expected.extend([0; 8]);
+        assert_eq!(core.read_memory(0x1018, 0x20).unwrap(), expected);
+        assert!(core.read_memory(0x1030, 0x20).is_err());
+        assert!(core.read_memory(0x1018, u64::MAX).is_err());
+
+        // Move the second region to the top of the address space.
+        let mut bytes = core_file(&thread);
+        bytes[32 + 72 + 24..32 + 72 + 32].copy_from_slice(&0xffff_ffff_ffff_ffe0u64.to_le_bytes());
+        let mut top = CoreFile::parse(Cursor::new(bytes)).unwrap();
+        let mut expected: Vec<u8> = (0x28..0x30).collect();
+        expected.extend([0; 0x10]);
+        assert_eq!(
+            top.read_memory(0xffff_ffff_ffff_ffe8, 0x18).unwrap(),
+            expected
+        );
+        assert!(top.read_memory(0xffff_ffff_ffff_ffe8, 0x19).is_err());
+
+        let notes = core.notes().unwrap();
+        assert_eq!(
+            notes[0],
+            NotePayload::AddrableBits(AddrableBits {
+                version: 4,
+                low_bits: 47,
+                high_bits: 39,
+            })
+        );
+        assert_eq!(
+            notes[1],
+            NotePayload::MainBinSpec(MainBinSpec {
+                version: 2,
+                bin_type: 2,
+                address: Some(0x1000),
+                slide: None,
+                uuid: Some(Uuid::from_bytes([0xaa; 16])),
+                log2_pagesize: 14,
+                platform: 1,
+            })
+        );
+        assert_eq!(
+            notes[2],
+            NotePayload::AllImageInfos(AllImageInfos {
+                version: 1,
+                images: vec![ImageInfo {
+                    path: Some("/bin/cat".to_string()),
+                    uuid: None,
+                    load_address: Some(0x1000),
+                    segments: vec![("__TEXT".to_string(), 0x1000)],
+                }],
+            })
+        );
+        assert_eq!(
+            notes[3],
+            NotePayload::Unknown {
+                data_owner: "com.example".to_string(),
+                data: b"provenance".to_vec(),
+            }
+        );
+    }
+}
@@ -0,0 +1,1113 @@
+use std::collections::HashMap;
+
+use num::FromPrimitive;
+
+use crate::command::dyld_chained_fixup::{
+    DyldChainedFixupsHeader, DyldChainedPtr64BindBF, DyldChainedPtr64RebaseBF,
+    DyldChainedPtrArm64eAuthBind24BF, DyldChainedPtrArm64eAuthBindBF,
+    DyldChainedPtrArm64eAuthRebaseBF, DyldChainedPtrArm64eBind24BF, DyldChainedPtrArm64eBindBF,
+    DyldChainedPtrArm64eRebaseBF, DyldFixupPACKey, DyldImportFormat, DyldPointerFormat,
+    DyldStartsInSegment, DyldSymbolsFormat,
+};
+use crate::command::dyld_exports_trie::DyldExport;
+use crate::command::dyld_info::{
+    BindInstruction, BindSymbolFlags, BindType, DyldInfoCommandResolved, RebaseInstruction,
+    RebaseType,
+};
+use crate::command::{
+    DyldChainedFixupCommand, DyldExportsTrie, LCLoadCommand, LinkeditDataCommand, LoadCommand,
+};
+use crate::helpers::string_upto_null_terminator;
+use crate::macho::{MachOErr, MachOResult};
+
+use super::layout::{LinkeditKind, MachOImage};
+
+// The library ordinal binds to weak definitions use, BIND_SPECIAL_DYLIB_WEAK_LOOKUP.
+const WEAK_LOOKUP_ORDINAL: u8 = 0xfd;
+
+/// How an arm64e pointer is signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerAuth {
+    pub key: DyldFixupPACKey,
+    pub diversity: u16,
+    pub addr_div: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixupTarget {
+    /// A pointer to `target`, an unslid address in the image. `high8` is the top byte of
+    /// the pointer, which dyld puts back after sliding it.
+    Rebase { target: u64, high8: u8 },
+    /// A pointer to an imported symbol. `library_ordinal` is a dylib ordinal, or a special
+    /// ordinal kept as its low byte (0xff for the main executable, 0xfe for flat lookup and
+    /// 0xfd for weak lookup).
+    Bind {
+        library_ordinal: u8,
+        name: String,
+        weak_import: bool,
+        addend: i64,
+    },
+}
+
+/// A pointer dyld has to fix up, independent of how it's encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainedFixup {
+    /// The unslid address of the pointer.
+    pub address: u64,
+    pub target: FixupTarget,
+    /// Only arm64e pointers can be signed.
+    pub auth: Option<PointerAuth>,
+}
+
+// Where a segment is mapped and how much of it has file contents.
+struct FixupSegment {
+    vmaddr: u64,
+    vmsize: u64,
+    filesize: u64,
+}
+
+fn out_of_range(what: &str, value: impl std::fmt::Display, fixup: &ChainedFixup) -> MachOErr {
+    MachOErr::InvalidValue(format!(
+        "The {} {} of the fixup at {:#x} doesn't fit its pointer format",
+        what, value, fixup.address
+    ))
+}
+
+fn fits(value: u64, bits: u32) -> bool {
+    value >> bits == 0
+}
+
+// Whether a bind's addend fits in the pointer itself.
+fn inline_addend_fits(format: DyldPointerFormat, fixup: &ChainedFixup) -> bool {
+    let FixupTarget::Bind { addend, .. } = &fixup.target else {
+        return true;
+    };
+    match (format, &fixup.auth) {
+        (_, Some(_)) => *addend == 0,
+        (DyldPointerFormat::Ptr64 | DyldPointerFormat::Ptr64Offset, None) => {
+            (0..=0xff).contains(addend)
+        }
+        (_, None) => (-(1 << 18)..1 << 18).contains(addend),
+    }
+}
+
+// Encodes a single pointer. `import` is the index of a bind's import and `next` the distance
+// to the next pointer in the chain in strides.
+fn encode_pointer(
+    format: DyldPointerFormat,
+    base: u64,
+    fixup: &ChainedFixup,
+    import: u32,
+    next: u64,
+) -> MachOResult<u64> {
+    let arm64e = matches!(
+        format,
+        DyldPointerFormat::Arm64e | DyldPointerFormat::Arm64eUserland
+    );
+    let offset_targets = matches!(
+        format,
+        DyldPointerFormat::Ptr64Offset | DyldPointerFormat::Arm64eUserland
+    );
+    let runtime_offset = |target: u64| {
+        target
+            .checked_sub(base)
+            .ok_or_else(|| out_of_range("target", format!("{:#x}", target), fixup))
+    };
+
+    let raw = match (&fixup.target, &fixup.auth, arm64e) {
+        (_, Some(_), false) => {
+            return Err(MachOErr::InvalidValue(format!(
+                "The fixup at {:#x} is signed, which only arm64e pointers can be",
+                fixup.address
+            )))
+        }
+        (FixupTarget::Rebase { target, high8 }, None, false) => {
+            let target = if offset_targets {
+                runtime_offset(*target)?
+            } else {
+                *target
+            };
+            if !fits(target, 36) {
+                return Err(out_of_range("target", format!("{:#x}", target), fixup));
+            }
+            let mut bf = DyldChainedPtr64RebaseBF(0);
+            bf.set_target(target);
+            bf.set_high8(*high8 as u64);
+            bf.set_next(next);
+            bf.0
+        }
+        (FixupTarget::Bind { addend, .. }, None, false) => {
+            if !(0..=0xff).contains(addend) {
+                return Err(out_of_range("addend", addend, fixup));
+            }
+            if !fits(import as u64, 24) {
+                return Err(out_of_range("import", import, fixup));
+            }
+            let mut bf = DyldChainedPtr64BindBF(0);
+            bf.set_ordinal(import as u64);
+            bf.set_addend(*addend as u64);
+            bf.set_next(next);
+            bf.set_bind(true);
+            bf.0
+        }
+        (FixupTarget::Rebase { target, high8 }, None, true) => {
+            let target = if offset_targets {
+                runtime_offset(*target)?
+            } else {
+                *target
+            };
+            if !fits(target, 43) {
+                return Err(out_of_range("target", format!("{:#x}", target), fixup));
+            }
+            let mut bf = DyldChainedPtrArm64eRebaseBF(0);
+            bf.set_target(target);
+            bf.set_high8(*high8 as u64);
+            bf.set_next(next);
+            bf.0
+        }
+        (FixupTarget::Rebase { target, .. }, Some(auth), true) => {
+            // Signed pointers always hold an offset from the start of the image.
+            let target = runtime_offset(*target)?;
+            if !fits(target, 32) {
+                return Err(out_of_range("target", format!("{:#x}", target), fixup));
+            }
+            let mut bf = DyldChainedPtrArm64eAuthRebaseBF(0);
+            bf.set_target(target);
+            bf.set_diversity(auth.diversity as u64);
+            bf.set_addr_div(auth.addr_div);
+            bf.set_key(auth.key.clone() as u64);
+            bf.set_next(next);
+            bf.set_auth(true);
+            bf.0
+        }
+        (FixupTarget::Bind { addend, .. }, None, true) => {
+            if !(-(1 << 18)..1 << 18).contains(addend) {
+                return Err(out_of_range("addend", addend, fixup));
+            }
+            if !fits(import as u64, 16) {
+                return Err(out_of_range("import", import, fixup));
+            }
+            let mut bf = DyldChainedPtrArm64eBindBF(0);
+            bf.set_ordinal(import as u64);
+            bf.set_addend(*addend as u64 & 0x7ffff);
+            bf.set_next(next);
+            bf.set_bind(true);
+            bf.0
+        }
+        (FixupTarget::Bind { addend, .. }, Some(auth), true) => {
+            if *addend != 0 {
+                return Err(out_of_range("addend", addend, fixup));
+            }
+            if !fits(import as u64, 16) {
+                return Err(out_of_range("import", import, fixup));
+            }
+            let mut bf = DyldChainedPtrArm64eAuthBindBF(0);
+            bf.set_ordinal(import as u64);
+            bf.set_diversity(auth.diversity as u64);
+            bf.set_addr_div(auth.addr_div);
+            bf.set_key(auth.key.clone() as u64);
+            bf.set_next(next);
+            bf.set_bind(true);
+            bf.set_auth(true);
+            bf.0
+        }
+    };
+    Ok(raw)
+}
+
+// Decodes a single pointer into its target, signing and the distance to the next pointer
+// in the chain in strides. `imports` are each import's library ordinal, name, weak flag and
+// addend.
+fn decode_pointer(
+    format: DyldPointerFormat,
+    base: u64,
+    raw: u64,
+    imports: &[(u8, String, bool, i64)],
+) -> MachOResult<(FixupTarget, Option<PointerAuth>, u64)> {
+    let bind = |import: u64, addend: i64| {
+        let (library_ordinal, name, weak_import, import_addend) =
+            imports.get(import as usize).ok_or_else(|| {
+                MachOErr::InvalidValue(format!("There's no chained fixup import {}", import))
+            })?;
+        Ok::<_, MachOErr>(FixupTarget::Bind {
+            library_ordinal: *library_ordinal,
+            name: name.clone(),
+            weak_import: *weak_import,
+            addend: import_addend + addend,
+        })
+    };
+    let auth = |key: u64, diversity: u64, addr_div: bool| {
+        Some(PointerAuth {
+            key: DyldFixupPACKey::from_u64(key).unwrap(),
+            diversity: diversity as u16,
+            addr_div,
+        })
+    };
+
+    let is_bind = raw >> 62 & 1 == 1;
+    let is_auth = raw >> 63 == 1;
+    Ok(match format {
+        DyldPointerFormat::Ptr64 | DyldPointerFormat::Ptr64Offset
+            if DyldChainedPtr64BindBF(raw).bind() =>
+        {
+            let bf = DyldChainedPtr64BindBF(raw);
+            (bind(bf.ordinal(), bf.addend() as i64)?, None, bf.next())
+        }
+        DyldPointerFormat::Ptr64 | DyldPointerFormat::Ptr64Offset => {
+            let bf = DyldChainedPtr64RebaseBF(raw);
+            let target = if format == DyldPointerFormat::Ptr64Offset {
+                base + bf.target()
+            } else {
+                bf.target()
+            };
+            let high8 = bf.high8() as u8;
+            (FixupTarget::Rebase { target, high8 }, None, bf.next())
+        }
+        DyldPointerFormat::Arm64e
+        | DyldPointerFormat::Arm64eUserland
+        | DyldPointerFormat::Arm64eUserland24 => match (is_bind, is_auth) {
+            (false, false) => {
+                let bf = DyldChainedPtrArm64eRebaseBF(raw);
+                let target = if format == DyldPointerFormat::Arm64e {
+                    bf.target()
+                } else {
+                    base + bf.target()
+                };
+                let high8 = bf.high8() as u8;
+                (FixupTarget::Rebase { target, high8 }, None, bf.next())
+            }
+            (false, true) => {
+                let bf = DyldChainedPtrArm64eAuthRebaseBF(raw);
+                let target = FixupTarget::Rebase {
+                    target: base + bf.target(),
+                    high8: 0,
+                };
+                let auth = auth(bf.key(), bf.diversity(), bf.addr_div());
+                (target, auth, bf.next())
+            }
+            (true, false) => {
+                // The addend is a signed 19-bit number.
+                let bf = DyldChainedPtrArm64eBindBF(raw);
+                let addend = ((bf.addend() << 45) as i64) >> 45;
+                let import = if format == DyldPointerFormat::Arm64eUserland24 {
+                    DyldChainedPtrArm64eBind24BF(raw).ordinal()
+                } else {
+                    bf.ordinal()
+                };
+                (bind(import, addend)?, None, bf.next())
+            }
+            (true, true) => {
+                let bf = DyldChainedPtrArm64eAuthBindBF(raw);
+                let import = if format == DyldPointerFormat::Arm64eUserland24 {
+                    DyldChainedPtrArm64eAuthBind24BF(raw).ordinal()
+                } else {
+                    bf.ordinal()
+                };
+                let auth = auth(bf.key(), bf.diversity(), bf.addr_div());
+                (bind(import, 0)?, auth, bf.next())
+            }
+        },
+        _ => {
+            return Err(MachOErr::InvalidValue(format!(
+                "Can't read {:?} chained fixups",
+                format
+            )))
+        }
+    })
+}
+
+impl MachOImage {
+    fn fixup_segments(&self) -> MachOResult<Vec<FixupSegment>> {
+        self.commands()
+            .load_commands()
+            .filter_map(|cmd| match cmd {
+                LoadCommand::Segment64(seg) => Some(Ok(FixupSegment {
+                    vmaddr: seg.vmaddr,
+                    vmsize: seg.vmsize,
+                    filesize: seg.filesize,
+                })),
+                LoadCommand::Segment32(_) => Some(Err(MachOErr::InvalidValue(
+                    "Chained fixups can only be written for 64-bit images".to_string(),
+                ))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // The address the image is loaded at, which is where its header is mapped.
+    fn image_base(&self) -> u64 {
+        self.commands()
+            .load_commands()
+            .find_map(|cmd| match cmd {
+                LoadCommand::Segment64(seg) if seg.fileoff == 0 && seg.filesize > 0 => {
+                    Some(seg.vmaddr)
+                }
+                _ => None,
+            })
+            .unwrap_or(0)
+    }
+
+    /// Encodes `fixups` as pointer chains in the segment contents and points
+    /// `LC_DYLD_CHAINED_FIXUPS` at the starts and imports that describe them, adding the
+    /// command if there isn't one. The 64-bit, 64-bit offset and arm64e formats are supported.
+    /// Bind addends too large for the pointers are kept in the imports instead.
+    pub fn set_chained_fixups(
+        &mut self,
+        format: DyldPointerFormat,
+        fixups: &[ChainedFixup],
+    ) -> MachOResult<()> {
+        let stride = match format {
+            DyldPointerFormat::Ptr64 | DyldPointerFormat::Ptr64Offset => 4,
+            DyldPointerFormat::Arm64e | DyldPointerFormat::Arm64eUserland => 8,
+            _ => {
+                return Err(MachOErr::InvalidValue(format!(
+                    "Can't write {:?} chained fixups",
+                    format
+                )))
+            }
+        };
+        // Ptr64 chains have 12 bits for the next pointer, arm64e ones 11.
+        let max_next = if stride == 4 { 0xfff } else { 0x7ff };
+        if self
+            .commands()
+            .load_commands()
+            .any(|cmd| matches!(cmd, LoadCommand::DyldInfo(_) | LoadCommand::DyldInfoOnly(_)))
+        {
+            return Err(MachOErr::InvalidValue(
+                "The image already has dyld info, convert it to chained fixups instead".to_string(),
+            ));
+        }
+
+        let segments = self.fixup_segments()?;
+        let base = self.image_base();
+        let page_size = self.commands().page_size();
+
+        let mut sorted: Vec<&ChainedFixup> = fixups.iter().collect();
+        sorted.sort_by_key(|fixup| fixup.address);
+        if let Some(pair) = sorted
+            .windows(2)
+            .find(|pair| pair[1].address - pair[0].address < 8)
+        {
+            return Err(MachOErr::InvalidValue(format!(
+                "The fixups at {:#x} and {:#x} overlap",
+                pair[0].address, pair[1].address
+            )));
+        }
+
+        // Addends that don't fit in the pointers go in the imports instead, as ld64 does.
+        let imports_format = if sorted.iter().all(|fixup| inline_addend_fits(format, fixup)) {
+            DyldImportFormat::Import
+        } else if sorted.iter().all(|fixup| match &fixup.target {
+            FixupTarget::Bind { addend, .. } => i32::try_from(*addend).is_ok(),
+            FixupTarget::Rebase { .. } => true,
+        }) {
+            DyldImportFormat::ImportAddend
+        } else {
+            DyldImportFormat::ImportAddend64
+        };
+
+        // Imports in order of first use, one per distinct symbol and addend.
+        let mut imports: Vec<(u8, &str, bool, i64)> = vec![];
+        let mut import_indices = HashMap::new();
+        // The fixups of each segment, with their imports.
+        let mut by_segment: Vec<Vec<(&ChainedFixup, u32)>> =
+            segments.iter().map(|_| vec![]).collect();
+        for fixup in sorted {
+            let segment = segments
+                .iter()
+                .position(|seg| {
+                    // Only contents that are mapped have page starts.
+                    let end = seg.vmaddr.saturating_add(seg.filesize.min(seg.vmsize));
+                    seg.vmaddr <= fixup.address && fixup.address.saturating_add(8) <= end
+                })
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!(
+                        "The fixup at {:#x} isn't in a segment's mapped file contents",
+                        fixup.address
+                    ))
+                })?;
+            if (fixup.address - segments[segment].vmaddr) % stride != 0 {
+                return Err(MachOErr::InvalidValue(format!(
+                    "The fixup at {:#x} isn't {} byte aligned",
+                    fixup.address, stride
+                )));
+            }
+            let import = match &fixup.target {
+                FixupTarget::Rebase { .. } => 0,
+                FixupTarget::Bind {
+                    library_ordinal,
+                    name,
+                    weak_import,
+                    addend,
+                } => {
+                    let addend = if imports_format == DyldImportFormat::Import {
+                        0
+                    } else {
+                        *addend
+                    };
+                    let key = (*library_ordinal, name.as_str(), *weak_import, addend);
+                    *import_indices.entry(key).or_insert_with(|| {
+                        imports.push(key);
+                        imports.len() as u32 - 1
+                    })
+                }
+            };
+            by_segment[segment].push((fixup, import));
+        }
+
+        // Chain each page's pointers together and record where each chain starts.
+        let mut starts = vec![];
+        for (index, (segment, fixups)) in segments.iter().zip(&by_segment).enumerate() {
+            if fixups.is_empty() {
+                starts.push(None);
+                continue;
+            }
+            let page_count = segment.vmsize.div_ceil(page_size) as usize;
+            let mut page_starts =
+                vec![DyldStartsInSegment::DYLD_CHAINED_PTR_START_NONE; page_count];
+            for (i, (fixup, import)) in fixups.iter().enumerate() {
+                let offset = fixup.address - segment.vmaddr;
+                let page = (offset / page_size) as usize;
+                if i == 0 || (fixups[i - 1].0.address - segment.vmaddr) / page_size != page as u64 {
+                    page_starts[page] = (offset % page_size) as u16;
+                }
+                let next = match fixups.get(i + 1) {
+                    Some((next, _))
+                        if (next.address - segment.vmaddr) / page_size == page as u64 =>
+                    {
+                        let next = (next.address - fixup.address) / stride;
+                        if next > max_next {
+                            return Err(MachOErr::InvalidValue(format!(
+                                "The fixups at {:#x} and {:#x} are too far apart to chain",
+                                fixup.address,
+                                fixups[i + 1].0.address
+                            )));
+                        }
+                        next
+                    }
+                    _ => 0,
+                };
+                let raw = match &fixup.target {
+                    // The import carries the addend.
+                    FixupTarget::Bind { .. } if imports_format != DyldImportFormat::Import => {
+                        let mut fixup = (*fixup).clone();
+                        if let FixupTarget::Bind { addend, .. } = &mut fixup.target {
+                            *addend = 0;
+                        }
+                        encode_pointer(format, base, &fixup, *import, next)?
+                    }
+                    _ => encode_pointer(format, base, fixup, *import, next)?,
+                };
+                let data = &mut self.segments[index].data;
+                let offset = offset as usize;
+                if data.len() < offset + 8 {
+                    data.resize(offset + 8, 0);
+                }
+                data[offset..offset + 8].copy_from_slice(&raw.to_le_bytes());
+            }
+            starts.push(Some((segment.vmaddr - base, page_starts)));
+        }
+
+        let mut blob = vec![0u8; 32];
+        let starts_offset = blob.len();
+        blob.extend((starts.len() as u32).to_le_bytes());
+        blob.resize(blob.len() + 4 * starts.len(), 0);
+        for (i, start) in starts.iter().enumerate() {
+            let Some((segment_offset, page_starts)) = start else {
+                continue;
+            };
+            blob.resize(blob.len().div_ceil(8) * 8, 0);
+            let offset = (blob.len() - starts_offset) as u32;
+            let entry = starts_offset + 4 + 4 * i;
+            blob[entry..entry + 4].copy_from_slice(&offset.to_le_bytes());
+            blob.extend((22 + 2 * page_starts.len() as u32).to_le_bytes());
+            blob.extend((page_size as u16).to_le_bytes());
+            blob.extend((format as u16).to_le_bytes());
+            blob.extend(segment_offset.to_le_bytes());
+            // max_valid_pointer only applies to 32-bit formats.
+            blob.extend(0u32.to_le_bytes());
+            blob.extend((page_starts.len() as u16).to_le_bytes());
+            for start in page_starts {
+                blob.extend(start.to_le_bytes());
+            }
+        }
+
+        let import_align = match imports_format {
+            DyldImportFormat::ImportAddend64 => 8,
+            _ => 4,
+        };
+        blob.resize(blob.len().div_ceil(import_align) * import_align, 0);
+        let imports_offset = blob.len();
+        let mut symbols: Vec<u8> = vec![];
+        let mut name_offsets = HashMap::new();
+        for (library_ordinal, name, weak_import, addend) in &imports {
+            let name_offset = *name_offsets.entry(*name).or_insert_with(|| {
+                let offset = symbols.len() as u32;
+                symbols.extend(name.as_bytes());
+                symbols.push(0);
+                offset
+            });
+            if imports_format == DyldImportFormat::ImportAddend64 {
+                // Special ordinals are negative 16-bit numbers here.
+                let ordinal = match *library_ordinal {
+                    ordinal @ 0xf0.. => 0xff00 | ordinal as u64,
+                    ordinal => ordinal as u64,
+                };
+                let import = ordinal | (*weak_import as u64) << 16 | (name_offset as u64) << 32;
+                blob.extend(import.to_le_bytes());
+                blob.extend(addend.to_le_bytes());
+                continue;
+            }
+            if !fits(name_offset as u64, 23) {
+                return Err(MachOErr::InvalidValue(
+                    "The chained fixup symbol names don't fit in 8MB".to_string(),
+                ));
+            }
+            let import = *library_ordinal as u32 | (*weak_import as u32) << 8 | name_offset << 9;
+            blob.extend(import.to_le_bytes());
+            if imports_format == DyldImportFormat::ImportAddend {
+                blob.extend((*addend as i32).to_le_bytes());
+            }
+        }
+        let symbols_offset = blob.len();
+        blob.extend(symbols);
+        blob.resize(blob.len().div_ceil(8) * 8, 0);
+
+        let header = [
+            0,
+            starts_offset as u32,
+            imports_offset as u32,
+            symbols_offset as u32,
+            imports.len() as u32,
+            imports_format as u32,
+            DyldSymbolsFormat::Uncompressed as u32,
+        ];
+        for (i, field) in header.iter().enumerate() {
+            blob[4 * i..4 * i + 4].copy_from_slice(&field.to_le_bytes());
+        }
+
+        if !self
+            .commands()
+            .load_commands()
+            .any(|cmd| matches!(cmd, LoadCommand::DyldChainedFixups(_)))
+        {
+            let index = self
+                .commands()
+                .load_commands()
+                .enumerate()
+                .filter(|(_, cmd)| matches!(cmd, LoadCommand::Segment64(_)))
+                .map(|(index, _)| index + 1)
+                .last()
+                .unwrap_or(0);
+            self.commands_mut()
+                .insert(index, chained_fixups_command())?;
+        }
+        self.set_linkedit_data(LinkeditKind::ChainedFixups, blob);
+        Ok(())
+    }
+
+    /// The fixups `info` describes, with lazy binds made eager and pointers the weak bind
+    /// stream overrides looked up by name. Rebase targets are read from the segment contents.
+    pub fn chained_fixups_from_dyld_info(
+        &self,
+        info: &DyldInfoCommandResolved,
+    ) -> MachOResult<Vec<ChainedFixup>> {
+        let segments = self.fixup_segments()?;
+        let address = |segment_index: u8, segment_offset: u64| {
+            segments
+                .get(segment_index as usize)
+                .map(|seg| seg.vmaddr + segment_offset)
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!("There's no segment {}", segment_index))
+                })
+        };
+
+        let mut fixups: HashMap<u64, FixupTarget> = HashMap::new();
+        for rebase in &info.rebase_instructions {
+            let RebaseInstruction {
+                segment_index,
+                segment_offset,
+                rebase_type,
+            } = rebase;
+            if *rebase_type != RebaseType::Pointer {
+                return Err(MachOErr::InvalidValue(format!(
+                    "{:?} rebases have no chained fixup equivalent",
+                    rebase_type
+                )));
+            }
+            let offset = *segment_offset as usize;
+            let value = self
+                .segments
+                .get(*segment_index as usize)
+                .and_then(|seg| seg.data.get(offset..offset + 8))
+                .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!(
+                        "The rebase at {:#x} in segment {} is outside its contents",
+                        segment_offset, segment_index
+                    ))
+                })?;
+            fixups.insert(
+                address(*segment_index, *segment_offset)?,
+                FixupTarget::Rebase {
+                    target: value & 0x00ff_ffff_ffff_ffff,
+                    high8: (value >> 56) as u8,
+                },
+            );
+        }
+
+        let binds = info.bind_instructions.iter().chain(&info.lazy_instructions);
+        let weak_binds = info.weak_instructions.iter().map(|bind| (bind, true));
+        for (bind, weak) in binds.map(|bind| (bind, false)).chain(weak_binds) {
+            let BindInstruction {
+                segment_index,
+                segment_offset,
+                bind_type,
+                dylib_ordinal,
+                symbol_name,
+                symbol_flags,
+                addend,
+            } = bind;
+            if *bind_type != BindType::Pointer {
+                return Err(MachOErr::InvalidValue(format!(
+                    "{:?} binds have no chained fixup equivalent",
+                    bind_type
+                )));
+            }
+            fixups.insert(
+                address(*segment_index, *segment_offset)?,
+                FixupTarget::Bind {
+                    library_ordinal: if weak {
+                        WEAK_LOOKUP_ORDINAL
+                    } else {
+                        *dylib_ordinal
+                    },
+                    name: symbol_name.clone(),
+                    weak_import: symbol_flags & BindSymbolFlags::WeakImport as u8 != 0,
+                    addend: *addend,
+                },
+            );
+        }
+
+        let mut fixups: Vec<ChainedFixup> = fixups
+            .into_iter()
+            .map(|(address, target)| ChainedFixup {
+                address,
+                target,
+                auth: None,
+            })
+            .collect();
+        fixups.sort_by_key(|fixup| fixup.address);
+        Ok(fixups)
+    }
+
+    // The rebases, binds and exports in the dyld info blobs.
+    pub(super) fn dyld_info(&self) -> MachOResult<DyldInfoCommandResolved> {
+        let blob = |kind| self.linkedit_data(kind).unwrap_or_default();
+        let binds = |kind| BindInstruction::parse(blob(kind)).map(|(_, binds)| binds);
+        let lazy_binds = BindInstruction::parse_lazy(blob(LinkeditKind::LazyBind))?.1;
+        let (weak_binds, strong_definitions) =
+            BindInstruction::parse_weak(blob(LinkeditKind::WeakBind))?.1;
+        Ok(DyldInfoCommandResolved {
+            rebase_instructions: RebaseInstruction::parse(blob(LinkeditKind::Rebase))?.1,
+            bind_instructions: binds(LinkeditKind::Bind)?,
+            weak_instructions: weak_binds,
+            strong_definitions,
+            lazy_instructions: lazy_binds,
+            exports: match blob(LinkeditKind::Export) {
+                [] => vec![],
+                exports => DyldExport::parse(exports)?,
+            },
+        })
+    }
+
+    /// The fixups encoded in the image's pointer chains, the inverse of `set_chained_fixups`.
+    /// Chains in the 64-bit, 64-bit offset and arm64e formats can be read.
+    pub fn chained_fixups(&self) -> MachOResult<Vec<ChainedFixup>> {
+        let blob = match self.linkedit_data(LinkeditKind::ChainedFixups) {
+            Some(blob) => blob,
+            None => return Ok(vec![]),
+        };
+        let truncated = || MachOErr::InvalidValue("The chained fixups are truncated".to_string());
+        let read = |offset: usize, size: usize| {
+            blob.get(offset..offset + size)
+                .map(|bytes| {
+                    bytes
+                        .iter()
+                        .rev()
+                        .fold(0u64, |value, byte| value << 8 | *byte as u64)
+                })
+                .ok_or_else(truncated)
+        };
+
+        let (_, header) = DyldChainedFixupsHeader::parse(blob)?;
+        if header.symbols_format != DyldSymbolsFormat::Uncompressed {
+            return Err(MachOErr::InvalidValue(
+                "Compressed chained fixup symbols aren't supported".to_string(),
+            ));
+        }
+        let mut imports = vec![];
+        let mut offset = header.imports_offset as usize;
+        for _ in 0..header.imports_count {
+            let (ordinal, weak, name_offset, addend) = match header.imports_format {
+                DyldImportFormat::Import | DyldImportFormat::ImportAddend => {
+                    let raw = read(offset, 4)?;
+                    let addend = if header.imports_format == DyldImportFormat::ImportAddend {
+                        offset += 4;
+                        read(offset, 4)? as u32 as i32 as i64
+                    } else {
+                        0
+                    };
+                    offset += 4;
+                    (raw & 0xff, raw >> 8 & 1, raw >> 9, addend)
+                }
+                DyldImportFormat::ImportAddend64 => {
+                    let raw = read(offset, 8)?;
+                    let addend = read(offset + 8, 8)? as i64;
+                    offset += 16;
+                    // Special ordinals are negative 16-bit numbers here.
+                    let ordinal = match raw & 0xffff {
+                        ordinal @ (0..=0xff | 0xfff0..) => ordinal & 0xff,
+                        ordinal => {
+                            return Err(MachOErr::InvalidValue(format!(
+                                "Library ordinal {} is out of range",
+                                ordinal
+                            )))
+                        }
+                    };
+                    (ordinal, raw >> 16 & 1, raw >> 32, addend)
+                }
+            };
+            let name = blob
+                .get((header.symbols_offset as u64 + name_offset) as usize..)
+                .and_then(|bytes| string_upto_null_terminator(bytes).ok())
+                .ok_or_else(truncated)?
+                .1;
+            imports.push((ordinal as u8, name, weak != 0, addend));
+        }
+
+        let segments = self.fixup_segments()?;
+        let base = self.image_base();
+        let starts = header.starts_offset as usize;
+        let mut fixups = vec![];
+        for (index, segment) in segments.iter().enumerate().take(read(starts, 4)? as usize) {
+            let info_offset = read(starts + 4 + index * 4, 4)? as usize;
+            if info_offset == 0 {
+                continue;
+            }
+            let seg_starts = blob.get(starts + info_offset..).ok_or_else(truncated)?;
+            let (_, seg_starts) = DyldStartsInSegment::parse(seg_starts)?;
+            let format = seg_starts.pointer_format;
+            let data = &self.segments[index].data;
+            for (page, &page_start) in seg_starts.page_start.iter().enumerate() {
+                if page_start == DyldStartsInSegment::DYLD_CHAINED_PTR_START_NONE {
+                    continue;
+                }
+                let mut offset = page * seg_starts.page_size as usize + page_start as usize;
+                loop {
+                    let raw = data
+                        .get(offset..offset + 8)
+                        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                        .ok_or_else(|| {
+                            MachOErr::InvalidValue(format!(
+                                "The fixup chain in {} runs past its contents",
+                                self.segments[index].name
+                            ))
+                        })?;
+                    let (target, auth, next) = decode_pointer(format, base, raw, &imports)?;
+                    fixups.push(ChainedFixup {
+                        address: segment.vmaddr + offset as u64,
+                        target,
+                        auth,
+                    });
+                    if next == 0 {
+                        break;
+                    }
+                    offset += (next * format.stride()) as usize;
+                }
+            }
+        }
+        Ok(fixups)
+    }
+
+    /// Replaces `LC_DYLD_INFO_ONLY` with `LC_DYLD_CHAINED_FIXUPS` and `LC_DYLD_EXPORTS_TRIE`,
+    /// rewriting every rebase and bind as a chained pointer in `format`.
+    pub fn convert_to_chained_fixups(&mut self, format: DyldPointerFormat) -> MachOResult<()> {
+        let index = self
+            .commands()
+            .position(|cmd| matches!(cmd, LoadCommand::DyldInfo(_) | LoadCommand::DyldInfoOnly(_)))
+            .ok_or_else(|| MachOErr::InvalidValue("The image has no dyld info".to_string()))?;
+
+        let fixups = self.chained_fixups_from_dyld_info(&self.dyld_info()?)?;
+        let exports = self
+            .linkedit_data(LinkeditKind::Export)
+            .unwrap_or_default()
+            .to_vec();
+
+        // The new tables take the place of the old ones in __LINKEDIT.
+        self.linkedit.retain(|blob| {
+            !matches!(
+                blob.kind,
+                LinkeditKind::Bind | LinkeditKind::WeakBind | LinkeditKind::LazyBind
+            )
+        });
+        for blob in &mut self.linkedit {
+            match blob.kind {
+                LinkeditKind::Rebase => blob.kind = LinkeditKind::ChainedFixups,
+                LinkeditKind::Export => blob.kind = LinkeditKind::ExportsTrie,
+                _ => {}
+            }
+        }
+
+        self.commands_mut().remove(index)?;
+        self.commands_mut()
+            .insert(index, chained_fixups_command())?;
+        if !exports.is_empty() {
+            let exports_trie = LoadCommand::DyldExportsTrie(DyldExportsTrie {
+                cmd: LinkeditDataCommand {
+                    cmd: LCLoadCommand::LcDyldExportsTrie,
+                    cmdsize: 16,
+                    dataoff: 0,
+                    datasize: 0,
+                },
+            });
+            self.commands_mut().insert(index + 1, exports_trie)?;
+        }
+        self.set_chained_fixups(format, &fixups)
+    }
+}
+
+fn chained_fixups_command() -> LoadCommand {
+    LoadCommand::DyldChainedFixups(DyldChainedFixupCommand {
+        cmd: LinkeditDataCommand {
+            cmd: LCLoadCommand::LcDyldChainedFixups,
+            cmdsize: 16,
+            dataoff: 0,
+            datasize: 0,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::command::dyld_chained_fixup::DyldPointerFixup;
+    use crate::command::dyld_exports_trie::DyldExportSymbolFlags;
+    use crate::command::dyld_info::DyldInfoCommand;
+    use crate::command::LoadCommandParser;
+    use crate::header::MHFileType;
+    use crate::macho::MachO;
+    use crate::testing::{dyld_info, image64, segment64};
+
+    // An arm64 executable with two pages of __DATA. Its dyld info rebases the first two
+    // pointers, binds _printf after them and lazily binds _maybe on the second page.
+    fn image() -> Vec<u8> {
+        let rebases = b"\x11\x21\x00\x52\x00".to_vec();
+        let binds = b"\x11\x40_printf\0\x51\x71\x10\x90\x00".to_vec();
+        let lazy_binds = b"\x71\x80\x80\x01\x3e\x41_maybe\0\x90\x00".to_vec();
+        let exports = DyldExport::build_trie(&[DyldExport {
+            flags: DyldExportSymbolFlags::empty(),
+            address: 0x3f00,
+            name: "_main".to_string(),
+            ordinal: None,
+            import_name: None,
+        }])
+        .unwrap();
+        let linkedit = [rebases, binds, lazy_binds, exports]
+            .into_iter()
+            .map(|mut blob| {
+                blob.resize(blob.len().div_ceil(8) * 8, 0);
+                blob
+            })
+            .collect::<Vec<_>>();
+        let offsets: Vec<u32> = linkedit
+            .iter()
+            .scan(0xc000, |offset, blob| {
+                *offset += blob.len() as u32;
+                Some(*offset - blob.len() as u32)
+            })
+            .collect();
+        let dyld_info = DyldInfoCommand {
+            rebase_off: offsets[0],
+            rebase_size: linkedit[0].len() as u32,
+            bind_off: offsets[1],
+            bind_size: linkedit[1].len() as u32,
+            lazy_bind_off: offsets[2],
+            lazy_bind_size: linkedit[2].len() as u32,
+            export_off: offsets[3],
+            export_size: linkedit[3].len() as u32,
+            ..dyld_info()
+        };
+        let linkedit = linkedit.concat();
+        let size = linkedit.len() as u64;
+        let mut bytes = image64(
+            MHFileType::MhExecute,
+            &[
+                segment64("__TEXT", 0, 0x4000, 0, 0x4000, vec![]),
+                segment64("__DATA", 0x4000, 0x8000, 0x4000, 0x8000, vec![]),
+                segment64("__LINKEDIT", 0xc000, size, 0xc000, size, vec![]),
+                dyld_info.serialize(),
+            ],
+        );
+        bytes.resize(0x4000, 0);
+        bytes.extend(0x3f00u64.to_le_bytes());
+        bytes.extend(0x3f10u64.to_le_bytes());
+        bytes.resize(0xc000, 0);
+        bytes.extend(linkedit);
+        bytes
+    }
+
+    fn resolve(bytes: Vec<u8>) -> MachO<Cursor<Vec<u8>>> {
+        MachO::parse(Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn test_convert_to_chained_fixups() {
+        let mut image = MachOImage::parse(image()).unwrap();
+        image
+            .convert_to_chained_fixups(DyldPointerFormat::Ptr64Offset)
+            .unwrap();
+        let mut macho = resolve(image.build().unwrap());
+        assert!(!macho
+            .load_commands
+            .iter()
+            .any(|cmd| matches!(cmd, LoadCommand::DyldInfoOnly(_))));
+        let exports = macho.resolve_dyldexportstrie().unwrap().exports;
+        assert_eq!(exports[0].name, "_main");
+
+        let resolved = macho.resolve_fixups().unwrap();
+        let imports: Vec<_> = resolved
+            .imports
+            .iter()
+            .map(|import| (import.ordinal, import.name.as_str(), import.is_weak))
+            .collect();
+        assert_eq!(imports, [(1, "_printf", false), (0xfe, "_maybe", true)]);
+        let fixups: Vec<_> = resolved
+            .fixups
+            .iter()
+            .map(|fixup| match &fixup.fixup {
+                DyldPointerFixup::Ptr64Rebase(rebase) => (fixup.offset, rebase.target, rebase.next),
+                DyldPointerFixup::Ptr64Bind(bind) => {
+                    (fixup.offset, bind.ordinal.len() as u64, bind.next)
+                }
+                other => panic!("Unexpected fixup {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            fixups,
+            [
+                (0x4000, 0x3f00, 2),
+                (0x4008, 0x3f10, 2),
+                (0x4010, "_printf".len() as u64, 0),
+                (0x8000, "_maybe".len() as u64, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arm64e_chained_fixups() {
+        let mut image = MachOImage::parse(image()).unwrap();
+        image.commands_mut().remove(3).unwrap();
+        image.linkedit.clear();
+        let auth = PointerAuth {
+            key: DyldFixupPACKey::IA,
+            diversity: 0x1234,
+            addr_div: true,
+        };
+        let fixups = [
+            ChainedFixup {
+                address: 0x4000,
+                target: FixupTarget::Rebase {
+                    target: 0x3f00,
+                    high8: 0,
+                },
+                auth: Some(auth.clone()),
+            },
+            ChainedFixup {
+                address: 0x4008,
+                target: FixupTarget::Bind {
+                    library_ordinal: 1,
+                    name: "_printf".to_string(),
+                    weak_import: false,
+                    addend: 8,
+                },
+                auth: None,
+            },
+        ];
+        assert!(image
+            .set_chained_fixups(DyldPointerFormat::Ptr64, &fixups)
+            .is_err());
+        image
+            .set_chained_fixups(DyldPointerFormat::Arm64e, &fixups)
+            .unwrap();
+
+        let resolved = resolve(image.build().unwrap()).resolve_fixups().unwrap();
+        match (&resolved.fixups[0].fixup, &resolved.fixups[1].fixup) {
+            (DyldPointerFixup::Arm64eAuthRebase(rebase), DyldPointerFixup::Arm64eBind(bind)) => {
+                assert_eq!(
+                    (rebase.target, rebase.diversity, rebase.next),
+                    (0x3f00, 0x1234, 1)
+                );
+                assert!(rebase.addr_div);
+                assert_eq!((bind.ordinal.as_str(), bind.addend), ("_printf", 8));
+            }
+            other => panic!("Unexpected fixups {:?}", other),
+        }
+
+        // The second page of __DATA is in the file but not mapped.
+        if let Some(LoadCommand::Segment64(data)) = image.commands_mut().get_mut(1) {
+            data.vmsize = 0x4000;
+        }
+        let unmapped = ChainedFixup {
+            address: 0x8000,
+            ..fixups[0].clone()
+        };
+        assert!(image
+            .set_chained_fixups(DyldPointerFormat::Arm64e, &[unmapped])
+            .is_err());
+    }
+
+    #[test]
+    fn test_import_addends() {
+        let bind = |address: u64, addend: i64| ChainedFixup {
+            address,
+            target: FixupTarget::Bind {
+                library_ordinal: 0xfe,
+                name: "_printf".to_string(),
+                weak_import: false,
+                addend,
+            },
+            auth: None,
+        };
+        for (addends, imports_format) in [
+            ([0x1000, -8], DyldImportFormat::ImportAddend),
+            ([0x1000, 1 << 40], DyldImportFormat::ImportAddend64),
+        ] {
+            let mut image = MachOImage::parse(image()).unwrap();
+            image.commands_mut().remove(3).unwrap();
+            image.linkedit.clear();
+            let fixups = [bind(0x4000, addends[0]), bind(0x4008, addends[1])];
+            image
+                .set_chained_fixups(DyldPointerFormat::Ptr64Offset, &fixups)
+                .unwrap();
+            image.build().unwrap();
+
+            let blob = image.linkedit_data(LinkeditKind::ChainedFixups).unwrap();
+            let (_, header) = DyldChainedFixupsHeader::parse(blob).unwrap();
+            assert_eq!(
+                (header.imports_format, header.imports_count),
+                (imports_format, 2)
+            );
+            assert_eq!(image.chained_fixups().unwrap(), fixups);
+
+            // Point __DATA's starts past the end of the blob.
+            let mut blob = blob.to_vec();
+            let starts = header.starts_offset as usize;
+            blob[starts + 8..starts + 12].copy_from_slice(&0x1000u32.to_le_bytes());
+            image.set_linkedit_data(LinkeditKind::ChainedFixups, blob);
+            assert!(image.chained_fixups().is_err());
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use crate::command::codesign::{CodeSignMagic, CodeSignSuperBlob};
+use crate::command::{CodeSignCommand, LCLoadCommand, LinkeditDataCommand, LoadCommand};
+use crate::machine::CpuType;
+use crate::macho::{MachOErr, MachOResult};
+
+use super::MachOEditor;
+
+// codesign places the signature on a 16 byte boundary.
+const SIGNATURE_ALIGN: usize = 16;
+
+impl MachOEditor {
+    /// File offset and size of the `LC_CODE_SIGNATURE` data.
+    pub fn code_signature(&self) -> Option<(u32, u32)> {
+        self.load_commands().find_map(|cmd| match cmd {
+            LoadCommand::CodeSignature(cs) => Some((cs.cmd.dataoff, cs.cmd.datasize)),
+            _ => None,
+        })
+    }
+
+    pub(super) fn page_size(&self) -> u64 {
+        match self.header.cputype() {
+            CpuType::Arm | CpuType::Arm64 | CpuType::Arm64_32 => 0x4000,
+            _ => 0x1000,
+        }
+    }
+
+    // The segment whose file range satisfies `predicate`, given as (start, end).
+    fn find_segment<P: Fn(u64, u64) -> bool>(&self, predicate: P) -> Option<usize> {
+        self.position(|cmd| match cmd {
+            LoadCommand::Segment32(seg) if seg.filesize > 0 => {
+                predicate(seg.fileoff as u64, (seg.fileoff + seg.filesize) as u64)
+            }
+            LoadCommand::Segment64(seg) if seg.filesize > 0 => {
+                predicate(seg.fileoff, seg.fileoff + seg.filesize)
+            }
+            _ => false,
+        })
+    }
+
+    // Makes the segment at `index` end at `end`, with a vmsize covering it in whole pages.
+    fn resize_segment(&mut self, index: usize, end: u64) {
+        let page_size = self.page_size();
+        match self.get_mut(index) {
+            Some(LoadCommand::Segment32(seg)) => {
+                seg.filesize = end as u32 - seg.fileoff;
+                seg.vmsize = (seg.filesize as u64).div_ceil(page_size) as u32 * page_size as u32;
+            }
+            Some(LoadCommand::Segment64(seg)) => {
+                seg.filesize = end - seg.fileoff;
+                seg.vmsize = seg.filesize.div_ceil(page_size) * page_size;
+            }
+            _ => {}
+        }
+    }
+
+    /// Removes `LC_CODE_SIGNATURE`, like `codesign --remove-signature`. A signature at the end
+    /// of the file, where the linker puts it, is truncated away along with its part of
+    /// `__LINKEDIT`; otherwise it's zeroed. Returns whether the image was signed.
+    pub fn remove_code_signature(&mut self) -> MachOResult<bool> {
+        let index = match self.position(|cmd| matches!(cmd, LoadCommand::CodeSignature(_))) {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        let (dataoff, datasize) = self.code_signature().unwrap();
+        let end = dataoff.checked_add(datasize).ok_or_else(|| {
+            MachOErr::InvalidValue(format!(
+                "Code signature at {:#x} overflows with size {:#x}",
+                dataoff, datasize
+            ))
+        })?;
+        self.remove(index)?;
+
+        let (start, end) = (dataoff as usize, end as usize);
+        if start >= self.data.len() {
+            return Ok(true);
+        }
+        if end < self.data.len() && self.data[end..].iter().any(|&b| b != 0) {
+            self.data[start..end].fill(0);
+            return Ok(true);
+        }
+        self.data.truncate(start);
+
+        let offset = dataoff as u64;
+        if let Some(linkedit) = self.find_segment(|start, end| start <= offset && offset < end) {
+            self.resize_segment(linkedit, dataoff as u64);
+        }
+        Ok(true)
+    }
+
+    /// Zeroes the signature but keeps its space and load command, so the image fails
+    /// validation until it's re-signed in place. Returns whether the image was signed.
+    pub fn invalidate_code_signature(&mut self) -> bool {
+        let (dataoff, datasize) = match self.code_signature() {
+            Some(signature) => signature,
+            None => return false,
+        };
+        let start = (dataoff as usize).min(self.data.len());
+        let end = (dataoff as usize + datasize as usize).min(self.data.len());
+        self.data[start..end].fill(0);
+        true
+    }
+
+    /// Replaces any existing signature with `blob`, an embedded signature SuperBlob built
+    /// elsewhere, appended to the end of `__LINKEDIT`.
+    pub fn replace_code_signature(&mut self, blob: &[u8]) -> MachOResult<()> {
+        match CodeSignSuperBlob::parse(blob) {
+            Ok((_, superblob))
+                if superblob.magic == CodeSignMagic::EmbeddedSignature
+                    && superblob.length as usize <= blob.len() => {}
+            _ => {
+                return Err(MachOErr::InvalidValue(
+                    "Code signature is not an embedded signature SuperBlob".to_string(),
+                ))
+            }
+        }
+
+        let index = self.position(|cmd| matches!(cmd, LoadCommand::CodeSignature(_)));
+        self.remove_code_signature()?;
+        // A signature that was only zeroed, rather than truncated, leaves no segment ending at
+        // the end of the file either.
+        let file_end = self.data.len() as u64;
+        let linkedit = self.find_segment(|_, end| end == file_end).ok_or_else(|| {
+            MachOErr::InvalidValue(
+                "The signature can only be placed after a segment that ends the file".to_string(),
+            )
+        })?;
+
+        let dataoff = self.data.len().div_ceil(SIGNATURE_ALIGN) * SIGNATURE_ALIGN;
+        self.data.resize(dataoff, 0);
+        self.data.extend(blob);
+        self.resize_segment(linkedit, self.data.len() as u64);
+
+        let cmd = LoadCommand::CodeSignature(CodeSignCommand {
+            cmd: LinkeditDataCommand {
+                cmd: LCLoadCommand::LcCodeSignature,
+                cmdsize: 16,
+                dataoff: dataoff as u32,
+                datasize: blob.len() as u32,
+            },
+        });
+        match index {
+            Some(index) => self.insert(index, cmd),
+            None => {
+                self.push(cmd);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::HeaderOverflow;
+    use crate::header::MHFileType;
+    use crate::machine::{CpuSubType, CpuSubTypeX86};
+    use crate::testing::{code_signature, image64_for, segment64};
+
+    // An x86_64 executable whose __LINKEDIT holds 0x18 bytes of data and a 0x20 byte signature.
+    fn image() -> Vec<u8> {
+        let mut bytes = image64_for(
+            CpuType::X86_64,
+            CpuSubType::CpuSubTypeX86(CpuSubTypeX86::All),
+            MHFileType::MhExecute,
+            &[
+                segment64("__TEXT", 0, 0x2000, 0, 0x1000, vec![]),
+                segment64("__LINKEDIT", 0x1000, 0x2000, 0x1000, 0x40, vec![]),
+                code_signature(0x1020, 0x20),
+            ],
+        );
+        bytes.resize(0x1000, 0);
+        bytes.extend([0x11; 0x18]);
+        bytes.resize(0x1020, 0);
+        bytes.extend([0xfa; 0x20]);
+        bytes
+    }
+
+    fn linkedit(editor: &MachOEditor) -> (u64, u64) {
+        match editor.get(1) {
+            Some(LoadCommand::Segment64(seg)) => (seg.filesize, seg.vmsize),
+            _ => panic!("Unexpected load commands"),
+        }
+    }
+
+    #[test]
+    fn test_remove_and_replace_code_signature() {
+        let mut editor = MachOEditor::parse(image()).unwrap();
+        assert!(editor.remove_code_signature().unwrap());
+        let removed = editor.write(HeaderOverflow::Fail).unwrap();
+        assert_eq!(removed.len(), 0x1020);
+        let mut editor = MachOEditor::parse(removed).unwrap();
+        assert_eq!(editor.len(), 2);
+        assert_eq!(linkedit(&editor), (0x20, 0x1000));
+
+        // A SuperBlob with no blobs.
+        let mut blob = vec![0xfa, 0xde, 0x0c, 0xc0, 0, 0, 0, 12, 0, 0, 0, 0];
+        blob.resize(0x30, 0);
+        assert!(editor.replace_code_signature(&blob[4..]).is_err());
+        editor.replace_code_signature(&blob).unwrap();
+        let replaced = editor.write(HeaderOverflow::Fail).unwrap();
+        assert_eq!(&replaced[0x1020..], &blob[..]);
+
+        let mut editor = MachOEditor::parse(replaced).unwrap();
+        assert_eq!(editor.code_signature(), Some((0x1020, 0x30)));
+        assert_eq!(linkedit(&editor), (0x50, 0x1000));
+
+        // Replacing an existing signature swaps it out rather than appending another.
+        editor.replace_code_signature(&blob[..0x10]).unwrap();
+        assert_eq!(editor.code_signature(), Some((0x1020, 0x10)));
+        assert_eq!(editor.data().len(), 0x1030);
+
+        let mut wrapping = image();
+        let cmd = 32 + 72 * 2;
+        wrapping[cmd + 12..cmd + 16].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut editor = MachOEditor::parse(wrapping).unwrap();
+        assert!(editor.remove_code_signature().is_err());
+        assert_eq!(editor.len(), 3);
+    }
+}
@@ -0,0 +1,428 @@
+use std::io::{Cursor, Read, Seek};
+
+use nom::number::complete::{be_u32, be_u64};
+
+use crate::archive::Archive;
+use crate::command::LoadCommand;
+use crate::fat::FatMagic;
+use crate::header::{MHFileType, MHMagic};
+use crate::machine::{
+    CpuSubType, CpuSubTypeArm, CpuSubTypeArm64, CpuSubTypeI386, CpuSubTypeX86, CpuType,
+};
+use crate::macho::{MachO, MachOErr, MachOResult};
+
+/// One architecture of a fat file. The CPU types are kept raw so capability bits, such as the
+/// arm64e pointer authentication ABI, survive a rewrite.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FatSlice {
+    pub cputype: u32,
+    pub cpusubtype: u32,
+    /// log2 of the slice's alignment within the fat file.
+    pub align: u32,
+    pub data: Vec<u8>,
+}
+
+// (name, cputype, cpusubtype) for the architectures lipo knows by name.
+const ARCH_NAMES: &[(&str, u32, u32)] = &[
+    ("i386", CpuType::I386 as u32, CpuSubTypeI386::All as u32),
+    ("x86_64", CpuType::X86_64 as u32, CpuSubTypeX86::All as u32),
+    ("x86_64h", CpuType::X86_64 as u32, CpuSubTypeX86::X86_64H as u32),
+    ("armv6", CpuType::Arm as u32, CpuSubTypeArm::V6 as u32),
+    ("armv7", CpuType::Arm as u32, CpuSubTypeArm::V7 as u32),
+    ("armv7s", CpuType::Arm as u32, CpuSubTypeArm::V7S as u32),
+    ("armv7k", CpuType::Arm as u32, CpuSubTypeArm::V7K as u32),
+    ("arm64", CpuType::Arm64 as u32, CpuSubTypeArm64::All as u32),
+    ("arm64e", CpuType::Arm64 as u32, CpuSubTypeArm64::ARM64E as u32),
+    // CPU_SUBTYPE_ARM64_32_V8 has the same value as CPU_SUBTYPE_ARM64_V8.
+    ("arm64_32", CpuType::Arm64_32 as u32, CpuSubTypeArm64::V8 as u32),
+    // There are no PowerPC subtypes in `machine`; CPU_SUBTYPE_POWERPC_ALL is 0.
+    ("ppc", CpuType::PowerPC as u32, 0),
+    ("ppc64", CpuType::PowerPC64 as u32, 0),
+];
+
+/// Looks up the cputype and cpusubtype for an architecture name such as `arm64e`.
+pub fn arch_from_name(name: &str) -> Option<(u32, u32)> {
+    ARCH_NAMES
+        .iter()
+        .find(|(arch, _, _)| *arch == name)
+        .map(|&(_, cputype, cpusubtype)| (cputype, cpusubtype))
+}
+
+pub fn arch_name(cputype: u32, cpusubtype: u32) -> String {
+    ARCH_NAMES
+        .iter()
+        .find(|&&(_, t, s)| t == cputype && s == cpusubtype & !CpuSubType::CPU_SUBTYPE_MASK)
+        .map(|(name, _, _)| name.to_string())
+        .unwrap_or_else(|| format!("cputype{}_{}", cputype, cpusubtype))
+}
+
+// Reads the raw cputype and cpusubtype from a mach header.
+fn macho_arch(data: &[u8]) -> MachOResult<(u32, u32)> {
+    let field = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .ok_or(MachOErr::MagicError)
+    };
+    let magic = field(0)?;
+    if magic != MHMagic::MhMagic as u32 && magic != MHMagic::MhMagic64 as u32 {
+        return Err(MachOErr::MagicError);
+    }
+    Ok((field(4)?, field(8)?))
+}
+
+impl FatSlice {
+    /// Describes a thin MachO, or a static archive of them, as a slice. The CPU types come
+    /// from the mach header, and the alignment is chosen the way lipo does.
+    pub fn from_macho(data: Vec<u8>) -> MachOResult<FatSlice> {
+        let mut cursor = Cursor::new(&data);
+        let (cputype, cpusubtype, align) = if Archive::is_archive_magic(&mut cursor)? {
+            let archive = Archive::parse(cursor)?;
+            let member = archive.members.first().ok_or_else(|| {
+                MachOErr::InvalidValue("Static archive has no members".to_string())
+            })?;
+            let (cputype, cpusubtype) = macho_arch(&data[member.offset as usize..])?;
+            (cputype, cpusubtype, Self::page_align(cputype))
+        } else {
+            let (cputype, cpusubtype) = macho_arch(&data)?;
+            let macho = MachO::parse(Cursor::new(&data))?;
+            let align = if *macho.header.filetype() == MHFileType::MhObject {
+                Self::section_align(&macho)
+            } else {
+                Self::page_align(cputype)
+            };
+            (cputype, cpusubtype, align)
+        };
+
+        Ok(FatSlice {
+            cputype,
+            cpusubtype,
+            align,
+            data,
+        })
+    }
+
+    // Log2 of the page size images for `cputype` are mapped with.
+    fn page_align(cputype: u32) -> u32 {
+        // Anything ARM gets 16K pages.
+        if cputype & 0xff == CpuType::Arm as u32 {
+            14
+        } else {
+            12
+        }
+    }
+
+    // Object files only need their most aligned section to stay aligned.
+    fn section_align<T: Read + Seek>(macho: &MachO<T>) -> u32 {
+        macho
+            .load_commands
+            .iter()
+            .flat_map(|cmd| match cmd {
+                LoadCommand::Segment32(seg) => seg.sects.iter().map(|s| s.align).collect(),
+                LoadCommand::Segment64(seg) => seg.sections.iter().map(|s| s.align).collect(),
+                _ => vec![],
+            })
+            .max()
+            .unwrap_or(0)
+            .max(2)
+    }
+
+    pub fn arch_name(&self) -> String {
+        arch_name(self.cputype, self.cpusubtype)
+    }
+
+    /// Whether this slice is for `cputype`/`cpusubtype`, ignoring capability bits.
+    pub fn is_arch(&self, cputype: u32, cpusubtype: u32) -> bool {
+        self.cputype == cputype
+            && self.cpusubtype & !CpuSubType::CPU_SUBTYPE_MASK
+                == cpusubtype & !CpuSubType::CPU_SUBTYPE_MASK
+    }
+}
+
+pub fn is_fat(data: &[u8]) -> bool {
+    data.len() >= 4 && {
+        let magic = u32::from_be_bytes(data[..4].try_into().unwrap());
+        magic == FatMagic::Fat as u32 || magic == FatMagic::Fat64 as u32
+    }
+}
+
+/// Splits a fat file into its slices.
+pub fn parse_fat(data: &[u8]) -> MachOResult<Vec<FatSlice>> {
+    if !is_fat(data) {
+        return Err(MachOErr::MagicError);
+    }
+    let (mut cursor, (magic, nfat_arch)) = nom::sequence::tuple((be_u32, be_u32))(data)?;
+
+    let mut slices = Vec::new();
+    for _ in 0..nfat_arch {
+        let (next, (cputype, cpusubtype)) = nom::sequence::tuple((be_u32, be_u32))(cursor)?;
+        let (next, offset, size) = if magic == FatMagic::Fat64 as u32 {
+            let (next, (offset, size)) = nom::sequence::tuple((be_u64, be_u64))(next)?;
+            (next, offset, size)
+        } else {
+            let (next, (offset, size)) = nom::sequence::tuple((be_u32, be_u32))(next)?;
+            (next, offset as u64, size as u64)
+        };
+        let (next, align) = be_u32(next)?;
+        cursor = if magic == FatMagic::Fat64 as u32 {
+            be_u32(next)?.0
+        } else {
+            next
+        };
+
+        let slice = offset
+            .checked_add(size)
+            .and_then(|end| data.get(offset as usize..end as usize))
+            .ok_or_else(|| {
+                MachOErr::InvalidValue(format!(
+                    "Fat slice at 0x{:x} extends past the end of the file",
+                    offset
+                ))
+            })?;
+        slices.push(FatSlice {
+            cputype,
+            cpusubtype,
+            align,
+            data: slice.to_vec(),
+        });
+    }
+    Ok(slices)
+}
+
+/// Lays the slices out at their alignments behind a fat header, switching to the 64-bit
+/// format only if an offset needs it.
+pub fn build_fat(slices: &[FatSlice]) -> MachOResult<Vec<u8>> {
+    let layout = |arch_size: usize| {
+        let mut offset = 8 + arch_size * slices.len();
+        slices
+            .iter()
+            .map(|slice| {
+                let align = 1usize << slice.align.min(31);
+                offset = offset.div_ceil(align) * align;
+                let start = offset;
+                offset += slice.data.len();
+                start
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let mut offsets = layout(20);
+    let is_64 = slices
+        .iter()
+        .zip(&offsets)
+        .any(|(slice, &offset)| offset + slice.data.len() > u32::MAX as usize);
+    if is_64 {
+        offsets = layout(32);
+    }
+
+    let mut out = Vec::new();
+    let magic = if is_64 {
+        FatMagic::Fat64
+    } else {
+        FatMagic::Fat
+    };
+    out.extend((magic as u32).to_be_bytes());
+    out.extend((slices.len() as u32).to_be_bytes());
+    for (slice, &offset) in slices.iter().zip(&offsets) {
+        out.extend(slice.cputype.to_be_bytes());
+        out.extend(slice.cpusubtype.to_be_bytes());
+        if is_64 {
+            out.extend((offset as u64).to_be_bytes());
+            out.extend((slice.data.len() as u64).to_be_bytes());
+        } else {
+            out.extend((offset as u32).to_be_bytes());
+            out.extend((slice.data.len() as u32).to_be_bytes());
+        }
+        out.extend(slice.align.to_be_bytes());
+        if is_64 {
+            out.extend(0u32.to_be_bytes());
+        }
+    }
+
+    for (slice, &offset) in slices.iter().zip(&offsets) {
+        out.resize(offset, 0);
+        out.extend(&slice.data);
+    }
+    Ok(out)
+}
+
+/// Creates a fat file from thin MachOs, static archives, or other fat files whose slices are
+/// merged in.
+pub fn create_fat(inputs: Vec<Vec<u8>>) -> MachOResult<Vec<u8>> {
+    let mut slices: Vec<FatSlice> = Vec::new();
+    for input in inputs {
+        let new = if is_fat(&input) {
+            parse_fat(&input)?
+        } else {
+            vec![FatSlice::from_macho(input)?]
+        };
+        for slice in new {
+            if slices
+                .iter()
+                .any(|existing| existing.is_arch(slice.cputype, slice.cpusubtype))
+            {
+                return Err(MachOErr::InvalidValue(format!(
+                    "More than one input has architecture {}",
+                    slice.arch_name()
+                )));
+            }
+            slices.push(slice);
+        }
+    }
+    build_fat(&slices)
+}
+
+fn find_slice(slices: &[FatSlice], cputype: u32, cpusubtype: u32) -> MachOResult<usize> {
+    slices
+        .iter()
+        .position(|slice| slice.is_arch(cputype, cpusubtype))
+        .ok_or_else(|| {
+            MachOErr::InvalidValue(format!(
+                "Fat file has no {} slice",
+                arch_name(cputype, cpusubtype)
+            ))
+        })
+}
+
+/// The contents of one slice, as a thin file.
+pub fn extract_slice(data: &[u8], cputype: u32, cpusubtype: u32) -> MachOResult<Vec<u8>> {
+    let mut slices = parse_fat(data)?;
+    let index = find_slice(&slices, cputype, cpusubtype)?;
+    Ok(slices.swap_remove(index).data)
+}
+
+pub fn remove_slice(data: &[u8], cputype: u32, cpusubtype: u32) -> MachOResult<Vec<u8>> {
+    let mut slices = parse_fat(data)?;
+    let index = find_slice(&slices, cputype, cpusubtype)?;
+    slices.remove(index);
+    build_fat(&slices)
+}
+
+/// Swaps the slice with the same architecture as `thin` for it.
+pub fn replace_slice(data: &[u8], thin: Vec<u8>) -> MachOResult<Vec<u8>> {
+    let mut slices = parse_fat(data)?;
+    let slice = FatSlice::from_macho(thin)?;
+    let index = find_slice(&slices, slice.cputype, slice.cpusubtype)?;
+    slices[index] = slice;
+    build_fat(&slices)
+}
+
+/// Applies `f` to a thin file, or to every slice of a fat one, and reassembles the result.
+pub fn map_slices<F>(data: Vec<u8>, mut f: F) -> MachOResult<Vec<u8>>
+where
+    F: FnMut(Vec<u8>) -> MachOResult<Vec<u8>>,
+{
+    if !is_fat(&data) {
+        return f(data);
+    }
+
+    let slices = parse_fat(&data)?
+        .into_iter()
+        .map(|slice| {
+            Ok(FatSlice {
+                data: f(slice.data)?,
+                ..slice
+            })
+        })
+        .collect::<MachOResult<Vec<_>>>()?;
+    build_fat(&slices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::{MHFlags, MachHeader64};
+    use crate::machine::{CpuSubTypeArm64, CpuSubTypeX86};
+
+    fn thin(cputype: CpuType, cpusubtype: CpuSubType, flags: MHFlags) -> Vec<u8> {
+        let mut bytes = MachHeader64 {
+            magic: MHMagic::MhMagic64,
+            cputype,
+            cpusubtype,
+            filetype: MHFileType::MhExecute,
+            ncmds: 0,
+            sizeofcmds: 0,
+            flags,
+            reserved: 0,
+        }
+        .serialize();
+        bytes.resize(0x100, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_lipo() {
+        let x86 = thin(
+            CpuType::X86_64,
+            CpuSubType::CpuSubTypeX86(CpuSubTypeX86::All),
+            MHFlags::empty(),
+        );
+        let arm = thin(
+            CpuType::Arm64,
+            CpuSubType::CpuSubTypeArm64(CpuSubTypeArm64::All),
+            MHFlags::empty(),
+        );
+        let fat = create_fat(vec![x86.clone(), arm.clone()]).unwrap();
+        assert!(create_fat(vec![fat.clone(), arm.clone()]).is_err());
+
+        let slices = parse_fat(&fat).unwrap();
+        assert_eq!(slices[0].arch_name(), "x86_64");
+        assert_eq!(slices[0].align, 12);
+        assert_eq!(slices[1].arch_name(), "arm64");
+        assert_eq!(slices[1].align, 14);
+        assert_eq!(fat.len(), 0x4000 + 0x100);
+
+        let (cputype, cpusubtype) = arch_from_name("arm64").unwrap();
+        assert_eq!(extract_slice(&fat, cputype, cpusubtype).unwrap(), arm);
+
+        let new_arm = thin(
+            CpuType::Arm64,
+            CpuSubType::CpuSubTypeArm64(CpuSubTypeArm64::All),
+            MHFlags::MH_PIE,
+        );
+        let replaced = replace_slice(&fat, new_arm.clone()).unwrap();
+        assert_eq!(
+            extract_slice(&replaced, cputype, cpusubtype).unwrap(),
+            new_arm
+        );
+
+        let removed = remove_slice(&replaced, cputype, cpusubtype).unwrap();
+        let slices = parse_fat(&removed).unwrap();
+        assert_eq!(slices.len(), 1);
+        assert_eq!(slices[0].data, x86);
+        assert!(remove_slice(&removed, cputype, cpusubtype).is_err());
+    }
+
+    #[test]
+    fn test_fat_round_trip() {
+        let slices = vec![
+            FatSlice {
+                cputype: 0x01000007,
+                cpusubtype: 3,
+                align: 12,
+                data: vec![1; 0x1800],
+            },
+            FatSlice {
+                cputype: 0x0100000c,
+                cpusubtype: 0x80000002,
+                align: 14,
+                data: vec![2; 0x10],
+            },
+        ];
+
+        let fat = build_fat(&slices).unwrap();
+        assert!(is_fat(&fat));
+        assert_eq!(fat.len(), 0x4000 + 0x10);
+        assert_eq!(parse_fat(&fat).unwrap(), slices);
+
+        let mapped = map_slices(fat, |data| Ok(data[..8].to_vec())).unwrap();
+        let mapped = parse_fat(&mapped).unwrap();
+        assert_eq!(mapped[0].data, [1; 8]);
+        assert_eq!(mapped[1].cpusubtype, 0x80000002);
+
+        let mut wrapping = vec![0xca, 0xfe, 0xba, 0xbf, 0, 0, 0, 1, 1, 0, 0, 7, 0, 0, 0, 3];
+        wrapping.extend(u64::MAX.to_be_bytes());
+        wrapping.extend(2u64.to_be_bytes());
+        wrapping.extend([0; 8]);
+        assert!(parse_fat(&wrapping).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::command::{DylibCommand, LCLoadCommand, LoadCommand};
+use crate::macho::{MachOErr, MachOResult};
+
+use super::install_name::DYLIB_NAME_OFFSET;
+use super::{normalize_version, MachOEditor};
+
+/// What to do with an existing signature, which an inserted dylib would invalidate anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeSignatureAction {
+    Keep,
+    Strip,
+    Invalidate,
+}
+
+impl MachOEditor {
+    /// Appends an `LC_LOAD_DYLIB`, or `LC_LOAD_WEAK_DYLIB` when `weak`, for `path`.
+    pub fn insert_dylib(
+        &mut self,
+        path: &str,
+        current_version: &str,
+        compatibility_version: &str,
+        weak: bool,
+    ) -> MachOResult<()> {
+        if self.dependent_dylibs().contains(&path) {
+            return Err(MachOErr::InvalidValue(format!(
+                "Image already loads {}",
+                path
+            )));
+        }
+
+        let dylib = DylibCommand {
+            cmd: if weak {
+                LCLoadCommand::LcLoadWeakDylib
+            } else {
+                LCLoadCommand::LcLoadDylib
+            },
+            cmdsize: self.string_cmdsize(DYLIB_NAME_OFFSET, path),
+            name: path.to_string(),
+            // The same timestamp ld64 writes.
+            timestamp: 2,
+            current_version: normalize_version(current_version)?,
+            compatibility_version: normalize_version(compatibility_version)?,
+        };
+        self.push(if weak {
+            LoadCommand::LoadWeakDylib(dylib)
+        } else {
+            LoadCommand::LoadDylib(dylib)
+        });
+        Ok(())
+    }
+
+    pub fn apply_code_signature_action(&mut self, action: CodeSignatureAction) -> MachOResult<()> {
+        match action {
+            CodeSignatureAction::Keep => {}
+            CodeSignatureAction::Strip => {
+                self.remove_code_signature()?;
+            }
+            CodeSignatureAction::Invalidate => {
+                self.invalidate_code_signature();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::edit::fat::{build_fat, map_slices, parse_fat, FatSlice};
+    use crate::edit::HeaderOverflow;
+    use crate::header::MHFileType;
+    use crate::testing::{code_signature, image64, segment64};
+
+    // A signed executable: __TEXT maps the header, and __LINKEDIT holds 0x10 bytes of symbols
+    // followed by a 0x20 byte signature.
+    fn image() -> Vec<u8> {
+        let mut bytes = image64(
+            MHFileType::MhExecute,
+            &[
+                segment64("__TEXT", 0, 0x1000, 0, 0x1000, vec![]),
+                segment64("__LINKEDIT", 0x1000, 0x1000, 0x1000, 0x30, vec![]),
+                code_signature(0x1010, 0x20),
+            ],
+        );
+        bytes.resize(0x1000, 0);
+        bytes.extend([0x11; 0x10]);
+        bytes.extend([0xfa; 0x20]);
+        bytes
+    }
+
+    fn insert(data: Vec<u8>, action: CodeSignatureAction) -> MachOResult<Vec<u8>> {
+        let mut editor = MachOEditor::parse(data)?;
+        editor.apply_code_signature_action(action)?;
+        editor.insert_dylib("@executable_path/libinject.dylib", "1.2", "1", true)?;
+        editor.write(HeaderOverflow::Fail)
+    }
+
+    #[test]
+    fn test_insert_dylib() {
+        let stripped = insert(image(), CodeSignatureAction::Strip).unwrap();
+        assert_eq!(stripped.len(), 0x1010);
+        let editor = MachOEditor::parse(stripped).unwrap();
+        assert_eq!(editor.code_signature(), None);
+        assert_eq!(editor.len(), 3);
+        match (editor.get(1), editor.get(2)) {
+            (Some(LoadCommand::Segment64(seg)), Some(LoadCommand::LoadWeakDylib(dylib))) => {
+                assert_eq!(seg.filesize, 0x10);
+                assert_eq!(dylib.name, "@executable_path/libinject.dylib");
+                assert_eq!(dylib.current_version, "1.2.0");
+                assert_eq!(dylib.cmdsize % 8, 0);
+            }
+            _ => panic!("Unexpected load commands"),
+        }
+
+        let invalidated = insert(image(), CodeSignatureAction::Invalidate).unwrap();
+        assert_eq!(invalidated.len(), image().len());
+        assert!(invalidated[0x1010..].iter().all(|&b| b == 0));
+
+        let mut editor = MachOEditor::parse(image()).unwrap();
+        assert!(editor
+            .insert_dylib("libfoo.dylib", "1.0.0", "1.256", false)
+            .is_err());
+    }
+
+    #[test]
+    fn test_insert_dylib_fat() {
+        let slice = FatSlice::from_macho(image()).unwrap();
+        let fat = build_fat(&[
+            slice.clone(),
+            FatSlice {
+                cputype: 7,
+                ..slice
+            },
+        ])
+        .unwrap();
+
+        let fat = map_slices(fat, |data| insert(data, CodeSignatureAction::Keep)).unwrap();
+        for slice in parse_fat(&fat).unwrap() {
+            let editor = MachOEditor::parse(slice.data).unwrap();
+            assert_eq!(
+                editor.dependent_dylibs(),
+                ["@executable_path/libinject.dylib"]
+            );
+        }
+    }
+}
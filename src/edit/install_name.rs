@@ -0,0 +1,210 @@
+use crate::command::{DylibCommand, LCLoadCommand, LoadCommand, RpathCommand};
+use crate::macho::{MachOErr, MachOResult};
+
+use super::MachOEditor;
+
+// Offset of the name string in `dylib_command` and `rpath_command`.
+pub(super) const DYLIB_NAME_OFFSET: usize = 0x18;
+const RPATH_PATH_OFFSET: usize = 0xC;
+
+fn dependent_dylib(cmd: &LoadCommand) -> Option<&DylibCommand> {
+    match cmd {
+        LoadCommand::LoadDylib(dylib)
+        | LoadCommand::LoadWeakDylib(dylib)
+        | LoadCommand::ReexportDylib(dylib)
+        | LoadCommand::LazyLoadDylib(dylib)
+        | LoadCommand::LoadUpwardDylib(dylib) => Some(dylib),
+        _ => None,
+    }
+}
+
+fn dependent_dylib_mut(cmd: &mut LoadCommand) -> Option<&mut DylibCommand> {
+    match cmd {
+        LoadCommand::LoadDylib(dylib)
+        | LoadCommand::LoadWeakDylib(dylib)
+        | LoadCommand::ReexportDylib(dylib)
+        | LoadCommand::LazyLoadDylib(dylib)
+        | LoadCommand::LoadUpwardDylib(dylib) => Some(dylib),
+        _ => None,
+    }
+}
+
+impl MachOEditor {
+    /// The install name from `LC_ID_DYLIB`.
+    pub fn id(&self) -> Option<&str> {
+        self.load_commands().find_map(|cmd| match cmd {
+            LoadCommand::DylibId(dylib) => Some(dylib.name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn set_id(&mut self, name: &str) -> MachOResult<()> {
+        let index = self
+            .position(|cmd| matches!(cmd, LoadCommand::DylibId(_)))
+            .ok_or_else(|| MachOErr::InvalidValue("Image has no LC_ID_DYLIB".to_string()))?;
+        let cmdsize = self.string_cmdsize(DYLIB_NAME_OFFSET, name);
+        if let Some(LoadCommand::DylibId(dylib)) = self.get_mut(index) {
+            dylib.name = name.to_string();
+            dylib.cmdsize = cmdsize;
+        }
+        Ok(())
+    }
+
+    /// The paths of the libraries this image links against, in load order.
+    pub fn dependent_dylibs(&self) -> Vec<&str> {
+        self.load_commands()
+            .filter_map(dependent_dylib)
+            .map(|dylib| dylib.name.as_str())
+            .collect()
+    }
+
+    /// Rewrites every dependency on `old` to `new`. Returns whether any were found.
+    pub fn change_install_name(&mut self, old: &str, new: &str) -> bool {
+        let cmdsize = self.string_cmdsize(DYLIB_NAME_OFFSET, new);
+        let indices: Vec<usize> = (0..self.len())
+            .filter(|&i| {
+                self.get(i)
+                    .and_then(dependent_dylib)
+                    .is_some_and(|dylib| dylib.name == old)
+            })
+            .collect();
+
+        for &index in &indices {
+            if let Some(dylib) = self.get_mut(index).and_then(dependent_dylib_mut) {
+                dylib.name = new.to_string();
+                dylib.cmdsize = cmdsize;
+            }
+        }
+        !indices.is_empty()
+    }
+
+    pub fn rpaths(&self) -> Vec<&str> {
+        self.load_commands()
+            .filter_map(|cmd| match cmd {
+                LoadCommand::Rpath(rpath) => Some(rpath.path.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn rpath_index(&self, path: &str) -> Option<usize> {
+        self.position(|cmd| matches!(cmd, LoadCommand::Rpath(rpath) if rpath.path == path))
+    }
+
+    fn rpath_command(&self, path: &str) -> RpathCommand {
+        RpathCommand {
+            cmd: LCLoadCommand::LcRpath,
+            cmdsize: self.string_cmdsize(RPATH_PATH_OFFSET, path),
+            path: path.to_string(),
+        }
+    }
+
+    /// Adds an `LC_RPATH` after any existing ones.
+    pub fn add_rpath(&mut self, path: &str) -> MachOResult<()> {
+        if self.rpath_index(path).is_some() {
+            return Err(MachOErr::InvalidValue(format!(
+                "Image already has an LC_RPATH for {}",
+                path
+            )));
+        }
+
+        let index = (0..self.len())
+            .rev()
+            .find(|&i| matches!(self.get(i), Some(LoadCommand::Rpath(_))))
+            .map(|i| i + 1)
+            .unwrap_or(self.len());
+        let rpath = self.rpath_command(path);
+        self.insert(index, LoadCommand::Rpath(rpath))
+    }
+
+    pub fn delete_rpath(&mut self, path: &str) -> MachOResult<()> {
+        let index = self
+            .rpath_index(path)
+            .ok_or_else(|| MachOErr::InvalidValue(format!("No LC_RPATH for {}", path)))?;
+        self.remove(index)?;
+        Ok(())
+    }
+
+    pub fn rename_rpath(&mut self, old: &str, new: &str) -> MachOResult<()> {
+        let index = self
+            .rpath_index(old)
+            .ok_or_else(|| MachOErr::InvalidValue(format!("No LC_RPATH for {}", old)))?;
+        if old != new && self.rpath_index(new).is_some() {
+            return Err(MachOErr::InvalidValue(format!(
+                "Image already has an LC_RPATH for {}",
+                new
+            )));
+        }
+        let rpath = self.rpath_command(new);
+        self.replace(index, LoadCommand::Rpath(rpath))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::command::LoadCommandParser;
+    use crate::edit::HeaderOverflow;
+    use crate::header::MHFileType;
+    use crate::macho::MachO;
+    use crate::testing::image64;
+
+    fn dylib(cmd: LCLoadCommand, name: &str) -> Vec<u8> {
+        DylibCommand {
+            cmd,
+            cmdsize: ((DYLIB_NAME_OFFSET + name.len() + 1 + 7) & !7) as u32,
+            name: name.to_string(),
+            timestamp: 2,
+            current_version: "1.0.0".to_string(),
+            compatibility_version: "1.0.0".to_string(),
+        }
+        .serialize()
+    }
+
+    fn image() -> Vec<u8> {
+        let mut bytes = image64(
+            MHFileType::MhDylib,
+            &[
+                dylib(LCLoadCommand::LcIdDylib, "/usr/local/lib/libfoo.dylib"),
+                dylib(LCLoadCommand::LcLoadDylib, "/usr/lib/libSystem.B.dylib"),
+                dylib(LCLoadCommand::LcLoadDylib, "/opt/lib/libbar.dylib"),
+            ],
+        );
+        bytes.resize(0x400, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_install_names_and_rpaths() {
+        let mut editor = MachOEditor::parse(image()).unwrap();
+        editor
+            .set_id("@rpath/Foo.framework/Versions/A/Foo")
+            .unwrap();
+        assert!(editor.change_install_name("/opt/lib/libbar.dylib", "@rpath/libbar.dylib"));
+        assert!(!editor.change_install_name("/opt/lib/libbaz.dylib", "@rpath/libbaz.dylib"));
+
+        editor.add_rpath("@loader_path/../Frameworks").unwrap();
+        editor.add_rpath("/opt/lib").unwrap();
+        assert!(editor.add_rpath("/opt/lib").is_err());
+        editor.rename_rpath("/opt/lib", "@executable_path").unwrap();
+        editor.delete_rpath("@loader_path/../Frameworks").unwrap();
+        assert!(editor.delete_rpath("/opt/lib").is_err());
+
+        let written = editor.write(HeaderOverflow::Fail).unwrap();
+        let editor = MachOEditor::parse(written.clone()).unwrap();
+        assert_eq!(editor.id(), Some("@rpath/Foo.framework/Versions/A/Foo"));
+        assert_eq!(
+            editor.dependent_dylibs(),
+            ["/usr/lib/libSystem.B.dylib", "@rpath/libbar.dylib"]
+        );
+        assert_eq!(editor.rpaths(), ["@executable_path"]);
+
+        let macho = MachO::parse(Cursor::new(written)).unwrap();
+        for cmd in &macho.load_commands {
+            assert_eq!(cmd.serialize().len() % 8, 0);
+        }
+    }
+}
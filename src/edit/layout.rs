@@ -0,0 +1,823 @@
+use crate::command::segment::SectionType;
+use crate::command::LoadCommand;
+use crate::header::MachHeader;
+use crate::macho::{MachOErr, MachOResult};
+
+use super::{HeaderOverflow, MachOEditor};
+
+/// A table or blob that a load command points at outside of any section, usually in
+/// `__LINKEDIT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkeditKind {
+    Rebase,
+    Bind,
+    WeakBind,
+    LazyBind,
+    Export,
+    ChainedFixups,
+    ExportsTrie,
+    FunctionStarts,
+    DataInCode,
+    SegmentSplitInfo,
+    DylibCodeSignDrs,
+    LinkerOptimizationHint,
+    AtomInfo,
+    Symbols,
+    Strings,
+    TableOfContents,
+    ModuleTable,
+    ExternalReferences,
+    IndirectSymbols,
+    ExternalRelocations,
+    LocalRelocations,
+    /// The relocations of a section in an object file, by segment and section index.
+    SectionRelocations {
+        segment: usize,
+        section: usize,
+    },
+    CodeSignature,
+    /// The payload of an `LC_NOTE`, by the note's index among the `LC_NOTE` commands.
+    Note {
+        index: usize,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkeditBlob {
+    pub kind: LinkeditKind,
+    pub data: Vec<u8>,
+    // Where the blob was read from, so unchanged blobs keep their place.
+    original: Option<(u64, u64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentData {
+    pub name: String,
+    /// The segment's file contents. Its file offset is fixed, since code refers to it by
+    /// address, but its size may change within the space before the next segment.
+    pub data: Vec<u8>,
+}
+
+struct SegmentInfo {
+    fileoff: u64,
+    filesize: u64,
+    vmsize: u64,
+    // (name, file offset, size) of every section with file contents.
+    sections: Vec<(String, u64, u64)>,
+}
+
+fn segment_info(cmd: &LoadCommand) -> Option<(&str, SegmentInfo)> {
+    let file_backed = |sectype: SectionType| {
+        !matches!(
+            sectype,
+            SectionType::SZeroFill | SectionType::SGbZeroFill | SectionType::SThreadLocalZeroFill
+        )
+    };
+    match cmd {
+        LoadCommand::Segment32(seg) => Some((
+            seg.segname.as_str(),
+            SegmentInfo {
+                fileoff: seg.fileoff as u64,
+                filesize: seg.filesize as u64,
+                vmsize: seg.vmsize as u64,
+                sections: seg
+                    .sects
+                    .iter()
+                    .filter(|sect| file_backed(sect.flags_sectype))
+                    .map(|sect| (sect.sectname.clone(), sect.offset as u64, sect.size as u64))
+                    .collect(),
+            },
+        )),
+        LoadCommand::Segment64(seg) => Some((
+            seg.segname.as_str(),
+            SegmentInfo {
+                fileoff: seg.fileoff,
+                filesize: seg.filesize,
+                vmsize: seg.vmsize,
+                sections: seg
+                    .sections
+                    .iter()
+                    .filter(|sect| file_backed(sect.flags_sectype))
+                    .map(|sect| (sect.sectname.clone(), sect.offset as u64, sect.size))
+                    .collect(),
+            },
+        )),
+        _ => None,
+    }
+}
+
+// A laid out offset or size for a 32-bit load command field.
+fn to_u32(value: u64) -> MachOResult<u32> {
+    u32::try_from(value)
+        .map_err(|_| MachOErr::InvalidValue(format!("{:#x} doesn't fit in 32 bits", value)))
+}
+
+// Each command's index among the segment commands for segments, and among the notes for
+// notes, which tells apart the blobs of commands that can appear more than once.
+fn command_ordinals(editor: &MachOEditor) -> Vec<usize> {
+    let (mut segments, mut notes) = (0, 0);
+    editor
+        .load_commands()
+        .map(|cmd| {
+            let count = match cmd {
+                LoadCommand::Note(_) => &mut notes,
+                _ if segment_info(cmd).is_some() => &mut segments,
+                _ => return 0,
+            };
+            *count += 1;
+            *count - 1
+        })
+        .collect()
+}
+
+// The blobs `cmd` points at as (kind, offset, size). `ordinal` is the command's entry in
+// `command_ordinals`.
+fn linkedit_ranges(
+    cmd: &LoadCommand,
+    ordinal: usize,
+    is_64: bool,
+) -> Vec<(LinkeditKind, u64, u64)> {
+    let segment = ordinal;
+    let nlist_size = if is_64 { 16 } else { 12 };
+    let module_size = if is_64 { 56 } else { 52 };
+    let range = |kind, offset: u32, size: u32| (kind, offset as u64, size as u64);
+    // Counts come straight from the file, so the sizes are worked out in 64 bits.
+    let table = |kind, offset: u32, count: u32, entry_size: u64| {
+        (kind, offset as u64, count as u64 * entry_size)
+    };
+    let relocations = |(section, (reloff, nreloc)): (usize, (u32, u32))| {
+        table(
+            LinkeditKind::SectionRelocations { segment, section },
+            reloff,
+            nreloc,
+            8,
+        )
+    };
+
+    match cmd {
+        LoadCommand::Segment32(seg) => seg
+            .sects
+            .iter()
+            .map(|sect| (sect.reloff, sect.nreloc))
+            .enumerate()
+            .map(relocations)
+            .collect(),
+        LoadCommand::Segment64(seg) => seg
+            .sections
+            .iter()
+            .map(|sect| (sect.reloff, sect.nreloc))
+            .enumerate()
+            .map(relocations)
+            .collect(),
+        LoadCommand::Symtab(cmd) => vec![
+            table(LinkeditKind::Symbols, cmd.symoff, cmd.nsyms, nlist_size),
+            range(LinkeditKind::Strings, cmd.stroff, cmd.strsize),
+        ],
+        LoadCommand::Dysymtab(cmd) => vec![
+            table(LinkeditKind::TableOfContents, cmd.tocoff, cmd.ntoc, 8),
+            table(
+                LinkeditKind::ModuleTable,
+                cmd.modtaboff,
+                cmd.nmodtab,
+                module_size,
+            ),
+            table(
+                LinkeditKind::ExternalReferences,
+                cmd.extrefsymoff,
+                cmd.nextrefsyms,
+                4,
+            ),
+            table(
+                LinkeditKind::IndirectSymbols,
+                cmd.indirectsymoff,
+                cmd.nindirectsyms,
+                4,
+            ),
+            table(
+                LinkeditKind::ExternalRelocations,
+                cmd.extreloff,
+                cmd.nextrel,
+                8,
+            ),
+            table(
+                LinkeditKind::LocalRelocations,
+                cmd.locreloff,
+                cmd.nlocrel,
+                8,
+            ),
+        ],
+        LoadCommand::DyldInfo(cmd) | LoadCommand::DyldInfoOnly(cmd) => vec![
+            range(LinkeditKind::Rebase, cmd.rebase_off, cmd.rebase_size),
+            range(LinkeditKind::Bind, cmd.bind_off, cmd.bind_size),
+            range(
+                LinkeditKind::WeakBind,
+                cmd.weak_bind_off,
+                cmd.weak_bind_size,
+            ),
+            range(
+                LinkeditKind::LazyBind,
+                cmd.lazy_bind_off,
+                cmd.lazy_bind_size,
+            ),
+            range(LinkeditKind::Export, cmd.export_off, cmd.export_size),
+        ],
+        LoadCommand::CodeSignature(cmd) => vec![range(
+            LinkeditKind::CodeSignature,
+            cmd.cmd.dataoff,
+            cmd.cmd.datasize,
+        )],
+        LoadCommand::DyldExportsTrie(cmd) => vec![range(
+            LinkeditKind::ExportsTrie,
+            cmd.cmd.dataoff,
+            cmd.cmd.datasize,
+        )],
+        LoadCommand::DyldChainedFixups(cmd) => vec![range(
+            LinkeditKind::ChainedFixups,
+            cmd.cmd.dataoff,
+            cmd.cmd.datasize,
+        )],
+        LoadCommand::FunctionStarts(cmd) => vec![range(
+            LinkeditKind::FunctionStarts,
+            cmd.dataoff,
+            cmd.datasize,
+        )],
+        LoadCommand::DataInCode(cmd) => {
+            vec![range(LinkeditKind::DataInCode, cmd.dataoff, cmd.datasize)]
+        }
+        LoadCommand::SegmentSplitInfo(cmd) => vec![range(
+            LinkeditKind::SegmentSplitInfo,
+            cmd.dataoff,
+            cmd.datasize,
+        )],
+        LoadCommand::DylibCodeSignDrs(cmd) => vec![range(
+            LinkeditKind::DylibCodeSignDrs,
+            cmd.dataoff,
+            cmd.datasize,
+        )],
+        LoadCommand::LinkerOptimizationHint(cmd) => vec![range(
+            LinkeditKind::LinkerOptimizationHint,
+            cmd.dataoff,
+            cmd.datasize,
+        )],
+        LoadCommand::AtomInfo(cmd) => {
+            vec![range(LinkeditKind::AtomInfo, cmd.dataoff, cmd.datasize)]
+        }
+        LoadCommand::Note(cmd) => {
+            vec![(LinkeditKind::Note { index: ordinal }, cmd.offset, cmd.size)]
+        }
+        _ => vec![],
+    }
+}
+
+// Points the field for `kind` in `cmd` at `offset`, sizing it for `size` bytes.
+fn set_linkedit_range(
+    cmd: &mut LoadCommand,
+    kind: LinkeditKind,
+    offset: u32,
+    size: u32,
+    is_64: bool,
+) {
+    let nlist_size = if is_64 { 16 } else { 12 };
+    let module_size = if is_64 { 56 } else { 52 };
+    match (cmd, kind) {
+        (LoadCommand::Segment32(seg), LinkeditKind::SectionRelocations { section, .. }) => {
+            seg.sects[section].reloff = offset;
+            seg.sects[section].nreloc = size / 8;
+        }
+        (LoadCommand::Segment64(seg), LinkeditKind::SectionRelocations { section, .. }) => {
+            seg.sections[section].reloff = offset;
+            seg.sections[section].nreloc = size / 8;
+        }
+        (LoadCommand::Symtab(cmd), LinkeditKind::Symbols) => {
+            cmd.symoff = offset;
+            cmd.nsyms = size / nlist_size;
+        }
+        (LoadCommand::Symtab(cmd), LinkeditKind::Strings) => {
+            cmd.stroff = offset;
+            cmd.strsize = size;
+        }
+        (LoadCommand::Dysymtab(cmd), LinkeditKind::TableOfContents) => {
+            cmd.tocoff = offset;
+            cmd.ntoc = size / 8;
+        }
+        (LoadCommand::Dysymtab(cmd), LinkeditKind::ModuleTable) => {
+            cmd.modtaboff = offset;
+            cmd.nmodtab = size / module_size;
+        }
+        (LoadCommand::Dysymtab(cmd), LinkeditKind::ExternalReferences) => {
+            cmd.extrefsymoff = offset;
+            cmd.nextrefsyms = size / 4;
+        }
+        (LoadCommand::Dysymtab(cmd), LinkeditKind::IndirectSymbols) => {
+            cmd.indirectsymoff = offset;
+            cmd.nindirectsyms = size / 4;
+        }
+        (LoadCommand::Dysymtab(cmd), LinkeditKind::ExternalRelocations) => {
+            cmd.extreloff = offset;
+            cmd.nextrel = size / 8;
+        }
+        (LoadCommand::Dysymtab(cmd), LinkeditKind::LocalRelocations) => {
+            cmd.locreloff = offset;
+            cmd.nlocrel = size / 8;
+        }
+        (LoadCommand::DyldInfo(cmd) | LoadCommand::DyldInfoOnly(cmd), kind) => {
+            let (off, len) = match kind {
+                LinkeditKind::Rebase => (&mut cmd.rebase_off, &mut cmd.rebase_size),
+                LinkeditKind::Bind => (&mut cmd.bind_off, &mut cmd.bind_size),
+                LinkeditKind::WeakBind => (&mut cmd.weak_bind_off, &mut cmd.weak_bind_size),
+                LinkeditKind::LazyBind => (&mut cmd.lazy_bind_off, &mut cmd.lazy_bind_size),
+                LinkeditKind::Export => (&mut cmd.export_off, &mut cmd.export_size),
+                _ => return,
+            };
+            *off = offset;
+            *len = size;
+        }
+        (LoadCommand::CodeSignature(cmd), _) => {
+            cmd.cmd.dataoff = offset;
+            cmd.cmd.datasize = size;
+        }
+        (LoadCommand::DyldExportsTrie(cmd), _) => {
+            cmd.cmd.dataoff = offset;
+            cmd.cmd.datasize = size;
+        }
+        (LoadCommand::DyldChainedFixups(cmd), _) => {
+            cmd.cmd.dataoff = offset;
+            cmd.cmd.datasize = size;
+        }
+        (LoadCommand::FunctionStarts(cmd), _) => {
+            cmd.dataoff = offset;
+            cmd.datasize = size;
+        }
+        (
+            LoadCommand::DataInCode(cmd)
+            | LoadCommand::SegmentSplitInfo(cmd)
+            | LoadCommand::DylibCodeSignDrs(cmd)
+            | LoadCommand::LinkerOptimizationHint(cmd)
+            | LoadCommand::AtomInfo(cmd),
+            _,
+        ) => {
+            cmd.dataoff = offset;
+            cmd.datasize = size;
+        }
+        (LoadCommand::Note(cmd), _) => {
+            cmd.offset = offset as u64;
+            cmd.size = size as u64;
+        }
+        _ => {}
+    }
+}
+
+// Whether `cmd` points at a single blob and nothing else.
+fn is_blob_command(cmd: &LoadCommand) -> bool {
+    matches!(
+        cmd,
+        LoadCommand::CodeSignature(_)
+            | LoadCommand::DyldExportsTrie(_)
+            | LoadCommand::DyldChainedFixups(_)
+            | LoadCommand::FunctionStarts(_)
+            | LoadCommand::DataInCode(_)
+            | LoadCommand::SegmentSplitInfo(_)
+            | LoadCommand::DylibCodeSignDrs(_)
+            | LoadCommand::LinkerOptimizationHint(_)
+            | LoadCommand::AtomInfo(_)
+            | LoadCommand::Note(_)
+    )
+}
+
+/// A whole MachO as segment contents plus the blobs its load commands point at, which
+/// `build` lays out again. Blobs that didn't change keep their offsets, so an unmodified
+/// image builds back to the bytes it was parsed from.
+#[derive(Debug)]
+pub struct MachOImage {
+    editor: MachOEditor,
+    pub segments: Vec<SegmentData>,
+    /// In file order. Blobs added later go at the end of `__LINKEDIT`, before any signature.
+    pub linkedit: Vec<LinkeditBlob>,
+}
+
+impl MachOImage {
+    pub fn parse(data: Vec<u8>) -> MachOResult<Self> {
+        let editor = MachOEditor::parse(data)?;
+        let is_64 = matches!(editor.header, MachHeader::Header64(_));
+        let read = |offset: u64, size: u64, what: &str| {
+            offset
+                .checked_add(size)
+                .and_then(|end| editor.data.get(offset as usize..end as usize))
+                .map(|bytes| bytes.to_vec())
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!("{} extends past the end of the file", what))
+                })
+        };
+
+        let mut segments = Vec::new();
+        let mut linkedit = Vec::new();
+        let ordinals = command_ordinals(&editor);
+        for (cmd, &ordinal) in editor.load_commands().zip(&ordinals) {
+            if let Some((name, info)) = segment_info(cmd) {
+                let data = if name == "__LINKEDIT" {
+                    vec![]
+                } else {
+                    read(info.fileoff, info.filesize, name)?
+                };
+                segments.push(SegmentData {
+                    name: name.to_string(),
+                    data,
+                });
+            }
+            for (kind, offset, size) in linkedit_ranges(cmd, ordinal, is_64) {
+                if offset == 0 && size == 0 {
+                    continue;
+                }
+                linkedit.push(LinkeditBlob {
+                    kind,
+                    data: read(offset, size, &format!("{:?}", kind))?,
+                    original: Some((offset, size)),
+                });
+            }
+        }
+        linkedit.sort_by_key(|blob| blob.original);
+
+        Ok(Self {
+            editor,
+            segments,
+            linkedit,
+        })
+    }
+
+    pub fn header(&self) -> &MachHeader {
+        &self.editor.header
+    }
+
+    /// The load commands. File offsets and sizes of segments and blobs are filled in by
+    /// `build`, so only the rest of each command needs editing here.
+    pub fn commands(&self) -> &MachOEditor {
+        &self.editor
+    }
+
+    pub fn commands_mut(&mut self) -> &mut MachOEditor {
+        &mut self.editor
+    }
+
+    fn is_64(&self) -> bool {
+        matches!(self.editor.header, MachHeader::Header64(_))
+    }
+
+    pub fn segment(&self, name: &str) -> Option<&SegmentData> {
+        self.segments.iter().find(|seg| seg.name == name)
+    }
+
+    pub fn segment_mut(&mut self, name: &str) -> Option<&mut SegmentData> {
+        self.segments.iter_mut().find(|seg| seg.name == name)
+    }
+
+    fn section_range(&self, segname: &str, sectname: &str) -> Option<(usize, usize, usize)> {
+        let index = self.segments.iter().position(|seg| seg.name == segname)?;
+        let (_, info) = self
+            .editor
+            .load_commands()
+            .filter_map(segment_info)
+            .nth(index)?;
+        let (_, offset, size) = info.sections.iter().find(|(name, _, _)| name == sectname)?;
+        let start = offset.checked_sub(info.fileoff)?;
+        let end = start.checked_add(*size)?;
+        Some((index, start as usize, end as usize))
+    }
+
+    /// The file contents of a section, which can be edited in place.
+    pub fn section_data(&self, segname: &str, sectname: &str) -> Option<&[u8]> {
+        let (index, start, end) = self.section_range(segname, sectname)?;
+        self.segments[index].data.get(start..end)
+    }
+
+    pub fn section_data_mut(&mut self, segname: &str, sectname: &str) -> Option<&mut [u8]> {
+        let (index, start, end) = self.section_range(segname, sectname)?;
+        self.segments[index].data.get_mut(start..end)
+    }
+
+    pub fn linkedit_data(&self, kind: LinkeditKind) -> Option<&[u8]> {
+        self.linkedit
+            .iter()
+            .find(|blob| blob.kind == kind)
+            .map(|blob| blob.data.as_slice())
+    }
+
+    /// Replaces the blob for `kind`, or adds one. The load command that points at it must
+    /// already exist when the image is built.
+    pub fn set_linkedit_data(&mut self, kind: LinkeditKind, data: Vec<u8>) {
+        if let Some(blob) = self.linkedit.iter_mut().find(|blob| blob.kind == kind) {
+            blob.data = data;
+            return;
+        }
+        // The code signature has to stay last.
+        let index = self
+            .linkedit
+            .iter()
+            .position(|blob| blob.kind == LinkeditKind::CodeSignature)
+            .unwrap_or(self.linkedit.len());
+        self.linkedit.insert(
+            index,
+            LinkeditBlob {
+                kind,
+                data,
+                original: None,
+            },
+        );
+    }
+
+    fn blob_align(&self, kind: LinkeditKind) -> u64 {
+        match kind {
+            // codesign places the signature on a 16 byte boundary.
+            LinkeditKind::CodeSignature => 16,
+            _ if self.is_64() => 8,
+            _ => 4,
+        }
+    }
+
+    // The index of the command that owns `kind`.
+    fn owner(&self, kind: LinkeditKind) -> Option<usize> {
+        let is_64 = self.is_64();
+        let ordinals = command_ordinals(&self.editor);
+        self.editor
+            .load_commands()
+            .zip(ordinals)
+            .position(|(cmd, ordinal)| {
+                linkedit_ranges(cmd, ordinal, is_64)
+                    .iter()
+                    .any(|(owned, _, _)| *owned == kind)
+            })
+    }
+
+    fn commands_blob_count(&self) -> usize {
+        let is_64 = self.is_64();
+        let ordinals = command_ordinals(&self.editor);
+        self.editor
+            .load_commands()
+            .zip(ordinals)
+            .map(|(cmd, ordinal)| {
+                linkedit_ranges(cmd, ordinal, is_64)
+                    .iter()
+                    .filter(|(_, offset, size)| (*offset, *size) != (0, 0))
+                    .count()
+            })
+            .sum()
+    }
+
+    /// Lays the image out and produces the complete file.
+    pub fn build(&mut self) -> MachOResult<Vec<u8>> {
+        let is_64 = self.is_64();
+        let page_size = self.editor.page_size();
+
+        // Segments keep their offsets and only their sizes follow their contents.
+        let mut segment_indices = vec![];
+        let mut linkedit_segment = None;
+        let mut contents_end = 0;
+        let mut extents = vec![];
+        for (index, cmd) in self.editor.load_commands().enumerate() {
+            if let Some((name, info)) = segment_info(cmd) {
+                let data = match self.segments.get(segment_indices.len()) {
+                    Some(segment) if segment.name == name => &segment.data,
+                    _ => {
+                        return Err(MachOErr::InvalidValue(format!(
+                            "The segment commands and contents disagree at {}",
+                            name
+                        )))
+                    }
+                };
+                if name == "__LINKEDIT" {
+                    linkedit_segment = Some((index, info.fileoff, info.fileoff + info.filesize));
+                } else {
+                    if data.len() as u64 > info.vmsize {
+                        return Err(MachOErr::InvalidValue(format!(
+                            "{} has {:#x} bytes of contents but a vmsize of {:#x}",
+                            name,
+                            data.len(),
+                            info.vmsize
+                        )));
+                    }
+                    if !data.is_empty() {
+                        contents_end = contents_end.max(info.fileoff + data.len() as u64);
+                        extents.push((info.fileoff, info.fileoff + data.len() as u64, name));
+                    }
+                }
+                segment_indices.push(index);
+            }
+        }
+
+        extents.sort();
+        if let Some(pair) = extents.windows(2).find(|pair| pair[0].1 > pair[1].0) {
+            return Err(MachOErr::InvalidValue(format!(
+                "{} has grown into {}",
+                pair[0].2, pair[1].2
+            )));
+        }
+
+        let mut placed = Vec::with_capacity(self.linkedit.len());
+        // Whether any blob moved, changed size, or was removed.
+        let mut moved = self.commands_blob_count() != self.linkedit.len();
+        let mut delta: i64 = 0;
+        let mut end = contents_end;
+        for blob in &self.linkedit {
+            let align = self.blob_align(blob.kind);
+            let size = blob.data.len() as u64;
+            let offset = match blob.original {
+                // Empty blobs that were read that way keep their offset, as some linkers
+                // write one.
+                Some((offset, 0)) if size == 0 => (offset as i64 + delta) as u64,
+                _ if size == 0 => 0,
+                Some((offset, old_size)) => {
+                    let new_offset =
+                        ((offset as i64 + delta) as u64).max(end).div_ceil(align) * align;
+                    delta = new_offset as i64 - offset as i64
+                        + (size.div_ceil(align) * align) as i64
+                        - (old_size.div_ceil(align) * align) as i64;
+                    new_offset
+                }
+                None => end.div_ceil(align) * align,
+            };
+            if size > 0 {
+                end = offset + size;
+            }
+            moved |= blob.original != Some((offset, size));
+            placed.push((blob.kind, offset, size));
+        }
+
+        let mut out = vec![0u8; end as usize];
+        for (blob, (_, offset, _)) in self.linkedit.iter().zip(&placed) {
+            let offset = *offset as usize;
+            out[offset..offset + blob.data.len()].copy_from_slice(&blob.data);
+        }
+
+        for (segment, &index) in self.segments.iter().zip(&segment_indices) {
+            let (name, info) = segment_info(self.editor.get(index).unwrap()).unwrap();
+            let filesize = match linkedit_segment {
+                Some((linkedit, _, old_end)) if linkedit == index => {
+                    // An untouched __LINKEDIT keeps any padding at its end. Otherwise it ends
+                    // with the last blob, as ld64 lays it out.
+                    let new_end = if moved {
+                        end.div_ceil(8) * 8
+                    } else {
+                        old_end.max(end)
+                    };
+                    out.resize(out.len().max(new_end as usize), 0);
+                    new_end - info.fileoff
+                }
+                _ if name == "__LINKEDIT" => continue,
+                _ => {
+                    let start = info.fileoff as usize;
+                    out.resize(out.len().max(start + segment.data.len()), 0);
+                    // The header and load commands are written over this by `write`.
+                    out[start..start + segment.data.len()].copy_from_slice(&segment.data);
+                    segment.data.len() as u64
+                }
+            };
+            let vmsize = info.vmsize.max(filesize.div_ceil(page_size) * page_size);
+            if (filesize, vmsize) != (info.filesize, info.vmsize) {
+                match self.editor.get_mut(index) {
+                    Some(LoadCommand::Segment32(seg)) => {
+                        seg.filesize = to_u32(filesize)?;
+                        seg.vmsize = to_u32(vmsize)?;
+                    }
+                    Some(LoadCommand::Segment64(seg)) => {
+                        seg.filesize = filesize;
+                        seg.vmsize = vmsize;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let ordinals = command_ordinals(&self.editor);
+        for &(kind, offset, size) in &placed {
+            let owner = self.owner(kind).ok_or_else(|| {
+                MachOErr::InvalidValue(format!("No load command points at {:?}", kind))
+            })?;
+            let current = linkedit_ranges(self.editor.get(owner).unwrap(), ordinals[owner], is_64);
+            if !current.contains(&(kind, offset, size)) {
+                let cmd = self.editor.get_mut(owner).unwrap();
+                set_linkedit_range(cmd, kind, to_u32(offset)?, to_u32(size)?, is_64);
+            }
+        }
+
+        // Blobs that were removed from `linkedit` are no longer pointed at. Commands that
+        // only exist to point at one blob go with it.
+        for index in (0..self.editor.len()).rev() {
+            let removed: Vec<LinkeditKind> =
+                linkedit_ranges(self.editor.get(index).unwrap(), ordinals[index], is_64)
+                    .into_iter()
+                    .filter(|(kind, offset, size)| {
+                        (*offset, *size) != (0, 0)
+                            && !placed.iter().any(|(placed, _, _)| placed == kind)
+                    })
+                    .map(|(kind, _, _)| kind)
+                    .collect();
+            if !removed.is_empty() && is_blob_command(self.editor.get(index).unwrap()) {
+                self.editor.remove(index)?;
+                continue;
+            }
+            for kind in removed {
+                set_linkedit_range(self.editor.get_mut(index).unwrap(), kind, 0, 0, is_64);
+            }
+        }
+
+        self.editor.data = out;
+        let written = self.editor.write(HeaderOverflow::Fail)?;
+        *self = Self::parse(written.clone())?;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::function_starts::FunctionStartsCommand;
+    use crate::command::segment::{Section64, SectionAttributes};
+    use crate::command::{LCLoadCommand, LoadCommandParser};
+    use crate::header::MHFileType;
+    use crate::testing::{code_signature, image64, section64, segment64, symtab};
+
+    // __TEXT with a __text section at 0x3f00, and a __LINKEDIT holding function starts,
+    // a symbol, the strings and a signature, padded the way ld64 pads them.
+    fn image() -> Vec<u8> {
+        let text = Section64 {
+            align: 2,
+            flags_secattrs: SectionAttributes::PURE_INSTRUCTIONS,
+            ..section64("__TEXT", "__text", 0x3f00, 8, 0x3f00)
+        };
+        let function_starts = FunctionStartsCommand {
+            cmd: LCLoadCommand::LcFunctionStarts,
+            cmdsize: 16,
+            dataoff: 0x4000,
+            datasize: 8,
+        };
+        let mut bytes = image64(
+            MHFileType::MhExecute,
+            &[
+                segment64("__TEXT", 0, 0x4000, 0, 0x4000, vec![text]),
+                segment64("__LINKEDIT", 0x4000, 0x4000, 0x4000, 0x50, vec![]),
+                function_starts.serialize(),
+                symtab(0x4008, 1, 0x4018, 0xb),
+                code_signature(0x4030, 0x20),
+            ],
+        );
+        bytes.resize(0x3f00, 0);
+        bytes.extend(b"\x1f\x20\x03\xd5\xc0\x03\x5f\xd6");
+        bytes.resize(0x4000, 0);
+        bytes.extend(b"\x80\x7e\0\0\0\0\0\0");
+        bytes.extend(b"\x02\0\0\0\x0f\x01\0\0\0\x3f\0\0\0\0\0\0");
+        bytes.extend(b" \0_main\0\0\0\0");
+        bytes.resize(0x4030, 0);
+        bytes.extend([0xfa; 0x20]);
+        bytes
+    }
+
+    #[test]
+    fn test_image_round_trip() {
+        let mut image = MachOImage::parse(image()).unwrap();
+        assert_eq!(image.segments.len(), 2);
+        assert_eq!(image.linkedit.len(), 4);
+        assert_eq!(image.build().unwrap(), self::image());
+
+        // 0x1000_0000 symbols are 4GiB, which doesn't wrap to an empty table.
+        let mut huge = self::image();
+        let symtab = 32 + 152 + 72 + 16;
+        huge[symtab + 12..symtab + 16].copy_from_slice(&0x1000_0000u32.to_le_bytes());
+        assert!(MachOImage::parse(huge).is_err());
+    }
+
+    #[test]
+    fn test_image_relayout() {
+        let mut image = MachOImage::parse(image()).unwrap();
+        image
+            .section_data_mut("__TEXT", "__text")
+            .unwrap()
+            .copy_from_slice(&[0xc0, 0x03, 0x5f, 0xd6, 0x1f, 0x20, 0x03, 0xd5]);
+        image.set_linkedit_data(
+            LinkeditKind::FunctionStarts,
+            vec![0x80, 0x7e, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        );
+        image
+            .linkedit
+            .retain(|blob| blob.kind != LinkeditKind::CodeSignature);
+        let built = image.build().unwrap();
+
+        assert_eq!(&built[0x3f00..0x3f04], [0xc0, 0x03, 0x5f, 0xd6]);
+        assert_eq!(
+            image.linkedit_data(LinkeditKind::Strings).unwrap(),
+            b" \0_main\0\0\0\0"
+        );
+        assert_eq!(image.linkedit_data(LinkeditKind::CodeSignature), None);
+        let commands: Vec<_> = image.commands().load_commands().collect();
+        assert_eq!(commands.len(), 4);
+        assert!(!commands
+            .iter()
+            .any(|cmd| matches!(cmd, LoadCommand::CodeSignature(_))));
+        match (commands[1], commands[3]) {
+            (LoadCommand::Segment64(linkedit), LoadCommand::Symtab(symtab)) => {
+                assert_eq!((symtab.symoff, symtab.stroff), (0x4010, 0x4020));
+                assert_eq!(linkedit.filesize, 0x30);
+                assert_eq!(built.len(), 0x4030);
+            }
+            _ => panic!("Unexpected load commands"),
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use crate::command::dyld_exports_trie::DyldExport;
+use crate::command::dyld_info::{BindInstruction, RebaseInstruction};
+use crate::command::dysymtab::DysymtabCommand;
+use crate::command::function_starts::FunctionStartsCommand;
+use crate::command::symtab::{Nlist, NlistTypeType};
+use crate::command::{LCLoadCommand, LoadCommand};
+use crate::header::MachHeader;
+use crate::helpers::write_uleb;
+use crate::macho::{MachOErr, MachOResult};
+use crate::relocation::{Relocation, RelocationInfoBF};
+
+use super::layout::{LinkeditKind, MachOImage};
+use super::StringTable;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+impl MachOImage {
+    fn pointer_size(&self) -> usize {
+        match self.header() {
+            MachHeader::Header32(_) => 4,
+            MachHeader::Header64(_) => 8,
+        }
+    }
+
+    fn has_command(&self, predicate: impl Fn(&LoadCommand) -> bool) -> bool {
+        self.commands().load_commands().any(predicate)
+    }
+
+    /// The symbol table, with the names looked up in the string table.
+    pub fn symbols(&self) -> MachOResult<Vec<Nlist>> {
+        let is_64 = self.pointer_size() == 8;
+        let size = if is_64 { Nlist::SIZE } else { Nlist::SIZE_32 } as usize;
+        let symbols = self
+            .linkedit_data(LinkeditKind::Symbols)
+            .unwrap_or_default();
+        let strings = self
+            .linkedit_data(LinkeditKind::Strings)
+            .unwrap_or_default();
+        symbols
+            .chunks_exact(size)
+            .map(|raw| {
+                let strx = read_u32(raw, 0);
+                if strx as usize >= strings.len() {
+                    return Err(MachOErr::InvalidValue(format!(
+                        "The symbol name at {:#x} is past the end of the string table",
+                        strx
+                    )));
+                }
+                Ok(Nlist::parse(raw, strings, is_64)?.1)
+            })
+            .collect()
+    }
+
+    /// Replaces the symbol table and rebuilds the string table. Symbols are grouped the way
+    /// ld64 writes them: locals in the order given, then external definitions and undefined
+    /// symbols, each sorted by name. The dysymtab ranges follow the groups, and indirect
+    /// symbols and external relocations are pointed at the new symbol with the old one's name.
+    pub fn set_symbols(&mut self, symbols: &[Nlist]) -> MachOResult<()> {
+        let is_64 = self.pointer_size() == 8;
+        if !self.has_command(|cmd| matches!(cmd, LoadCommand::Symtab(_))) {
+            return Err(MachOErr::InvalidValue(
+                "The image has no symbol table".to_string(),
+            ));
+        }
+        let dysymtab = self
+            .commands()
+            .position(|cmd| matches!(cmd, LoadCommand::Dysymtab(_)));
+        if let Some(LoadCommand::Dysymtab(cmd)) = dysymtab.and_then(|i| self.commands().get(i)) {
+            if cmd.ntoc > 0 || cmd.nmodtab > 0 || cmd.nextrefsyms > 0 {
+                return Err(MachOErr::InvalidValue(
+                    "Rewriting the symbols of images with a module table isn't supported"
+                        .to_string(),
+                ));
+            }
+        }
+
+        let mut locals = vec![];
+        let mut extdefs = vec![];
+        let mut undefs = vec![];
+        for symbol in symbols {
+            let undefined = matches!(
+                symbol.n_type.type_,
+                NlistTypeType::Undefined | NlistTypeType::PreboundUndefined
+            );
+            match (symbol.n_type.stab || !symbol.n_type.ext, undefined) {
+                (true, _) => locals.push(symbol),
+                (false, false) => extdefs.push(symbol),
+                (false, true) => undefs.push(symbol),
+            }
+        }
+        extdefs.sort_by(|a, b| a.n_strx.cmp(&b.n_strx));
+        undefs.sort_by(|a, b| a.n_strx.cmp(&b.n_strx));
+        let (nlocalsym, nextdefsym, nundefsym) = (
+            locals.len() as u32,
+            extdefs.len() as u32,
+            undefs.len() as u32,
+        );
+        let sorted: Vec<&Nlist> = locals.into_iter().chain(extdefs).chain(undefs).collect();
+
+        // Externals go in first, so a name that's also a local's refers to the external.
+        let mut by_name = HashMap::new();
+        let externals = sorted.iter().enumerate().skip(nlocalsym as usize);
+        let locals = sorted.iter().enumerate().take(nlocalsym as usize);
+        for (index, symbol) in externals.chain(locals) {
+            by_name
+                .entry(symbol.n_strx.as_str())
+                .or_insert(index as u32);
+        }
+        let old = self.symbols()?;
+        let new_index = |index: u32| match old.get(index as usize) {
+            Some(symbol) => by_name.get(symbol.n_strx.as_str()).copied().ok_or_else(|| {
+                MachOErr::InvalidValue(format!(
+                    "{} is used by the indirect symbol table or an external relocation",
+                    symbol.n_strx
+                ))
+            }),
+            None => Ok(index),
+        };
+
+        let indirect_flags =
+            DysymtabCommand::INDIRECT_SYMBOL_LOCAL | DysymtabCommand::INDIRECT_SYMBOL_ABS;
+        let indirect = match self.linkedit_data(LinkeditKind::IndirectSymbols) {
+            Some(indirect) => {
+                let mut new_indirect = Vec::with_capacity(indirect.len());
+                for entry in indirect.chunks_exact(4) {
+                    let index = read_u32(entry, 0);
+                    let index = if index & indirect_flags != 0 {
+                        index
+                    } else {
+                        new_index(index)?
+                    };
+                    new_indirect.extend(index.to_le_bytes());
+                }
+                Some(new_indirect)
+            }
+            None => None,
+        };
+        let extrel = match self.linkedit_data(LinkeditKind::ExternalRelocations) {
+            Some(extrel) => {
+                let mut new_extrel = extrel.to_vec();
+                for reloc in new_extrel.chunks_exact_mut(Relocation::SIZE) {
+                    let mut info = RelocationInfoBF(read_u32(reloc, 4));
+                    if info.is_extern() {
+                        info.set_symbolnum(new_index(info.symbolnum())?);
+                        reloc[4..].copy_from_slice(&info.0.to_le_bytes());
+                    }
+                }
+                Some(new_extrel)
+            }
+            None => None,
+        };
+
+        let mut strings = StringTable::new();
+        let mut table = vec![];
+        for symbol in &sorted {
+            table.extend(symbol.serialize(strings.add(&symbol.n_strx), is_64));
+        }
+        self.set_linkedit_data(LinkeditKind::Symbols, table);
+        self.set_linkedit_data(LinkeditKind::Strings, strings.finish(self.pointer_size()));
+        if let Some(indirect) = indirect {
+            self.set_linkedit_data(LinkeditKind::IndirectSymbols, indirect);
+        }
+        if let Some(extrel) = extrel {
+            self.set_linkedit_data(LinkeditKind::ExternalRelocations, extrel);
+        }
+        if let Some(LoadCommand::Dysymtab(cmd)) =
+            dysymtab.and_then(|i| self.commands_mut().get_mut(i))
+        {
+            cmd.ilocalsym = 0;
+            cmd.nlocalsym = nlocalsym;
+            cmd.iextdefsym = nlocalsym;
+            cmd.nextdefsym = nextdefsym;
+            cmd.iundefsym = nlocalsym + nextdefsym;
+            cmd.nundefsym = nundefsym;
+        }
+        Ok(())
+    }
+
+    /// Encodes `exports` as a trie for `LC_DYLD_EXPORTS_TRIE`, or for the export stream of
+    /// images that keep their exports in the dyld info.
+    pub fn set_exports(&mut self, exports: &[DyldExport]) -> MachOResult<()> {
+        let kind = if self.has_command(|cmd| matches!(cmd, LoadCommand::DyldExportsTrie(_))) {
+            LinkeditKind::ExportsTrie
+        } else if self.has_dyld_info() {
+            LinkeditKind::Export
+        } else {
+            return Err(MachOErr::InvalidValue(
+                "The image has neither an exports trie nor dyld info".to_string(),
+            ));
+        };
+        self.set_linkedit_data(kind, DyldExport::build_trie(exports)?);
+        Ok(())
+    }
+
+    fn has_dyld_info(&self) -> bool {
+        self.has_command(|cmd| {
+            matches!(cmd, LoadCommand::DyldInfo(_) | LoadCommand::DyldInfoOnly(_))
+        })
+    }
+
+    // The opcode streams are encoded with 8-byte pointers, as `RebaseInstruction::parse`
+    // reads them.
+    fn set_dyld_info_stream(&mut self, kind: LinkeditKind, data: Vec<u8>) -> MachOResult<()> {
+        if !self.has_dyld_info() {
+            return Err(MachOErr::InvalidValue(
+                "The image has no dyld info".to_string(),
+            ));
+        }
+        if self.pointer_size() != 8 {
+            return Err(MachOErr::InvalidValue(
+                "Dyld info can only be written for 64-bit images".to_string(),
+            ));
+        }
+        self.set_linkedit_data(kind, data);
+        Ok(())
+    }
+
+    pub fn set_rebases(&mut self, rebases: &[RebaseInstruction]) -> MachOResult<()> {
+        self.set_dyld_info_stream(LinkeditKind::Rebase, RebaseInstruction::serialize(rebases)?)
+    }
+
+    pub fn set_binds(&mut self, binds: &[BindInstruction]) -> MachOResult<()> {
+        self.set_dyld_info_stream(LinkeditKind::Bind, BindInstruction::serialize(binds)?)
+    }
+
+    /// `strong_definitions` are the symbols this image defines that override weak
+    /// definitions elsewhere.
+    pub fn set_weak_binds(
+        &mut self,
+        binds: &[BindInstruction],
+        strong_definitions: &[String],
+    ) -> MachOResult<()> {
+        self.set_dyld_info_stream(
+            LinkeditKind::WeakBind,
+            BindInstruction::serialize_weak(binds, strong_definitions)?,
+        )
+    }
+
+    /// Returns the offset of each bind in the lazy bind stream, which the stub helpers pass
+    /// to dyld.
+    pub fn set_lazy_binds(&mut self, binds: &[BindInstruction]) -> MachOResult<Vec<u32>> {
+        let (data, offsets) = BindInstruction::serialize_lazy(binds)?;
+        self.set_dyld_info_stream(LinkeditKind::LazyBind, data)?;
+        Ok(offsets)
+    }
+
+    /// Encodes the start of each function, as an offset from the start of `__TEXT`, adding
+    /// `LC_FUNCTION_STARTS` if there isn't one.
+    pub fn set_function_starts(&mut self, offsets: &[u64]) -> MachOResult<()> {
+        let mut sorted = offsets.to_vec();
+        sorted.sort();
+        sorted.dedup();
+        if sorted.first() == Some(&0) {
+            return Err(MachOErr::InvalidValue(
+                "A function can't start at the Mach header".to_string(),
+            ));
+        }
+        let mut data = vec![];
+        let mut last = 0;
+        for offset in sorted {
+            write_uleb(&mut data, offset - last);
+            last = offset;
+        }
+        data.push(0);
+        data.resize(
+            data.len().div_ceil(self.pointer_size()) * self.pointer_size(),
+            0,
+        );
+
+        if !self.has_command(|cmd| matches!(cmd, LoadCommand::FunctionStarts(_))) {
+            self.commands_mut()
+                .push(LoadCommand::FunctionStarts(FunctionStartsCommand {
+                    cmd: LCLoadCommand::LcFunctionStarts,
+                    cmdsize: 16,
+                    dataoff: 0,
+                    datasize: 0,
+                }));
+        }
+        self.set_linkedit_data(LinkeditKind::FunctionStarts, data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::symtab::{NlistDesc, NlistReferenceType, NlistType};
+    use crate::command::LoadCommandParser;
+    use crate::header::MHFileType;
+    use crate::testing::{dyld_info, dysymtab, image32, image64, segment32, segment64, symtab};
+
+    fn symbol(name: &str, type_: NlistTypeType, ext: bool, n_value: u64) -> Nlist {
+        Nlist {
+            n_strx: name.to_string(),
+            n_type: NlistType {
+                stab: false,
+                stab_type: 0,
+                pext: false,
+                type_,
+                ext,
+            },
+            n_sect: if type_ == NlistTypeType::Section {
+                1
+            } else {
+                0
+            },
+            n_desc: NlistDesc {
+                reference_type: NlistReferenceType::UndefinedNonLazy,
+                arm_thumb_def: false,
+                referenced_dynamically: false,
+                no_dead_strip: false,
+                n_weak_ref: false,
+                n_weak_def: false,
+                library_ordinal: if ext && n_value == 0 { 1 } else { 0 },
+            },
+            n_value,
+        }
+    }
+
+    // An executable whose __LINKEDIT holds an unsorted symbol table (_puts, _helper, _main),
+    // an indirect symbol table pointing at _puts and at an absolute local, and the strings.
+    fn image() -> Vec<u8> {
+        let dysymtab = DysymtabCommand {
+            nlocalsym: 1,
+            iextdefsym: 1,
+            nextdefsym: 1,
+            iundefsym: 2,
+            nundefsym: 1,
+            indirectsymoff: 0x4030,
+            nindirectsyms: 2,
+            ..dysymtab()
+        };
+        let mut bytes = image64(
+            MHFileType::MhExecute,
+            &[
+                segment64("__TEXT", 0, 0x4000, 0, 0x4000, vec![]),
+                segment64("__LINKEDIT", 0x4000, 0x4000, 0x4000, 0x50, vec![]),
+                symtab(0x4000, 3, 0x4038, 0x18),
+                dysymtab.serialize(),
+            ],
+        );
+        bytes.resize(0x4000, 0);
+        bytes.extend(symbol("_puts", NlistTypeType::Undefined, true, 0).serialize(2, true));
+        bytes.extend(symbol("_helper", NlistTypeType::Section, false, 0x3f00).serialize(8, true));
+        bytes.extend(symbol("_main", NlistTypeType::Section, true, 0x3f04).serialize(16, true));
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(
+            (DysymtabCommand::INDIRECT_SYMBOL_LOCAL | DysymtabCommand::INDIRECT_SYMBOL_ABS)
+                .to_le_bytes(),
+        );
+        bytes.extend(b" \0_puts\0_helper\0_main\0\0\0");
+        bytes
+    }
+
+    #[test]
+    fn test_set_symbols() {
+        let mut image = MachOImage::parse(image()).unwrap();
+        let symbols = image.symbols().unwrap();
+        assert_eq!(symbols[0].n_strx, "_puts");
+        image.set_symbols(&symbols).unwrap();
+        image.build().unwrap();
+
+        let names: Vec<String> = image
+            .symbols()
+            .unwrap()
+            .into_iter()
+            .map(|symbol| symbol.n_strx)
+            .collect();
+        assert_eq!(names, ["_helper", "_main", "_puts"]);
+        assert_eq!(
+            image.linkedit_data(LinkeditKind::IndirectSymbols).unwrap(),
+            [2, 0, 0, 0, 0, 0, 0, 0xc0]
+        );
+        match image.commands().load_commands().nth(3) {
+            Some(LoadCommand::Dysymtab(cmd)) => assert_eq!(
+                (
+                    cmd.nlocalsym,
+                    cmd.iextdefsym,
+                    cmd.nextdefsym,
+                    cmd.iundefsym,
+                    cmd.nundefsym
+                ),
+                (1, 1, 1, 2, 1)
+            ),
+            _ => panic!("Expected the dysymtab"),
+        }
+
+        let dropped: Vec<Nlist> = symbols
+            .into_iter()
+            .filter(|s| s.n_strx != "_puts")
+            .collect();
+        assert!(image.set_symbols(&dropped).is_err());
+    }
+
+    #[test]
+    fn test_set_function_starts() {
+        let mut image = MachOImage::parse(image()).unwrap();
+        image.set_function_starts(&[0x3f04, 0x3f00]).unwrap();
+        image.build().unwrap();
+        assert_eq!(
+            image.linkedit_data(LinkeditKind::FunctionStarts).unwrap(),
+            [0x80, 0x7e, 4, 0, 0, 0, 0, 0]
+        );
+        assert!(image
+            .commands()
+            .load_commands()
+            .any(|cmd| matches!(cmd, LoadCommand::FunctionStarts(_))));
+        assert!(image.set_rebases(&[]).is_err());
+    }
+
+    #[test]
+    fn test_dyld_info_32() {
+        let mut bytes = image32(
+            MHFileType::MhExecute,
+            &[
+                segment32("__TEXT", 0, 0x1000, 0, 0x1000, vec![]),
+                segment32("__LINKEDIT", 0x1000, 0x1000, 0x1000, 0, vec![]),
+                dyld_info().serialize(),
+            ],
+        );
+        bytes.resize(0x1000, 0);
+        let mut image = MachOImage::parse(bytes).unwrap();
+        assert!(image.set_rebases(&[]).is_err());
+        assert!(image.set_binds(&[]).is_err());
+    }
+}
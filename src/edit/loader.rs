@@ -0,0 +1,298 @@
+use crate::command::LoadCommand;
+use crate::header::MachHeader;
+use crate::macho::{MachOErr, MachOResult};
+
+use super::chained_fixups::{ChainedFixup, FixupTarget};
+use super::layout::MachOImage;
+
+// The most memory `LoadedImage::load` maps, so a corrupt vmsize can't exhaust memory.
+const MAX_MAPPED_SIZE: u64 = 1 << 32;
+
+/// The symbol a bind points at, as passed to the resolver of `LoadedImage::load`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoundSymbol<'a> {
+    /// A dylib ordinal, or a special ordinal kept as its low byte (0xff for the main
+    /// executable, 0xfe for flat lookup and 0xfd for weak lookup).
+    pub library_ordinal: u8,
+    pub name: &'a str,
+    pub weak_import: bool,
+}
+
+/// A 64-bit image mapped the way dyld would map it, with its rebases slid and its binds
+/// resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedImage {
+    /// The slid address of the first byte of `data`, which is where the lowest segment
+    /// is mapped.
+    pub address: u64,
+    pub data: Vec<u8>,
+}
+
+impl LoadedImage {
+    /// Maps every segment but `__PAGEZERO` at its address plus `slide`, zero filling the
+    /// space past each segment's file contents, and applies the image's chained fixups or
+    /// dyld info. Lazy binds are bound straight away. `resolve` gives the address of a
+    /// symbol, or `None` if it isn't defined, which is only allowed for weak imports.
+    /// Signed arm64e pointers are written without their signature.
+    pub fn load<F>(data: Vec<u8>, slide: u64, mut resolve: F) -> MachOResult<Self>
+    where
+        F: FnMut(&BoundSymbol) -> Option<u64>,
+    {
+        let image = MachOImage::parse(data)?;
+        if !matches!(image.header(), MachHeader::Header64(_)) {
+            return Err(MachOErr::InvalidValue(
+                "Only 64-bit images can be loaded".to_string(),
+            ));
+        }
+
+        let segments: Vec<_> = image
+            .commands()
+            .load_commands()
+            .filter_map(|cmd| match cmd {
+                LoadCommand::Segment64(seg) if seg.vmsize > 0 => Some(seg),
+                _ => None,
+            })
+            .filter(|seg| !(seg.filesize == 0 && seg.initprot.is_empty()))
+            .collect();
+        for seg in &segments {
+            let invalid = |problem: &str| {
+                Err(MachOErr::InvalidValue(format!(
+                    "{} {}",
+                    seg.segname, problem
+                )))
+            };
+            if seg.filesize > seg.vmsize {
+                return invalid("has more file contents than its vmsize");
+            }
+            if seg.vmaddr.checked_add(seg.vmsize).is_none() {
+                return invalid("extends past the end of the address space");
+            }
+            if seg.fileoff.checked_add(seg.filesize).is_none() {
+                return invalid("extends past the end of the file");
+            }
+        }
+        let start = segments.iter().map(|seg| seg.vmaddr).min().unwrap_or(0);
+        let end = segments
+            .iter()
+            .map(|seg| seg.vmaddr + seg.vmsize)
+            .max()
+            .unwrap_or(0);
+        if end - start > MAX_MAPPED_SIZE {
+            return Err(MachOErr::InvalidValue(format!(
+                "The segments span {:#x} bytes, which is too much to map",
+                end - start
+            )));
+        }
+
+        let file = &image.commands().data;
+        let mut mapped = vec![];
+        mapped
+            .try_reserve_exact((end - start) as usize)
+            .map_err(|_| {
+                MachOErr::InvalidValue(format!("Couldn't allocate {:#x} bytes", end - start))
+            })?;
+        mapped.resize((end - start) as usize, 0);
+        for seg in &segments {
+            let contents = file
+                .get(seg.fileoff as usize..(seg.fileoff + seg.filesize) as usize)
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!(
+                        "{} extends past the end of the file",
+                        seg.segname
+                    ))
+                })?;
+            let offset = (seg.vmaddr - start) as usize;
+            mapped[offset..offset + contents.len()].copy_from_slice(contents);
+        }
+
+        let has_dyld_info = image
+            .commands()
+            .load_commands()
+            .any(|cmd| matches!(cmd, LoadCommand::DyldInfo(_) | LoadCommand::DyldInfoOnly(_)));
+        let fixups = if has_dyld_info {
+            image.chained_fixups_from_dyld_info(&image.dyld_info()?)?
+        } else {
+            image.chained_fixups()?
+        };
+
+        for ChainedFixup {
+            address, target, ..
+        } in fixups
+        {
+            let value = match target {
+                FixupTarget::Rebase { target, high8 } => {
+                    (high8 as u64) << 56 | target.wrapping_add(slide)
+                }
+                FixupTarget::Bind {
+                    library_ordinal,
+                    name,
+                    weak_import,
+                    addend,
+                } => {
+                    let symbol = BoundSymbol {
+                        library_ordinal,
+                        name: &name,
+                        weak_import,
+                    };
+                    match resolve(&symbol) {
+                        Some(address) => address.wrapping_add(addend as u64),
+                        None if weak_import => 0,
+                        None => {
+                            return Err(MachOErr::InvalidValue(format!(
+                                "Couldn't resolve {}",
+                                name
+                            )))
+                        }
+                    }
+                }
+            };
+            let offset = address
+                .checked_sub(start)
+                .map(|offset| offset as usize)
+                .filter(|offset| offset.checked_add(8).is_some_and(|end| end <= mapped.len()))
+                .ok_or_else(|| {
+                    MachOErr::InvalidValue(format!(
+                        "The fixup at {:#x} is outside the image",
+                        address
+                    ))
+                })?;
+            mapped[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+
+        Ok(Self {
+            address: start.wrapping_add(slide),
+            data: mapped,
+        })
+    }
+
+    /// Reads the pointer at a slid address.
+    pub fn read_u64(&self, address: u64) -> Option<u64> {
+        let offset = address.checked_sub(self.address)? as usize;
+        self.data
+            .get(offset..offset.checked_add(8)?)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::dyld_chained_fixup::{DyldFixupPACKey, DyldPointerFormat};
+    use crate::command::dyld_info::{
+        BindInstruction, BindSymbolFlags, BindType, DyldInfoCommand, RebaseInstruction, RebaseType,
+    };
+    use crate::command::LoadCommandParser;
+    use crate::edit::chained_fixups::PointerAuth;
+    use crate::header::MHFileType;
+    use crate::testing::{dyld_info, image64, segment64};
+
+    const BASE: u64 = 0x1_0000_0000;
+
+    // An arm64 executable whose __DATA has a page of zero fill. Dyld info rebases its first
+    // two pointers, binds _printf after them and lazily binds the weak import _maybe.
+    fn image() -> Vec<u8> {
+        let rebase = |segment_offset| RebaseInstruction {
+            segment_index: 2,
+            segment_offset,
+            rebase_type: RebaseType::Pointer,
+        };
+        let bind = |dylib_ordinal, name: &str, symbol_flags, segment_offset| BindInstruction {
+            segment_index: 2,
+            segment_offset,
+            bind_type: BindType::Pointer,
+            dylib_ordinal,
+            symbol_name: name.to_string(),
+            symbol_flags,
+            addend: 0,
+        };
+        let rebases = RebaseInstruction::serialize(&[rebase(0), rebase(8)]).unwrap();
+        let binds = BindInstruction::serialize(&[bind(1, "_printf", 0, 0x10)]).unwrap();
+        let weak_import = BindSymbolFlags::WeakImport as u8;
+        let (lazy_binds, _) =
+            BindInstruction::serialize_lazy(&[bind(0xfe, "_maybe", weak_import, 0x18)]).unwrap();
+        let dyld_info = DyldInfoCommand {
+            rebase_off: 0x8000,
+            rebase_size: rebases.len() as u32,
+            bind_off: 0x8000 + rebases.len() as u32,
+            bind_size: binds.len() as u32,
+            lazy_bind_off: 0x8000 + (rebases.len() + binds.len()) as u32,
+            lazy_bind_size: lazy_binds.len() as u32,
+            ..dyld_info()
+        };
+        let linkedit = [rebases, binds, lazy_binds].concat();
+        let size = linkedit.len() as u64;
+        let mut bytes = image64(
+            MHFileType::MhExecute,
+            &[
+                segment64("__PAGEZERO", 0, BASE, 0, 0, vec![]),
+                segment64("__TEXT", BASE, 0x4000, 0, 0x4000, vec![]),
+                segment64("__DATA", BASE + 0x4000, 0x8000, 0x4000, 0x4000, vec![]),
+                segment64("__LINKEDIT", BASE + 0xc000, 0x4000, 0x8000, size, vec![]),
+                dyld_info.serialize(),
+            ],
+        );
+        bytes.resize(0x4000, 0);
+        bytes.extend((BASE + 0x3f00).to_le_bytes());
+        bytes.extend((BASE + 0x3f10).to_le_bytes());
+        bytes.resize(0x8000, 0);
+        bytes.extend(linkedit);
+        bytes
+    }
+
+    fn resolve(symbol: &BoundSymbol) -> Option<u64> {
+        match symbol.name {
+            "_printf" => Some(0x7000_0000),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_load_image() {
+        let slide = 0x10_0000;
+        let loaded = LoadedImage::load(image(), slide, resolve).unwrap();
+        let data = BASE + slide + 0x4000;
+        assert_eq!(loaded.address, BASE + slide);
+        assert_eq!(loaded.data.len(), 0x10000);
+        assert_eq!(loaded.read_u64(data), Some(BASE + slide + 0x3f00));
+        assert_eq!(loaded.read_u64(data + 8), Some(BASE + slide + 0x3f10));
+        assert_eq!(loaded.read_u64(data + 0x10), Some(0x7000_0000));
+        assert_eq!(loaded.read_u64(data + 0x18), Some(0));
+        assert_eq!(loaded.read_u64(data + 0x7ff8), Some(0));
+        assert!(LoadedImage::load(image(), slide, |_| None).is_err());
+
+        // The same fixups as signed arm64e chains load to the same pointers.
+        let mut image = MachOImage::parse(image()).unwrap();
+        image
+            .convert_to_chained_fixups(DyldPointerFormat::Arm64eUserland)
+            .unwrap();
+        let mut fixups = image.chained_fixups().unwrap();
+        assert_eq!(fixups.len(), 4);
+        fixups[0].auth = Some(PointerAuth {
+            key: DyldFixupPACKey::IA,
+            diversity: 0x1234,
+            addr_div: true,
+        });
+        image
+            .set_chained_fixups(DyldPointerFormat::Arm64eUserland, &fixups)
+            .unwrap();
+        assert_eq!(image.chained_fixups().unwrap(), fixups);
+
+        let chained = LoadedImage::load(image.build().unwrap(), slide, resolve).unwrap();
+        assert_eq!(chained.data[0x4000..0xc000], loaded.data[0x4000..0xc000]);
+    }
+
+    #[test]
+    fn test_load_invalid_segments() {
+        // Overwrites a field of the __DATA segment command.
+        let patch = |field: usize, value: u64| {
+            let mut bytes = image();
+            let segname = b"__DATA\0\0\0\0\0\0\0\0\0\0";
+            let cmd = bytes.windows(16).position(|w| w == segname).unwrap() - 8;
+            bytes[cmd + field..cmd + field + 8].copy_from_slice(&value.to_le_bytes());
+            bytes
+        };
+        for (field, value) in [(32, 0x10), (32, u64::MAX), (32, 1 << 40), (40, u64::MAX)] {
+            assert!(LoadedImage::load(patch(field, value), 0, resolve).is_err());
+        }
+    }
+}
@@ -0,0 +1,488 @@
+pub mod chained_fixups;
+pub mod code_signature;
+pub mod fat;
+pub mod insert_dylib;
+pub mod install_name;
+pub mod layout;
+pub mod linkedit;
+pub mod loader;
+pub mod note;
+pub mod object;
+pub mod platform;
+pub mod strip;
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use crate::command::{iterate_load_commands, LoadCommand, LoadCommandBase};
+use crate::header::MachHeader;
+use crate::macho::{MachOErr, MachOResult};
+
+// Normalises a `major[.minor[.revision]]` version to the three part form that load commands
+// pack into a u32.
+pub(crate) fn normalize_version(version: &str) -> MachOResult<String> {
+    let invalid = || MachOErr::InvalidValue(format!("Invalid version {:?}", version));
+
+    let parts = version
+        .split('.')
+        .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+        .collect::<MachOResult<Vec<_>>>()?;
+    if parts.len() > 3 || parts[0] > 0xffff || parts[1..].iter().any(|&part| part > 0xff) {
+        return Err(invalid());
+    }
+
+    let part = |i: usize| parts.get(i).copied().unwrap_or(0);
+    Ok(format!("{}.{}.{}", part(0), part(1), part(2)))
+}
+
+// A symbol string table laid out the way ld64 does it: it starts with " \0", so that index 1
+// is the empty name, and each name is stored once.
+pub(crate) struct StringTable {
+    bytes: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringTable {
+    pub(crate) fn new() -> Self {
+        Self {
+            bytes: b" \0".to_vec(),
+            offsets: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn add(&mut self, name: &str) -> u32 {
+        if name.is_empty() {
+            return 1;
+        }
+        if let Some(&offset) = self.offsets.get(name) {
+            return offset;
+        }
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend(name.as_bytes());
+        self.bytes.push(0);
+        self.offsets.insert(name.to_string(), offset);
+        offset
+    }
+
+    /// The table, padded to `align`.
+    pub(crate) fn finish(mut self, align: usize) -> Vec<u8> {
+        self.bytes
+            .resize(self.bytes.len().div_ceil(align) * align, 0);
+        self.bytes
+    }
+}
+
+/// What `MachOEditor::write` should do when the load commands no longer fit before the first
+/// byte of file content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderOverflow {
+    Fail,
+    /// Move the file content forward to make room. Only possible for images, such as object
+    /// files, whose segments don't map the mach header.
+    Shift,
+}
+
+#[derive(Debug)]
+struct EditorCommand {
+    command: LoadCommand,
+    // The command's original bytes, kept until it's modified so untouched commands are written
+    // back exactly as they were read.
+    raw: Option<Vec<u8>>,
+}
+
+impl EditorCommand {
+    fn serialize(&self) -> Vec<u8> {
+        match &self.raw {
+            Some(raw) => raw.clone(),
+            None => self.command.serialize(),
+        }
+    }
+}
+
+/// Edits the load commands of a thin MachO and writes the whole file back out.
+#[derive(Debug)]
+pub struct MachOEditor {
+    pub header: MachHeader,
+    commands: Vec<EditorCommand>,
+    pub(crate) data: Vec<u8>,
+    // End of the load commands in `data`, so stale bytes can be cleared when they shrink.
+    original_cmds_end: usize,
+}
+
+impl MachOEditor {
+    pub fn parse(data: Vec<u8>) -> MachOResult<Self> {
+        let mut cursor = Cursor::new(&data);
+        let header = MachHeader::parse(&mut cursor)?;
+        let cputype = *header.cputype();
+
+        let mut raws = Vec::new();
+        let cmds = iterate_load_commands(&mut cursor, header, |base, ldcmd| {
+            raws.push(ldcmd.to_vec());
+            LoadCommand::parse_for(base, ldcmd, cputype)
+        })?;
+
+        let commands = cmds
+            .into_iter()
+            .zip(raws)
+            .map(|(command, raw)| EditorCommand {
+                command,
+                raw: Some(raw),
+            })
+            .collect();
+
+        Ok(Self {
+            header,
+            commands,
+            data,
+            original_cmds_end: header.size() as usize + header.sizeofcmds() as usize,
+        })
+    }
+
+    pub fn new<T: Read + Seek>(buf: &mut T) -> MachOResult<Self> {
+        let mut data = Vec::new();
+        buf.seek(SeekFrom::Start(0)).map_err(MachOErr::IOError)?;
+        buf.read_to_end(&mut data).map_err(MachOErr::IOError)?;
+        Self::parse(data)
+    }
+
+    /// The original file contents, which `write` copies everything past the load commands from.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load_commands(&self) -> impl Iterator<Item = &LoadCommand> {
+        self.commands.iter().map(|cmd| &cmd.command)
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&LoadCommand> {
+        self.commands.get(index).map(|cmd| &cmd.command)
+    }
+
+    /// Mutable access to a command. It gets re-serialized when written, rather than copied.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut LoadCommand> {
+        self.commands.get_mut(index).map(|cmd| {
+            cmd.raw = None;
+            &mut cmd.command
+        })
+    }
+
+    pub fn position<P>(&self, mut predicate: P) -> Option<usize>
+    where
+        P: FnMut(&LoadCommand) -> bool,
+    {
+        self.commands.iter().position(|cmd| predicate(&cmd.command))
+    }
+
+    fn check_index(&self, index: usize, len: usize) -> MachOResult<()> {
+        if index >= len {
+            return Err(MachOErr::InvalidValue(format!(
+                "Load command index {} out of range",
+                index
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, index: usize, command: LoadCommand) -> MachOResult<()> {
+        self.check_index(index, self.commands.len() + 1)?;
+        self.commands
+            .insert(index, EditorCommand { command, raw: None });
+        Ok(())
+    }
+
+    pub fn push(&mut self, command: LoadCommand) {
+        self.commands.push(EditorCommand { command, raw: None });
+    }
+
+    pub fn remove(&mut self, index: usize) -> MachOResult<LoadCommand> {
+        self.check_index(index, self.commands.len())?;
+        Ok(self.commands.remove(index).command)
+    }
+
+    pub fn replace(&mut self, index: usize, command: LoadCommand) -> MachOResult<LoadCommand> {
+        self.check_index(index, self.commands.len())?;
+        let old = std::mem::replace(
+            &mut self.commands[index],
+            EditorCommand { command, raw: None },
+        );
+        Ok(old.command)
+    }
+
+    /// Moves the command at `from` so that it ends up at index `to`.
+    pub fn move_command(&mut self, from: usize, to: usize) -> MachOResult<()> {
+        self.check_index(from, self.commands.len())?;
+        self.check_index(to, self.commands.len())?;
+        let cmd = self.commands.remove(from);
+        self.commands.insert(to, cmd);
+        Ok(())
+    }
+
+    pub(crate) fn command_align(&self) -> usize {
+        match self.header {
+            MachHeader::Header32(_) => 4,
+            MachHeader::Header64(_) => 8,
+        }
+    }
+
+    /// The cmdsize of a command with `fixed` bytes before a trailing, null terminated string,
+    /// padded to the image's load command alignment.
+    pub(crate) fn string_cmdsize(&self, fixed: usize, string: &str) -> u32 {
+        let align = self.command_align();
+        ((fixed + string.len() + 1).div_ceil(align) * align) as u32
+    }
+
+    fn serialize_commands(&self) -> MachOResult<Vec<Vec<u8>>> {
+        let align = self.command_align();
+
+        self.commands
+            .iter()
+            .map(|cmd| {
+                let bytes = cmd.serialize();
+                let (_, base) = LoadCommandBase::parse(&bytes).map_err(|_| {
+                    MachOErr::InvalidValue(format!("{:?} can't be serialized", cmd.command))
+                })?;
+                if base.cmdsize as usize != bytes.len() || bytes.len() % align != 0 {
+                    return Err(MachOErr::InvalidValue(format!(
+                        "{:?} has cmdsize {} but serializes to {} bytes, which must be a multiple of {}",
+                        base.cmd,
+                        base.cmdsize,
+                        bytes.len(),
+                        align
+                    )));
+                }
+                Ok(bytes)
+            })
+            .collect()
+    }
+
+    /// Offset of the first byte of file content after the load commands.
+    pub fn data_start(&self) -> u64 {
+        self.load_commands()
+            .flat_map(|cmd| cmd.file_offsets())
+            .min()
+            .unwrap_or(self.data.len() as u64)
+            .min(self.data.len() as u64)
+    }
+
+    fn sizeofcmds(&self) -> MachOResult<u64> {
+        Ok(self
+            .serialize_commands()?
+            .iter()
+            .map(|cmd| cmd.len() as u64)
+            .sum())
+    }
+
+    /// Free space between the end of the load commands, as currently edited, and the file
+    /// content. Negative when the commands no longer fit.
+    pub fn header_padding(&self) -> MachOResult<i64> {
+        let cmds_end = self.header.size() as u64 + self.sizeofcmds()?;
+        Ok(self.data_start() as i64 - cmds_end as i64)
+    }
+
+    // The header is copied from the file, as `MachHeader` drops the capability bits of the
+    // cpusubtype, such as arm64e's pointer authentication ABI.
+    fn write_header(&self, out: &mut Vec<u8>, cmds: &[Vec<u8>]) {
+        let sizeofcmds: u32 = cmds.iter().map(|cmd| cmd.len() as u32).sum();
+        let mut header = self.data[..self.header.size() as usize].to_vec();
+        header[16..20].copy_from_slice(&(cmds.len() as u32).to_le_bytes());
+        header[20..24].copy_from_slice(&sizeofcmds.to_le_bytes());
+        header[24..28].copy_from_slice(&self.header.flags().bits().to_le_bytes());
+        out.extend(header);
+        for cmd in cmds {
+            out.extend(cmd);
+        }
+    }
+
+    /// Produces the complete edited file.
+    pub fn write(&self, overflow: HeaderOverflow) -> MachOResult<Vec<u8>> {
+        let cmds = self.serialize_commands()?;
+        let header_size = self.header.size() as usize;
+        let cmds_end = header_size + cmds.iter().map(|cmd| cmd.len()).sum::<usize>();
+        let data_start = self.data_start() as usize;
+
+        if cmds_end <= data_start {
+            let mut head = Vec::with_capacity(cmds_end);
+            self.write_header(&mut head, &cmds);
+
+            let mut out = self.data.clone();
+            out[..cmds_end].copy_from_slice(&head);
+            // Clear whatever the old commands left behind.
+            let old_end = self.original_cmds_end.min(data_start);
+            if old_end > cmds_end {
+                out[cmds_end..old_end].fill(0);
+            }
+            return Ok(out);
+        }
+
+        match overflow {
+            HeaderOverflow::Fail => Err(MachOErr::GenericError(format!(
+                "Load commands need {} bytes but only {} are available before file content",
+                cmds_end - header_size,
+                data_start.saturating_sub(header_size)
+            ))),
+            HeaderOverflow::Shift => self.write_shifted(cmds_end, data_start),
+        }
+    }
+
+    fn write_shifted(&self, cmds_end: usize, data_start: usize) -> MachOResult<Vec<u8>> {
+        let maps_header = self.load_commands().any(|cmd| match cmd {
+            LoadCommand::Segment32(seg) => seg.fileoff == 0 && seg.filesize > 0,
+            LoadCommand::Segment64(seg) => seg.fileoff == 0 && seg.filesize > 0,
+            _ => false,
+        });
+        if maps_header {
+            return Err(MachOErr::GenericError(
+                "Not enough header padding, and the file content can't be moved because a segment maps the mach header".to_string(),
+            ));
+        }
+
+        // Keep every section at its required alignment.
+        let align = self
+            .load_commands()
+            .flat_map(|cmd| match cmd {
+                LoadCommand::Segment32(seg) => seg.sects.iter().map(|s| s.align).collect(),
+                LoadCommand::Segment64(seg) => seg.sections.iter().map(|s| s.align).collect(),
+                _ => vec![],
+            })
+            .map(|align| 1usize << align.min(16))
+            .fold(8, usize::max);
+        let delta = (cmds_end - data_start).div_ceil(align) * align;
+
+        let cmds = self
+            .commands
+            .iter()
+            .map(|cmd| {
+                if cmd.command.file_offsets().is_empty() {
+                    return Ok(cmd.serialize());
+                }
+                let bytes = cmd.serialize();
+                let (_, base) = LoadCommandBase::parse(&bytes)?;
+                let mut command = LoadCommand::parse_for(base, &bytes, *self.header.cputype())?;
+                command.shift_file_offsets(data_start as u64, delta as u64)?;
+                Ok(command.serialize())
+            })
+            .collect::<MachOResult<Vec<_>>>()?;
+
+        let mut out = Vec::with_capacity(self.data.len() + delta);
+        self.write_header(&mut out, &cmds);
+        out.resize(data_start + delta, 0);
+        out.extend(&self.data[data_start..]);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::segment::{Section64, SectionAttributes};
+    use crate::command::{LCLoadCommand, RpathCommand, SymtabCommand};
+    use crate::header::MHFileType;
+    use crate::macho::MachO;
+    use crate::testing::{image64, section64, segment64, symtab};
+
+    // A header, a segment with one `__text` section at `text_off` and a symtab after it.
+    fn image(filetype: MHFileType, fileoff: u64, text_off: u32) -> Vec<u8> {
+        let section = Section64 {
+            align: 2,
+            flags_secattrs: SectionAttributes::PURE_INSTRUCTIONS,
+            ..section64("__TEXT", "__text", text_off as u64, 8, text_off)
+        };
+        let end = text_off as u64 + 8;
+        let segment = segment64("__TEXT", 0, end, fileoff, end - fileoff, vec![section]);
+        let symtab = symtab(0, 0, text_off + 8, 8);
+        let mut bytes = image64(filetype, &[segment, symtab]);
+        bytes.resize(text_off as usize, 0);
+        bytes.extend(b"\x1f\x20\x03\xd5\xc0\x03\x5f\xd6\0_main\0\0");
+        bytes
+    }
+
+    fn rpath() -> LoadCommand {
+        LoadCommand::Rpath(RpathCommand {
+            cmd: LCLoadCommand::LcRpath,
+            cmdsize: 40,
+            path: "@loader_path/Frameworks".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_edit_within_padding() {
+        let original = image(MHFileType::MhExecute, 0, 0x400);
+        let mut editor = MachOEditor::parse(original.clone()).unwrap();
+        assert_eq!(editor.write(HeaderOverflow::Fail).unwrap(), original);
+
+        editor.insert(1, rpath()).unwrap();
+        editor.move_command(1, 0).unwrap();
+        assert_eq!(editor.header_padding().unwrap(), 0x400 - 32 - 176 - 40);
+
+        let edited = editor.write(HeaderOverflow::Fail).unwrap();
+        assert_eq!(edited.len(), original.len());
+        let macho = MachO::parse(Cursor::new(edited.clone())).unwrap();
+        assert_eq!(macho.header.ncmds(), 3);
+        assert!(matches!(macho.load_commands[0], LoadCommand::Rpath(_)));
+
+        let mut editor = MachOEditor::parse(edited).unwrap();
+        editor.remove(0).unwrap();
+        assert_eq!(editor.write(HeaderOverflow::Fail).unwrap(), original);
+    }
+
+    #[test]
+    fn test_keep_cpusubtype_bits() {
+        let mut original = image(MHFileType::MhExecute, 0, 0x400);
+        original[8..12].copy_from_slice(&0x80000002u32.to_le_bytes());
+        let mut editor = MachOEditor::parse(original.clone()).unwrap();
+        assert_eq!(editor.write(HeaderOverflow::Fail).unwrap(), original);
+
+        editor.push(rpath());
+        let edited = editor.write(HeaderOverflow::Fail).unwrap();
+        assert_eq!(&edited[..16], &original[..16]);
+        assert_eq!(edited[16], 3);
+    }
+
+    #[test]
+    fn test_edit_overflow() {
+        // An executable with no padding can't grow its load commands.
+        let exe = image(MHFileType::MhExecute, 0, 0xd0);
+        let mut editor = MachOEditor::parse(exe).unwrap();
+        editor.push(rpath());
+        assert!(editor.write(HeaderOverflow::Fail).is_err());
+        assert!(editor.write(HeaderOverflow::Shift).is_err());
+
+        // An object file's content can be moved.
+        let object = image(MHFileType::MhObject, 0xd0, 0xd0);
+        let mut editor = MachOEditor::parse(object.clone()).unwrap();
+        editor.push(rpath());
+        assert!(editor.write(HeaderOverflow::Fail).is_err());
+
+        let shifted = editor.write(HeaderOverflow::Shift).unwrap();
+        assert_eq!(shifted.len(), object.len() + 40);
+        assert_eq!(&shifted[0xd0 + 40..], &object[0xd0..]);
+        let macho = MachO::parse(Cursor::new(shifted)).unwrap();
+        match (&macho.load_commands[0], &macho.load_commands[1]) {
+            (LoadCommand::Segment64(seg), LoadCommand::Symtab(symtab)) => {
+                assert_eq!(seg.fileoff, 0xd0 + 40);
+                assert_eq!(seg.sections[0].offset, 0xd0 + 40);
+                assert_eq!(symtab.stroff, 0xd8 + 40);
+            }
+            _ => panic!("Unexpected load commands"),
+        }
+
+        // A 32-bit offset can't be moved past 4 GiB.
+        let mut symtab = LoadCommand::Symtab(SymtabCommand {
+            cmd: LCLoadCommand::LcSymtab,
+            cmdsize: 24,
+            symoff: 0x1000,
+            nsyms: 0,
+            stroff: 0xffff_f000,
+            strsize: 8,
+        });
+        assert!(symtab.shift_file_offsets(0x1000, 0x4000).is_err());
+    }
+}
@@ -0,0 +1,182 @@
+use crate::command::note::NoteCommand;
+use crate::command::{LCLoadCommand, LoadCommand};
+use crate::macho::{MachOErr, MachOResult};
+
+use super::layout::{LinkeditKind, MachOImage};
+
+impl MachOImage {
+    // The command index and index among the notes of the `occurrence`th note from
+    // `data_owner`.
+    fn find_note(&self, data_owner: &str, occurrence: usize) -> Option<(usize, usize)> {
+        self.commands()
+            .load_commands()
+            .enumerate()
+            .filter(|(_, cmd)| matches!(cmd, LoadCommand::Note(_)))
+            .enumerate()
+            .filter(|(_, (_, cmd))| {
+                matches!(cmd, LoadCommand::Note(note) if note.data_owner == data_owner)
+            })
+            .nth(occurrence)
+            .map(|(note, (index, _))| (index, note))
+    }
+
+    fn no_note(data_owner: &str, occurrence: usize) -> MachOErr {
+        MachOErr::InvalidValue(format!(
+            "There's no note {} from {:?}",
+            occurrence, data_owner
+        ))
+    }
+
+    /// The payload of the `occurrence`th note from `data_owner`, counting from 0.
+    pub fn note(&self, data_owner: &str, occurrence: usize) -> Option<&[u8]> {
+        let (_, index) = self.find_note(data_owner, occurrence)?;
+        Some(
+            self.linkedit_data(LinkeditKind::Note { index })
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Attaches a note from `data_owner`, whose payload goes at the end of `__LINKEDIT`.
+    /// Returns which of the notes from `data_owner` it is.
+    pub fn add_note(&mut self, data_owner: &str, payload: Vec<u8>) -> MachOResult<usize> {
+        if data_owner.is_empty() || data_owner.len() > 16 {
+            return Err(MachOErr::InvalidValue(format!(
+                "The note owner {:?} isn't 1 to 16 bytes long",
+                data_owner
+            )));
+        }
+        let notes: Vec<&str> = self
+            .commands()
+            .load_commands()
+            .filter_map(|cmd| match cmd {
+                LoadCommand::Note(note) => Some(note.data_owner.as_str()),
+                _ => None,
+            })
+            .collect();
+        let index = notes.len();
+        let occurrence = notes.iter().filter(|&&owner| owner == data_owner).count();
+        self.commands_mut().push(LoadCommand::Note(NoteCommand {
+            cmd: LCLoadCommand::LcNote,
+            cmdsize: 40,
+            data_owner: data_owner.to_string(),
+            offset: 0,
+            size: 0,
+        }));
+        self.set_linkedit_data(LinkeditKind::Note { index }, payload);
+        Ok(occurrence)
+    }
+
+    /// Replaces the payload of the `occurrence`th note from `data_owner`.
+    pub fn set_note(
+        &mut self,
+        data_owner: &str,
+        occurrence: usize,
+        payload: Vec<u8>,
+    ) -> MachOResult<()> {
+        let (_, index) = self
+            .find_note(data_owner, occurrence)
+            .ok_or_else(|| Self::no_note(data_owner, occurrence))?;
+        self.set_linkedit_data(LinkeditKind::Note { index }, payload);
+        Ok(())
+    }
+
+    pub fn remove_note(&mut self, data_owner: &str, occurrence: usize) -> MachOResult<()> {
+        let (command, index) = self
+            .find_note(data_owner, occurrence)
+            .ok_or_else(|| Self::no_note(data_owner, occurrence))?;
+        self.commands_mut().remove(command)?;
+        self.linkedit
+            .retain(|blob| blob.kind != LinkeditKind::Note { index });
+        // The notes after it move up one.
+        for blob in &mut self.linkedit {
+            if let LinkeditKind::Note { index: later } = &mut blob.kind {
+                if *later > index {
+                    *later -= 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::note::{NotePayload, NoteRegistry};
+    use crate::header::MHFileType;
+    use crate::macho::MachO;
+    use crate::testing::{image64, segment64};
+    use std::io::Cursor;
+
+    // An executable with an empty __TEXT and __LINKEDIT.
+    fn image() -> Vec<u8> {
+        let mut bytes = image64(
+            MHFileType::MhExecute,
+            &[
+                segment64("__TEXT", 0, 0x4000, 0, 0x4000, vec![]),
+                segment64("__LINKEDIT", 0x4000, 0x4000, 0x4000, 0, vec![]),
+            ],
+        );
+        bytes.resize(0x4000, 0);
+        bytes
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct BuildInfo {
+        revision: u32,
+    }
+
+    #[test]
+    fn test_set_note() {
+        let mut image = MachOImage::parse(image()).unwrap();
+        assert_eq!(
+            image.add_note("com.example.bld", vec![7, 0, 0, 0]).unwrap(),
+            0
+        );
+        image.add_note("com.example.raw", vec![1, 2, 3]).unwrap();
+        assert_eq!(
+            image.add_note("com.example.bld", vec![8, 0, 0, 0]).unwrap(),
+            1
+        );
+        image
+            .set_note("com.example.bld", 0, vec![42, 0, 0, 0])
+            .unwrap();
+        assert!(image.set_note("com.example.bld", 2, vec![]).is_err());
+        assert!(image.add_note("com.example.too.long", vec![]).is_err());
+        let bytes = image.build().unwrap();
+        assert_eq!(image.note("com.example.bld", 0), Some(&[42, 0, 0, 0][..]));
+        assert_eq!(image.note("com.example.bld", 1), Some(&[8, 0, 0, 0][..]));
+
+        let mut registry = NoteRegistry::new();
+        registry.register("com.example.bld", |bytes| {
+            Ok(BuildInfo {
+                revision: u32::from_le_bytes(bytes.try_into().unwrap()),
+            })
+        });
+        let mut macho = MachO::parse(Cursor::new(bytes)).unwrap();
+        let notes = macho.resolve_notes(&registry).unwrap();
+        assert_eq!(notes.len(), 3);
+        assert_eq!(
+            notes[0].downcast_ref::<BuildInfo>(),
+            Some(&BuildInfo { revision: 42 })
+        );
+        assert_eq!(
+            notes[1],
+            NotePayload::Unknown {
+                data_owner: "com.example.raw".to_string(),
+                data: vec![1, 2, 3],
+            }
+        );
+        assert_eq!(
+            notes[2].downcast_ref::<BuildInfo>(),
+            Some(&BuildInfo { revision: 8 })
+        );
+
+        // Removing the first note from an owner leaves the second one alone.
+        image.remove_note("com.example.bld", 0).unwrap();
+        image.build().unwrap();
+        assert_eq!(image.note("com.example.bld", 0), Some(&[8, 0, 0, 0][..]));
+        assert_eq!(image.note("com.example.bld", 1), None);
+        assert_eq!(image.note("com.example.raw", 0), Some(&[1, 2, 3][..]));
+    }
+}
@@ -0,0 +1,414 @@
+use crate::command::build_version::Platform;
+use crate::command::dysymtab::DysymtabCommand;
+use crate::command::segment::{
+    Protection, SGFlags, Section64, SectionAttributes, SectionType, SegmentCommand64,
+};
+use crate::command::symtab::{Nlist, NlistTypeType};
+use crate::command::{LCLoadCommand, LoadCommandParser, SymtabCommand};
+use crate::header::{MHFileType, MHFlags, MHMagic, MachHeader64};
+use crate::machine::{CpuSubType, CpuType};
+use crate::macho::{MachOErr, MachOResult};
+use crate::relocation::{Arm64RelocType, Relocation, RelocationType};
+
+use super::platform::BuildTarget;
+use super::StringTable;
+
+#[derive(Debug, Clone)]
+pub struct ObjectSection {
+    /// The section header. `addr`, `offset`, `reloff` and `nreloc` are filled in when the
+    /// object is written, as is `size` unless the section is zerofill.
+    pub header: Section64,
+    pub data: Vec<u8>,
+    /// Extern relocations refer to symbols by their index in `ObjectFile::symbols`, the rest
+    /// by 1-based section ordinal.
+    pub relocations: Vec<Relocation>,
+}
+
+impl ObjectSection {
+    pub fn new(segname: &str, sectname: &str, align: u32, data: Vec<u8>) -> Self {
+        Self {
+            header: Section64 {
+                sectname: sectname.to_string(),
+                segname: segname.to_string(),
+                addr: 0,
+                size: data.len() as u64,
+                offset: 0,
+                align,
+                reloff: 0,
+                nreloc: 0,
+                flags_sectype: SectionType::SRegular,
+                flags_secattrs: SectionAttributes::empty(),
+                reserved1: 0,
+                reserved2: 0,
+                reserved3: 0,
+            },
+            data,
+            relocations: vec![],
+        }
+    }
+
+    fn is_zerofill(&self) -> bool {
+        matches!(
+            self.header.flags_sectype,
+            SectionType::SZeroFill | SectionType::SGbZeroFill | SectionType::SThreadLocalZeroFill
+        )
+    }
+}
+
+/// A relocatable object file, written as a single unnamed segment followed by relocations,
+/// `LC_SYMTAB` and `LC_DYSYMTAB` data, the way compilers lay them out.
+#[derive(Debug, Clone)]
+pub struct ObjectFile {
+    pub cputype: CpuType,
+    pub cpusubtype: CpuSubType,
+    pub flags: MHFlags,
+    pub target: BuildTarget,
+    pub sections: Vec<ObjectSection>,
+    /// Symbols in any order; they're sorted into locals, external definitions and undefined
+    /// symbols when written. `n_sect` is a 1-based index into `sections` and the `n_value` of
+    /// a section symbol is its offset in that section.
+    pub symbols: Vec<Nlist>,
+}
+
+impl ObjectFile {
+    pub fn new(cputype: CpuType, cpusubtype: CpuSubType, platform: Platform, minos: &str) -> Self {
+        Self {
+            cputype,
+            cpusubtype,
+            flags: MHFlags::MH_SUBSECTIONS_VIA_SYMBOLS,
+            target: BuildTarget {
+                platform,
+                minos: minos.to_string(),
+                sdk: "0.0.0".to_string(),
+                tools: vec![],
+            },
+            sections: vec![],
+            symbols: vec![],
+        }
+    }
+
+    // The order symbols are written in: locals in the order given, then external definitions
+    // and undefined symbols, each sorted by name.
+    fn symbol_order(&self) -> (Vec<usize>, u32, u32, u32) {
+        let is_undefined =
+            |symbol: &Nlist| !symbol.n_type.stab && symbol.n_type.type_ == NlistTypeType::Undefined;
+        let is_external = |symbol: &Nlist| !symbol.n_type.stab && symbol.n_type.ext;
+
+        let mut locals = vec![];
+        let mut extdefs = vec![];
+        let mut undefs = vec![];
+        for (i, symbol) in self.symbols.iter().enumerate() {
+            match (is_external(symbol), is_undefined(symbol)) {
+                (false, _) => locals.push(i),
+                (true, false) => extdefs.push(i),
+                (true, true) => undefs.push(i),
+            }
+        }
+        let by_name = |a: &usize, b: &usize| self.symbols[*a].n_strx.cmp(&self.symbols[*b].n_strx);
+        extdefs.sort_by(by_name);
+        undefs.sort_by(by_name);
+
+        let counts = (
+            locals.len() as u32,
+            extdefs.len() as u32,
+            undefs.len() as u32,
+        );
+        let order = [locals, extdefs, undefs].concat();
+        (order, counts.0, counts.1, counts.2)
+    }
+
+    pub fn write(&self) -> MachOResult<Vec<u8>> {
+        if !matches!(self.cputype, CpuType::X86_64 | CpuType::Arm64) {
+            return Err(MachOErr::InvalidValue(format!(
+                "Can't write {:?} object files, only x86_64 and arm64",
+                self.cputype
+            )));
+        }
+        let nsects = self.sections.len();
+        if let Some(i) = (1..nsects)
+            .find(|&i| self.sections[i - 1].is_zerofill() && !self.sections[i].is_zerofill())
+        {
+            return Err(MachOErr::InvalidValue(format!(
+                "Zerofill section {} must come after the sections with contents",
+                self.sections[i - 1].header.sectname
+            )));
+        }
+        for symbol in &self.symbols {
+            let in_section = !symbol.n_type.stab && symbol.n_type.type_ == NlistTypeType::Section;
+            if in_section && (symbol.n_sect == 0 || symbol.n_sect as usize > nsects) {
+                return Err(MachOErr::InvalidValue(format!(
+                    "{} is in section {}, but there are {} sections",
+                    symbol.n_strx, symbol.n_sect, nsects
+                )));
+            }
+        }
+
+        let (order, nlocalsym, nextdefsym, nundefsym) = self.symbol_order();
+        let mut new_index = vec![0u32; order.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old] = new as u32;
+        }
+
+        let build_version = self.target.build_version()?;
+        let segment_cmdsize = 72 + 80 * nsects as u32;
+        let sizeofcmds = segment_cmdsize + build_version.serialize().len() as u32 + 24 + 80;
+        let header_size = MachHeader64::SIZE as u64 + sizeofcmds as u64;
+
+        // Lay the sections out in address order, with file contents following the commands.
+        let mut sections: Vec<Section64> = vec![];
+        let mut addr = 0u64;
+        let mut offset = header_size;
+        let mut contents = vec![];
+        for section in &self.sections {
+            let align = 1u64 << section.header.align;
+            addr = addr.div_ceil(align) * align;
+            let mut header = Section64 {
+                addr,
+                ..section.header.clone()
+            };
+            if section.is_zerofill() {
+                header.offset = 0;
+            } else {
+                let aligned = offset.div_ceil(align) * align;
+                contents.resize(contents.len() + (aligned - offset) as usize, 0);
+                contents.extend(&section.data);
+                header.offset = aligned as u32;
+                header.size = section.data.len() as u64;
+                offset = aligned + header.size;
+            }
+            addr += header.size;
+            sections.push(header);
+        }
+        let file_size = offset - header_size;
+
+        // Relocations, with extern symbol numbers pointing at the sorted symbols.
+        let mut relocations = vec![];
+        for (section, header) in self.sections.iter().zip(sections.iter_mut()) {
+            if section.relocations.is_empty() {
+                continue;
+            }
+            header.reloff = (header_size + file_size) as u32 + relocations.len() as u32;
+            header.nreloc = section.relocations.len() as u32;
+            for relocation in &section.relocations {
+                let mut relocation = relocation.clone();
+                let valid = matches!(
+                    (self.cputype, relocation.r_type()),
+                    (CpuType::X86_64, RelocationType::X86_64(_))
+                        | (CpuType::Arm64, RelocationType::Arm64(_))
+                );
+                if !valid || matches!(relocation, Relocation::Scattered(_)) {
+                    return Err(MachOErr::InvalidValue(format!(
+                        "{:?} is not a {:?} relocation",
+                        relocation.r_type(),
+                        self.cputype
+                    )));
+                }
+                if let Relocation::Normal(info) = &mut relocation {
+                    // ARM64_RELOC_ADDEND keeps its addend in symbolnum.
+                    let addend = info.r_type == RelocationType::Arm64(Arm64RelocType::Addend);
+                    if info.is_extern && !addend {
+                        info.symbolnum =
+                            *new_index.get(info.symbolnum as usize).ok_or_else(|| {
+                                MachOErr::InvalidValue(format!(
+                                "Relocation in {} refers to symbol {}, but there are {} symbols",
+                                section.header.sectname,
+                                info.symbolnum,
+                                self.symbols.len()
+                            ))
+                            })?;
+                    }
+                }
+                relocations.extend(relocation.serialize());
+            }
+        }
+
+        let mut strings = StringTable::new();
+        let mut symbols = vec![];
+        for &i in &order {
+            let mut symbol = self.symbols[i].clone();
+            if !symbol.n_type.stab && symbol.n_type.type_ == NlistTypeType::Section {
+                symbol.n_value += sections[symbol.n_sect as usize - 1].addr;
+            }
+            symbols.extend(symbol.serialize(strings.add(&symbol.n_strx), true));
+        }
+        let strings = strings.finish(8);
+
+        let symoff = (header_size + file_size) as usize + relocations.len();
+        let symoff = symoff.div_ceil(8) * 8;
+        let stroff = symoff + symbols.len();
+
+        let segment = SegmentCommand64 {
+            cmd: LCLoadCommand::LcSegment64,
+            cmdsize: segment_cmdsize,
+            segname: String::new(),
+            vmaddr: 0,
+            vmsize: addr,
+            fileoff: header_size,
+            filesize: file_size,
+            maxprot: Protection::READ | Protection::WRITE | Protection::EXECUTE,
+            initprot: Protection::READ | Protection::WRITE | Protection::EXECUTE,
+            nsects: nsects as u32,
+            flags: SGFlags::empty(),
+            sections,
+        };
+        let symtab = SymtabCommand {
+            cmd: LCLoadCommand::LcSymtab,
+            cmdsize: 24,
+            symoff: symoff as u32,
+            nsyms: order.len() as u32,
+            stroff: stroff as u32,
+            strsize: strings.len() as u32,
+        };
+        let dysymtab = DysymtabCommand {
+            cmd: LCLoadCommand::LcDysymtab,
+            cmdsize: 80,
+            ilocalsym: 0,
+            nlocalsym,
+            iextdefsym: nlocalsym,
+            nextdefsym,
+            iundefsym: nlocalsym + nextdefsym,
+            nundefsym,
+            tocoff: 0,
+            ntoc: 0,
+            modtaboff: 0,
+            nmodtab: 0,
+            extrefsymoff: 0,
+            nextrefsyms: 0,
+            indirectsymoff: 0,
+            nindirectsyms: 0,
+            extreloff: 0,
+            nextrel: 0,
+            locreloff: 0,
+            nlocrel: 0,
+        };
+
+        let mut out = MachHeader64 {
+            magic: MHMagic::MhMagic64,
+            cputype: self.cputype,
+            cpusubtype: self.cpusubtype,
+            filetype: MHFileType::MhObject,
+            ncmds: 4,
+            sizeofcmds,
+            flags: self.flags,
+            reserved: 0,
+        }
+        .serialize();
+        out.extend(segment.serialize());
+        out.extend(build_version.serialize());
+        out.extend(symtab.serialize());
+        out.extend(dysymtab.serialize());
+        out.extend(contents);
+        out.extend(relocations);
+        out.resize(symoff, 0);
+        out.extend(symbols);
+        out.extend(strings);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::command::symtab::{NlistDesc, NlistReferenceType, NlistType};
+    use crate::command::LoadCommand;
+    use crate::machine::CpuSubTypeArm64;
+    use crate::macho::MachO;
+    use crate::relocation::RelocationInfo;
+
+    fn symbol(name: &str, type_: NlistTypeType, ext: bool, n_sect: u8, n_value: u64) -> Nlist {
+        Nlist {
+            n_strx: name.to_string(),
+            n_type: NlistType {
+                stab: false,
+                stab_type: 0,
+                pext: false,
+                type_,
+                ext,
+            },
+            n_sect,
+            n_desc: NlistDesc {
+                reference_type: NlistReferenceType::UndefinedNonLazy,
+                arm_thumb_def: false,
+                referenced_dynamically: false,
+                no_dead_strip: false,
+                n_weak_ref: false,
+                n_weak_def: false,
+                library_ordinal: 0,
+            },
+            n_value,
+        }
+    }
+
+    #[test]
+    fn test_write_object() {
+        let mut object = ObjectFile::new(
+            CpuType::Arm64,
+            CpuSubType::CpuSubTypeArm64(CpuSubTypeArm64::All),
+            Platform::MacOS,
+            "14.0",
+        );
+        // bl _printf; ret
+        let mut text = ObjectSection::new(
+            "__TEXT",
+            "__text",
+            2,
+            vec![0, 0, 0, 0x94, 0xc0, 3, 0x5f, 0xd6],
+        );
+        text.header.flags_secattrs =
+            SectionAttributes::PURE_INSTRUCTIONS | SectionAttributes::SOME_INSTRUCTIONS;
+        text.relocations.push(Relocation::Normal(RelocationInfo {
+            address: 0,
+            symbolnum: 0,
+            pcrel: true,
+            length: 2,
+            is_extern: true,
+            r_type: RelocationType::Arm64(Arm64RelocType::Branch26),
+        }));
+        let mut bss = ObjectSection::new("__DATA", "__bss", 3, vec![]);
+        bss.header.flags_sectype = SectionType::SZeroFill;
+        bss.header.size = 0x10;
+        object.sections = vec![text, bss];
+        object.symbols = vec![
+            symbol("_printf", NlistTypeType::Undefined, true, 0, 0),
+            symbol("_main", NlistTypeType::Section, true, 1, 0),
+            symbol("_buffer", NlistTypeType::Section, true, 2, 0),
+            symbol("ltmp0", NlistTypeType::Section, false, 1, 0),
+        ];
+
+        let bytes = object.write().unwrap();
+        let mut macho = MachO::parse(Cursor::new(bytes)).unwrap();
+        assert_eq!(*macho.header.filetype(), MHFileType::MhObject);
+        let names: Vec<_> = macho
+            .resolve_symtab()
+            .unwrap()
+            .symbols
+            .iter()
+            .map(|s| (s.n_strx.clone(), s.n_value))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("ltmp0".to_string(), 0),
+                ("_buffer".to_string(), 8),
+                ("_main".to_string(), 0),
+                ("_printf".to_string(), 0)
+            ]
+        );
+        let relocations = macho.resolve_section_relocations().unwrap();
+        match &relocations[0].relocations[0] {
+            Relocation::Normal(info) => assert_eq!(info.symbolnum, 3),
+            _ => panic!("Unexpected relocation"),
+        }
+        let dysymtab = macho.load_commands.iter().find_map(|lc| match lc {
+            LoadCommand::Dysymtab(cmd) => Some((cmd.nlocalsym, cmd.nextdefsym, cmd.nundefsym)),
+            _ => None,
+        });
+        assert_eq!(dysymtab, Some((1, 2, 1)));
+
+        // Zerofill sections have to come last.
+        object.sections.reverse();
+        assert!(object.write().is_err());
+    }
+}
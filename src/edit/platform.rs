@@ -0,0 +1,260 @@
+use crate::command::build_version::{BuildToolVersion, Platform};
+use crate::command::{BuildVersionCommand, LCLoadCommand, LoadCommand, VersionMinCommand};
+use crate::macho::{MachOErr, MachOResult};
+
+use super::{normalize_version, MachOEditor};
+
+/// A platform and deployment target, from either `LC_BUILD_VERSION` or `LC_VERSION_MIN_*`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildTarget {
+    pub platform: Platform,
+    pub minos: String,
+    pub sdk: String,
+    /// Always empty for targets read from `LC_VERSION_MIN_*`.
+    pub tools: Vec<BuildToolVersion>,
+}
+
+fn version_min_platform(cmd: LCLoadCommand) -> Option<Platform> {
+    match cmd {
+        LCLoadCommand::LcVersionMinMacosx => Some(Platform::MacOS),
+        LCLoadCommand::LcVersionMinIphoneos => Some(Platform::IOS),
+        LCLoadCommand::LcVersionMinTvos => Some(Platform::TvOS),
+        LCLoadCommand::LcVersionMinWatchos => Some(Platform::WatchOS),
+        _ => None,
+    }
+}
+
+fn is_version_command(cmd: &LoadCommand) -> bool {
+    matches!(
+        cmd,
+        LoadCommand::BuildVersion(_)
+            | LoadCommand::VersionMinMacosx(_)
+            | LoadCommand::VersionMinIphoneos(_)
+            | LoadCommand::VersionMinTvos(_)
+            | LoadCommand::VersionMinWatchos(_)
+    )
+}
+
+impl BuildTarget {
+    pub(super) fn build_version(&self) -> MachOResult<LoadCommand> {
+        let tools = self
+            .tools
+            .iter()
+            .map(|tool| {
+                Ok(BuildToolVersion {
+                    tool: tool.tool,
+                    version: normalize_version(&tool.version)?,
+                })
+            })
+            .collect::<MachOResult<Vec<_>>>()?;
+
+        Ok(LoadCommand::BuildVersion(BuildVersionCommand {
+            cmd: LCLoadCommand::LcBuildVersion,
+            cmdsize: 24 + 8 * tools.len() as u32,
+            platform: self.platform,
+            minos: normalize_version(&self.minos)?,
+            sdk: normalize_version(&self.sdk)?,
+            ntools: tools.len() as u32,
+            tools,
+        }))
+    }
+
+    fn version_min(&self) -> MachOResult<LoadCommand> {
+        let cmd = VersionMinCommand {
+            cmd: LCLoadCommand::None,
+            cmdsize: 16,
+            version: normalize_version(&self.minos)?,
+            sdk: normalize_version(&self.sdk)?,
+        };
+        Ok(match self.platform {
+            Platform::MacOS => LoadCommand::VersionMinMacosx(VersionMinCommand {
+                cmd: LCLoadCommand::LcVersionMinMacosx,
+                ..cmd
+            }),
+            Platform::IOS => LoadCommand::VersionMinIphoneos(VersionMinCommand {
+                cmd: LCLoadCommand::LcVersionMinIphoneos,
+                ..cmd
+            }),
+            Platform::TvOS => LoadCommand::VersionMinTvos(VersionMinCommand {
+                cmd: LCLoadCommand::LcVersionMinTvos,
+                ..cmd
+            }),
+            Platform::WatchOS => LoadCommand::VersionMinWatchos(VersionMinCommand {
+                cmd: LCLoadCommand::LcVersionMinWatchos,
+                ..cmd
+            }),
+            platform => {
+                return Err(MachOErr::InvalidValue(format!(
+                    "{} has no LC_VERSION_MIN command",
+                    platform
+                )))
+            }
+        })
+    }
+}
+
+impl MachOEditor {
+    pub fn build_targets(&self) -> Vec<BuildTarget> {
+        self.load_commands()
+            .filter_map(|cmd| match cmd {
+                LoadCommand::BuildVersion(build) => Some(BuildTarget {
+                    platform: build.platform,
+                    minos: build.minos.clone(),
+                    sdk: build.sdk.clone(),
+                    tools: build
+                        .tools
+                        .iter()
+                        .map(|tool| BuildToolVersion {
+                            tool: tool.tool,
+                            version: tool.version.clone(),
+                        })
+                        .collect(),
+                }),
+                LoadCommand::VersionMinMacosx(min)
+                | LoadCommand::VersionMinIphoneos(min)
+                | LoadCommand::VersionMinTvos(min)
+                | LoadCommand::VersionMinWatchos(min) => Some(BuildTarget {
+                    platform: version_min_platform(min.cmd)?,
+                    minos: min.version.clone(),
+                    sdk: min.sdk.clone(),
+                    tools: vec![],
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Swaps every version command for `cmd`, which takes the place of the first one.
+    fn replace_version_commands(&mut self, cmd: LoadCommand) -> MachOResult<()> {
+        let index = self.position(is_version_command);
+        while let Some(i) = self.position(is_version_command) {
+            self.remove(i)?;
+        }
+        match index {
+            Some(index) => self.insert(index, cmd),
+            None => {
+                self.push(cmd);
+                Ok(())
+            }
+        }
+    }
+
+    /// Retargets the image to a single `LC_BUILD_VERSION`, replacing any existing
+    /// `LC_BUILD_VERSION` or `LC_VERSION_MIN_*` commands.
+    pub fn set_build_version(&mut self, target: &BuildTarget) -> MachOResult<()> {
+        let cmd = target.build_version()?;
+        self.replace_version_commands(cmd)
+    }
+
+    /// Retargets the image to a single `LC_VERSION_MIN_*`, for deployment targets that predate
+    /// `LC_BUILD_VERSION`.
+    pub fn set_version_min(&mut self, target: &BuildTarget) -> MachOResult<()> {
+        let cmd = target.version_min()?;
+        self.replace_version_commands(cmd)
+    }
+
+    /// Adds an `LC_BUILD_VERSION` for another platform, as zippered Mac Catalyst images have.
+    pub fn add_build_version(&mut self, target: &BuildTarget) -> MachOResult<()> {
+        if self
+            .build_targets()
+            .iter()
+            .any(|existing| existing.platform == target.platform)
+        {
+            return Err(MachOErr::InvalidValue(format!(
+                "Image already has a build version for {}",
+                target.platform
+            )));
+        }
+
+        let cmd = target.build_version()?;
+        let index = (0..self.len())
+            .rev()
+            .find(|&i| self.get(i).is_some_and(is_version_command))
+            .map(|i| i + 1)
+            .unwrap_or(self.len());
+        self.insert(index, cmd)
+    }
+
+    /// Removes the `LC_BUILD_VERSION` or `LC_VERSION_MIN_*` for `platform`.
+    pub fn remove_build_version(&mut self, platform: Platform) -> MachOResult<()> {
+        let index = self
+            .position(|cmd| match cmd {
+                LoadCommand::BuildVersion(build) => build.platform == platform,
+                LoadCommand::VersionMinMacosx(min)
+                | LoadCommand::VersionMinIphoneos(min)
+                | LoadCommand::VersionMinTvos(min)
+                | LoadCommand::VersionMinWatchos(min) => {
+                    version_min_platform(min.cmd) == Some(platform)
+                }
+                _ => false,
+            })
+            .ok_or_else(|| {
+                MachOErr::InvalidValue(format!("Image has no build version for {}", platform))
+            })?;
+        self.remove(index)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::build_version::Tool;
+    use crate::command::LoadCommandParser;
+    use crate::edit::HeaderOverflow;
+    use crate::header::MHFileType;
+    use crate::testing::image64;
+
+    fn image() -> Vec<u8> {
+        let cmd = VersionMinCommand {
+            cmd: LCLoadCommand::LcVersionMinIphoneos,
+            cmdsize: 16,
+            version: "12.0.0".to_string(),
+            sdk: "16.4.0".to_string(),
+        }
+        .serialize();
+        let mut bytes = image64(MHFileType::MhDylib, &[cmd]);
+        bytes.resize(0x200, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_retarget_to_simulator() {
+        let mut editor = MachOEditor::parse(image()).unwrap();
+        assert_eq!(editor.build_targets()[0].platform, Platform::IOS);
+
+        let simulator = BuildTarget {
+            platform: Platform::IOSSimulator,
+            minos: "13".to_string(),
+            sdk: "17.2".to_string(),
+            tools: vec![BuildToolVersion {
+                tool: Tool::Ld,
+                version: "1053.12".to_string(),
+            }],
+        };
+        editor.set_build_version(&simulator).unwrap();
+        assert!(editor.add_build_version(&simulator).is_err());
+        assert!(editor.set_version_min(&simulator).is_err());
+
+        let written = editor.write(HeaderOverflow::Fail).unwrap();
+        let mut editor = MachOEditor::parse(written).unwrap();
+        assert_eq!(editor.len(), 1);
+        let targets = editor.build_targets();
+        assert_eq!(targets[0].platform, Platform::IOSSimulator);
+        assert_eq!(targets[0].minos, "13.0.0");
+        assert_eq!(targets[0].tools[0].version, "1053.12.0");
+
+        editor
+            .add_build_version(&BuildTarget {
+                platform: Platform::MacCatalyst,
+                minos: "14.0".to_string(),
+                sdk: "14.0".to_string(),
+                tools: vec![],
+            })
+            .unwrap();
+        editor.remove_build_version(Platform::IOSSimulator).unwrap();
+        let targets = editor.build_targets();
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].platform, Platform::MacCatalyst);
+    }
+}
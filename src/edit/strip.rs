@@ -0,0 +1,541 @@
+use crate::command::dysymtab::DysymtabCommand;
+use crate::command::symtab::{NlistDesc, NlistType, NlistTypeType};
+use crate::command::LoadCommand;
+use crate::header::{MHFileType, MachHeader};
+use crate::helpers::string_upto_null_terminator;
+use crate::macho::{MachOErr, MachOResult};
+use crate::relocation::{Relocation, RelocationInfoBF};
+
+use super::{MachOEditor, StringTable};
+
+/// Which symbols `MachOEditor::strip` removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripMode {
+    /// STABS debug entries, like `strip -S`.
+    Debug,
+    /// Debug entries and every non-global symbol, like `strip -x`.
+    Locals,
+    /// Everything the dynamic linker doesn't need, like `strip -u -r`: undefined symbols,
+    /// symbols used by the indirect symbol table or external relocations, and symbols marked
+    /// `REFERENCED_DYNAMICALLY` are kept. Dylibs and bundles also keep their exported symbols.
+    All,
+}
+
+// One nlist entry, kept as raw bytes so every field survives except the string index.
+struct Symbol {
+    raw: Vec<u8>,
+    name: String,
+}
+
+impl Symbol {
+    fn n_type(&self) -> u8 {
+        self.raw[4]
+    }
+
+    fn n_desc(&self) -> u16 {
+        u16::from_le_bytes([self.raw[6], self.raw[7]])
+    }
+
+    fn is_stab(&self) -> bool {
+        self.n_type() & NlistType::NLIST_TYPE_STAB_BITMASK != 0
+    }
+
+    fn is_external(&self) -> bool {
+        !self.is_stab() && self.n_type() & NlistType::NLIST_TYPE_EXT_BITMASK != 0
+    }
+
+    fn type_is(&self, type_: NlistTypeType) -> bool {
+        !self.is_stab() && self.n_type() & NlistTypeType::NLIST_TYPE_TYPE_BITMASK == type_ as u8
+    }
+}
+
+struct Symtab {
+    index: usize,
+    symoff: u32,
+    stroff: u32,
+    strsize: u32,
+    symbols: Vec<Symbol>,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn out_of_bounds(what: &str) -> MachOErr {
+    MachOErr::InvalidValue(format!("The {} extends past the end of the file", what))
+}
+
+impl MachOEditor {
+    fn nlist_size(&self) -> usize {
+        match self.header {
+            MachHeader::Header32(_) => 12,
+            MachHeader::Header64(_) => 16,
+        }
+    }
+
+    fn read_symtab(&self) -> MachOResult<Option<Symtab>> {
+        let index = match self.position(|cmd| matches!(cmd, LoadCommand::Symtab(_))) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+        let (symoff, nsyms, stroff, strsize) = match self.get(index) {
+            Some(LoadCommand::Symtab(symtab)) => {
+                (symtab.symoff, symtab.nsyms, symtab.stroff, symtab.strsize)
+            }
+            _ => unreachable!(),
+        };
+
+        let size = self.nlist_size();
+        let symbols_end = symoff as usize + nsyms as usize * size;
+        let strings_end = stroff as usize + strsize as usize;
+        if symbols_end > self.data.len() {
+            return Err(out_of_bounds("symbol table"));
+        }
+        if strings_end > self.data.len() {
+            return Err(out_of_bounds("string table"));
+        }
+        let strings = &self.data[stroff as usize..strings_end];
+
+        let symbols = self.data[symoff as usize..symbols_end]
+            .chunks(size)
+            .map(|raw| {
+                let strx = read_u32(raw, 0) as usize;
+                let name = match strings.get(strx..) {
+                    Some(bytes) => string_upto_null_terminator(bytes)
+                        .map(|(_, name)| name)
+                        .unwrap_or_default(),
+                    None => String::new(),
+                };
+                Symbol {
+                    raw: raw.to_vec(),
+                    name,
+                }
+            })
+            .collect();
+
+        Ok(Some(Symtab {
+            index,
+            symoff,
+            stroff,
+            strsize,
+            symbols,
+        }))
+    }
+
+    fn dysymtab(&self) -> Option<(usize, &DysymtabCommand)> {
+        let index = self.position(|cmd| matches!(cmd, LoadCommand::Dysymtab(_)))?;
+        match self.get(index) {
+            Some(LoadCommand::Dysymtab(dysymtab)) => Some((index, dysymtab)),
+            _ => None,
+        }
+    }
+
+    fn read_table(
+        &self,
+        offset: u32,
+        count: u32,
+        entry_size: usize,
+        what: &str,
+    ) -> MachOResult<Vec<u8>> {
+        let start = offset as usize;
+        let end = start + count as usize * entry_size;
+        self.data
+            .get(start..end)
+            .map(|bytes| bytes.to_vec())
+            .ok_or_else(|| out_of_bounds(what))
+    }
+
+    // Places `tables` at their old offsets when they don't sit at the end of the file, otherwise
+    // packs them at the start of the tail and shrinks the segment holding them.
+    fn write_tables(&mut self, tables: &mut [(u32, u32, Vec<u8>)]) -> MachOResult<()> {
+        let tail_start = tables
+            .iter()
+            .filter(|(_, old_size, _)| *old_size > 0)
+            .map(|(offset, _, _)| *offset as u64)
+            .min();
+        let in_tail = |offset: u64| tail_start.is_some_and(|start| offset > start);
+        // Nothing but the tables may start past the first of them, which is where __LINKEDIT
+        // itself can start. The relocations stay where they are.
+        let others_in_tail = self
+            .load_commands()
+            .flat_map(|cmd| match cmd {
+                LoadCommand::Symtab(_) => vec![],
+                LoadCommand::Dysymtab(dysymtab) => [dysymtab.extreloff, dysymtab.locreloff]
+                    .into_iter()
+                    .filter(|&offset| offset != 0)
+                    .map(u64::from)
+                    .collect(),
+                cmd => cmd.file_offsets(),
+            })
+            .any(in_tail);
+        let linkedit = self.position(|cmd| match cmd {
+            LoadCommand::Segment32(seg) => {
+                seg.filesize > 0 && (seg.fileoff + seg.filesize) as usize == self.data.len()
+            }
+            LoadCommand::Segment64(seg) => {
+                seg.filesize > 0 && (seg.fileoff + seg.filesize) as usize == self.data.len()
+            }
+            _ => false,
+        });
+
+        let (tail_start, linkedit) = match (tail_start, linkedit) {
+            (Some(start), Some(linkedit)) if !others_in_tail => (start, linkedit),
+            _ => {
+                for (offset, old_size, bytes) in tables.iter() {
+                    if bytes.len() > *old_size as usize {
+                        return Err(MachOErr::GenericError(format!(
+                            "Rebuilt table at {:#x} doesn't fit in its old space",
+                            offset
+                        )));
+                    }
+                    let start = *offset as usize;
+                    self.data[start..start + *old_size as usize].fill(0);
+                    self.data[start..start + bytes.len()].copy_from_slice(bytes);
+                }
+                return Ok(());
+            }
+        };
+
+        let align = self.command_align();
+        self.data.truncate(tail_start as usize);
+        let mut order: Vec<usize> = (0..tables.len()).collect();
+        order.sort_by_key(|&i| tables[i].0);
+        for i in order {
+            let (offset, _, bytes) = &mut tables[i];
+            *offset = if bytes.is_empty() {
+                0
+            } else {
+                self.data.len() as u32
+            };
+            self.data.extend(bytes.iter());
+            self.data.resize(self.data.len().div_ceil(align) * align, 0);
+        }
+
+        let end = self.data.len() as u64;
+        match self.get_mut(linkedit) {
+            Some(LoadCommand::Segment32(seg)) => seg.filesize = end as u32 - seg.fileoff,
+            Some(LoadCommand::Segment64(seg)) => seg.filesize = end - seg.fileoff,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Removes symbols according to `mode` and rebuilds the string table, dysymtab ranges,
+    /// indirect symbol table and external relocations to match, shrinking `__LINKEDIT` when the
+    /// tables are at its end. The code signature is removed since it no longer matches, so
+    /// sign the image again afterwards. Returns the number of symbols removed.
+    pub fn strip(&mut self, mode: StripMode) -> MachOResult<usize> {
+        if *self.header.filetype() == MHFileType::MhObject {
+            return Err(MachOErr::InvalidValue(
+                "Stripping object files isn't supported".to_string(),
+            ));
+        }
+        if let Some((_, dysymtab)) = self.dysymtab() {
+            if dysymtab.ntoc > 0 || dysymtab.nmodtab > 0 || dysymtab.nextrefsyms > 0 {
+                return Err(MachOErr::InvalidValue(
+                    "Stripping images with a module table isn't supported".to_string(),
+                ));
+            }
+        }
+
+        let symtab = match self.read_symtab()? {
+            Some(symtab) => symtab,
+            None => return Ok(0),
+        };
+        let nsyms = symtab.symbols.len();
+
+        let (indirect, extrel) = match self.dysymtab() {
+            Some((_, dysymtab)) => (
+                self.read_table(
+                    dysymtab.indirectsymoff,
+                    dysymtab.nindirectsyms,
+                    4,
+                    "indirect symbol table",
+                )?,
+                self.read_table(
+                    dysymtab.extreloff,
+                    dysymtab.nextrel,
+                    Relocation::SIZE,
+                    "external relocations",
+                )?,
+            ),
+            None => (vec![], vec![]),
+        };
+        let indirect_flags =
+            DysymtabCommand::INDIRECT_SYMBOL_LOCAL | DysymtabCommand::INDIRECT_SYMBOL_ABS;
+
+        let mut referenced = vec![false; nsyms];
+        let mut relocated = vec![false; nsyms];
+        for entry in indirect.chunks(4) {
+            let index = read_u32(entry, 0);
+            if index & indirect_flags == 0 && (index as usize) < nsyms {
+                referenced[index as usize] = true;
+            }
+        }
+        for reloc in extrel.chunks(Relocation::SIZE) {
+            let info = RelocationInfoBF(read_u32(reloc, 4));
+            if info.is_extern() && (info.symbolnum() as usize) < nsyms {
+                referenced[info.symbolnum() as usize] = true;
+                relocated[info.symbolnum() as usize] = true;
+            }
+        }
+
+        let exports = matches!(
+            self.header.filetype(),
+            MHFileType::MhDylib | MHFileType::MhBundle
+        );
+        let keep: Vec<bool> = symtab
+            .symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| {
+                let dynamic = symbol.n_desc() & NlistDesc::REFERENCED_DYNAMICALLY_BITMASK != 0;
+                match mode {
+                    StripMode::Debug => !symbol.is_stab(),
+                    StripMode::Locals => symbol.is_external() || dynamic,
+                    StripMode::All => {
+                        let undefined = symbol.type_is(NlistTypeType::Undefined)
+                            || symbol.type_is(NlistTypeType::PreboundUndefined);
+                        symbol.is_external() && (undefined || referenced[i] || exports) || dynamic
+                    }
+                }
+            })
+            .collect();
+
+        // Relocations can't be pointed at a local stand-in the way indirect entries can.
+        if let Some(i) = (0..nsyms).find(|&i| relocated[i] && !keep[i]) {
+            return Err(MachOErr::InvalidValue(format!(
+                "{} is used by an external relocation",
+                symtab.symbols[i].name
+            )));
+        }
+
+        let mut new_index = vec![None; nsyms];
+        let mut kept = 0u32;
+        for i in 0..nsyms {
+            if keep[i] {
+                new_index[i] = Some(kept);
+                kept += 1;
+            }
+        }
+        let removed = nsyms - kept as usize;
+
+        let mut strings = StringTable::new();
+        let mut symbols = Vec::with_capacity(kept as usize * self.nlist_size());
+        for (symbol, _) in symtab.symbols.iter().zip(&keep).filter(|(_, &keep)| keep) {
+            symbols.extend(strings.add(&symbol.name).to_le_bytes());
+            symbols.extend(&symbol.raw[4..]);
+        }
+        let strings = strings.finish(self.command_align());
+
+        let mut new_indirect = Vec::with_capacity(indirect.len());
+        for entry in indirect.chunks(4) {
+            let index = read_u32(entry, 0);
+            let index = if index & indirect_flags != 0 || index as usize >= nsyms {
+                index
+            } else {
+                match new_index[index as usize] {
+                    Some(index) => index,
+                    None if symtab.symbols[index as usize].type_is(NlistTypeType::Absolute) => {
+                        DysymtabCommand::INDIRECT_SYMBOL_LOCAL
+                            | DysymtabCommand::INDIRECT_SYMBOL_ABS
+                    }
+                    None => DysymtabCommand::INDIRECT_SYMBOL_LOCAL,
+                }
+            };
+            new_indirect.extend(index.to_le_bytes());
+        }
+
+        let mut new_extrel = extrel.clone();
+        for reloc in new_extrel.chunks_mut(Relocation::SIZE) {
+            let mut info = RelocationInfoBF(read_u32(reloc, 4));
+            if info.is_extern() {
+                if let Some(Some(index)) = new_index.get(info.symbolnum() as usize) {
+                    info.set_symbolnum(*index);
+                    reloc[4..].copy_from_slice(&info.0.to_le_bytes());
+                }
+            }
+        }
+
+        let dysymtab = self.dysymtab().map(|(index, dysymtab)| {
+            let count = |start: u32, n: u32| {
+                (start as usize..(start + n) as usize)
+                    .filter(|&i| keep.get(i).copied().unwrap_or(false))
+                    .count() as u32
+            };
+            (
+                index,
+                count(dysymtab.ilocalsym, dysymtab.nlocalsym),
+                count(dysymtab.iextdefsym, dysymtab.nextdefsym),
+                count(dysymtab.iundefsym, dysymtab.nundefsym),
+                dysymtab.indirectsymoff,
+                dysymtab.extreloff,
+            )
+        });
+
+        // Done before the tables are written so a signature at the end of the file is
+        // truncated away and the tables become the tail of `__LINKEDIT`.
+        self.remove_code_signature()?;
+        if let Some((_, _, _, _, _, extreloff)) = dysymtab {
+            let start = extreloff as usize;
+            self.data[start..start + new_extrel.len()].copy_from_slice(&new_extrel);
+        }
+
+        let old_symbols_size = (nsyms * self.nlist_size()) as u32;
+        let mut tables = vec![
+            (symtab.symoff, old_symbols_size, symbols),
+            (symtab.stroff, symtab.strsize, strings),
+        ];
+        if let Some((_, _, _, _, indirectsymoff, _)) = dysymtab {
+            tables.push((indirectsymoff, indirect.len() as u32, new_indirect));
+        }
+        self.write_tables(&mut tables)?;
+
+        if let Some(LoadCommand::Symtab(cmd)) = self.get_mut(symtab.index) {
+            cmd.symoff = tables[0].0;
+            cmd.nsyms = kept;
+            cmd.stroff = tables[1].0;
+            cmd.strsize = tables[1].2.len() as u32;
+        }
+        if let Some((index, nlocalsym, nextdefsym, nundefsym, _, _)) = dysymtab {
+            let indirectsymoff = tables[2].0;
+            if let Some(LoadCommand::Dysymtab(cmd)) = self.get_mut(index) {
+                cmd.ilocalsym = 0;
+                cmd.nlocalsym = nlocalsym;
+                cmd.iextdefsym = nlocalsym;
+                cmd.nextdefsym = nextdefsym;
+                cmd.iundefsym = nlocalsym + nextdefsym;
+                cmd.nundefsym = nundefsym;
+                cmd.indirectsymoff = indirectsymoff;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::LoadCommandParser;
+    use crate::edit::HeaderOverflow;
+    use crate::testing::{code_signature, dysymtab, image64, segment64, symtab};
+
+    // An executable whose __LINKEDIT holds five symbols (a stab, a local, two globals and an
+    // import), a two entry indirect symbol table, the strings and a signature.
+    fn image() -> Vec<u8> {
+        let names = [
+            "/tmp/main.c",
+            "_helper",
+            "_main",
+            "__mh_execute_header",
+            "_printf",
+        ];
+        let types = [0x64u8, 0x0e, 0x0f, 0x0f, 0x01];
+        let descs = [0u16, 0, 0, NlistDesc::REFERENCED_DYNAMICALLY_BITMASK, 0x100];
+
+        let mut strings = b" \0".to_vec();
+        let mut symbols = Vec::new();
+        for i in 0..names.len() {
+            symbols.extend((strings.len() as u32).to_le_bytes());
+            symbols.push(types[i]);
+            symbols.push(if types[i] == 0x01 { 0 } else { 1 });
+            symbols.extend(descs[i].to_le_bytes());
+            symbols.extend((0x100000000u64 + i as u64).to_le_bytes());
+            strings.extend(names[i].as_bytes());
+            strings.push(0);
+        }
+        strings.resize(strings.len().div_ceil(8) * 8, 0);
+        let indirect: Vec<u8> = [4u32, 1].iter().flat_map(|i| i.to_le_bytes()).collect();
+
+        let symoff = 0x1000;
+        let indirectsymoff = symoff + symbols.len() as u32;
+        let stroff = indirectsymoff + indirect.len() as u32;
+        let sigoff = stroff + strings.len() as u32;
+        let dysymtab = DysymtabCommand {
+            nlocalsym: 2,
+            iextdefsym: 2,
+            nextdefsym: 2,
+            iundefsym: 4,
+            nundefsym: 1,
+            indirectsymoff,
+            nindirectsyms: 2,
+            ..dysymtab()
+        };
+        let linkedit_size = (sigoff + 0x20 - 0x1000) as u64;
+        let mut bytes = image64(
+            MHFileType::MhExecute,
+            &[
+                segment64("__TEXT", 0x100000000, 0x4000, 0, 0x1000, vec![]),
+                segment64(
+                    "__LINKEDIT",
+                    0x100001000,
+                    0x4000,
+                    0x1000,
+                    linkedit_size,
+                    vec![],
+                ),
+                symtab(symoff, names.len() as u32, stroff, strings.len() as u32),
+                dysymtab.serialize(),
+                code_signature(sigoff, 0x20),
+            ],
+        );
+        bytes.resize(0x1000, 0);
+        bytes.extend(symbols);
+        bytes.extend(indirect);
+        bytes.extend(strings);
+        bytes.extend([0xfa; 0x20]);
+        bytes
+    }
+
+    fn strip(mode: StripMode) -> (MachOEditor, usize) {
+        let mut editor = MachOEditor::parse(image()).unwrap();
+        let removed = editor.strip(mode).unwrap();
+        let written = editor.write(HeaderOverflow::Fail).unwrap();
+        (MachOEditor::parse(written).unwrap(), removed)
+    }
+
+    fn names(editor: &MachOEditor) -> Vec<String> {
+        let symtab = editor.read_symtab().unwrap().unwrap();
+        symtab
+            .symbols
+            .into_iter()
+            .map(|symbol| symbol.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_strip() {
+        let (editor, removed) = strip(StripMode::Debug);
+        assert_eq!(removed, 1);
+        assert_eq!(names(&editor)[0], "_helper");
+
+        let (editor, removed) = strip(StripMode::Locals);
+        assert_eq!(removed, 2);
+        assert_eq!(names(&editor), ["_main", "__mh_execute_header", "_printf"]);
+        assert_eq!(editor.code_signature(), None);
+        let (_, dysymtab) = editor.dysymtab().unwrap();
+        assert_eq!(
+            (dysymtab.nlocalsym, dysymtab.iextdefsym, dysymtab.iundefsym),
+            (0, 0, 2)
+        );
+        let indirect = editor
+            .read_table(dysymtab.indirectsymoff, 2, 4, "indirect symbol table")
+            .unwrap();
+        assert_eq!(read_u32(&indirect, 0), 2);
+        assert_eq!(
+            read_u32(&indirect, 4),
+            DysymtabCommand::INDIRECT_SYMBOL_LOCAL
+        );
+
+        let (editor, removed) = strip(StripMode::All);
+        assert_eq!(removed, 3);
+        assert_eq!(names(&editor), ["__mh_execute_header", "_printf"]);
+        assert!(editor.data().len() < image().len() - 0x20);
+        match editor.get(1) {
+            Some(LoadCommand::Segment64(seg)) => {
+                assert_eq!(seg.fileoff + seg.filesize, editor.data().len() as u64)
+            }
+            _ => panic!("Unexpected load commands"),
+        }
+    }
+}
@@ -272,6 +272,27 @@ impl MachHeader {
         }
     }
 
+    pub fn set_ncmds(&mut self, ncmds: u32) {
+        match self {
+            MachHeader::Header32(h) => h.ncmds = ncmds,
+            MachHeader::Header64(h) => h.ncmds = ncmds,
+        }
+    }
+
+    pub fn set_sizeofcmds(&mut self, sizeofcmds: u32) {
+        match self {
+            MachHeader::Header32(h) => h.sizeofcmds = sizeofcmds,
+            MachHeader::Header64(h) => h.sizeofcmds = sizeofcmds,
+        }
+    }
+
+    pub fn set_flags(&mut self, flags: MHFlags) {
+        match self {
+            MachHeader::Header32(h) => h.flags = flags,
+            MachHeader::Header64(h) => h.flags = flags,
+        }
+    }
+
     pub fn size(&self) -> u8 {
         match self {
             MachHeader::Header32(_) => MachHeader32::SIZE,
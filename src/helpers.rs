@@ -12,7 +12,7 @@ pub fn string_upto_null_terminator(bytes: &[u8]) -> IResult<&[u8], String> {
 pub fn version_string(version: u32) -> String {
     format!(
         "{}.{}.{}",
-        (version >> 16) & 0xff,
+        (version >> 16) & 0xffff,
         (version >> 8) & 0xff,
         version & 0xff
     )
@@ -45,6 +45,35 @@ pub fn read_uleb(bytes: &[u8]) -> IResult<&[u8], u64> {
     Ok((cursor, result))
 }
 
+pub fn write_uleb(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+pub fn write_sleb(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            buf.push(byte);
+            break;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+pub fn uleb_size(value: u64) -> usize {
+    (64 - value.leading_zeros() as usize).div_ceil(7).max(1)
+}
+
 pub fn read_uleb_many<'a>(mut bytes: &'a [u8]) -> IResult<&'a [u8], Vec<u64>> {
     let mut result = Vec::new();
     if bytes.is_empty() {
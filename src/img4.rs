@@ -0,0 +1,373 @@
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use nom::{bytes::complete::take, number::complete::be_u8, IResult};
+
+use crate::compression::{self, CompressionFormat};
+use crate::macho::{MachO, MachOErr, MachOResult};
+
+// The handful of DER tags that appear in IMG4 containers.
+const DER_INTEGER: u8 = 0x02;
+const DER_OCTET_STRING: u8 = 0x04;
+const DER_IA5_STRING: u8 = 0x16;
+const DER_SEQUENCE: u8 = 0x30;
+const DER_CONTEXT_0: u8 = 0xa0;
+const DER_CONTEXT_1: u8 = 0xa1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DerObject<'a> {
+    tag: u8,
+    value: &'a [u8],
+}
+
+impl<'a> DerObject<'a> {
+    fn parse(bytes: &'a [u8]) -> IResult<&'a [u8], DerObject<'a>> {
+        let (bytes, tag) = be_u8(bytes)?;
+        let (bytes, len) = be_u8(bytes)?;
+        let (bytes, len) = if len & 0x80 == 0 {
+            (bytes, len as usize)
+        } else {
+            let (bytes, len_bytes) = take(len & 0x7f)(bytes)?;
+            let len = len_bytes
+                .iter()
+                .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+            (bytes, len)
+        };
+        let (bytes, value) = take(len)(bytes)?;
+
+        Ok((bytes, DerObject { tag, value }))
+    }
+
+    fn expect(self, tag: u8) -> MachOResult<DerObject<'a>> {
+        if self.tag != tag {
+            return Err(MachOErr::ParsingError(format!(
+                "Expected DER tag 0x{:02x}, found 0x{:02x}",
+                tag, self.tag
+            )));
+        }
+        Ok(self)
+    }
+
+    fn string(self) -> MachOResult<String> {
+        let obj = self.expect(DER_IA5_STRING)?;
+        String::from_utf8(obj.value.to_vec())
+            .map_err(|_| MachOErr::InvalidValue("IA5String is not valid UTF8".to_string()))
+    }
+
+    fn integer(self) -> MachOResult<u64> {
+        let obj = self.expect(DER_INTEGER)?;
+        // A ninth byte is only allowed as the sign pad of a value with its top bit set.
+        if obj.value.len() > 9 || (obj.value.len() == 9 && obj.value[0] != 0) {
+            return Err(MachOErr::InvalidValue(
+                "DER integer is too large".to_string(),
+            ));
+        }
+        Ok(obj.value.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+    }
+
+    fn children(self) -> MachOResult<Vec<DerObject<'a>>> {
+        let mut children = Vec::new();
+        let mut cursor = self.value;
+        while !cursor.is_empty() {
+            let (next, child) = DerObject::parse(cursor)?;
+            children.push(child);
+            cursor = next;
+        }
+        Ok(children)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Im4pKeybag {
+    pub kind: u64,
+    pub iv: Vec<u8>,
+    pub key: Vec<u8>,
+}
+
+impl Im4pKeybag {
+    fn parse_all(bytes: &[u8]) -> MachOResult<Vec<Im4pKeybag>> {
+        let (_, seq) = DerObject::parse(bytes)?;
+        seq.expect(DER_SEQUENCE)?
+            .children()?
+            .into_iter()
+            .map(|entry| {
+                let fields = entry.expect(DER_SEQUENCE)?.children()?;
+                if fields.len() < 3 {
+                    return Err(MachOErr::ParsingError("Truncated IM4P keybag".to_string()));
+                }
+                Ok(Im4pKeybag {
+                    kind: fields[0].integer()?,
+                    iv: fields[1].expect(DER_OCTET_STRING)?.value.to_vec(),
+                    key: fields[2].expect(DER_OCTET_STRING)?.value.to_vec(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The optional compression descriptor that newer IM4P payloads carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Im4pCompression {
+    pub algorithm: u64,
+    pub uncompressed_size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Im4p {
+    pub kind: String,
+    pub description: String,
+    pub payload: Vec<u8>,
+    pub keybags: Vec<Im4pKeybag>,
+    pub compression: Option<Im4pCompression>,
+}
+
+impl Im4p {
+    pub const MAGIC: &'static str = "IM4P";
+
+    pub fn parse(bytes: &[u8]) -> MachOResult<Im4p> {
+        let (_, seq) = DerObject::parse(bytes)?;
+        Im4p::from_der(seq)
+    }
+
+    fn from_der(seq: DerObject) -> MachOResult<Im4p> {
+        let fields = seq.expect(DER_SEQUENCE)?.children()?;
+        if fields.len() < 4 {
+            return Err(MachOErr::ParsingError("Truncated IM4P".to_string()));
+        }
+        if fields[0].string()? != Self::MAGIC {
+            return Err(MachOErr::MagicError);
+        }
+
+        let kind = fields[1].string()?;
+        let description = fields[2].string()?;
+        let payload = fields[3].expect(DER_OCTET_STRING)?.value.to_vec();
+
+        let mut keybags = Vec::new();
+        let mut compression = None;
+        for field in &fields[4..] {
+            match field.tag {
+                DER_OCTET_STRING => keybags = Im4pKeybag::parse_all(field.value)?,
+                DER_SEQUENCE => {
+                    let info = field.children()?;
+                    if info.len() < 2 {
+                        return Err(MachOErr::ParsingError(
+                            "Truncated IM4P compression info".to_string(),
+                        ));
+                    }
+                    compression = Some(Im4pCompression {
+                        algorithm: info[0].integer()?,
+                        uncompressed_size: info[1].integer()?,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        Ok(Im4p {
+            kind,
+            description,
+            payload,
+            keybags,
+            compression,
+        })
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        !self.keybags.is_empty()
+    }
+
+    pub fn compression_format(&self) -> CompressionFormat {
+        CompressionFormat::detect(&self.payload)
+    }
+
+    /// Returns the payload with any LZSS/LZFSE compression removed.
+    pub fn decompress(&self) -> MachOResult<Vec<u8>> {
+        if self.is_encrypted() {
+            return Err(MachOErr::GenericError(
+                "IM4P payload is encrypted and can't be decompressed".to_string(),
+            ));
+        }
+        compression::decompress(&self.payload)
+    }
+
+    pub fn macho(&self) -> MachOResult<MachO<Cursor<Vec<u8>>>> {
+        let mut buf = Cursor::new(self.decompress()?);
+        if !MachO::is_macho_magic(&mut buf)? {
+            return Err(MachOErr::InvalidValue(
+                "IM4P payload is not a MachO".to_string(),
+            ));
+        }
+        MachO::parse(buf)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Img4 {
+    pub im4p: Im4p,
+    pub im4m: Option<Vec<u8>>,
+    pub im4r: Option<Vec<u8>>,
+}
+
+impl Img4 {
+    pub const MAGIC: &'static str = "IMG4";
+
+    /// Returns true if the buffer holds either a full IMG4 or a bare IM4P.
+    pub fn is_img4_magic<T: Read + Seek>(buf: &mut T) -> MachOResult<bool> {
+        let mut head = [0u8; 16];
+        buf.seek(SeekFrom::Start(0)).map_err(MachOErr::IOError)?;
+        let len = buf.read(&mut head).map_err(MachOErr::IOError)?;
+        Ok(Img4::container_magic(&head[..len]).is_some())
+    }
+
+    fn container_magic(bytes: &[u8]) -> Option<String> {
+        let (_, seq) = DerObject::parse(bytes)
+            .ok()
+            .or_else(|| DerObject::parse_header_only(bytes))?;
+        if seq.tag != DER_SEQUENCE {
+            return None;
+        }
+        let (_, magic) = DerObject::parse(seq.value).ok()?;
+        let magic = magic.string().ok()?;
+        (magic == Self::MAGIC || magic == Im4p::MAGIC).then_some(magic)
+    }
+
+    pub fn parse(bytes: &[u8]) -> MachOResult<Img4> {
+        let (_, seq) = DerObject::parse(bytes)?;
+        let fields = seq.expect(DER_SEQUENCE)?.children()?;
+        if fields.len() < 2 {
+            return Err(MachOErr::ParsingError("Truncated IMG4".to_string()));
+        }
+        if fields[0].string()? != Self::MAGIC {
+            return Err(MachOErr::MagicError);
+        }
+
+        let im4p = Im4p::from_der(fields[1])?;
+        let mut im4m = None;
+        let mut im4r = None;
+        for field in &fields[2..] {
+            match field.tag {
+                DER_CONTEXT_0 => im4m = Some(field.value.to_vec()),
+                DER_CONTEXT_1 => im4r = Some(field.value.to_vec()),
+                _ => (),
+            }
+        }
+
+        Ok(Img4 { im4p, im4m, im4r })
+    }
+
+    /// Extracts the IM4P from either an IMG4 or a bare IM4P container.
+    pub fn parse_im4p(bytes: &[u8]) -> MachOResult<Im4p> {
+        match Img4::container_magic(bytes).as_deref() {
+            Some(Self::MAGIC) => Ok(Img4::parse(bytes)?.im4p),
+            Some(Im4p::MAGIC) => Im4p::parse(bytes),
+            _ => Err(MachOErr::MagicError),
+        }
+    }
+}
+
+impl<'a> DerObject<'a> {
+    // Only the start of large containers is available when sniffing magic, so parse the header
+    // and clamp the value to what was read.
+    fn parse_header_only(bytes: &'a [u8]) -> Option<(&'a [u8], DerObject<'a>)> {
+        let (&tag, rest) = bytes.split_first()?;
+        let (&len, rest) = rest.split_first()?;
+        let skip = if len & 0x80 == 0 {
+            0
+        } else {
+            (len & 0x7f) as usize
+        };
+        let value = rest.get(skip..)?;
+        Some((&[], DerObject { tag, value }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::lzss::{adler32, ComplzssHeader};
+    use crate::header::MHFileType;
+
+    fn der(tag: u8, value: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if value.len() < 0x80 {
+            out.push(value.len() as u8);
+        } else {
+            out.push(0x82);
+            out.extend((value.len() as u16).to_be_bytes());
+        }
+        out.extend(value);
+        out
+    }
+
+    fn im4p(payload: &[u8]) -> Vec<u8> {
+        let mut fields = Vec::new();
+        fields.extend(der(DER_IA5_STRING, b"IM4P"));
+        fields.extend(der(DER_IA5_STRING, b"krnl"));
+        fields.extend(der(DER_IA5_STRING, b"KernelCacheBuilder"));
+        fields.extend(der(DER_OCTET_STRING, payload));
+        der(DER_SEQUENCE, &fields)
+    }
+
+    fn complzss_macho() -> Vec<u8> {
+        // A bare MH_EXECUTE header with no load commands, stored as LZSS literals.
+        let mut macho = Vec::new();
+        macho.extend(0xfeedfacfu32.to_le_bytes());
+        macho.extend(0x0100000cu32.to_le_bytes());
+        macho.extend(0u32.to_le_bytes());
+        macho.extend(2u32.to_le_bytes());
+        macho.extend([0u8; 16]);
+
+        let mut compressed = Vec::new();
+        for chunk in macho.chunks(8) {
+            compressed.push(0xff);
+            compressed.extend(chunk);
+        }
+
+        let mut blob = Vec::new();
+        blob.extend(ComplzssHeader::MAGIC);
+        blob.extend(adler32(&macho).to_be_bytes());
+        blob.extend((macho.len() as u32).to_be_bytes());
+        blob.extend((compressed.len() as u32).to_be_bytes());
+        blob.extend(0u32.to_be_bytes());
+        blob.resize(ComplzssHeader::SIZE, 0);
+        blob.extend(compressed);
+        blob
+    }
+
+    #[test]
+    fn test_im4p_lzss_macho() {
+        let bytes = im4p(&complzss_macho());
+        assert!(Img4::is_img4_magic(&mut Cursor::new(&bytes)).unwrap());
+
+        let im4p = Img4::parse_im4p(&bytes).unwrap();
+        assert_eq!(im4p.kind, "krnl");
+        assert_eq!(im4p.compression_format(), CompressionFormat::Lzss);
+
+        let macho = im4p.macho().unwrap();
+        assert_eq!(*macho.header.filetype(), MHFileType::MhExecute);
+    }
+
+    #[test]
+    fn test_der_integer() {
+        let integer = |value| DerObject {
+            tag: DER_INTEGER,
+            value,
+        };
+        let padded = [0, 0xff, 0, 0, 0, 0, 0, 0, 1];
+        assert_eq!(integer(&padded).integer().unwrap(), 0xff00_0000_0000_0001);
+        assert!(integer(&[1, 0, 0, 0, 0, 0, 0, 0, 0]).integer().is_err());
+    }
+
+    #[test]
+    fn test_img4_wrapper() {
+        let mut fields = Vec::new();
+        fields.extend(der(DER_IA5_STRING, b"IMG4"));
+        fields.extend(im4p(b"payload"));
+        fields.extend(der(DER_CONTEXT_0, b"manifest"));
+        let bytes = der(DER_SEQUENCE, &fields);
+
+        let img4 = Img4::parse(&bytes).unwrap();
+        assert_eq!(img4.im4p.payload, b"payload");
+        assert_eq!(img4.im4m, Some(b"manifest".to_vec()));
+        assert_eq!(img4.im4r, None);
+    }
+}
@@ -0,0 +1,256 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::command::LoadCommand;
+use crate::file_subset::FileSubset;
+use crate::header::MHFileType;
+use crate::macho::{MachO, MachOErr, MachOResult};
+use crate::plist::PlistValue;
+
+/// The `__PRELINK_INFO,__info` plist of a pre-fileset kernelcache.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrelinkInfo {
+    pub plist: PlistValue,
+}
+
+impl PrelinkInfo {
+    pub const SEGNAME: &'static str = "__PRELINK_INFO";
+    pub const SECTNAME: &'static str = "__info";
+    pub const TEXT_SEGNAME: &'static str = "__PRELINK_TEXT";
+
+    pub fn parse<T: Read + Seek>(macho: &mut MachO<T>) -> MachOResult<Option<PrelinkInfo>> {
+        let info = macho
+            .load_commands
+            .iter()
+            .filter_map(|lc| match lc {
+                LoadCommand::Segment64(seg) => Some(seg),
+                _ => None,
+            })
+            .flat_map(|seg| &seg.sections)
+            .find(|sect| sect.segname == Self::SEGNAME && sect.sectname == Self::SECTNAME);
+
+        let info = match info {
+            Some(info) => info,
+            None => return Ok(None),
+        };
+
+        let mut xml = vec![0u8; info.size as usize];
+        macho
+            .buf
+            .seek(SeekFrom::Start(info.offset as u64))
+            .map_err(MachOErr::IOError)?;
+        macho.buf.read_exact(&mut xml).map_err(MachOErr::IOError)?;
+
+        // The section is zero padded past the end of the document.
+        let len = xml.iter().position(|&b| b == 0).unwrap_or(xml.len());
+        let xml = std::str::from_utf8(&xml[..len])
+            .map_err(|_| MachOErr::InvalidValue("Prelink info is not valid UTF8".to_string()))?;
+
+        Ok(Some(PrelinkInfo {
+            plist: PlistValue::parse_xml(xml)?,
+        }))
+    }
+
+    /// The per-kext info dictionaries.
+    pub fn kexts(&self) -> &[PlistValue] {
+        self.plist
+            .get("_PrelinkInfoDictionary")
+            .and_then(|kexts| kexts.as_array())
+            .map(|kexts| kexts.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Kext {
+    pub bundle_id: String,
+    pub load_addr: Option<u64>,
+    /// File offset of the kext's mach header within the kernelcache, if it has an executable.
+    pub fileoff: Option<u64>,
+    pub size: Option<u64>,
+    pub path: Option<String>,
+}
+
+impl Kext {
+    /// Lists the kexts in a kernelcache, using `LC_FILESET_ENTRY` commands for fileset
+    /// kernelcaches and the prelink info plist for older ones.
+    pub fn enumerate<T: Read + Seek>(macho: &mut MachO<T>) -> MachOResult<Vec<Kext>> {
+        if *macho.header.filetype() == MHFileType::MhFileset {
+            return Ok(macho
+                .load_commands
+                .iter()
+                .filter_map(|lc| match lc {
+                    LoadCommand::FilesetEntry(entry) => Some(Kext {
+                        bundle_id: entry.entry_id.clone(),
+                        load_addr: Some(entry.vmaddr),
+                        fileoff: Some(entry.fileoff),
+                        size: None,
+                        path: None,
+                    }),
+                    _ => None,
+                })
+                .collect());
+        }
+
+        let info = match PrelinkInfo::parse(macho)? {
+            Some(info) => info,
+            None => return Ok(Vec::new()),
+        };
+
+        info.kexts()
+            .iter()
+            .map(|kext| {
+                let bundle_id = kext
+                    .get("CFBundleIdentifier")
+                    .and_then(|id| id.as_str())
+                    .ok_or_else(|| {
+                        MachOErr::InvalidValue("Prelinked kext has no bundle ID".to_string())
+                    })?
+                    .to_string();
+                let load_addr = kext
+                    .get("_PrelinkExecutableLoadAddr")
+                    .and_then(|addr| addr.as_u64());
+                // The source address is where the executable sits in __PRELINK_TEXT when it
+                // differs from where it gets loaded.
+                let source_addr = kext
+                    .get("_PrelinkExecutableSourceAddr")
+                    .and_then(|addr| addr.as_u64())
+                    .or(load_addr);
+
+                Ok(Kext {
+                    bundle_id,
+                    load_addr,
+                    fileoff: source_addr.and_then(|addr| macho.vm_addr_to_offset(addr).ok()),
+                    size: kext
+                        .get("_PrelinkExecutableSize")
+                        .and_then(|size| size.as_u64()),
+                    path: kext
+                        .get("_PrelinkBundlePath")
+                        .and_then(|path| path.as_str())
+                        .map(|path| path.to_string()),
+                })
+            })
+            .collect()
+    }
+
+    /// Parses the kext's executable out of the kernelcache it was enumerated from.
+    pub fn macho<'a, T: Read + Seek>(
+        &self,
+        kernelcache: &'a mut MachO<T>,
+    ) -> MachOResult<MachO<FileSubset<'a, T>>> {
+        let offset = self.fileoff.ok_or_else(|| {
+            MachOErr::InvalidValue(format!("Kext {} has no executable", self.bundle_id))
+        })?;
+        let size = kernelcache
+            .buf
+            .seek(SeekFrom::End(0))
+            .map_err(MachOErr::IOError)?;
+
+        // Kexts normally use file offsets relative to the whole kernelcache, but some older
+        // caches keep them relative to the kext, which shows up as a segment at offset zero.
+        let relative = {
+            let kext = MachO::parse_at(&mut kernelcache.buf, offset)?;
+            offset != 0
+                && kext.load_commands.iter().any(|lc| match lc {
+                    LoadCommand::Segment64(seg) => seg.fileoff == 0 && seg.filesize > 0,
+                    _ => false,
+                })
+        };
+
+        let (start, header_offset) = if relative { (offset, 0) } else { (0, offset) };
+        let subset = FileSubset::new(&mut kernelcache.buf, start, size - start)
+            .map_err(MachOErr::IOError)?;
+        MachO::parse_at(subset, header_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::command::segment::Section64;
+    use crate::command::{FilesetEntryCommand, LCLoadCommand, LoadCommandParser};
+    use crate::testing::{image64, section64, segment64};
+
+    const KEXT_VMADDR: u64 = 0xfffffff007001000;
+
+    fn segment(segname: &str, vmaddr: u64, fileoff: u64, sections: Vec<Section64>) -> Vec<u8> {
+        segment64(segname, vmaddr, 0x1000, fileoff, 0x1000, sections)
+    }
+
+    // A kernelcache with a kext at 0x1000 and whatever `extra` load commands describe it.
+    fn kernelcache(filetype: MHFileType, extra: Vec<Vec<u8>>, info: &[u8]) -> Cursor<Vec<u8>> {
+        let mut cmds = vec![segment("__PRELINK_TEXT", KEXT_VMADDR, 0x1000, vec![])];
+        cmds.extend(extra);
+
+        let mut bytes = image64(filetype, &cmds);
+        bytes.resize(0x1000, 0);
+        bytes.extend(image64(
+            MHFileType::MhKextBundle,
+            &[segment("__TEXT", KEXT_VMADDR, 0x1000, vec![])],
+        ));
+        bytes.resize(0x2000, 0);
+        bytes.extend(info);
+        bytes.resize(0x3000, 0);
+        Cursor::new(bytes)
+    }
+
+    #[test]
+    fn test_prelinked_kexts() {
+        let xml = format!(
+            r#"<dict><key>_PrelinkInfoDictionary</key><array>
+<dict><key>CFBundleIdentifier</key><string ID="0">com.example.driver</string>
+<key>_PrelinkExecutableLoadAddr</key><integer size="64" ID="1">0x{:x}</integer>
+<key>_PrelinkExecutableSize</key><integer size="64">0x1000</integer></dict>
+<dict><key>CFBundleIdentifier</key><string>com.example.codeless</string>
+<key>OSBundleRequired</key><string IDREF="0"/></dict>
+</array></dict>"#,
+            KEXT_VMADDR
+        );
+        let (segname, sectname) = (PrelinkInfo::SEGNAME, PrelinkInfo::SECTNAME);
+        let info = section64(
+            segname,
+            sectname,
+            0xfffffff007002000,
+            xml.len() as u64,
+            0x2000,
+        );
+        let info_seg = segment(PrelinkInfo::SEGNAME, 0xfffffff007002000, 0x2000, vec![info]);
+        let mut macho = MachO::parse(kernelcache(
+            MHFileType::MhExecute,
+            vec![info_seg],
+            xml.as_bytes(),
+        ))
+        .unwrap();
+
+        let kexts = Kext::enumerate(&mut macho).unwrap();
+        assert_eq!(kexts.len(), 2);
+        assert_eq!(kexts[0].bundle_id, "com.example.driver");
+        assert_eq!(kexts[0].fileoff, Some(0x1000));
+        assert_eq!(kexts[0].size, Some(0x1000));
+        assert_eq!(kexts[1].fileoff, None);
+
+        let kext = kexts[0].macho(&mut macho).unwrap();
+        assert_eq!(*kext.header.filetype(), MHFileType::MhKextBundle);
+        assert_eq!(kext.vm_addr_to_offset(KEXT_VMADDR).unwrap(), 0x1000);
+    }
+
+    #[test]
+    fn test_fileset_kexts() {
+        let entry = FilesetEntryCommand {
+            cmd: LCLoadCommand::LcFilesetEntry,
+            cmdsize: 56,
+            vmaddr: KEXT_VMADDR,
+            fileoff: 0x1000,
+            entry_id: "com.example.driver".to_string(),
+            reserved: 0,
+        }
+        .serialize();
+        let mut macho = MachO::parse(kernelcache(MHFileType::MhFileset, vec![entry], &[])).unwrap();
+
+        let kexts = Kext::enumerate(&mut macho).unwrap();
+        assert_eq!(kexts.len(), 1);
+        let kext = kexts[0].macho(&mut macho).unwrap();
+        assert_eq!(*kext.header.filetype(), MHFileType::MhKextBundle);
+    }
+}
@@ -1,8 +1,19 @@
+pub mod archive;
 pub mod command;
+pub mod compression;
+pub mod corefile;
+pub mod edit;
 pub mod fat;
 pub mod file_subset;
 pub mod header;
 mod helpers;
+pub mod img4;
+pub mod kernelcache;
 pub mod machine;
 pub mod macho;
 pub mod objc;
+pub mod plist;
+pub mod relocation;
+#[cfg(test)]
+mod testing;
+pub mod vm_mapped;
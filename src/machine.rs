@@ -1,7 +1,8 @@
 use nom::{
+    bytes::complete::take,
     error::{Error, ErrorKind},
     multi,
-    number::complete::{be_u32, le_u32, le_u64},
+    number::complete::{be_u32, le_u128, le_u16, le_u32, le_u64, le_u8},
     sequence,
     Err::Failure,
     IResult, Parser,
@@ -276,50 +277,233 @@ impl CpuSubTypeArm64 {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
+/// The thread state flavors that are decoded. x86 and ARM number their flavors separately,
+/// so a flavor number is only meaningful for the image's CPU type. Without one, a flavor is
+/// known by its number and its size in words together.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ThreadStateFlavor {
-    X86ThreadState32 = 1,
-    X86ThreadState64 = 4,
-    Arm64ThreadState64 = 6,
+    X86ThreadState32,
+    X86FloatState32,
+    X86ExceptionState32,
+    X86ThreadState64,
+    X86FloatState64,
+    X86ExceptionState64,
+    X86DebugState32,
+    X86DebugState64,
+    X86AvxState32,
+    X86AvxState64,
+    ArmThreadState32,
+    Arm64ThreadState64,
+    Arm64ExceptionState64,
+    Arm64DebugState64,
+    ArmNeonState64,
 }
 
 impl ThreadStateFlavor {
-    pub fn parse(bytes: &[u8]) -> IResult<&[u8], ThreadStateFlavor> {
-        let (bytes, flavor) = le_u32(bytes)?;
-        match num::FromPrimitive::from_u32(flavor) {
-            Some(flavor) => Ok((bytes, flavor)),
-            None => Err(Failure(Error::new(bytes, ErrorKind::Tag))),
+    const X86: [ThreadStateFlavor; 10] = [
+        ThreadStateFlavor::X86ThreadState32,
+        ThreadStateFlavor::X86FloatState32,
+        ThreadStateFlavor::X86ExceptionState32,
+        ThreadStateFlavor::X86ThreadState64,
+        ThreadStateFlavor::X86FloatState64,
+        ThreadStateFlavor::X86ExceptionState64,
+        ThreadStateFlavor::X86DebugState32,
+        ThreadStateFlavor::X86DebugState64,
+        ThreadStateFlavor::X86AvxState32,
+        ThreadStateFlavor::X86AvxState64,
+    ];
+    const ARM: [ThreadStateFlavor; 5] = [
+        ThreadStateFlavor::ArmThreadState32,
+        ThreadStateFlavor::Arm64ThreadState64,
+        ThreadStateFlavor::Arm64ExceptionState64,
+        ThreadStateFlavor::Arm64DebugState64,
+        ThreadStateFlavor::ArmNeonState64,
+    ];
+
+    /// The flavor with this number and size for `cputype`. With `CpuType::Any` both sets are
+    /// searched, and `ARM_EXCEPTION_STATE` reads as `x86_EXCEPTION_STATE32`, which has the same
+    /// number and size.
+    pub fn from_raw(flavor: u32, count: u32, cputype: CpuType) -> Option<Self> {
+        let sets: &[&[ThreadStateFlavor]] = match cputype {
+            CpuType::I386 | CpuType::X86_64 => &[&Self::X86],
+            CpuType::Arm | CpuType::Arm64 | CpuType::Arm64_32 => &[&Self::ARM],
+            CpuType::Any => &[&Self::X86, &Self::ARM],
+            _ => &[],
+        };
+        sets.iter()
+            .flat_map(|set| set.iter().copied())
+            .find(|known| known.raw() == flavor && known.count() == count)
+    }
+
+    pub fn raw(&self) -> u32 {
+        match self {
+            ThreadStateFlavor::X86ThreadState32 => 1,
+            ThreadStateFlavor::X86FloatState32 => 2,
+            ThreadStateFlavor::X86ExceptionState32 => 3,
+            ThreadStateFlavor::X86ThreadState64 => 4,
+            ThreadStateFlavor::X86FloatState64 => 5,
+            ThreadStateFlavor::X86ExceptionState64 => 6,
+            ThreadStateFlavor::X86DebugState32 => 10,
+            ThreadStateFlavor::X86DebugState64 => 11,
+            ThreadStateFlavor::X86AvxState32 => 16,
+            ThreadStateFlavor::X86AvxState64 => 17,
+            ThreadStateFlavor::ArmThreadState32 => 1,
+            ThreadStateFlavor::Arm64ThreadState64 => 6,
+            ThreadStateFlavor::Arm64ExceptionState64 => 7,
+            ThreadStateFlavor::Arm64DebugState64 => 15,
+            ThreadStateFlavor::ArmNeonState64 => 17,
         }
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
-        let flavor = *self as u32;
-        flavor.to_le_bytes().to_vec()
+    /// The size of the state in 32-bit words.
+    pub fn count(&self) -> u32 {
+        match self {
+            ThreadStateFlavor::X86ThreadState32 => X86ThreadState32::SIZE,
+            ThreadStateFlavor::X86FloatState32 => X86FloatState32::SIZE,
+            ThreadStateFlavor::X86ExceptionState32 => X86ExceptionState32::SIZE,
+            ThreadStateFlavor::X86ThreadState64 => X86ThreadState64::SIZE,
+            ThreadStateFlavor::X86FloatState64 => X86FloatState64::SIZE,
+            ThreadStateFlavor::X86ExceptionState64 => X86ExceptionState64::SIZE,
+            ThreadStateFlavor::X86DebugState32 => X86DebugState32::SIZE,
+            ThreadStateFlavor::X86DebugState64 => X86DebugState64::SIZE,
+            ThreadStateFlavor::X86AvxState32 => X86AvxState32::SIZE,
+            ThreadStateFlavor::X86AvxState64 => X86AvxState64::SIZE,
+            ThreadStateFlavor::ArmThreadState32 => ArmThreadState32::SIZE,
+            ThreadStateFlavor::Arm64ThreadState64 => Arm64ThreadState64::SIZE,
+            ThreadStateFlavor::Arm64ExceptionState64 => Arm64ExceptionState64::SIZE,
+            ThreadStateFlavor::Arm64DebugState64 => Arm64DebugState64::SIZE,
+            ThreadStateFlavor::ArmNeonState64 => ArmNeonState64::SIZE,
+        }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ThreadState {
     X86State32(X86ThreadState32),
     X86State64(X86ThreadState64),
+    X86FloatState32(X86FloatState32),
+    X86FloatState64(X86FloatState64),
+    X86ExceptionState32(X86ExceptionState32),
+    X86ExceptionState64(X86ExceptionState64),
+    X86DebugState32(X86DebugState32),
+    X86DebugState64(X86DebugState64),
+    X86AvxState32(X86AvxState32),
+    X86AvxState64(X86AvxState64),
+    ArmState32(ArmThreadState32),
     Arm64State64(Arm64ThreadState64),
+    Arm64ExceptionState64(Arm64ExceptionState64),
+    Arm64DebugState64(Arm64DebugState64),
+    ArmNeonState64(ArmNeonState64),
+    /// A flavor that isn't decoded, kept as it was read.
+    Unknown {
+        flavor: u32,
+        data: Vec<u8>,
+    },
 }
 
 impl ThreadState {
+    /// Decodes the state without knowing the CPU type, by its flavor's number and size.
     pub fn parse(bytes: &[u8], base: ThreadStateBase) -> IResult<&[u8], ThreadState> {
-        match base.flavor {
+        ThreadState::parse_for(bytes, base, CpuType::Any)
+    }
+
+    /// Flavors this crate doesn't decode for `cputype`, or whose size doesn't match, are kept
+    /// as [`ThreadState::Unknown`].
+    pub fn parse_for(
+        bytes: &[u8],
+        base: ThreadStateBase,
+        cputype: CpuType,
+    ) -> IResult<&[u8], ThreadState> {
+        let (bytes, state) = take(base.size as usize * 4)(bytes)?;
+        let flavor = ThreadStateFlavor::from_raw(base.flavor, base.size, cputype);
+        let Some(flavor) = flavor else {
+            return Ok((
+                bytes,
+                ThreadState::Unknown {
+                    flavor: base.flavor,
+                    data: state.to_vec(),
+                },
+            ));
+        };
+
+        let thread = match flavor {
+            ThreadStateFlavor::X86ThreadState32 => {
+                ThreadState::X86State32(X86ThreadState32::parse(state)?.1)
+            }
+            ThreadStateFlavor::X86FloatState32 => {
+                ThreadState::X86FloatState32(X86FloatState32::parse(state)?.1)
+            }
+            ThreadStateFlavor::X86ExceptionState32 => {
+                ThreadState::X86ExceptionState32(X86ExceptionState32::parse(state)?.1)
+            }
             ThreadStateFlavor::X86ThreadState64 => {
-                let (bytes, state) = X86ThreadState64::parse(bytes)?;
-                Ok((bytes, ThreadState::X86State64(state)))
+                ThreadState::X86State64(X86ThreadState64::parse(state)?.1)
+            }
+            ThreadStateFlavor::X86FloatState64 => {
+                ThreadState::X86FloatState64(X86FloatState64::parse(state)?.1)
+            }
+            ThreadStateFlavor::X86ExceptionState64 => {
+                ThreadState::X86ExceptionState64(X86ExceptionState64::parse(state)?.1)
+            }
+            ThreadStateFlavor::X86DebugState32 => {
+                ThreadState::X86DebugState32(X86DebugState32::parse(state)?.1)
+            }
+            ThreadStateFlavor::X86DebugState64 => {
+                ThreadState::X86DebugState64(X86DebugState64::parse(state)?.1)
+            }
+            ThreadStateFlavor::X86AvxState32 => {
+                ThreadState::X86AvxState32(X86AvxState32::parse(state)?.1)
+            }
+            ThreadStateFlavor::X86AvxState64 => {
+                ThreadState::X86AvxState64(X86AvxState64::parse(state)?.1)
+            }
+            ThreadStateFlavor::ArmThreadState32 => {
+                ThreadState::ArmState32(ArmThreadState32::parse(state)?.1)
             }
             ThreadStateFlavor::Arm64ThreadState64 => {
-                let (bytes, state) = Arm64ThreadState64::parse(bytes)?;
-                Ok((bytes, ThreadState::Arm64State64(state)))
+                ThreadState::Arm64State64(Arm64ThreadState64::parse(state)?.1)
             }
-            ThreadStateFlavor::X86ThreadState32 => {
-                let (bytes, state) = X86ThreadState32::parse(bytes)?;
-                Ok((bytes, ThreadState::X86State32(state)))
+            ThreadStateFlavor::Arm64ExceptionState64 => {
+                ThreadState::Arm64ExceptionState64(Arm64ExceptionState64::parse(state)?.1)
+            }
+            ThreadStateFlavor::Arm64DebugState64 => {
+                ThreadState::Arm64DebugState64(Arm64DebugState64::parse(state)?.1)
+            }
+            ThreadStateFlavor::ArmNeonState64 => {
+                ThreadState::ArmNeonState64(ArmNeonState64::parse(state)?.1)
             }
+        };
+        Ok((bytes, thread))
+    }
+
+    /// The flavor and size that precede the state in a thread command.
+    pub fn base(&self) -> ThreadStateBase {
+        let flavor = match self {
+            ThreadState::X86State32(_) => ThreadStateFlavor::X86ThreadState32,
+            ThreadState::X86State64(_) => ThreadStateFlavor::X86ThreadState64,
+            ThreadState::X86FloatState32(_) => ThreadStateFlavor::X86FloatState32,
+            ThreadState::X86FloatState64(_) => ThreadStateFlavor::X86FloatState64,
+            ThreadState::X86ExceptionState32(_) => ThreadStateFlavor::X86ExceptionState32,
+            ThreadState::X86ExceptionState64(_) => ThreadStateFlavor::X86ExceptionState64,
+            ThreadState::X86DebugState32(_) => ThreadStateFlavor::X86DebugState32,
+            ThreadState::X86DebugState64(_) => ThreadStateFlavor::X86DebugState64,
+            ThreadState::X86AvxState32(_) => ThreadStateFlavor::X86AvxState32,
+            ThreadState::X86AvxState64(_) => ThreadStateFlavor::X86AvxState64,
+            ThreadState::ArmState32(_) => ThreadStateFlavor::ArmThreadState32,
+            ThreadState::Arm64State64(_) => ThreadStateFlavor::Arm64ThreadState64,
+            ThreadState::Arm64ExceptionState64(_) => ThreadStateFlavor::Arm64ExceptionState64,
+            ThreadState::Arm64DebugState64(_) => ThreadStateFlavor::Arm64DebugState64,
+            ThreadState::ArmNeonState64(_) => ThreadStateFlavor::ArmNeonState64,
+            ThreadState::Unknown { flavor, data } => {
+                return ThreadStateBase {
+                    flavor: *flavor,
+                    size: (data.len() / 4) as u32,
+                }
+            }
+        };
+        ThreadStateBase {
+            flavor: flavor.raw(),
+            size: flavor.count(),
         }
     }
 
@@ -327,19 +511,33 @@ impl ThreadState {
         match self {
             ThreadState::X86State32(state) => state.serialize(),
             ThreadState::X86State64(state) => state.serialize(),
+            ThreadState::X86FloatState32(state) => state.serialize(),
+            ThreadState::X86FloatState64(state) => state.serialize(),
+            ThreadState::X86ExceptionState32(state) => state.serialize(),
+            ThreadState::X86ExceptionState64(state) => state.serialize(),
+            ThreadState::X86DebugState32(state) => state.serialize(),
+            ThreadState::X86DebugState64(state) => state.serialize(),
+            ThreadState::X86AvxState32(state) => state.serialize(),
+            ThreadState::X86AvxState64(state) => state.serialize(),
+            ThreadState::ArmState32(state) => state.serialize(),
             ThreadState::Arm64State64(state) => state.serialize(),
+            ThreadState::Arm64ExceptionState64(state) => state.serialize(),
+            ThreadState::Arm64DebugState64(state) => state.serialize(),
+            ThreadState::ArmNeonState64(state) => state.serialize(),
+            ThreadState::Unknown { data, .. } => data.clone(),
         }
     }
 }
 
 pub struct ThreadStateBase {
-    pub flavor: ThreadStateFlavor,
+    pub flavor: u32,
+    /// The size of the state in 32-bit words.
     pub size: u32,
 }
 
 impl ThreadStateBase {
     pub fn parse(bytes: &[u8]) -> IResult<&[u8], ThreadStateBase> {
-        let (bytes, flavor) = ThreadStateFlavor::parse(bytes)?;
+        let (bytes, flavor) = le_u32(bytes)?;
         let (bytes, size) = le_u32(bytes)?;
 
         Ok((bytes, ThreadStateBase { flavor, size }))
@@ -347,7 +545,7 @@ impl ThreadStateBase {
 
     pub fn serialize(&self) -> Vec<u8> {
         let mut buf = Vec::new();
-        buf.extend(self.flavor.serialize());
+        buf.extend(self.flavor.to_le_bytes());
         buf.extend(self.size.to_le_bytes());
         buf
     }
@@ -610,3 +808,447 @@ impl Arm64ThreadState64 {
         buf
     }
 }
+
+fn parse_array<'a, T: std::fmt::Debug, const N: usize>(
+    bytes: &'a [u8],
+    parser: fn(&'a [u8]) -> IResult<&'a [u8], T>,
+) -> IResult<&'a [u8], [T; N]> {
+    let (bytes, values) = multi::count(parser, N).parse(bytes)?;
+    Ok((bytes, values.try_into().unwrap()))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86ExceptionState32 {
+    pub trapno: u16,
+    pub cpu: u16,
+    pub err: u32,
+    pub faultvaddr: u32,
+}
+
+impl X86ExceptionState32 {
+    pub const SIZE: u32 = 3;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86ExceptionState32> {
+        let (bytes, (trapno, cpu, err, faultvaddr)) =
+            sequence::tuple((le_u16, le_u16, le_u32, le_u32))(bytes)?;
+
+        Ok((
+            bytes,
+            X86ExceptionState32 {
+                trapno,
+                cpu,
+                err,
+                faultvaddr,
+            },
+        ))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(self.trapno.to_le_bytes());
+        buf.extend(self.cpu.to_le_bytes());
+        buf.extend(self.err.to_le_bytes());
+        buf.extend(self.faultvaddr.to_le_bytes());
+        buf
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86ExceptionState64 {
+    pub trapno: u16,
+    pub cpu: u16,
+    pub err: u32,
+    pub faultvaddr: u64,
+}
+
+impl X86ExceptionState64 {
+    pub const SIZE: u32 = 4;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86ExceptionState64> {
+        let (bytes, (trapno, cpu, err, faultvaddr)) =
+            sequence::tuple((le_u16, le_u16, le_u32, le_u64))(bytes)?;
+
+        Ok((
+            bytes,
+            X86ExceptionState64 {
+                trapno,
+                cpu,
+                err,
+                faultvaddr,
+            },
+        ))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(self.trapno.to_le_bytes());
+        buf.extend(self.cpu.to_le_bytes());
+        buf.extend(self.err.to_le_bytes());
+        buf.extend(self.faultvaddr.to_le_bytes());
+        buf
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86DebugState32 {
+    pub dr: [u32; 8],
+}
+
+impl X86DebugState32 {
+    pub const SIZE: u32 = 8;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86DebugState32> {
+        let (bytes, dr) = parse_array(bytes, le_u32)?;
+        Ok((bytes, X86DebugState32 { dr }))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.dr.iter().flat_map(|dr| dr.to_le_bytes()).collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86DebugState64 {
+    pub dr: [u64; 8],
+}
+
+impl X86DebugState64 {
+    pub const SIZE: u32 = 16;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86DebugState64> {
+        let (bytes, dr) = parse_array(bytes, le_u64)?;
+        Ok((bytes, X86DebugState64 { dr }))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        self.dr.iter().flat_map(|dr| dr.to_le_bytes()).collect()
+    }
+}
+
+/// The x87 and SSE control and status registers that start every x86 float state.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86FpuControl {
+    pub fcw: u16,
+    pub fsw: u16,
+    pub ftw: u8,
+    pub fop: u16,
+    pub ip: u32,
+    pub cs: u16,
+    pub dp: u32,
+    pub ds: u16,
+    pub mxcsr: u32,
+    pub mxcsrmask: u32,
+}
+
+impl X86FpuControl {
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86FpuControl> {
+        let (bytes, _) = take(8usize)(bytes)?;
+        let (bytes, (fcw, fsw, ftw, _, fop, ip, cs, _, dp, ds, _, mxcsr, mxcsrmask)) =
+            sequence::tuple((
+                le_u16, le_u16, le_u8, le_u8, le_u16, le_u32, le_u16, le_u16, le_u32, le_u16,
+                le_u16, le_u32, le_u32,
+            ))(bytes)?;
+
+        Ok((
+            bytes,
+            X86FpuControl {
+                fcw,
+                fsw,
+                ftw,
+                fop,
+                ip,
+                cs,
+                dp,
+                ds,
+                mxcsr,
+                mxcsrmask,
+            },
+        ))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = vec![0; 8];
+        buf.extend(self.fcw.to_le_bytes());
+        buf.extend(self.fsw.to_le_bytes());
+        buf.push(self.ftw);
+        buf.push(0);
+        buf.extend(self.fop.to_le_bytes());
+        buf.extend(self.ip.to_le_bytes());
+        buf.extend(self.cs.to_le_bytes());
+        buf.extend([0; 2]);
+        buf.extend(self.dp.to_le_bytes());
+        buf.extend(self.ds.to_le_bytes());
+        buf.extend([0; 2]);
+        buf.extend(self.mxcsr.to_le_bytes());
+        buf.extend(self.mxcsrmask.to_le_bytes());
+        buf
+    }
+}
+
+// Both float states are this long, with reserved space after the XMM registers.
+const X86_FLOAT_STATE_LEN: usize = 524;
+// The AVX states put the upper halves of the YMM registers after this much reserved space.
+const X86_AVX_RESERVED_LEN: usize = 64;
+
+type X86FloatRegisters<const XMM: usize> = (X86FpuControl, [u128; 8], [u128; XMM]);
+
+fn parse_x86_float_state<const XMM: usize>(bytes: &[u8]) -> IResult<&[u8], X86FloatRegisters<XMM>> {
+    let (rest, float_state) = take(X86_FLOAT_STATE_LEN)(bytes)?;
+    let (float_state, control) = X86FpuControl::parse(float_state)?;
+    let (float_state, stmm) = parse_array(float_state, le_u128)?;
+    let (_, xmm) = parse_array(float_state, le_u128)?;
+    Ok((rest, (control, stmm, xmm)))
+}
+
+fn serialize_x86_float_state(control: &X86FpuControl, stmm: &[u128], xmm: &[u128]) -> Vec<u8> {
+    let mut buf = control.serialize();
+    for reg in stmm.iter().chain(xmm) {
+        buf.extend(reg.to_le_bytes());
+    }
+    buf.resize(X86_FLOAT_STATE_LEN, 0);
+    buf
+}
+
+/// The x87 registers, with each 80-bit value in the low bytes of `stmm`, and the SSE
+/// registers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86FloatState32 {
+    pub control: X86FpuControl,
+    pub stmm: [u128; 8],
+    pub xmm: [u128; 8],
+}
+
+impl X86FloatState32 {
+    pub const SIZE: u32 = 131;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86FloatState32> {
+        let (bytes, (control, stmm, xmm)) = parse_x86_float_state(bytes)?;
+        Ok((bytes, X86FloatState32 { control, stmm, xmm }))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        serialize_x86_float_state(&self.control, &self.stmm, &self.xmm)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86FloatState64 {
+    pub control: X86FpuControl,
+    pub stmm: [u128; 8],
+    pub xmm: [u128; 16],
+}
+
+impl X86FloatState64 {
+    pub const SIZE: u32 = 131;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86FloatState64> {
+        let (bytes, (control, stmm, xmm)) = parse_x86_float_state(bytes)?;
+        Ok((bytes, X86FloatState64 { control, stmm, xmm }))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        serialize_x86_float_state(&self.control, &self.stmm, &self.xmm)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86AvxState32 {
+    pub float: X86FloatState32,
+    /// The upper halves of the YMM registers.
+    pub ymmh: [u128; 8],
+}
+
+impl X86AvxState32 {
+    pub const SIZE: u32 = 179;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86AvxState32> {
+        let (bytes, float) = X86FloatState32::parse(bytes)?;
+        let (bytes, _) = take(X86_AVX_RESERVED_LEN)(bytes)?;
+        let (bytes, ymmh) = parse_array(bytes, le_u128)?;
+        Ok((bytes, X86AvxState32 { float, ymmh }))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = self.float.serialize();
+        buf.extend([0; X86_AVX_RESERVED_LEN]);
+        for reg in &self.ymmh {
+            buf.extend(reg.to_le_bytes());
+        }
+        buf
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct X86AvxState64 {
+    pub float: X86FloatState64,
+    /// The upper halves of the YMM registers.
+    pub ymmh: [u128; 16],
+}
+
+impl X86AvxState64 {
+    pub const SIZE: u32 = 211;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], X86AvxState64> {
+        let (bytes, float) = X86FloatState64::parse(bytes)?;
+        let (bytes, _) = take(X86_AVX_RESERVED_LEN)(bytes)?;
+        let (bytes, ymmh) = parse_array(bytes, le_u128)?;
+        Ok((bytes, X86AvxState64 { float, ymmh }))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = self.float.serialize();
+        buf.extend([0; X86_AVX_RESERVED_LEN]);
+        for reg in &self.ymmh {
+            buf.extend(reg.to_le_bytes());
+        }
+        buf
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ArmThreadState32 {
+    pub r: [u32; 13],
+    pub sp: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub cpsr: u32,
+}
+
+impl ArmThreadState32 {
+    pub const SIZE: u32 = 17;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], ArmThreadState32> {
+        let (bytes, r) = parse_array(bytes, le_u32)?;
+        let (bytes, (sp, lr, pc, cpsr)) = sequence::tuple((le_u32, le_u32, le_u32, le_u32))(bytes)?;
+
+        Ok((
+            bytes,
+            ArmThreadState32 {
+                r,
+                sp,
+                lr,
+                pc,
+                cpsr,
+            },
+        ))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for r in self.r.iter() {
+            buf.extend(r.to_le_bytes());
+        }
+        buf.extend(self.sp.to_le_bytes());
+        buf.extend(self.lr.to_le_bytes());
+        buf.extend(self.pc.to_le_bytes());
+        buf.extend(self.cpsr.to_le_bytes());
+        buf
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Arm64ExceptionState64 {
+    /// The fault address
+    pub far: u64,
+    /// The exception syndrome
+    pub esr: u32,
+    pub exception: u32,
+}
+
+impl Arm64ExceptionState64 {
+    pub const SIZE: u32 = 4;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], Arm64ExceptionState64> {
+        let (bytes, (far, esr, exception)) = sequence::tuple((le_u64, le_u32, le_u32))(bytes)?;
+        Ok((
+            bytes,
+            Arm64ExceptionState64 {
+                far,
+                esr,
+                exception,
+            },
+        ))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(self.far.to_le_bytes());
+        buf.extend(self.esr.to_le_bytes());
+        buf.extend(self.exception.to_le_bytes());
+        buf
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Arm64DebugState64 {
+    pub bvr: [u64; 16],
+    pub bcr: [u64; 16],
+    pub wvr: [u64; 16],
+    pub wcr: [u64; 16],
+    pub mdscr_el1: u64,
+}
+
+impl Arm64DebugState64 {
+    pub const SIZE: u32 = 130;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], Arm64DebugState64> {
+        let (bytes, bvr) = parse_array(bytes, le_u64)?;
+        let (bytes, bcr) = parse_array(bytes, le_u64)?;
+        let (bytes, wvr) = parse_array(bytes, le_u64)?;
+        let (bytes, wcr) = parse_array(bytes, le_u64)?;
+        let (bytes, mdscr_el1) = le_u64(bytes)?;
+
+        Ok((
+            bytes,
+            Arm64DebugState64 {
+                bvr,
+                bcr,
+                wvr,
+                wcr,
+                mdscr_el1,
+            },
+        ))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for reg in self
+            .bvr
+            .iter()
+            .chain(&self.bcr)
+            .chain(&self.wvr)
+            .chain(&self.wcr)
+        {
+            buf.extend(reg.to_le_bytes());
+        }
+        buf.extend(self.mdscr_el1.to_le_bytes());
+        buf
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ArmNeonState64 {
+    pub q: [u128; 32],
+    pub fpsr: u32,
+    pub fpcr: u32,
+}
+
+impl ArmNeonState64 {
+    pub const SIZE: u32 = 130;
+
+    pub fn parse(bytes: &[u8]) -> IResult<&[u8], ArmNeonState64> {
+        let (bytes, q) = parse_array(bytes, le_u128)?;
+        let (bytes, (fpsr, fpcr)) = sequence::tuple((le_u32, le_u32))(bytes)?;
+        Ok((bytes, ArmNeonState64 { q, fpsr, fpcr }))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for q in self.q.iter() {
+            buf.extend(q.to_le_bytes());
+        }
+        buf.extend(self.fpsr.to_le_bytes());
+        buf.extend(self.fpcr.to_le_bytes());
+        buf
+    }
+}
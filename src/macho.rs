@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::error;
 use std::io::{Read, Seek, SeekFrom};
 use std::num::NonZeroU64;
@@ -8,14 +9,18 @@ use crate::command::dyld_exports_trie::DyldExportsTrieResolved;
 use crate::command::dyld_info::DyldInfoCommandResolved;
 use crate::command::dysymtab::DysymtabCommandResolved;
 use crate::command::function_starts::FunctionStartsCommandResolved;
-use crate::command::segment::SegmentCommand64;
-use crate::command::symtab::SymtabCommandResolved;
+use crate::command::note::{AddrableBits, NotePayload, NoteRegistry};
+use crate::command::segment::{Section64, SectionType, SegmentCommand32, SegmentCommand64};
+use crate::command::symtab::{NlistTypeType, SymtabCommandResolved};
 use crate::command::{LoadCommand, LoadCommandResolver};
 use crate::fat::{FatArch, FatHeader, FatMagic};
 use crate::file_subset::FileSubset;
 use crate::header::{MHMagic, MachHeader};
+use crate::relocation::{Relocation, SectionRelocations};
+use crate::vm_mapped::{MappedSegment, VmMapped};
 
 use crate::machine;
+use crate::machine::ThreadState;
 use std::fmt;
 
 #[derive(Debug)]
@@ -62,6 +67,20 @@ impl ImageValue {
     }
 }
 
+/// A function an image runs, or interposes, when it's loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadTimeFunction {
+    /// None for a function bound from another image.
+    pub address: Option<u64>,
+    pub symbol: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interpose {
+    pub replacement: LoadTimeFunction,
+    pub replacee: LoadTimeFunction,
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct MachO<T: Seek + Read> {
@@ -69,6 +88,10 @@ pub struct MachO<T: Seek + Read> {
     pub buf: T,
     pub load_commands: Vec<LoadCommand>,
     segs: Vec<SegmentCommand64>,
+    // How far the image was slid when it was loaded, for images parsed from memory.
+    slide: Option<u64>,
+    // The significant bits of user and kernel addresses in loaded arm64e pointers.
+    addrable_bits: (u32, u32),
 }
 
 impl<T: Seek + Read> MachO<T> {
@@ -76,21 +99,43 @@ impl<T: Seek + Read> MachO<T> {
         let header = MachHeader::parse(&mut buf)?;
         let load_commands = LoadCommand::parse_all(&mut buf, header)?;
 
+        Ok(Self::from_parts(header, load_commands, buf))
+    }
+
+    /// Parses a MachO whose header sits at `header_offset` within `buf`, such as a kext inside
+    /// a kernelcache. File offsets in its load commands stay relative to the start of `buf`.
+    pub fn parse_at(mut buf: T, header_offset: u64) -> MachOResult<Self> {
+        let (header, load_commands) = {
+            let size = buf.seek(SeekFrom::End(0)).map_err(MachOErr::IOError)?;
+            let mut subset =
+                FileSubset::new(&mut buf, header_offset, size.saturating_sub(header_offset))
+                    .map_err(MachOErr::IOError)?;
+            let header = MachHeader::parse(&mut subset)?;
+            let load_commands = LoadCommand::parse_all(&mut subset, header)?;
+            (header, load_commands)
+        };
+
+        Ok(Self::from_parts(header, load_commands, buf))
+    }
+
+    fn from_parts(header: MachHeader, load_commands: Vec<LoadCommand>, buf: T) -> Self {
         let segs: Vec<SegmentCommand64> = load_commands
             .iter()
             .filter_map(|lc| match lc {
-                LoadCommand::Segment64(cmd) => Some(cmd),
+                LoadCommand::Segment64(cmd) => Some(cmd.clone()),
+                LoadCommand::Segment32(cmd) => Some(widen_segment(cmd)),
                 _ => None,
             })
-            .cloned()
             .collect();
 
-        Ok(Self {
+        Self {
             header,
             load_commands,
             buf,
             segs,
-        })
+            slide: None,
+            addrable_bits: (47, 47),
+        }
     }
 
     pub fn read_offset_u64(&mut self, offset: u64) -> MachOResult<ImageValue> {
@@ -98,6 +143,34 @@ impl<T: Seek + Read> MachO<T> {
             return Err(MachOErr::InvalidValue(format!("Invalid offset: 0x{:x}", offset)));
         }
 
+        if let Some(slide) = self.slide {
+            let mut value = [0u8; 8];
+            self.buf.seek(SeekFrom::Start(offset)).map_err(MachOErr::IOError)?;
+            self.buf.read_exact(&mut value).map_err(MachOErr::IOError)?;
+            let value = u64::from_le_bytes(value);
+
+            // dyld has already applied the fixups, so pointers into the image are slid and,
+            // on arm64e, may be signed.
+            let arm64e = machine::CpuSubType::CpuSubTypeArm64(machine::CpuSubTypeArm64::ARM64E);
+            let target = if self.header.cpusubtype() == arm64e {
+                strip_pac(value, self.addrable_bits)
+            } else {
+                value
+            }
+            .wrapping_sub(slide);
+            let in_image = self.segs.iter().any(|seg| {
+                !seg.initprot.is_empty()
+                    && target
+                        .checked_sub(seg.vmaddr)
+                        .is_some_and(|offset| offset < seg.vmsize)
+            });
+            return Ok(if in_image {
+                ImageValue::Rebase(target)
+            } else {
+                ImageValue::Value(value)
+            });
+        }
+
         // TODO: cache this
         let fixups = self
             .load_commands
@@ -291,6 +364,93 @@ impl<T: Seek + Read> MachO<T> {
             })
     }
 
+    fn read_relocations(&mut self, offset: u32, count: u32) -> MachOResult<Vec<Relocation>> {
+        let mut bytes = vec![0u8; count as usize * Relocation::SIZE];
+        self.buf
+            .seek(SeekFrom::Start(offset as u64))
+            .map_err(MachOErr::IOError)?;
+        self.buf.read_exact(&mut bytes).map_err(MachOErr::IOError)?;
+        Relocation::parse_all(&bytes, count, *self.header.cputype())
+    }
+
+    /// Decodes the relocations of every section that has any, as found in object files.
+    pub fn resolve_section_relocations(&mut self) -> MachOResult<Vec<SectionRelocations>> {
+        let sections: Vec<(String, String, u32, u32)> = self
+            .load_commands
+            .iter()
+            .flat_map(|lc| match lc {
+                LoadCommand::Segment64(seg) => seg
+                    .sections
+                    .iter()
+                    .map(|s| (s.segname.clone(), s.sectname.clone(), s.reloff, s.nreloc))
+                    .collect(),
+                LoadCommand::Segment32(seg) => seg
+                    .sects
+                    .iter()
+                    .map(|s| (s.segname.clone(), s.sectname.clone(), s.reloff, s.nreloc))
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .filter(|(_, _, _, nreloc)| *nreloc > 0)
+            .collect();
+
+        sections
+            .into_iter()
+            .map(|(segname, sectname, reloff, nreloc)| {
+                Ok(SectionRelocations {
+                    segname,
+                    sectname,
+                    relocations: self.read_relocations(reloff, nreloc)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Decodes the image-wide external relocations referenced by LC_DYSYMTAB.
+    pub fn resolve_external_relocations(&mut self) -> MachOResult<Vec<Relocation>> {
+        let range = self.load_commands.iter().find_map(|lc| match lc {
+            LoadCommand::Dysymtab(cmd) => Some((cmd.extreloff, cmd.nextrel)),
+            _ => None,
+        });
+        match range {
+            Some((offset, count)) if count > 0 => self.read_relocations(offset, count),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Decodes the image-wide local relocations referenced by LC_DYSYMTAB.
+    pub fn resolve_local_relocations(&mut self) -> MachOResult<Vec<Relocation>> {
+        let range = self.load_commands.iter().find_map(|lc| match lc {
+            LoadCommand::Dysymtab(cmd) => Some((cmd.locreloff, cmd.nlocrel)),
+            _ => None,
+        });
+        match range {
+            Some((offset, count)) if count > 0 => self.read_relocations(offset, count),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// The payload of every `LC_NOTE`, in load command order, decoded with `registry`.
+    pub fn resolve_notes(&mut self, registry: &NoteRegistry) -> MachOResult<Vec<NotePayload>> {
+        let mut payloads = vec![];
+        let len = self.buf.seek(SeekFrom::End(0)).map_err(MachOErr::IOError)?;
+        for lc in &self.load_commands {
+            if let LoadCommand::Note(note) = lc {
+                if !matches!(note.offset.checked_add(note.size), Some(end) if end <= len) {
+                    return Err(MachOErr::InvalidValue(format!(
+                        "The note from {:?} extends past the end of the file",
+                        note.data_owner
+                    )));
+                }
+                let mut bytes = vec![0u8; note.size as usize];
+                self.buf.seek(SeekFrom::Start(note.offset)).map_err(MachOErr::IOError)?;
+                self.buf.read_exact(&mut bytes).map_err(MachOErr::IOError)?;
+                payloads.push(registry.decode(note, &bytes)?);
+            }
+        }
+        Ok(payloads)
+    }
+
     pub fn resolve_fixups(&mut self) -> Option<DyldChainedFixupCommandResolved> {
         self.load_commands
             .iter()
@@ -299,12 +459,328 @@ impl<T: Seek + Read> MachO<T> {
                 _ => None,
             })
     }
+
+    // The address the header is mapped at, which is where __TEXT starts.
+    fn header_address(&self) -> MachOResult<u64> {
+        self.segs
+            .iter()
+            .find(|seg| seg.fileoff == 0 && seg.filesize > 0)
+            .map(|seg| seg.vmaddr)
+            .ok_or(MachOErr::InvalidValue(
+                "No segment maps the header".to_string(),
+            ))
+    }
+
+    fn section_ranges<P>(&self, predicate: P) -> Vec<(u64, u64)>
+    where
+        P: Fn(&Section64) -> bool,
+    {
+        self.segs
+            .iter()
+            .flat_map(|seg| &seg.sections)
+            .filter(|sect| predicate(sect))
+            .map(|sect| (sect.addr, sect.size))
+            .collect()
+    }
+
+    fn pointer_size(&self) -> u64 {
+        match self.header {
+            MachHeader::Header32(_) => 4,
+            MachHeader::Header64(_) => 8,
+        }
+    }
+
+    // A pointer in a 32-bit image is a plain value, as chained fixups are only used by 64-bit
+    // images.
+    fn read_pointer(&mut self, vm_addr: u64) -> MachOResult<ImageValue> {
+        match self.header {
+            MachHeader::Header32(_) => {
+                Ok(ImageValue::Value(self.read_vm_addr_u32(vm_addr)? as u64))
+            }
+            MachHeader::Header64(_) => self.read_vm_addr_u64(vm_addr),
+        }
+    }
+
+    // Each pointer in the sections of `section_type` and where it was read from.
+    fn function_pointers(
+        &mut self,
+        section_type: SectionType,
+    ) -> MachOResult<Vec<(u64, ImageValue)>> {
+        let pointer_size = self.pointer_size();
+        let mut pointers = vec![];
+        for (addr, size) in self.section_ranges(|sect| sect.flags_sectype == section_type) {
+            let end = entries_end(addr, size, pointer_size)?;
+            for pointer in (addr..end).step_by(pointer_size as usize) {
+                pointers.push((pointer, self.read_pointer(pointer)?));
+            }
+        }
+        Ok(pointers)
+    }
+
+    fn init_offsets(&mut self) -> MachOResult<Vec<(u64, ImageValue)>> {
+        let ranges =
+            self.section_ranges(|sect| sect.flags_sectype == SectionType::SInitFuncOffsets);
+        let mut initializers = vec![];
+        if !ranges.is_empty() {
+            let header = self.header_address()?;
+            for (addr, size) in ranges {
+                for offset in (addr..entries_end(addr, size, 4)?).step_by(4) {
+                    let address = header
+                        .checked_add(self.read_vm_addr_u32(offset)? as u64)
+                        .ok_or_else(|| {
+                            MachOErr::InvalidValue(format!(
+                                "The initializer offset at {:#x} overflows",
+                                offset
+                            ))
+                        })?;
+                    initializers.push((offset, ImageValue::Value(address)));
+                }
+            }
+        }
+        Ok(initializers)
+    }
+
+    // Names the functions `pointers` point at from the symbol table, or the symbols they're
+    // bound to, from chained fixups or the dyld info.
+    fn symbolicate(&mut self, pointers: Vec<(u64, ImageValue)>) -> Vec<LoadTimeFunction> {
+        let symbols: HashMap<u64, String> = self
+            .resolve_symtab()
+            .map(|symtab| {
+                symtab
+                    .symbols
+                    .into_iter()
+                    .filter(|sym| !sym.n_type.stab && sym.n_type.type_ == NlistTypeType::Section)
+                    .map(|sym| (sym.n_value, sym.n_strx))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let binds: HashMap<u64, String> = self
+            .resolve_dyldinfoonly()
+            .or_else(|| self.resolve_dyldinfo())
+            .map(|info| {
+                info.bind_instructions
+                    .into_iter()
+                    .filter_map(|bind| {
+                        let seg = self.segs.get(bind.segment_index as usize)?;
+                        Some((seg.vmaddr + bind.segment_offset, bind.symbol_name))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        pointers
+            .into_iter()
+            .map(|(pointer, value)| match value {
+                ImageValue::Bind(name) => LoadTimeFunction {
+                    address: None,
+                    symbol: Some(name),
+                },
+                _ if binds.contains_key(&pointer) => LoadTimeFunction {
+                    address: None,
+                    symbol: binds.get(&pointer).cloned(),
+                },
+                ImageValue::Value(address) | ImageValue::Rebase(address) => LoadTimeFunction {
+                    address: Some(address),
+                    symbol: symbols.get(&address).cloned(),
+                },
+            })
+            .collect()
+    }
+
+    /// Where execution starts: `LC_MAIN`'s entry, the program counter of `LC_UNIXTHREAD`,
+    /// or for a dylib the init routine of `LC_ROUTINES`. Initializers run before it.
+    pub fn entry_point(&self) -> MachOResult<Option<u64>> {
+        for lc in &self.load_commands {
+            match lc {
+                LoadCommand::Main(cmd) => return Ok(Some(self.header_address()? + cmd.entryoff)),
+                LoadCommand::UnixThread(cmd) => {
+                    let pc = cmd.threads.iter().find_map(|thread| match thread {
+                        ThreadState::X86State32(state) => Some(state.eip as u64),
+                        ThreadState::X86State64(state) => Some(state.rip),
+                        ThreadState::ArmState32(state) => Some(state.pc as u64),
+                        ThreadState::Arm64State64(state) => Some(state.pc),
+                        _ => None,
+                    });
+                    return pc.map(Some).ok_or(MachOErr::InvalidValue(
+                        "LC_UNIXTHREAD has no thread state with a program counter".to_string(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(self.load_commands.iter().find_map(|lc| match lc {
+            LoadCommand::Routines(cmd) | LoadCommand::Routines64(cmd) => Some(cmd.init_address),
+            _ => None,
+        }))
+    }
+
+    /// The addresses of the functions in the initializer lists, `__mod_init_func` pointers and
+    /// then `__init_offsets` offsets from the header, in the order dyld calls them. Initializers
+    /// bound from other images are left out; `resolve_initializers` names them.
+    pub fn initializers(&mut self) -> MachOResult<Vec<u64>> {
+        Ok(self
+            .resolve_initializers()?
+            .into_iter()
+            .filter_map(|function| function.address)
+            .collect())
+    }
+
+    /// The initializers with their symbols, in the order dyld calls them.
+    pub fn resolve_initializers(&mut self) -> MachOResult<Vec<LoadTimeFunction>> {
+        let mut pointers = self.function_pointers(SectionType::SModInitFuncPointers)?;
+        pointers.extend(self.init_offsets()?);
+        Ok(self.symbolicate(pointers))
+    }
+
+    /// The functions in `__mod_term_func` with their symbols.
+    pub fn resolve_terminators(&mut self) -> MachOResult<Vec<LoadTimeFunction>> {
+        let pointers = self.function_pointers(SectionType::SModTermFuncPointers)?;
+        Ok(self.symbolicate(pointers))
+    }
+
+    /// The pairs in the interposing sections, which replace a function in every image dyld
+    /// loads after this one.
+    pub fn resolve_interposes(&mut self) -> MachOResult<Vec<Interpose>> {
+        // dyld also takes a __DATA,__interpose section that wasn't given the type.
+        let ranges = self.section_ranges(|sect| {
+            sect.flags_sectype == SectionType::SInterposing
+                || (sect.sectname == "__interpose"
+                    && (sect.segname.starts_with("__DATA") || sect.segname.starts_with("__AUTH")))
+        });
+        let pointer_size = self.pointer_size();
+        let tuple_size = pointer_size * 2;
+        let mut pointers = vec![];
+        for (addr, size) in ranges {
+            let end = entries_end(addr, size, tuple_size)?;
+            for pointer in (addr..end).step_by(pointer_size as usize) {
+                pointers.push((pointer, self.read_pointer(pointer)?));
+            }
+        }
+
+        let mut functions = self.symbolicate(pointers).into_iter();
+        let mut interposes = vec![];
+        while let (Some(replacement), Some(replacee)) = (functions.next(), functions.next()) {
+            interposes.push(Interpose {
+                replacement,
+                replacee,
+            });
+        }
+        Ok(interposes)
+    }
+}
+
+// The end of the whole `entry_size` entries in the section at `addr`. A trailing partial
+// entry is ignored, as dyld does.
+fn entries_end(addr: u64, size: u64, entry_size: u64) -> MachOResult<u64> {
+    addr.checked_add(size / entry_size * entry_size)
+        .ok_or_else(|| MachOErr::InvalidValue(format!("The section at {:#x} overflows", addr)))
+}
+
+// `segs` is only used to translate addresses and find sections, so 32-bit segments are kept
+// there widened.
+fn widen_segment(seg: &SegmentCommand32) -> SegmentCommand64 {
+    SegmentCommand64 {
+        cmd: seg.cmd,
+        cmdsize: seg.cmdsize,
+        segname: seg.segname.clone(),
+        vmaddr: seg.vmaddr as u64,
+        vmsize: seg.vmsize as u64,
+        fileoff: seg.fileoff as u64,
+        filesize: seg.filesize as u64,
+        maxprot: seg.maxprot,
+        initprot: seg.initprot,
+        nsects: seg.nsects,
+        flags: seg.flags,
+        sections: seg
+            .sects
+            .iter()
+            .map(|sect| Section64 {
+                sectname: sect.sectname.clone(),
+                segname: sect.segname.clone(),
+                addr: sect.addr as u64,
+                size: sect.size as u64,
+                offset: sect.offset,
+                align: sect.align,
+                reloff: sect.reloff,
+                nreloc: sect.nreloc,
+                flags_sectype: sect.flags_sectype,
+                flags_secattrs: sect.flags_secattrs,
+                reserved1: sect.reserved1,
+                reserved2: sect.reserved2,
+                reserved3: 0,
+            })
+            .collect(),
+    }
 }
 
 pub struct FatMachO<'a, T: Seek + Read> {
     pub header: FatHeader,
     pub archs: Vec<FatArch>,
-    buf: &'a mut T,
+    pub(crate) buf: &'a mut T,
+}
+
+impl<T: Seek + Read> MachO<VmMapped<T>> {
+    /// Parses an image dumped from memory, laid out by VM address from `base`, the address
+    /// its header was loaded at. File offsets are read from where their segment was loaded, so
+    /// `__LINKEDIT` can be anywhere after `base`, and pointers read from the image are unslid.
+    pub fn parse_memory(mut buf: T, base: u64) -> MachOResult<Self> {
+        buf.seek(SeekFrom::Start(0)).map_err(MachOErr::IOError)?;
+        let header = MachHeader::parse(&mut buf)?;
+        let load_commands = LoadCommand::parse_all(&mut buf, header)?;
+
+        let segments: Vec<&SegmentCommand64> = load_commands
+            .iter()
+            .filter_map(|lc| match lc {
+                LoadCommand::Segment64(seg) => Some(seg),
+                _ => None,
+            })
+            .collect();
+        let text = segments
+            .iter()
+            .find(|seg| seg.fileoff == 0 && seg.filesize > 0)
+            .ok_or_else(|| {
+                MachOErr::InvalidValue("No segment maps the image's header".to_string())
+            })?;
+        let slide = base.wrapping_sub(text.vmaddr);
+        let mapped = segments
+            .iter()
+            .filter(|seg| seg.vmaddr >= text.vmaddr)
+            .map(|seg| MappedSegment {
+                fileoff: seg.fileoff,
+                filesize: seg.filesize,
+                offset: seg.vmaddr - text.vmaddr,
+            })
+            .collect();
+
+        let mut macho = Self::from_parts(header, load_commands, VmMapped::new(buf, mapped));
+        macho.slide = Some(slide);
+        Ok(macho)
+    }
+
+    /// How far the image was slid from its preferred address when it was loaded.
+    pub fn slide(&self) -> u64 {
+        self.slide.unwrap_or(0)
+    }
+
+    /// Sets how many bits of a signed arm64e pointer are address, such as from a core file's
+    /// addrable bits note. Without it, 47 bits are assumed.
+    pub fn set_addrable_bits(&mut self, bits: &AddrableBits) {
+        self.addrable_bits = (bits.low_bits, bits.high_bits);
+    }
+}
+
+// Removes the signature from an arm64e pointer. Bit 55 picks the user or kernel half of
+// the address space, and the bits above the address are cleared or set to match.
+fn strip_pac(value: u64, (low_bits, high_bits): (u32, u32)) -> u64 {
+    let kernel = value & 1 << 55 != 0;
+    let bits = if kernel { high_bits } else { low_bits };
+    let mask = u64::MAX >> (64 - bits.clamp(1, 64));
+    if kernel {
+        value | !mask
+    } else {
+        value & mask
+    }
 }
 
 impl<'a, T: Seek + Read> FatMachO<'a, T> {
@@ -336,19 +812,45 @@ impl<'a, T: Seek + Read> FatMachO<'a, T> {
         })
     }
 
+    /// Parses the first slice for `cputype`. Use `macho_for` or `macho_at` to reach other
+    /// slices of the same CPU type, such as x86_64h next to x86_64.
     pub fn macho(
         &'a mut self,
         cputype: machine::CpuType,
     ) -> MachOResult<MachO<FileSubset<'a, T>>> {
-        let arch = self
+        let index = self
             .archs
             .iter()
-            .find(|arch| arch.cputype() == cputype)
+            .position(|arch| arch.cputype() == cputype)
             .ok_or(MachOErr::InvalidValue(format!("CPU type {:?} not found in fat binary", cputype)))?;
+        self.macho_at(index)
+    }
+
+    pub fn macho_for(
+        &'a mut self,
+        cputype: machine::CpuType,
+        cpusubtype: machine::CpuSubType,
+    ) -> MachOResult<MachO<FileSubset<'a, T>>> {
+        let index = self
+            .archs
+            .iter()
+            .position(|arch| arch.cputype() == cputype && arch.cpusubtype() == cpusubtype)
+            .ok_or(MachOErr::InvalidValue(format!(
+                "CPU type {:?} {:?} not found in fat binary",
+                cputype, cpusubtype
+            )))?;
+        self.macho_at(index)
+    }
+
+    pub fn macho_at(&mut self, index: usize) -> MachOResult<MachO<FileSubset<'_, T>>> {
+        let arch = self
+            .archs
+            .get(index)
+            .ok_or(MachOErr::InvalidValue(format!("Fat binary has no slice {}", index)))?;
         let offset = arch.offset();
         let size = arch.size();
 
-        let mut partial = FileSubset::new(self.buf, offset, size).map_err(|_| MachOErr::InvalidValue("Unable to create subset".to_string()))?;
+        let mut partial = FileSubset::new(&mut *self.buf, offset, size).map_err(|_| MachOErr::InvalidValue("Unable to create subset".to_string()))?;
 
         if !MachO::is_macho_magic(&mut partial)? {
             return Err(MachOErr::InvalidValue("Fat MachO slice is not a MachO".to_string()));
@@ -356,4 +858,457 @@ impl<'a, T: Seek + Read> FatMachO<'a, T> {
 
         MachO::parse(partial)
     }
+
+    /// The index of the slice dyld would pick on a `cputype` machine: the most capable subtype
+    /// first (arm64e over arm64, x86_64h over x86_64), then the first slice of that type.
+    pub fn best_arch(&self, cputype: machine::CpuType) -> Option<usize> {
+        let preference = match cputype {
+            machine::CpuType::Arm64 => vec![
+                machine::CpuSubType::CpuSubTypeArm64(machine::CpuSubTypeArm64::ARM64E),
+                machine::CpuSubType::CpuSubTypeArm64(machine::CpuSubTypeArm64::All),
+                machine::CpuSubType::CpuSubTypeArm64(machine::CpuSubTypeArm64::V8),
+            ],
+            machine::CpuType::X86_64 => vec![
+                machine::CpuSubType::CpuSubTypeX86(machine::CpuSubTypeX86::X86_64H),
+                machine::CpuSubType::CpuSubTypeX86(machine::CpuSubTypeX86::All),
+            ],
+            _ => vec![],
+        };
+
+        let candidates: Vec<usize> = (0..self.archs.len())
+            .filter(|&i| self.archs[i].cputype() == cputype)
+            .collect();
+        preference
+            .iter()
+            .find_map(|&cpusubtype| {
+                candidates
+                    .iter()
+                    .copied()
+                    .find(|&i| self.archs[i].cpusubtype() == cpusubtype)
+            })
+            .or(candidates.first().copied())
+    }
+
+    /// `best_arch` for the machine this code is running on.
+    pub fn best_arch_for_host(&self) -> Option<usize> {
+        let cputype = if cfg!(target_arch = "aarch64") {
+            machine::CpuType::Arm64
+        } else if cfg!(target_arch = "x86_64") {
+            machine::CpuType::X86_64
+        } else if cfg!(target_arch = "x86") {
+            machine::CpuType::I386
+        } else if cfg!(target_arch = "arm") {
+            machine::CpuType::Arm
+        } else {
+            return None;
+        };
+        self.best_arch(cputype)
+    }
+
+    /// Parses every slice in turn and hands it to `f`. The slices are views into the file, so
+    /// only one is alive at a time.
+    pub fn for_each_macho<F>(&mut self, mut f: F) -> MachOResult<()>
+    where
+        F: FnMut(usize, MachO<FileSubset<'_, T>>) -> MachOResult<()>,
+    {
+        for index in 0..self.archs.len() {
+            f(index, self.macho_at(index)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::command::dyld_info::{
+        BindInstruction, BindType, DyldInfoCommand, RebaseInstruction, RebaseType,
+    };
+    use crate::command::segment::{Section32, Section64, SectionAttributes};
+    use crate::command::thread::ThreadCommand;
+    use crate::command::{EntryPointCommand, LCLoadCommand, LoadCommandParser};
+    use crate::edit::fat::{build_fat, FatSlice};
+    use crate::edit::loader::LoadedImage;
+    use crate::header::MHFileType;
+    use crate::machine::{
+        Arm64ThreadState64, CpuSubType, CpuSubTypeArm64, CpuSubTypeX86, CpuType,
+    };
+    use crate::testing::{
+        dyld_info, image32, image64, image64_for, section64, segment32, segment64, symtab,
+    };
+
+    fn slice(cputype: CpuType, cpusubtype: CpuSubType) -> FatSlice {
+        let mut data = image64_for(cputype, cpusubtype, MHFileType::MhExecute, &[]);
+        data.resize(0x1000, 0);
+        FatSlice::from_macho(data).unwrap()
+    }
+
+    #[test]
+    fn test_fat_slice_selection() {
+        let fat = build_fat(&[
+            slice(CpuType::X86_64, CpuSubType::CpuSubTypeX86(CpuSubTypeX86::All)),
+            slice(CpuType::X86_64, CpuSubType::CpuSubTypeX86(CpuSubTypeX86::X86_64H)),
+            slice(CpuType::Arm64, CpuSubType::CpuSubTypeArm64(CpuSubTypeArm64::All)),
+            slice(CpuType::Arm64, CpuSubType::CpuSubTypeArm64(CpuSubTypeArm64::ARM64E)),
+        ])
+        .unwrap();
+        let mut cursor = Cursor::new(fat);
+        let mut fat = FatMachO::parse(&mut cursor).unwrap();
+
+        assert_eq!(fat.best_arch(CpuType::X86_64), Some(1));
+        assert_eq!(fat.best_arch(CpuType::Arm64), Some(3));
+        assert_eq!(fat.best_arch(CpuType::I386), None);
+
+        let mut subtypes = vec![];
+        fat.for_each_macho(|_, macho| {
+            subtypes.push(macho.header.cpusubtype());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(subtypes.len(), 4);
+        assert_eq!(
+            subtypes[2],
+            CpuSubType::CpuSubTypeArm64(CpuSubTypeArm64::All)
+        );
+        let macho = fat.macho_at(1).unwrap();
+        assert_eq!(
+            macho.header.cpusubtype(),
+            CpuSubType::CpuSubTypeX86(CpuSubTypeX86::X86_64H)
+        );
+
+        let macho = fat
+            .macho_for(
+                CpuType::Arm64,
+                CpuSubType::CpuSubTypeArm64(CpuSubTypeArm64::ARM64E),
+            )
+            .unwrap();
+        assert_eq!(*macho.header.cputype(), CpuType::Arm64);
+    }
+
+    // An arm64 executable with a rebased pointer to _main in __DATA and _main in its symtab.
+    fn executable() -> Vec<u8> {
+        let rebases = RebaseInstruction::serialize(&[RebaseInstruction {
+            segment_index: 2,
+            segment_offset: 0,
+            rebase_type: RebaseType::Pointer,
+        }])
+        .unwrap();
+        let mut symbols = vec![1, 0, 0, 0, 0x0f, 1, 0, 0];
+        symbols.extend(0x1_0000_3f00u64.to_le_bytes());
+        let linkedit = [rebases.clone(), symbols, b"\0_main\0\0".to_vec()].concat();
+        let size = linkedit.len() as u64;
+        let symoff = 0x8000 + rebases.len() as u32;
+        let cmds = [
+            segment64("__PAGEZERO", 0, 0x1_0000_0000, 0, 0, vec![]),
+            segment64("__TEXT", 0x1_0000_0000, 0x4000, 0, 0x4000, vec![]),
+            segment64("__DATA", 0x1_0000_4000, 0x8000, 0x4000, 0x4000, vec![]),
+            segment64("__LINKEDIT", 0x1_0000_c000, 0x4000, 0x8000, size, vec![]),
+            DyldInfoCommand {
+                rebase_off: 0x8000,
+                rebase_size: rebases.len() as u32,
+                ..dyld_info()
+            }
+            .serialize(),
+            symtab(symoff, 1, symoff + 0x10, 8),
+        ];
+        let mut bytes = image64(MHFileType::MhExecute, &cmds);
+        bytes.resize(0x4000, 0);
+        bytes.extend(0x1_0000_3f00u64.to_le_bytes());
+        bytes.resize(0x8000, 0);
+        bytes.extend(linkedit);
+        bytes
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        let file = executable();
+        let slide = 0x2_0000;
+        let dump = LoadedImage::load(file.clone(), slide, |_| None).unwrap();
+        let mut macho = MachO::parse_memory(Cursor::new(dump.data), dump.address).unwrap();
+        assert_eq!(macho.slide(), slide);
+
+        let symbols = MachO::parse(Cursor::new(file)).unwrap().resolve_symtab();
+        assert!(symbols.is_some());
+        assert_eq!(macho.resolve_symtab(), symbols);
+        let pointer = macho.read_vm_addr_u64(0x1_0000_4000).unwrap();
+        assert!(matches!(pointer, ImageValue::Rebase(0x1_0000_3f00)));
+
+        // Signatures are only stripped from arm64e pointers.
+        for (cpusubtype, stripped) in [(0u32, false), (0x8000_0002, true)] {
+            let mut file = executable();
+            file[8..12].copy_from_slice(&cpusubtype.to_le_bytes());
+            let mut dump = LoadedImage::load(file, slide, |_| None).unwrap();
+            dump.data[0x4006] |= 0x2a;
+            let mut macho = MachO::parse_memory(Cursor::new(dump.data), dump.address).unwrap();
+            let pointer = macho.read_vm_addr_u64(0x1_0000_4000).unwrap();
+            assert_eq!(
+                matches!(pointer, ImageValue::Rebase(0x1_0000_3f00)),
+                stripped
+            );
+        }
+        assert_eq!(
+            strip_pac(0x80aa_fff0_0700_1000, (47, 39)),
+            0xffff_fff0_0700_1000
+        );
+    }
+
+    // An arm64 executable with main at 0x1_0000_3e00, initializers in __init_offsets and
+    // __mod_init_func, a terminator and a __DATA,__interpose section without a section type
+    // that replaces the bound _malloc.
+    fn executable_with_initializers() -> Vec<u8> {
+        let section = |segname, sectname, addr: u64, size, flags_sectype| Section64 {
+            flags_sectype,
+            ..section64(segname, sectname, addr, size, (addr - 0x1_0000_0000) as u32)
+        };
+        let text = vec![section(
+            "__TEXT",
+            "__init_offsets",
+            0x1_0000_3f00,
+            8,
+            SectionType::SInitFuncOffsets,
+        )];
+        let data = vec![
+            section(
+                "__DATA",
+                "__mod_init_func",
+                0x1_0000_4000,
+                8,
+                SectionType::SModInitFuncPointers,
+            ),
+            section(
+                "__DATA",
+                "__mod_term_func",
+                0x1_0000_4008,
+                8,
+                SectionType::SModTermFuncPointers,
+            ),
+            section(
+                "__DATA",
+                "__interpose",
+                0x1_0000_4010,
+                16,
+                SectionType::SRegular,
+            ),
+        ];
+
+        let binds = BindInstruction::serialize(&[BindInstruction {
+            segment_index: 2,
+            segment_offset: 0x18,
+            bind_type: BindType::Pointer,
+            dylib_ordinal: 1,
+            symbol_name: "_malloc".to_string(),
+            symbol_flags: 0,
+            addend: 0,
+        }])
+        .unwrap();
+        let names = ["_main", "_init_a", "_init_b", "_fini", "_my_malloc"];
+        let addresses = [0x3e00u64, 0x3e20, 0x3e40, 0x3ea0, 0x3ec0];
+        let mut symbols = vec![];
+        let mut strings = b"\0".to_vec();
+        for (name, address) in names.iter().zip(addresses) {
+            symbols.extend((strings.len() as u32).to_le_bytes());
+            symbols.extend([0x0f, 1, 0, 0]);
+            symbols.extend((0x1_0000_0000 + address).to_le_bytes());
+            strings.extend(name.as_bytes());
+            strings.push(0);
+        }
+        let symoff = 0x8000 + binds.len() as u32;
+        let stroff = symoff + symbols.len() as u32;
+
+        let cmds = [
+            segment64("__PAGEZERO", 0, 0x1_0000_0000, 0, 0, vec![]),
+            segment64("__TEXT", 0x1_0000_0000, 0x4000, 0, 0x4000, text),
+            segment64("__DATA", 0x1_0000_4000, 0x4000, 0x4000, 0x4000, data),
+            segment64("__LINKEDIT", 0x1_0000_8000, 0x4000, 0x8000, 0x4000, vec![]),
+            DyldInfoCommand {
+                bind_off: 0x8000,
+                bind_size: binds.len() as u32,
+                ..dyld_info()
+            }
+            .serialize(),
+            symtab(symoff, names.len() as u32, stroff, strings.len() as u32),
+            EntryPointCommand {
+                cmd: LCLoadCommand::LcMain,
+                cmdsize: 24,
+                entryoff: 0x3e00,
+                stacksize: 0,
+            }
+            .serialize(),
+        ];
+        let mut bytes = image64(MHFileType::MhExecute, &cmds);
+        bytes.resize(0x3f00, 0);
+        bytes.extend(0x3e40u32.to_le_bytes());
+        bytes.extend(0x3e80u32.to_le_bytes());
+        bytes.resize(0x4000, 0);
+        for pointer in [0x1_0000_3e20u64, 0x1_0000_3ea0, 0x1_0000_3ec0, 0] {
+            bytes.extend(pointer.to_le_bytes());
+        }
+        bytes.resize(0x8000, 0);
+        bytes.extend([binds, symbols, strings].concat());
+        bytes.resize(0xc000, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_entry_point() {
+        let mut macho = MachO::parse(Cursor::new(executable_with_initializers())).unwrap();
+        assert_eq!(macho.entry_point().unwrap(), Some(0x1_0000_3e00));
+        assert_eq!(
+            macho.initializers().unwrap(),
+            vec![0x1_0000_3e20, 0x1_0000_3e40, 0x1_0000_3e80]
+        );
+
+        let thread = Arm64ThreadState64 {
+            x: [0; 29],
+            fp: 0,
+            lr: 0,
+            sp: 0,
+            pc: 0x1_0000_3e10,
+            cpsr: 0,
+        };
+        let main = macho
+            .load_commands
+            .iter()
+            .position(|lc| matches!(lc, LoadCommand::Main(_)))
+            .unwrap();
+        macho.load_commands[main] = LoadCommand::UnixThread(ThreadCommand {
+            cmd: LCLoadCommand::LcUnixThread,
+            cmdsize: 288,
+            threads: vec![ThreadState::Arm64State64(thread)],
+        });
+        assert_eq!(macho.entry_point().unwrap(), Some(0x1_0000_3e10));
+
+        macho.load_commands.remove(main);
+        assert_eq!(macho.entry_point().unwrap(), None);
+
+        // Point __mod_init_func at the bound _malloc.
+        macho.segs[2].sections[0].addr = 0x1_0000_4018;
+        assert_eq!(
+            macho.initializers().unwrap(),
+            vec![0x1_0000_3e40, 0x1_0000_3e80]
+        );
+    }
+
+    #[test]
+    fn test_load_time_functions() {
+        let function = |address, symbol: &str| LoadTimeFunction {
+            address,
+            symbol: Some(symbol.to_string()),
+        };
+        let mut macho = MachO::parse(Cursor::new(executable_with_initializers())).unwrap();
+        assert_eq!(
+            macho.resolve_initializers().unwrap(),
+            vec![
+                function(Some(0x1_0000_3e20), "_init_a"),
+                function(Some(0x1_0000_3e40), "_init_b"),
+                LoadTimeFunction {
+                    address: Some(0x1_0000_3e80),
+                    symbol: None,
+                },
+            ]
+        );
+        assert_eq!(
+            macho.resolve_terminators().unwrap(),
+            vec![function(Some(0x1_0000_3ea0), "_fini")]
+        );
+        // A trailing partial pointer is ignored.
+        macho.segs[2].sections[1].size = 12;
+        assert_eq!(macho.resolve_terminators().unwrap().len(), 1);
+        macho.segs[2].sections[1].addr = u64::MAX - 4;
+        assert!(macho.resolve_terminators().is_err());
+        assert_eq!(
+            macho.resolve_interposes().unwrap(),
+            vec![Interpose {
+                replacement: function(Some(0x1_0000_3ec0), "_my_malloc"),
+                replacee: function(None, "_malloc"),
+            }]
+        );
+    }
+
+    // An i386 executable with main at 0x1f00, initializers in __mod_init_func and
+    // __init_offsets, and an __interpose section replacing 0x1ec0 with 0x1e80.
+    fn executable32() -> Vec<u8> {
+        let section = |sectname: &str, segname: &str, addr: u32, size: u32, sectype| Section32 {
+            sectname: sectname.to_string(),
+            segname: segname.to_string(),
+            addr,
+            size,
+            offset: addr - 0x1000,
+            align: 2,
+            reloff: 0,
+            nreloc: 0,
+            flags_sectype: sectype,
+            flags_secattrs: SectionAttributes::empty(),
+            reserved1: 0,
+            reserved2: 0,
+        };
+        let text = vec![section(
+            "__init_offsets",
+            "__TEXT",
+            0x1f80,
+            4,
+            SectionType::SInitFuncOffsets,
+        )];
+        let data = vec![
+            section(
+                "__mod_init_func",
+                "__DATA",
+                0x2000,
+                4,
+                SectionType::SModInitFuncPointers,
+            ),
+            section(
+                "__interpose",
+                "__DATA",
+                0x2008,
+                8,
+                SectionType::SInterposing,
+            ),
+        ];
+
+        let mut bytes = image32(
+            MHFileType::MhExecute,
+            &[
+                segment32("__PAGEZERO", 0, 0x1000, 0, 0, vec![]),
+                segment32("__TEXT", 0x1000, 0x1000, 0, 0x1000, text),
+                segment32("__DATA", 0x2000, 0x1000, 0x1000, 0x1000, data),
+                EntryPointCommand {
+                    cmd: LCLoadCommand::LcMain,
+                    cmdsize: 24,
+                    entryoff: 0xf00,
+                    stacksize: 0,
+                }
+                .serialize(),
+            ],
+        );
+        bytes.resize(0xf80, 0);
+        bytes.extend(0xe40u32.to_le_bytes());
+        bytes.resize(0x1000, 0);
+        for pointer in [0x1e00u32, 0, 0x1e80, 0x1ec0] {
+            bytes.extend(pointer.to_le_bytes());
+        }
+        bytes.resize(0x2000, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_entry_point_32() {
+        let mut macho = MachO::parse(Cursor::new(executable32())).unwrap();
+        assert_eq!(macho.entry_point().unwrap(), Some(0x1f00));
+        assert_eq!(macho.initializers().unwrap(), vec![0x1e00, 0x1e40]);
+
+        let function = |address| LoadTimeFunction {
+            address: Some(address),
+            symbol: None,
+        };
+        assert_eq!(
+            macho.resolve_interposes().unwrap(),
+            vec![Interpose {
+                replacement: function(0x1e80),
+                replacee: function(0x1ec0),
+            }]
+        );
+    }
 }
@@ -0,0 +1,381 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::macho::{MachOErr, MachOResult};
+
+/// A value from an XML property list.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlistValue {
+    Dict(BTreeMap<String, PlistValue>),
+    Array(Vec<PlistValue>),
+    String(String),
+    Integer(u64),
+    Real(f64),
+    Bool(bool),
+    Data(Vec<u8>),
+    Date(String),
+}
+
+impl PlistValue {
+    /// Parses an XML plist document. Kernelcaches de-duplicate repeated values with `ID` and
+    /// `IDREF` attributes, which are expanded here so the result is a plain value tree.
+    pub fn parse_xml(xml: &str) -> MachOResult<PlistValue> {
+        let tokens = tokenize(xml)?;
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+            ids: HashMap::new(),
+        };
+
+        loop {
+            match parser.tokens.next() {
+                Some(Token::Open(tag)) if tag.name == "plist" => {
+                    if tag.self_closing {
+                        return Err(MachOErr::ParsingError("Empty plist".to_string()));
+                    }
+                }
+                Some(Token::Open(tag)) => return parser.value(tag),
+                Some(_) => (),
+                None => return Err(MachOErr::ParsingError("Plist has no value".to_string())),
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&PlistValue> {
+        self.as_dict().and_then(|dict| dict.get(key))
+    }
+
+    pub fn as_dict(&self) -> Option<&BTreeMap<String, PlistValue>> {
+        match self {
+            PlistValue::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<PlistValue>> {
+        match self {
+            PlistValue::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PlistValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            PlistValue::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            PlistValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Tag {
+    name: String,
+    attrs: Vec<(String, String)>,
+    self_closing: bool,
+}
+
+impl Tag {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open(Tag),
+    Close(String),
+    Text(String),
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn parse_tag(body: &str) -> MachOResult<Tag> {
+    let self_closing = body.ends_with('/');
+    let body = body.trim_end_matches('/');
+    let name_end = body
+        .find(|c: char| c.is_ascii_whitespace())
+        .unwrap_or(body.len());
+    let name = body[..name_end].to_string();
+
+    let mut attrs = Vec::new();
+    let mut rest = body[name_end..].trim_start();
+    while !rest.is_empty() {
+        let eq = rest
+            .find('=')
+            .ok_or_else(|| MachOErr::ParsingError(format!("Malformed attribute in <{}>", name)))?;
+        let key = rest[..eq].trim().to_string();
+        let value = rest[eq + 1..].trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+            .ok_or_else(|| MachOErr::ParsingError(format!("Unquoted attribute in <{}>", name)))?;
+        let end = value[1..].find(quote).ok_or_else(|| {
+            MachOErr::ParsingError(format!("Unterminated attribute in <{}>", name))
+        })?;
+        attrs.push((key, unescape(&value[1..end + 1])));
+        rest = value[end + 2..].trim_start();
+    }
+
+    Ok(Tag {
+        name,
+        attrs,
+        self_closing,
+    })
+}
+
+fn tokenize(xml: &str) -> MachOResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = xml;
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment
+                .find("-->")
+                .ok_or_else(|| MachOErr::ParsingError("Unterminated comment".to_string()))?;
+            rest = &comment[end + 3..];
+        } else if rest.starts_with('<') {
+            let end = rest
+                .find('>')
+                .ok_or_else(|| MachOErr::ParsingError("Unterminated tag".to_string()))?;
+            let body = rest[1..end].trim();
+            if let Some(name) = body.strip_prefix('/') {
+                tokens.push(Token::Close(name.trim().to_string()));
+            } else if !body.starts_with('?') && !body.starts_with('!') {
+                tokens.push(Token::Open(parse_tag(body)?));
+            }
+            rest = &rest[end + 1..];
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            tokens.push(Token::Text(unescape(&rest[..end])));
+            rest = &rest[end..];
+        }
+    }
+    Ok(tokens)
+}
+
+fn decode_base64(text: &str) -> MachOResult<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut accum = 0u32;
+    let mut nbits = 0;
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ if c.is_ascii_whitespace() => continue,
+            _ => {
+                return Err(MachOErr::ParsingError(
+                    "Invalid base64 in <data>".to_string(),
+                ))
+            }
+        };
+        accum = (accum << 6) | value as u32;
+        nbits += 6;
+        if nbits >= 8 {
+            nbits -= 8;
+            out.push((accum >> nbits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+fn parse_integer(text: &str) -> MachOResult<u64> {
+    let text = text.trim();
+    let parsed = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(neg) = text.strip_prefix('-') {
+        neg.parse::<i64>().ok().map(|i| i.wrapping_neg() as u64)
+    } else {
+        text.parse::<u64>().ok()
+    };
+    parsed.ok_or_else(|| MachOErr::ParsingError(format!("Invalid plist integer {:?}", text)))
+}
+
+struct Parser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    ids: HashMap<String, PlistValue>,
+}
+
+impl Parser {
+    // Collects the text content of `tag` up to and including its closing tag.
+    fn text(&mut self, tag: &Tag) -> MachOResult<String> {
+        if tag.self_closing {
+            return Ok(String::new());
+        }
+        let mut text = String::new();
+        loop {
+            match self.tokens.next() {
+                Some(Token::Text(t)) => text.push_str(&t),
+                Some(Token::Close(name)) if name == tag.name => return Ok(text),
+                _ => {
+                    return Err(MachOErr::ParsingError(format!(
+                        "Unterminated <{}> in plist",
+                        tag.name
+                    )))
+                }
+            }
+        }
+    }
+
+    // Returns the next tag, skipping whitespace. Returns None when `parent` is closed.
+    fn next_child(&mut self, parent: &str) -> MachOResult<Option<Tag>> {
+        loop {
+            match self.tokens.next() {
+                Some(Token::Text(_)) => (),
+                Some(Token::Open(tag)) => return Ok(Some(tag)),
+                Some(Token::Close(name)) if name == parent => return Ok(None),
+                _ => {
+                    return Err(MachOErr::ParsingError(format!(
+                        "Unterminated <{}> in plist",
+                        parent
+                    )))
+                }
+            }
+        }
+    }
+
+    fn value(&mut self, tag: Tag) -> MachOResult<PlistValue> {
+        if let Some(idref) = tag.attr("IDREF") {
+            let value =
+                self.ids.get(idref).cloned().ok_or_else(|| {
+                    MachOErr::ParsingError(format!("Unknown plist IDREF {}", idref))
+                })?;
+            self.text(&tag)?;
+            return Ok(value);
+        }
+
+        let value = match tag.name.as_str() {
+            "dict" => {
+                let mut dict = BTreeMap::new();
+                if !tag.self_closing {
+                    while let Some(key) = self.next_child("dict")? {
+                        if key.name != "key" {
+                            return Err(MachOErr::ParsingError(format!(
+                                "Expected <key> in dict, found <{}>",
+                                key.name
+                            )));
+                        }
+                        let key = self.text(&key)?;
+                        let value = self.next_child("dict")?.ok_or_else(|| {
+                            MachOErr::ParsingError(format!("Missing value for key {}", key))
+                        })?;
+                        dict.insert(key, self.value(value)?);
+                    }
+                }
+                PlistValue::Dict(dict)
+            }
+            "array" => {
+                let mut array = Vec::new();
+                if !tag.self_closing {
+                    while let Some(child) = self.next_child("array")? {
+                        array.push(self.value(child)?);
+                    }
+                }
+                PlistValue::Array(array)
+            }
+            "string" => PlistValue::String(self.text(&tag)?),
+            "integer" => PlistValue::Integer(parse_integer(&self.text(&tag)?)?),
+            "real" => {
+                let text = self.text(&tag)?;
+                PlistValue::Real(text.trim().parse().map_err(|_| {
+                    MachOErr::ParsingError(format!("Invalid plist real {:?}", text))
+                })?)
+            }
+            "true" | "false" => {
+                self.text(&tag)?;
+                PlistValue::Bool(tag.name == "true")
+            }
+            "data" => PlistValue::Data(decode_base64(&self.text(&tag)?)?),
+            "date" => PlistValue::Date(self.text(&tag)?),
+            name => {
+                return Err(MachOErr::ParsingError(format!(
+                    "Unknown plist element <{}>",
+                    name
+                )))
+            }
+        };
+
+        if let Some(id) = tag.attr("ID") {
+            self.ids.insert(id.to_string(), value.clone());
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_xml_plist() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Name</key><string>a &amp; b</string>
+    <key>Flags</key><array><true/><false/></array>
+    <key>Blob</key><data>AAEC/w==</data>
+    <key>Neg</key><integer>-1</integer>
+</dict>
+</plist>"#;
+        let plist = PlistValue::parse_xml(xml).unwrap();
+        assert_eq!(plist.get("Name").unwrap().as_str(), Some("a & b"));
+        assert_eq!(
+            plist.get("Flags").unwrap().as_array().unwrap(),
+            &vec![PlistValue::Bool(true), PlistValue::Bool(false)]
+        );
+        assert_eq!(
+            plist.get("Blob"),
+            Some(&PlistValue::Data(vec![0, 1, 2, 0xff]))
+        );
+        assert_eq!(plist.get("Neg").unwrap().as_u64(), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_parse_xml_plist_idref() {
+        let xml = r#"<dict><key>_PrelinkInfoDictionary</key><array>
+<dict><key>CFBundleIdentifier</key><string ID="1">com.apple.a</string>
+<key>_PrelinkExecutableSize</key><integer size="64" ID="2">0x4000</integer></dict>
+<dict><key>OSBundleRequired</key><string IDREF="1"/>
+<key>_PrelinkExecutableSize</key><integer IDREF="2"/></dict>
+</array></dict>"#;
+        let plist = PlistValue::parse_xml(xml).unwrap();
+        let kexts = plist
+            .get("_PrelinkInfoDictionary")
+            .unwrap()
+            .as_array()
+            .unwrap();
+        assert_eq!(
+            kexts[1].get("OSBundleRequired").unwrap().as_str(),
+            Some("com.apple.a")
+        );
+        assert_eq!(
+            kexts[1].get("_PrelinkExecutableSize").unwrap().as_u64(),
+            Some(0x4000)
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use bitfield::bitfield;
+use nom::{multi, number::complete::le_u32, IResult};
+use num_derive::FromPrimitive;
+
+use crate::machine::CpuType;
+use crate::macho::MachOResult;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum GenericRelocType {
+    Vanilla = 0,
+    Pair = 1,
+    Sectdiff = 2,
+    PbLaPtr = 3,
+    LocalSectdiff = 4,
+    Tlv = 5,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum X86_64RelocType {
+    Unsigned = 0,
+    Signed = 1,
+    Branch = 2,
+    GotLoad = 3,
+    Got = 4,
+    Subtractor = 5,
+    Signed1 = 6,
+    Signed2 = 7,
+    Signed4 = 8,
+    Tlv = 9,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum Arm64RelocType {
+    Unsigned = 0,
+    Subtractor = 1,
+    Branch26 = 2,
+    Page21 = 3,
+    Pageoff12 = 4,
+    GotLoadPage21 = 5,
+    GotLoadPageoff12 = 6,
+    PointerToGot = 7,
+    TlvpLoadPage21 = 8,
+    TlvpLoadPageoff12 = 9,
+    Addend = 10,
+    AuthenticatedPointer = 11,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum ArmRelocType {
+    Vanilla = 0,
+    Pair = 1,
+    Sectdiff = 2,
+    LocalSectdiff = 3,
+    PbLaPtr = 4,
+    Br24 = 5,
+    ThumbBr22 = 6,
+    Thumb32BitBranch = 7,
+    Half = 8,
+    HalfSectdiff = 9,
+}
+
+/// The `r_type` of a relocation, which is interpreted according to the image's CPU type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationType {
+    Generic(GenericRelocType),
+    X86_64(X86_64RelocType),
+    Arm64(Arm64RelocType),
+    Arm(ArmRelocType),
+    Unknown(u8),
+}
+
+impl RelocationType {
+    pub fn from_raw(r_type: u8, cputype: CpuType) -> RelocationType {
+        let r_type_u32 = r_type as u32;
+        let known = match cputype {
+            CpuType::I386 => num::FromPrimitive::from_u32(r_type_u32).map(Self::Generic),
+            CpuType::X86_64 => num::FromPrimitive::from_u32(r_type_u32).map(Self::X86_64),
+            CpuType::Arm64 | CpuType::Arm64_32 => {
+                num::FromPrimitive::from_u32(r_type_u32).map(Self::Arm64)
+            }
+            CpuType::Arm => num::FromPrimitive::from_u32(r_type_u32).map(Self::Arm),
+            _ => None,
+        };
+        known.unwrap_or(RelocationType::Unknown(r_type))
+    }
+
+    pub fn raw(&self) -> u8 {
+        match self {
+            RelocationType::Generic(t) => *t as u8,
+            RelocationType::X86_64(t) => *t as u8,
+            RelocationType::Arm64(t) => *t as u8,
+            RelocationType::Arm(t) => *t as u8,
+            RelocationType::Unknown(t) => *t,
+        }
+    }
+}
+
+bitfield! {
+    pub struct RelocationInfoBF(u32);
+    impl Debug;
+    pub symbolnum, set_symbolnum: 23, 0;
+    pub pcrel, set_pcrel: 24;
+    pub length, set_length: 26, 25;
+    pub is_extern, set_extern: 27;
+    pub r_type, set_type: 31, 28;
+}
+
+bitfield! {
+    pub struct ScatteredRelocationInfoBF(u32);
+    impl Debug;
+    pub address, set_address: 23, 0;
+    pub r_type, set_type: 27, 24;
+    pub length, set_length: 29, 28;
+    pub pcrel, set_pcrel: 30;
+    pub scattered, set_scattered: 31;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelocationInfo {
+    /// Offset from the start of the section, or from the first segment for image relocations.
+    pub address: i32,
+    /// Symbol table index when `is_extern`, otherwise a 1-based section ordinal.
+    pub symbolnum: u32,
+    pub pcrel: bool,
+    /// log2 of the fixup size: 0=byte, 1=word, 2=long, 3=quad.
+    pub length: u8,
+    pub is_extern: bool,
+    pub r_type: RelocationType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScatteredRelocationInfo {
+    pub address: u32,
+    pub r_type: RelocationType,
+    pub length: u8,
+    pub pcrel: bool,
+    /// Address of the relocatable expression's target.
+    pub value: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Relocation {
+    Normal(RelocationInfo),
+    Scattered(ScatteredRelocationInfo),
+}
+
+impl Relocation {
+    pub const SIZE: usize = 8;
+    pub const R_SCATTERED: u32 = 0x80000000;
+    /// `symbolnum` of a non-extern relocation that refers to an absolute address.
+    pub const R_ABS: u32 = 0;
+
+    pub fn parse(bytes: &[u8], cputype: CpuType) -> IResult<&[u8], Relocation> {
+        let (bytes, word0) = le_u32(bytes)?;
+        let (bytes, word1) = le_u32(bytes)?;
+
+        // 64-bit architectures never use scattered relocations.
+        let has_scattered = !matches!(cputype, CpuType::X86_64 | CpuType::Arm64);
+        if has_scattered && word0 & Self::R_SCATTERED != 0 {
+            let bf = ScatteredRelocationInfoBF(word0);
+            return Ok((
+                bytes,
+                Relocation::Scattered(ScatteredRelocationInfo {
+                    address: bf.address(),
+                    r_type: RelocationType::from_raw(bf.r_type() as u8, cputype),
+                    length: bf.length() as u8,
+                    pcrel: bf.pcrel(),
+                    value: word1 as i32,
+                }),
+            ));
+        }
+
+        let bf = RelocationInfoBF(word1);
+        Ok((
+            bytes,
+            Relocation::Normal(RelocationInfo {
+                address: word0 as i32,
+                symbolnum: bf.symbolnum(),
+                pcrel: bf.pcrel(),
+                length: bf.length() as u8,
+                is_extern: bf.is_extern(),
+                r_type: RelocationType::from_raw(bf.r_type() as u8, cputype),
+            }),
+        ))
+    }
+
+    pub fn parse_all(bytes: &[u8], count: u32, cputype: CpuType) -> MachOResult<Vec<Relocation>> {
+        let (_, relocs) = multi::count(|b| Relocation::parse(b, cputype), count as usize)(bytes)?;
+        Ok(relocs)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let (word0, word1) = match self {
+            Relocation::Normal(info) => {
+                let mut bf = RelocationInfoBF(0);
+                bf.set_symbolnum(info.symbolnum);
+                bf.set_pcrel(info.pcrel);
+                bf.set_length(info.length as u32);
+                bf.set_extern(info.is_extern);
+                bf.set_type(info.r_type.raw() as u32);
+                (info.address as u32, bf.0)
+            }
+            Relocation::Scattered(info) => {
+                let mut bf = ScatteredRelocationInfoBF(0);
+                bf.set_address(info.address);
+                bf.set_type(info.r_type.raw() as u32);
+                bf.set_length(info.length as u32);
+                bf.set_pcrel(info.pcrel);
+                bf.set_scattered(true);
+                (bf.0, info.value as u32)
+            }
+        };
+
+        let mut buf = Vec::new();
+        buf.extend(word0.to_le_bytes());
+        buf.extend(word1.to_le_bytes());
+        buf
+    }
+
+    pub fn address(&self) -> u64 {
+        match self {
+            Relocation::Normal(info) => info.address as u32 as u64,
+            Relocation::Scattered(info) => info.address as u64,
+        }
+    }
+
+    pub fn r_type(&self) -> RelocationType {
+        match self {
+            Relocation::Normal(info) => info.r_type,
+            Relocation::Scattered(info) => info.r_type,
+        }
+    }
+}
+
+/// The relocations attached to a single section of an object file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionRelocations {
+    pub segname: String,
+    pub sectname: String,
+    pub relocations: Vec<Relocation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arm64_relocation() {
+        // adrp x8, _foo@PAGE
+        let reloc = Relocation::Normal(RelocationInfo {
+            address: 0x14,
+            symbolnum: 3,
+            pcrel: true,
+            length: 2,
+            is_extern: true,
+            r_type: RelocationType::Arm64(Arm64RelocType::Page21),
+        });
+
+        let serialized = reloc.serialize();
+        assert_eq!(serialized, [0x14, 0, 0, 0, 3, 0, 0, 0x3d]);
+        let (_, parsed) = Relocation::parse(&serialized, CpuType::Arm64).unwrap();
+        assert_eq!(parsed, reloc);
+    }
+
+    #[test]
+    fn test_scattered_relocation() {
+        let reloc = Relocation::Scattered(ScatteredRelocationInfo {
+            address: 0x20,
+            r_type: RelocationType::Generic(GenericRelocType::Sectdiff),
+            length: 2,
+            pcrel: false,
+            value: 0x1000,
+        });
+
+        let serialized = reloc.serialize();
+        let (_, parsed) = Relocation::parse(&serialized, CpuType::I386).unwrap();
+        assert_eq!(parsed, reloc);
+
+        // The same bits on x86_64 are an ordinary relocation.
+        let (_, parsed) = Relocation::parse(&serialized, CpuType::X86_64).unwrap();
+        assert!(matches!(parsed, Relocation::Normal(_)));
+    }
+}
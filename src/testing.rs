@@ -0,0 +1,210 @@
+// Builders for the images the tests parse.
+
+use crate::command::dyld_info::DyldInfoCommand;
+use crate::command::dysymtab::DysymtabCommand;
+use crate::command::segment::{
+    Protection, SGFlags, Section32, Section64, SectionAttributes, SectionType, SegmentCommand32,
+    SegmentCommand64,
+};
+use crate::command::{
+    CodeSignCommand, LCLoadCommand, LinkeditDataCommand, LoadCommandParser, SymtabCommand,
+};
+use crate::header::{MHFileType, MHFlags, MHMagic, MachHeader32, MachHeader64};
+use crate::machine::{CpuSubType, CpuSubTypeArm64, CpuSubTypeI386, CpuType};
+
+// A readable 64-bit segment command, or an inaccessible one for an empty segment at 0 like
+// __PAGEZERO.
+pub(crate) fn segment64(
+    segname: &str,
+    vmaddr: u64,
+    vmsize: u64,
+    fileoff: u64,
+    filesize: u64,
+    sections: Vec<Section64>,
+) -> Vec<u8> {
+    SegmentCommand64 {
+        cmd: LCLoadCommand::LcSegment64,
+        cmdsize: 72 + 80 * sections.len() as u32,
+        segname: segname.to_string(),
+        vmaddr,
+        vmsize,
+        fileoff,
+        filesize,
+        maxprot: Protection::READ,
+        initprot: if vmaddr == 0 && filesize == 0 {
+            Protection::NONE
+        } else {
+            Protection::READ
+        },
+        nsects: sections.len() as u32,
+        flags: SGFlags::empty(),
+        sections,
+    }
+    .serialize()
+}
+
+// A regular, unaligned section of `size` bytes at `addr` and file offset `offset`.
+pub(crate) fn section64(
+    segname: &str,
+    sectname: &str,
+    addr: u64,
+    size: u64,
+    offset: u32,
+) -> Section64 {
+    Section64 {
+        sectname: sectname.to_string(),
+        segname: segname.to_string(),
+        addr,
+        size,
+        offset,
+        align: 0,
+        reloff: 0,
+        nreloc: 0,
+        flags_sectype: SectionType::SRegular,
+        flags_secattrs: SectionAttributes::empty(),
+        reserved1: 0,
+        reserved2: 0,
+        reserved3: 0,
+    }
+}
+
+pub(crate) fn symtab(symoff: u32, nsyms: u32, stroff: u32, strsize: u32) -> Vec<u8> {
+    SymtabCommand {
+        cmd: LCLoadCommand::LcSymtab,
+        cmdsize: 24,
+        symoff,
+        nsyms,
+        stroff,
+        strsize,
+    }
+    .serialize()
+}
+
+pub(crate) fn code_signature(dataoff: u32, datasize: u32) -> Vec<u8> {
+    CodeSignCommand {
+        cmd: LinkeditDataCommand {
+            cmd: LCLoadCommand::LcCodeSignature,
+            cmdsize: 16,
+            dataoff,
+            datasize,
+        },
+    }
+    .serialize()
+}
+
+// An empty dynamic symbol table to fill in with struct update syntax.
+pub(crate) fn dysymtab() -> DysymtabCommand {
+    DysymtabCommand {
+        cmd: LCLoadCommand::LcDysymtab,
+        cmdsize: 80,
+        ilocalsym: 0,
+        nlocalsym: 0,
+        iextdefsym: 0,
+        nextdefsym: 0,
+        iundefsym: 0,
+        nundefsym: 0,
+        tocoff: 0,
+        ntoc: 0,
+        modtaboff: 0,
+        nmodtab: 0,
+        extrefsymoff: 0,
+        nextrefsyms: 0,
+        indirectsymoff: 0,
+        nindirectsyms: 0,
+        extreloff: 0,
+        nextrel: 0,
+        locreloff: 0,
+        nlocrel: 0,
+    }
+}
+
+// An empty LC_DYLD_INFO_ONLY to fill in with struct update syntax.
+pub(crate) fn dyld_info() -> DyldInfoCommand {
+    DyldInfoCommand {
+        cmd: LCLoadCommand::LcDyldInfoOnly,
+        cmdsize: 48,
+        rebase_off: 0,
+        rebase_size: 0,
+        bind_off: 0,
+        bind_size: 0,
+        weak_bind_off: 0,
+        weak_bind_size: 0,
+        lazy_bind_off: 0,
+        lazy_bind_size: 0,
+        export_off: 0,
+        export_size: 0,
+    }
+}
+
+// The 32-bit form of `segment64`.
+pub(crate) fn segment32(
+    segname: &str,
+    vmaddr: u32,
+    vmsize: u32,
+    fileoff: u32,
+    filesize: u32,
+    sects: Vec<Section32>,
+) -> Vec<u8> {
+    SegmentCommand32 {
+        cmd: LCLoadCommand::LcSegment,
+        cmdsize: 56 + 68 * sects.len() as u32,
+        segname: segname.to_string(),
+        vmaddr,
+        vmsize,
+        fileoff,
+        filesize,
+        maxprot: Protection::READ,
+        initprot: if vmaddr == 0 && filesize == 0 {
+            Protection::NONE
+        } else {
+            Protection::READ
+        },
+        nsects: sects.len() as u32,
+        flags: SGFlags::empty(),
+        sects,
+    }
+    .serialize()
+}
+
+// An i386 header followed by `cmds`.
+pub(crate) fn image32(filetype: MHFileType, cmds: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = MachHeader32 {
+        magic: MHMagic::MhMagic,
+        cputype: CpuType::I386,
+        cpusubtype: CpuSubType::CpuSubTypeI386(CpuSubTypeI386::All),
+        filetype,
+        ncmds: cmds.len() as u32,
+        sizeofcmds: cmds.iter().map(|cmd| cmd.len() as u32).sum(),
+        flags: MHFlags::empty(),
+    }
+    .serialize();
+    bytes.extend(cmds.concat());
+    bytes
+}
+
+// An arm64 header followed by `cmds`.
+pub(crate) fn image64(filetype: MHFileType, cmds: &[Vec<u8>]) -> Vec<u8> {
+    let cpusubtype = CpuSubType::CpuSubTypeArm64(CpuSubTypeArm64::All);
+    image64_for(CpuType::Arm64, cpusubtype, filetype, cmds)
+}
+
+pub(crate) fn image64_for(
+    cputype: CpuType,
+    cpusubtype: CpuSubType,
+    filetype: MHFileType,
+    cmds: &[Vec<u8>],
+) -> Vec<u8> {
+    let mut bytes = MachHeader64 {
+        magic: MHMagic::MhMagic64,
+        cputype,
+        cpusubtype,
+        filetype,
+        ncmds: cmds.len() as u32,
+        sizeofcmds: cmds.iter().map(|cmd| cmd.len() as u32).sum(),
+        flags: MHFlags::empty(),
+        reserved: 0,
+    }
+    .serialize();
+    bytes.extend(cmds.concat());
+    bytes
+}
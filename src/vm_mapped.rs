@@ -0,0 +1,131 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// A segment's place in the file and in a memory dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedSegment {
+    pub fileoff: u64,
+    pub filesize: u64,
+    /// Where the segment starts in the dump, relative to the load address.
+    pub offset: u64,
+}
+
+/// Reads an image laid out by VM address, such as one dumped from a process or a core file,
+/// as if it were laid out like its file. Each file offset is read from where its segment was
+/// loaded, so the load commands' file offsets can be used unchanged.
+pub struct VmMapped<T: Read + Seek> {
+    /// The dump, which starts at the image's load address
+    memory: T,
+    segments: Vec<MappedSegment>,
+    /// Current position in file offsets
+    position: u64,
+}
+
+impl<T: Read + Seek> VmMapped<T> {
+    pub fn new(memory: T, segments: Vec<MappedSegment>) -> Self {
+        Self {
+            memory,
+            segments,
+            position: 0,
+        }
+    }
+
+    /// The size of the file the segments were mapped from.
+    pub fn size(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|seg| seg.fileoff + seg.filesize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Get the underlying dump
+    pub fn get_source(&mut self) -> &mut T {
+        &mut self.memory
+    }
+
+    // The dump offset of `position` and how much of its segment follows it.
+    fn map(&self, position: u64) -> Option<(u64, u64)> {
+        self.segments
+            .iter()
+            .find(|seg| seg.fileoff <= position && position < seg.fileoff + seg.filesize)
+            .map(|seg| {
+                let delta = position - seg.fileoff;
+                (seg.offset + delta, seg.filesize - delta)
+            })
+    }
+}
+
+impl<T: Read + Seek> Read for VmMapped<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.size() {
+            return Ok(0);
+        }
+        let (offset, remaining) = self.map(self.position).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("File offset {:#x} isn't in a segment", self.position),
+            )
+        })?;
+
+        // Reads stop at the end of the segment, since the next one may be mapped elsewhere.
+        let to_read = std::cmp::min(buf.len() as u64, remaining) as usize;
+        self.memory.seek(SeekFrom::Start(offset))?;
+        let bytes_read = self.memory.read(&mut buf[..to_read])?;
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<T: Read + Seek> Seek for VmMapped<T> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.size().checked_add_signed(delta),
+        };
+        self.position = new_pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Attempted to seek before the start of the image",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_vm_mapped_read() -> io::Result<()> {
+        // The second segment is loaded 0x10 bytes after the first, but follows it in the file.
+        let memory = b"0123456789______ABCDEFGHIJ".to_vec();
+        let mut mapped = VmMapped::new(
+            Cursor::new(memory),
+            vec![
+                MappedSegment {
+                    fileoff: 0,
+                    filesize: 10,
+                    offset: 0,
+                },
+                MappedSegment {
+                    fileoff: 10,
+                    filesize: 10,
+                    offset: 0x10,
+                },
+            ],
+        );
+
+        let mut buffer = [0u8; 6];
+        mapped.seek(SeekFrom::Start(7))?;
+        mapped.read_exact(&mut buffer)?;
+        assert_eq!(&buffer, b"789ABC");
+
+        let mut rest = vec![];
+        mapped.read_to_end(&mut rest)?;
+        assert_eq!(rest, b"DEFGHIJ");
+        Ok(())
+    }
+}